@@ -0,0 +1,38 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::process::Command;
+
+/// Records the git commit darkfid is built from, so `get_version` can
+/// report it. Building outside a git checkout leaves it unset.
+fn main() {
+    println!("cargo:rerun-if-env-changed=DARKFI_GIT_HASH");
+    if std::env::var("DARKFI_GIT_HASH").is_ok() {
+        return
+    }
+
+    let Ok(output) = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output() else {
+        return
+    };
+    if !output.status.success() {
+        return
+    }
+
+    let hash = String::from_utf8_lossy(&output.stdout);
+    println!("cargo:rustc-env=DARKFI_GIT_HASH={}", hash.trim());
+}
@@ -0,0 +1,180 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, path::PathBuf};
+
+use log::error;
+use smol::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+    lock::Mutex,
+};
+use tinyjson::JsonValue;
+
+use darkfi::{
+    rpc::{
+        jsonrpc::{JsonRequest, JsonResult},
+        logging::redact_params,
+    },
+    util::{path::expand_path, time::Timestamp},
+    Result,
+};
+
+/// JSON-RPC methods mutating node state, recorded in the audit log
+pub const AUDITED_METHODS: &[&str] = &[
+    "tx.broadcast",
+    "tx.clean_pending",
+    "dnet.switch",
+    "p2p.disconnect_peer",
+    "p2p.blacklist_peer",
+    "p2p.connect_peer",
+    "stop",
+    "reload_config",
+];
+
+/// Append-only log of the audited JSON-RPC calls, stored as JSON lines
+pub struct AuditLog {
+    /// Path to the log file
+    path: PathBuf,
+    /// Lock serializing writes, so lines don't interleave
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    /// Open the audit log at given path, creating its parent directories
+    pub fn new(path: &str) -> Result<Self> {
+        let path = expand_path(path)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        Ok(Self { path, lock: Mutex::new(()) })
+    }
+
+    /// Record a call to an audited method along with its outcome. The
+    /// caller is identified by a fingerprint of its authentication token,
+    /// so the token itself never ends up in the log, and sensitive params
+    /// are redacted the same way as in the request logs.
+    pub async fn record(&self, req: &JsonRequest, rep: &JsonResult) {
+        let caller = match req.auth {
+            Some(ref token) => blake3::hash(token.as_bytes()).to_hex()[..16].to_string(),
+            None => "anonymous".to_string(),
+        };
+
+        let result = match rep {
+            JsonResult::Response(r) => HashMap::from([("ok".to_string(), r.result.clone())]),
+            JsonResult::Error(e) => {
+                HashMap::from([("error".to_string(), JsonValue::Number(e.error.code as f64))])
+            }
+            _ => HashMap::from([("ok".to_string(), JsonValue::Null)]),
+        };
+
+        let entry = JsonValue::Object(HashMap::from([
+            ("timestamp".to_string(), JsonValue::Number(Timestamp::current_time().inner() as f64)),
+            ("method".to_string(), JsonValue::String(req.method.clone())),
+            ("params".to_string(), redact_params(&req.params)),
+            ("caller".to_string(), JsonValue::String(caller)),
+            ("result".to_string(), JsonValue::Object(result)),
+        ]));
+
+        if let Err(e) = self.append(&entry).await {
+            error!(target: "darkfid::audit::record", "Failed writing audit log entry: {}", e);
+        }
+    }
+
+    /// Append an entry to the log file
+    async fn append(&self, entry: &JsonValue) -> Result<()> {
+        let line = format!("{}\n", entry.stringify()?);
+
+        let _lock = self.lock.lock().await;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path).await?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+
+        Ok(())
+    }
+
+    /// Retrieve the last `limit` entries of the log, oldest first,
+    /// optionally only the ones of given method.
+    pub async fn entries(&self, method: Option<&str>, limit: usize) -> Result<Vec<JsonValue>> {
+        let lock = self.lock.lock().await;
+        let contents = match fs::read_to_string(&self.path).await {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        drop(lock);
+
+        let mut entries = vec![];
+        for line in contents.lines().rev() {
+            if entries.len() == limit {
+                break
+            }
+
+            let entry: JsonValue = line.parse()?;
+            if let Some(method) = method {
+                if entry["method"] != JsonValue::String(method.to_string()) {
+                    continue
+                }
+            }
+            entries.push(entry);
+        }
+        entries.reverse();
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use darkfi::rpc::jsonrpc::{ErrorCode, JsonError, JsonResponse};
+
+    use super::*;
+
+    #[test]
+    fn test_audit_log() {
+        smol::block_on(async {
+            let path =
+                std::env::temp_dir().join(format!("darkfid_test_audit_{}.log", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let log = AuditLog::new(path.to_str().unwrap()).unwrap();
+
+            let params = JsonValue::Array(vec![JsonValue::String("tx".to_string())]);
+            let req = JsonRequest::new("tx.broadcast", params).with_auth("secret");
+            let rep = JsonResponse::new(JsonValue::String("hash".to_string()), req.id).into();
+            log.record(&req, &rep).await;
+
+            let req = JsonRequest::new("tx.clean_pending", JsonValue::Array(vec![]));
+            let rep = JsonError::new(ErrorCode::Unauthorized, None, req.id).into();
+            log.record(&req, &rep).await;
+
+            let entries = log.entries(None, 10).await.unwrap();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[1]["caller"], JsonValue::String("anonymous".to_string()));
+            assert!(!std::fs::read_to_string(&path).unwrap().contains("secret"));
+
+            let entries = log.entries(Some("tx.broadcast"), 10).await.unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0]["result"]["ok"], JsonValue::String("hash".to_string()));
+
+            assert_eq!(log.entries(None, 1).await.unwrap().len(), 1);
+
+            std::fs::remove_file(&path).unwrap();
+        });
+    }
+}
@@ -0,0 +1,176 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use smol::lock::Mutex;
+use tinyjson::JsonValue;
+
+use darkfi::rpc::jsonrpc::JsonRequest;
+
+/// Idempotent read-only JSON-RPC methods whose replies get cached, along
+/// with how long (in seconds) a cached reply is served for
+pub const CACHED_METHODS: &[(&str, u64)] = &[
+    ("get_version", 3600),
+    ("merge_mining_get_chain_id", 3600),
+    ("blockchain.block_target", 3600),
+    ("blockchain.get_block", 60),
+    ("blockchain.lookup_zkas", 60),
+    ("blockchain.last_known_block", 10),
+    ("blockchain.best_fork_next_block_height", 10),
+    ("p2p.get_info", 5),
+];
+
+/// Cached methods ignoring their params, whose replies are cached once
+/// regardless of the params they were requested with
+pub const PARAMLESS_METHODS: &[&str] = &["get_version", "merge_mining_get_chain_id"];
+
+/// Maximum number of cached replies. Once reached, further replies are
+/// not cached until entries expire or get invalidated.
+pub const MAX_CACHE_ENTRIES: usize = 1024;
+
+/// Prefix of the cached methods whose replies depend on the blockchain
+/// state, invalidated whenever new blocks are appended
+pub const BLOCKCHAIN_METHODS_PREFIX: &str = "blockchain.";
+
+/// Key of a cached reply: the method and its stringified params, which
+/// are left empty for [`PARAMLESS_METHODS`]
+pub type CacheKey = (String, String);
+
+/// In-memory cache of the replies to the methods in [`CACHED_METHODS`]
+pub struct ReplyCache {
+    /// Cached results along with their expiry time
+    entries: Mutex<HashMap<CacheKey, (Instant, JsonValue)>>,
+}
+
+impl ReplyCache {
+    pub fn new() -> Self {
+        Self { entries: Mutex::new(HashMap::new()) }
+    }
+
+    /// Cache key of given request, if its method replies get cached
+    pub fn key(req: &JsonRequest) -> Option<CacheKey> {
+        if !CACHED_METHODS.iter().any(|(method, _)| *method == req.method) {
+            return None
+        }
+
+        if PARAMLESS_METHODS.contains(&req.method.as_str()) {
+            return Some((req.method.clone(), String::new()))
+        }
+
+        let params = req.params.stringify().ok()?;
+        Some((req.method.clone(), params))
+    }
+
+    /// Retrieve the cached result for given key, if it hasn't expired
+    pub async fn get(&self, key: &CacheKey) -> Option<JsonValue> {
+        let mut entries = self.entries.lock().await;
+        let (expiry, result) = entries.get(key)?;
+        if *expiry > Instant::now() {
+            return Some(result.clone())
+        }
+
+        entries.remove(key);
+        None
+    }
+
+    /// Cache the result for given key, for the TTL of its method. Expired
+    /// entries are swept first, and nothing new is cached if the cache is
+    /// still full afterwards.
+    pub async fn insert(&self, key: CacheKey, result: JsonValue) {
+        let Some((_, ttl)) = CACHED_METHODS.iter().find(|(method, _)| *method == key.0) else {
+            return
+        };
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().await;
+        entries.retain(|_, (expiry, _)| *expiry > now);
+        if entries.len() >= MAX_CACHE_ENTRIES && !entries.contains_key(&key) {
+            return
+        }
+
+        entries.insert(key, (now + Duration::from_secs(*ttl), result));
+    }
+
+    /// Drop all the cached replies of methods starting with given prefix.
+    /// An empty prefix clears the whole cache.
+    pub async fn invalidate(&self, prefix: &str) {
+        self.entries.lock().await.retain(|(method, _), _| !method.starts_with(prefix));
+    }
+}
+
+impl Default for ReplyCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply_cache() {
+        smol::block_on(async {
+            let cache = ReplyCache::new();
+
+            let req = JsonRequest::new("tx.broadcast", JsonValue::Array(vec![]));
+            assert!(ReplyCache::key(&req).is_none());
+
+            let params = JsonValue::Array(vec![JsonValue::String("1".to_string())]);
+            let req = JsonRequest::new("blockchain.get_block", params);
+            let key = ReplyCache::key(&req).unwrap();
+            assert!(cache.get(&key).await.is_none());
+
+            let block = JsonValue::String("block".to_string());
+            cache.insert(key.clone(), block.clone()).await;
+            assert_eq!(cache.get(&key).await, Some(block));
+
+            let req = JsonRequest::new("p2p.get_info", JsonValue::Array(vec![]));
+            let info_key = ReplyCache::key(&req).unwrap();
+            cache.insert(info_key.clone(), JsonValue::Null).await;
+
+            cache.invalidate(BLOCKCHAIN_METHODS_PREFIX).await;
+            assert!(cache.get(&key).await.is_none());
+            assert_eq!(cache.get(&info_key).await, Some(JsonValue::Null));
+
+            cache.invalidate("").await;
+            assert!(cache.get(&info_key).await.is_none());
+
+            // Params of paramless methods don't make distinct entries
+            let params = JsonValue::Array(vec![JsonValue::String("junk".to_string())]);
+            let junk_key = ReplyCache::key(&JsonRequest::new("get_version", params)).unwrap();
+            let req = JsonRequest::new("get_version", JsonValue::Array(vec![]));
+            assert_eq!(ReplyCache::key(&req), Some(junk_key));
+
+            // Nothing new gets cached once full
+            for i in 0..MAX_CACHE_ENTRIES + 1 {
+                let params = JsonValue::Array(vec![JsonValue::String(i.to_string())]);
+                let key = ReplyCache::key(&JsonRequest::new("blockchain.get_block", params));
+                cache.insert(key.unwrap(), JsonValue::Null).await;
+            }
+            assert_eq!(cache.entries.lock().await.len(), MAX_CACHE_ENTRIES);
+            let params = JsonValue::Array(vec![JsonValue::String("0".to_string())]);
+            let key = ReplyCache::key(&JsonRequest::new("blockchain.get_block", params));
+            assert_eq!(cache.get(&key.unwrap()).await, Some(JsonValue::Null));
+        });
+    }
+}
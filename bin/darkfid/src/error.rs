@@ -37,6 +37,7 @@ pub enum RpcError {
 
     // Misc errors
     PingFailed = -32300,
+    AuditLogDisabled = -32301,
 }
 
 fn to_tuple(e: RpcError) -> (i32, String) {
@@ -53,6 +54,7 @@ fn to_tuple(e: RpcError) -> (i32, String) {
         RpcError::ContractZkasDbNotFound => "zkas database not found for given contract",
         // Misc errors
         RpcError::PingFailed => "Miner daemon ping error",
+        RpcError::AuditLogDisabled => "Audit log is not enabled",
     };
 
     (e as i32, msg.to_string())
@@ -22,7 +22,10 @@ use std::{
 };
 
 use log::{debug, error, info};
-use smol::lock::Mutex;
+use smol::{
+    channel::{Receiver, Sender},
+    lock::Mutex,
+};
 use url::Url;
 
 use darkfi::{
@@ -31,6 +34,7 @@ use darkfi::{
         client::RpcChadClient,
         jsonrpc::JsonSubscriber,
         server::{listen_and_serve, RequestHandler},
+        util::JsonValue,
     },
     system::{ExecutorPtr, StoppableTask, StoppableTaskPtr},
     validator::{Validator, ValidatorConfig, ValidatorPtr},
@@ -43,9 +47,22 @@ mod tests;
 mod error;
 use error::{server_error, RpcError};
 
+/// Audit log of state mutating JSON-RPC calls
+mod audit;
+use audit::AuditLog;
+
+/// Cache of idempotent JSON-RPC method replies
+mod cache;
+use cache::{ReplyCache, BLOCKCHAIN_METHODS_PREFIX};
+
+/// Block producer signing keys
+mod signer;
+
 /// JSON-RPC requests handler and methods
 mod rpc;
+use rpc::RpcAccess;
 mod rpc_blockchain;
+mod rpc_payment;
 mod rpc_tx;
 
 /// Validator async tasks
@@ -69,6 +86,12 @@ impl MinerRpcClient {
         let client = RpcChadClient::new(endpoint.clone(), ex.clone()).await?;
         Ok(Self { endpoint, ex, client })
     }
+
+    /// Replace the client with a new connection to the same endpoint
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.client = RpcChadClient::new(self.endpoint.clone(), self.ex.clone()).await?;
+        Ok(())
+    }
 }
 
 /// Atomic pointer to the DarkFi node
@@ -88,6 +111,26 @@ pub struct DarkfiNode {
     rpc_connections: Mutex<HashSet<StoppableTaskPtr>>,
     /// JSON-RPC client to execute requests to the miner daemon
     rpc_client: Option<Mutex<MinerRpcClient>>,
+    /// JSON-RPC client to the external signer holding the block
+    /// producer keys, if one is configured
+    signer_client: Option<Mutex<MinerRpcClient>>,
+    /// Flag indicating only the read-only public JSON-RPC methods are served
+    public_mode: bool,
+    /// JSON-RPC authentication tokens and their access level.
+    /// Authentication is disabled when empty.
+    rpc_auth: HashMap<String, RpcAccess>,
+    /// Audit log of state mutating JSON-RPC calls, if enabled
+    audit_log: Option<AuditLog>,
+    /// Cache of idempotent JSON-RPC method replies, if enabled
+    reply_cache: Option<ReplyCache>,
+    /// Sender of shutdown requests made over JSON-RPC
+    shutdown_tx: Sender<()>,
+    /// Receiver of shutdown requests made over JSON-RPC
+    shutdown_rx: Receiver<()>,
+    /// Sender of configuration reload requests made over JSON-RPC
+    reload_tx: Sender<()>,
+    /// Receiver of configuration reload requests made over JSON-RPC
+    reload_rx: Receiver<()>,
 }
 
 impl DarkfiNode {
@@ -97,7 +140,14 @@ impl DarkfiNode {
         txs_batch_size: usize,
         subscribers: HashMap<&'static str, JsonSubscriber>,
         rpc_client: Option<Mutex<MinerRpcClient>>,
+        signer_client: Option<Mutex<MinerRpcClient>>,
+        public_mode: bool,
+        rpc_auth: HashMap<String, RpcAccess>,
+        audit_log: Option<AuditLog>,
+        reply_cache: Option<ReplyCache>,
     ) -> DarkfiNodePtr {
+        let (shutdown_tx, shutdown_rx) = smol::channel::bounded(1);
+        let (reload_tx, reload_rx) = smol::channel::bounded(1);
         Arc::new(Self {
             p2p_handler,
             validator,
@@ -105,6 +155,15 @@ impl DarkfiNode {
             subscribers,
             rpc_connections: Mutex::new(HashSet::new()),
             rpc_client,
+            signer_client,
+            public_mode,
+            rpc_auth,
+            audit_log,
+            reply_cache,
+            shutdown_tx,
+            shutdown_rx,
+            reload_tx,
+            reload_rx,
         })
     }
 }
@@ -118,6 +177,8 @@ pub struct Darkfid {
     node: DarkfiNodePtr,
     /// `dnet` background task
     dnet_task: StoppableTaskPtr,
+    /// JSON-RPC reply cache invalidation background task
+    cache_task: StoppableTaskPtr,
     /// JSON-RPC background task
     rpc_task: StoppableTaskPtr,
     /// Consensus protocol background task
@@ -134,7 +195,13 @@ impl Darkfid {
         config: &ValidatorConfig,
         net_settings: &Settings,
         minerd_endpoint: &Option<Url>,
+        signer_endpoint: &Option<Url>,
         txs_batch_size: &Option<usize>,
+        public_mode: bool,
+        rpc_read_tokens: &[String],
+        rpc_full_tokens: &[String],
+        audit_log: &Option<String>,
+        rpc_cache: bool,
         ex: &ExecutorPtr,
     ) -> Result<DarkfidPtr> {
         info!(target: "darkfid::Darkfid::init", "Initializing a Darkfi daemon...");
@@ -175,18 +242,77 @@ impl Darkfid {
             None => None,
         };
 
+        // Initialize JSON-RPC client to perform requests to the external signer
+        let signer_client = match signer_endpoint {
+            Some(endpoint) => {
+                let Ok(signer_client) = MinerRpcClient::new(endpoint.clone(), ex.clone()).await
+                else {
+                    error!(target: "darkfid::Darkfid::init", "Failed to initialize signer rpc client, check if the signer is running");
+                    return Err(Error::RpcClientStopped)
+                };
+                info!(target: "darkfid::Darkfid::init", "Delegating block signing to external signer at {}", endpoint);
+                Some(Mutex::new(signer_client))
+            }
+            None => None,
+        };
+
+        if public_mode {
+            info!(target: "darkfid::Darkfid::init", "Node is configured to run in public mode, only serving read-only methods");
+        }
+
+        // Map the configured JSON-RPC authentication tokens to their access level
+        let mut rpc_auth = HashMap::new();
+        for token in rpc_read_tokens {
+            rpc_auth.insert(token.clone(), RpcAccess::Read);
+        }
+        for token in rpc_full_tokens {
+            rpc_auth.insert(token.clone(), RpcAccess::Full);
+        }
+        if !rpc_auth.is_empty() {
+            info!(target: "darkfid::Darkfid::init", "JSON-RPC authentication is enabled with {} tokens", rpc_auth.len());
+        }
+
+        // Open the audit log, if configured
+        let audit_log = match audit_log {
+            Some(path) => {
+                info!(target: "darkfid::Darkfid::init", "Recording state mutating JSON-RPC calls to {}", path);
+                Some(AuditLog::new(path)?)
+            }
+            None => None,
+        };
+
+        // Create the JSON-RPC reply cache, if enabled
+        let reply_cache = if rpc_cache {
+            info!(target: "darkfid::Darkfid::init", "Caching replies of idempotent JSON-RPC methods");
+            Some(ReplyCache::new())
+        } else {
+            None
+        };
+
         // Initialize node
-        let node =
-            DarkfiNode::new(p2p_handler, validator, txs_batch_size, subscribers, rpc_client).await;
+        let node = DarkfiNode::new(
+            p2p_handler,
+            validator,
+            txs_batch_size,
+            subscribers,
+            rpc_client,
+            signer_client,
+            public_mode,
+            rpc_auth,
+            audit_log,
+            reply_cache,
+        )
+        .await;
 
         // Generate the background tasks
         let dnet_task = StoppableTask::new();
+        let cache_task = StoppableTask::new();
         let rpc_task = StoppableTask::new();
         let consensus_task = StoppableTask::new();
 
         info!(target: "darkfid::Darkfid::init", "Darkfi daemon initialized successfully!");
 
-        Ok(Arc::new(Self { node, dnet_task, rpc_task, consensus_task }))
+        Ok(Arc::new(Self { node, dnet_task, cache_task, rpc_task, consensus_task }))
     }
 
     /// Start the DarkFi daemon in the given executor, using the provided JSON-RPC listen url
@@ -207,6 +333,14 @@ impl Darkfid {
             }
         }
 
+        // Pinging the external signer to verify it listens
+        if self.node.signer_client.is_some() {
+            if let Err(e) = self.node.signer_request("ping", &JsonValue::Array(vec![])).await {
+                error!(target: "darkfid::Darkfid::start", "Failed to ping signer: {}", e);
+                return Err(Error::RpcClientStopped)
+            }
+        }
+
         // Start the `dnet` task
         info!(target: "darkfid::Darkfid::start", "Starting dnet subs task");
         let dnet_sub_ = self.node.subscribers.get("dnet").unwrap().clone();
@@ -230,6 +364,32 @@ impl Darkfid {
             executor.clone(),
         );
 
+        // Start the JSON-RPC reply cache invalidation task
+        if self.node.reply_cache.is_some() {
+            info!(target: "darkfid::Darkfid::start", "Starting JSON-RPC reply cache invalidation task");
+            let blocks_pub = self.node.subscribers.get("blocks").unwrap().publisher.clone();
+            let node_ = self.node.clone();
+            self.cache_task.clone().start(
+                async move {
+                    let block_sub = blocks_pub.subscribe().await;
+                    loop {
+                        block_sub.receive().await;
+                        debug!(target: "darkfid::Darkfid::cache_task", "Got new blocks, invalidating blockchain replies");
+                        let reply_cache = node_.reply_cache.as_ref().unwrap();
+                        reply_cache.invalidate(BLOCKCHAIN_METHODS_PREFIX).await;
+                    }
+                },
+                |res| async {
+                    match res {
+                        Ok(()) | Err(Error::DetachedTaskStopped) => { /* Do nothing */ }
+                        Err(e) => error!(target: "darkfid::Darkfid::start", "Failed starting reply cache invalidation task: {}", e),
+                    }
+                },
+                Error::DetachedTaskStopped,
+                executor.clone(),
+            );
+        }
+
         // Start the JSON-RPC task
         info!(target: "darkfid::Darkfid::start", "Starting JSON-RPC server");
         let node_ = self.node.clone();
@@ -275,6 +435,27 @@ impl Darkfid {
         Ok(())
     }
 
+    /// Wait until a shutdown of the daemon is requested over JSON-RPC.
+    pub async fn shutdown_requested(&self) {
+        // The sender lives in the node, so the channel never closes
+        let _ = self.node.shutdown_rx.recv().await;
+    }
+
+    /// Wait until a configuration reload is requested over JSON-RPC.
+    pub async fn reload_requested(&self) {
+        // The sender lives in the node, so the channel never closes
+        let _ = self.node.reload_rx.recv().await;
+    }
+
+    /// Apply the settings of a reloaded configuration that can change
+    /// while the daemon is running. See [`Settings::reload()`] for the
+    /// P2P settings that get applied.
+    pub async fn reload(&self, net_settings: &Settings) {
+        info!(target: "darkfid::Darkfid::reload", "Reloading configuration...");
+        self.node.p2p_handler.p2p.settings().write().await.reload(net_settings);
+        info!(target: "darkfid::Darkfid::reload", "Configuration reloaded successfully!");
+    }
+
     /// Stop the DarkFi daemon.
     pub async fn stop(&self) -> Result<()> {
         info!(target: "darkfid::Darkfid::stop", "Terminating Darkfi daemon...");
@@ -283,6 +464,12 @@ impl Darkfid {
         info!(target: "darkfid::Darkfid::stop", "Stopping dnet subs task...");
         self.dnet_task.stop().await;
 
+        // Stop the JSON-RPC reply cache invalidation task
+        if self.node.reply_cache.is_some() {
+            info!(target: "darkfid::Darkfid::stop", "Stopping JSON-RPC reply cache invalidation task...");
+            self.cache_task.stop().await;
+        }
+
         // Stop the JSON-RPC task
         info!(target: "darkfid::Darkfid::stop", "Stopping JSON-RPC server...");
         self.rpc_task.stop().await;
@@ -306,6 +493,12 @@ impl Darkfid {
             rpc_client.lock().await.client.stop().await;
         };
 
+        // Close the signer JSON-RPC client, if it was initialized
+        if let Some(ref signer_client) = self.node.signer_client {
+            info!(target: "darkfid::Darkfid::stop", "Stopping signer JSON-RPC client...");
+            signer_client.lock().await.client.stop().await;
+        };
+
         info!(target: "darkfid::Darkfid::stop", "Darkfi daemon terminated successfully!");
         Ok(())
     }
@@ -28,6 +28,7 @@ use darkfi::{
     blockchain::BlockInfo,
     cli_desc,
     net::settings::SettingsOpt,
+    system::Subscription,
     util::{
         encoding::base64,
         path::{expand_path, get_config_path},
@@ -37,7 +38,7 @@ use darkfi::{
 };
 use darkfi_serial::deserialize_async;
 
-use darkfid::{task::consensus::ConsensusInitTaskConfig, Darkfid};
+use darkfid::{task::consensus::ConsensusInitTaskConfig, Darkfid, DarkfidPtr};
 
 const CONFIG_FILE: &str = "darkfid_config.toml";
 const CONFIG_FILE_CONTENTS: &str = include_str!("../darkfid_config.toml");
@@ -90,6 +91,10 @@ pub struct BlockchainNetwork {
     /// minerd JSON-RPC endpoint
     minerd_endpoint: Option<Url>,
 
+    #[structopt(long)]
+    /// External signer JSON-RPC endpoint, holding the block producer keys
+    signer_endpoint: Option<Url>,
+
     #[structopt(long, default_value = "10")]
     /// PoW block production target, in seconds
     pow_target: u32,
@@ -134,6 +139,30 @@ pub struct BlockchainNetwork {
     /// Garbage collection task transactions batch size
     txs_batch_size: Option<usize>,
 
+    #[structopt(long)]
+    #[serde(default)]
+    /// Only serve the read-only, non-sensitive JSON-RPC methods
+    public_mode: bool,
+
+    #[structopt(long)]
+    #[serde(default)]
+    /// JSON-RPC authentication tokens allowed to call the read-only methods
+    rpc_read_tokens: Vec<String>,
+
+    #[structopt(long)]
+    #[serde(default)]
+    /// JSON-RPC authentication tokens allowed to call every method
+    rpc_full_tokens: Vec<String>,
+
+    #[structopt(long)]
+    /// Path to the audit log of state mutating JSON-RPC calls
+    audit_log: Option<String>,
+
+    #[structopt(long)]
+    #[serde(default)]
+    /// Cache the replies of idempotent read-only JSON-RPC methods
+    rpc_cache: bool,
+
     /// P2P network settings
     #[structopt(flatten)]
     net: SettingsOpt,
@@ -143,6 +172,9 @@ async_daemonize!(realmain);
 async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
     info!(target: "darkfid", "Initializing DarkFi node...");
 
+    // Keep the configuration file path around for reloads
+    let config_path = args.config.clone();
+
     // Grab blockchain network configuration
     let (blockchain_config, genesis_block) = match args.network.as_str() {
         "localnet" => {
@@ -196,7 +228,13 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         &config,
         &blockchain_config.net.into(),
         &blockchain_config.minerd_endpoint,
+        &blockchain_config.signer_endpoint,
         &blockchain_config.txs_batch_size,
+        blockchain_config.public_mode,
+        &blockchain_config.rpc_read_tokens,
+        &blockchain_config.rpc_full_tokens,
+        &blockchain_config.audit_log,
+        blockchain_config.rpc_cache,
         &ex,
     )
     .await?;
@@ -214,11 +252,29 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
     };
     daemon.start(&ex, &blockchain_config.rpc_listen, &config).await?;
 
-    // Signal handling for graceful termination.
-    let (signals_handler, signals_task) = SignalHandler::new(ex)?;
-    signals_handler.wait_termination(signals_task).await?;
-    info!(target: "darkfid", "Caught termination signal, cleaning up and exiting...");
+    // Signal handling for graceful termination. A shutdown can also be
+    // requested over JSON-RPC.
+    let (signals_handler, signals_task) = SignalHandler::new(ex.clone())?;
 
+    // Configuration reloading, on SIGHUP or when requested over JSON-RPC
+    let sighup_sub = signals_handler.sighup_pub.clone().subscribe().await;
+    let reload_task =
+        ex.spawn(reload_config(daemon.clone(), config_path, args.network, sighup_sub));
+
+    let termination = async { signals_handler.wait_termination(signals_task).await.map(|_| true) };
+    let request = async {
+        daemon.shutdown_requested().await;
+        Ok(false)
+    };
+    if smol::future::or(termination, request).await? {
+        info!(target: "darkfid", "Caught termination signal, cleaning up and exiting...");
+    } else {
+        // Dropping the termination future stopped the signals task
+        signals_handler.handle.close();
+        info!(target: "darkfid", "Shutdown requested, cleaning up and exiting...");
+    }
+
+    reload_task.cancel().await;
     daemon.stop().await?;
 
     info!(target: "darkfid", "Shut down successfully");
@@ -226,6 +282,32 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
     Ok(())
 }
 
+/// Auxiliary task re-reading the configuration file whenever a reload is
+/// requested, and applying the settings that can change at runtime to the
+/// daemon. Configuration errors are logged and the previous settings kept.
+async fn reload_config(
+    daemon: DarkfidPtr,
+    config: Option<String>,
+    network: String,
+    sighup_sub: Subscription<Args>,
+) {
+    loop {
+        let sighup = async {
+            sighup_sub.receive().await;
+        };
+        smol::future::or(sighup, daemon.reload_requested()).await;
+
+        let blockchain_config = match parse_blockchain_config(config.clone(), &network).await {
+            Ok(c) => c,
+            Err(e) => {
+                error!(target: "darkfid", "Failed reloading configuration: {}", e);
+                continue
+            }
+        };
+        daemon.reload(&blockchain_config.net.into()).await;
+    }
+}
+
 /// Auxiliary function to parse darkfid configuration file and extract requested
 /// blockchain network config.
 pub async fn parse_blockchain_config(
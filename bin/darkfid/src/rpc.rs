@@ -16,7 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{collections::HashSet, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
 
 use async_trait::async_trait;
 use log::{debug, error, info};
@@ -30,6 +33,7 @@ use darkfi::{
         jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResponse, JsonResult},
         p2p_method::HandlerP2p,
         server::RequestHandler,
+        version_method::{DaemonVersion, HandlerVersion},
     },
     system::{sleep, StoppableTaskPtr},
     util::time::Timestamp,
@@ -37,27 +41,132 @@ use darkfi::{
 };
 
 use crate::{
+    audit::AUDITED_METHODS,
+    cache::ReplyCache,
     error::{server_error, RpcError},
     DarkfiNode,
 };
 
+/// Revision of the darkfid RPC API, bumped when existing methods are
+/// removed or change their params or response.
+pub const RPC_API_REVISION: u64 = 2;
+
+/// Methods exposed when the node runs in public mode. These only read
+/// public blockchain data, so they are safe to serve to anyone. Methods
+/// mutating node state, exposing our network view, or running contract
+/// code on caller input are left out.
+pub const PUBLIC_METHODS: &[&str] = &[
+    "ping",
+    "get_version",
+    "clock",
+    "blockchain.get_block",
+    "blockchain.get_tx",
+    "tx.get_status",
+    "blockchain.last_known_block",
+    "blockchain.best_fork_next_block_height",
+    "blockchain.block_target",
+    "blockchain.lookup_zkas",
+    "blockchain.subscribe_blocks",
+    "merge_mining_get_chain_id",
+    "create_payment_request",
+    "parse_payment_request",
+];
+
+/// Access level granted to a JSON-RPC authentication token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RpcAccess {
+    /// Only the read-only methods listed in [`PUBLIC_METHODS`]
+    Read,
+    /// Every method, including the ones mutating node state
+    Full,
+}
+
 #[async_trait]
 #[rustfmt::skip]
 impl RequestHandler for DarkfiNode {
     async fn handle_request(&self, req: JsonRequest) -> JsonResult {
-        debug!(target: "darkfid::rpc", "--> {}", req.stringify().unwrap());
+        // Keep a copy of audited calls, to record them along with their outcome
+        let mutating = AUDITED_METHODS.contains(&req.method.as_str());
+        let audited = match self.audit_log {
+            Some(_) if mutating => Some(req.clone()),
+            _ => None,
+        };
+
+        let rep = self.dispatch(req).await;
+
+        // Calls mutating node state may change any cached reply
+        if let (Some(reply_cache), true) = (&self.reply_cache, mutating) {
+            reply_cache.invalidate("").await;
+        }
+
+        if let (Some(audit_log), Some(req)) = (&self.audit_log, audited) {
+            audit_log.record(&req, &rep).await;
+        }
+
+        rep
+    }
+
+    async fn connections_mut(&self) -> MutexGuard<'life0, HashSet<StoppableTaskPtr>> {
+        self.rpc_connections.lock().await
+    }
+}
+
+impl DarkfiNode {
+    /// Check the caller may call the requested method, and dispatch the
+    /// request to it.
+    #[rustfmt::skip]
+    async fn dispatch(&self, req: JsonRequest) -> JsonResult {
+        if !self.rpc_auth.is_empty() {
+            let access = req.auth.as_ref().and_then(|token| self.rpc_auth.get(token));
+            let allowed = match access {
+                Some(RpcAccess::Full) => true,
+                Some(RpcAccess::Read) => PUBLIC_METHODS.contains(&req.method.as_str()),
+                None => false,
+            };
+            if !allowed {
+                return JsonError::new(ErrorCode::Unauthorized, None, req.id).into()
+            }
+        }
 
-        match req.method.as_str() {
+        if self.public_mode && !PUBLIC_METHODS.contains(&req.method.as_str()) {
+            return JsonError::new(ErrorCode::MethodNotFound, None, req.id).into()
+        }
+
+        // Serve the cached reply of idempotent methods, if there is one
+        let cache_key = match self.reply_cache {
+            Some(ref reply_cache) => match ReplyCache::key(&req) {
+                Some(key) => {
+                    if let Some(result) = reply_cache.get(&key).await {
+                        return JsonResponse::new(result, req.id).into()
+                    }
+                    Some(key)
+                }
+                None => None,
+            },
+            None => None,
+        };
+
+        let rep = match req.method.as_str() {
             // =====================
             // Miscellaneous methods
             // =====================
             "ping" => self.pong(req.id, req.params).await,
+            "get_version" => self.get_version(req.id, req.params).await,
             "clock" => self.clock(req.id, req.params).await,
+            "get_status" => self.get_status(req.id, req.params).await,
+            "get_audit_log" => self.get_audit_log(req.id, req.params).await,
+            "stop" => self.request_shutdown(req.id, req.params).await,
+            "reload_config" => self.request_reload(req.id, req.params).await,
             "ping_miner" => self.ping_miner(req.id, req.params).await,
             "dnet.switch" => self.dnet_switch(req.id, req.params).await,
             "dnet.subscribe_events" => self.dnet_subscribe_events(req.id, req.params).await,
+            "dnet.get_recent_events" => self.dnet_get_recent_events(req.id, req.params).await,
             // TODO: Make this optional
             "p2p.get_info" => self.p2p_get_info(req.id, req.params).await,
+            "p2p.disconnect_peer" => self.p2p_disconnect_peer(req.id, req.params).await,
+            "p2p.blacklist_peer" => self.p2p_blacklist_peer(req.id, req.params).await,
+            "p2p.connect_peer" => self.p2p_connect_peer(req.id, req.params).await,
+            "p2p.get_refinery_stats" => self.p2p_get_refinery_stats(req.id, req.params).await,
 
             // ==================
             // Blockchain methods
@@ -78,23 +187,30 @@ impl RequestHandler for DarkfiNode {
             // ===================
             "tx.simulate" => self.tx_simulate(req.id, req.params).await,
             "tx.broadcast" => self.tx_broadcast(req.id, req.params).await,
+            "tx.get_status" => self.tx_get_status(req.id, req.params).await,
             "tx.pending" => self.tx_pending(req.id, req.params).await,
             "tx.clean_pending" => self.tx_pending(req.id, req.params).await,
             "tx.calculate_gas" => self.tx_calculate_gas(req.id, req.params).await,
 
+            // ===============
+            // Payment methods
+            // ===============
+            "create_payment_request" => self.create_payment_request(req.id, req.params).await,
+            "parse_payment_request" => self.parse_payment_request(req.id, req.params).await,
+
             // ==============
             // Invalid method
             // ==============
             _ => JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
+        };
+
+        if let (Some(reply_cache), Some(key), JsonResult::Response(r)) = (&self.reply_cache, cache_key, &rep) {
+            reply_cache.insert(key, r.result.clone()).await;
         }
-    }
 
-    async fn connections_mut(&self) -> MutexGuard<'life0, HashSet<StoppableTaskPtr>> {
-        self.rpc_connections.lock().await
+        rep
     }
-}
 
-impl DarkfiNode {
     // RPCAPI:
     // Returns current system clock as `u64` (String) timestamp.
     //
@@ -105,6 +221,127 @@ impl DarkfiNode {
             .into()
     }
 
+    // RPCAPI:
+    // Returns an overview of the node status in a single call: the height
+    // of the last finalized block, the height of the best fork tip, whether
+    // the node is synced, the number of pending transactions and the number
+    // of connected peers.
+    //
+    // --> {"jsonrpc": "2.0", "method": "get_status", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"height": 1234, "best_fork_height": 1236, "synced": true, "pending_txs": 2, "peers": 8}, "id": 1}
+    async fn get_status(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if !params.is_empty() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let Ok((height, _)) = self.validator.blockchain.last() else {
+            return JsonError::new(ErrorCode::InternalError, None, id).into()
+        };
+
+        let best_fork_height = match self.validator.consensus.best_fork_last_header().await {
+            Ok((h, _)) => h,
+            Err(e) => {
+                error!(target: "darkfid::rpc::get_status", "Failed fetching best fork last header: {}", e);
+                return JsonError::new(ErrorCode::InternalError, None, id).into()
+            }
+        };
+
+        let synced = *self.validator.synced.read().await;
+        let pending_txs = self.validator.blockchain.transactions.pending.len();
+        let peers = self.p2p_handler.p2p.hosts().channels().len();
+
+        let status = HashMap::from([
+            ("height".to_string(), JsonValue::Number(height as f64)),
+            ("best_fork_height".to_string(), JsonValue::Number(best_fork_height as f64)),
+            ("synced".to_string(), JsonValue::Boolean(synced)),
+            ("pending_txs".to_string(), JsonValue::Number(pending_txs as f64)),
+            ("peers".to_string(), JsonValue::Number(peers as f64)),
+        ]);
+
+        JsonResponse::new(JsonValue::Object(status), id).into()
+    }
+
+    // RPCAPI:
+    // Requests a graceful shutdown of the node. Right after replying, the
+    // node stops its JSON-RPC server, P2P network and consensus tasks,
+    // saves its hostlist and flushes its database, then exits.
+    //
+    // --> {"jsonrpc": "2.0", "method": "stop", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn request_shutdown(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if !params.is_empty() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        info!(target: "darkfid::rpc::request_shutdown", "Shutdown requested over JSON-RPC");
+        // A full channel means a shutdown is already underway
+        let _ = self.shutdown_tx.try_send(());
+
+        JsonResponse::new(JsonValue::Boolean(true), id).into()
+    }
+
+    // RPCAPI:
+    // Requests the node to re-read its configuration file and apply the
+    // settings that can change while it's running, the same as on SIGHUP:
+    // the P2P connection limits and timeouts, blacklist, data and rate
+    // limits, and ban thresholds. Other settings need a restart.
+    //
+    // --> {"jsonrpc": "2.0", "method": "reload_config", "params": [], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": true, "id": 1}
+    async fn request_reload(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if !params.is_empty() {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        info!(target: "darkfid::rpc::request_reload", "Configuration reload requested over JSON-RPC");
+        // A full channel means a reload is already pending
+        let _ = self.reload_tx.try_send(());
+
+        JsonResponse::new(JsonValue::Boolean(true), id).into()
+    }
+
+    // RPCAPI:
+    // Returns the last entries of the audit log, oldest first. Each entry
+    // holds the call timestamp, method, params, a fingerprint of the
+    // caller's authentication token and the call outcome. Optionally takes
+    // a method to filter entries by, and the number of entries to return,
+    // which defaults to 100.
+    //
+    // --> {"jsonrpc": "2.0", "method": "get_audit_log", "params": ["tx.broadcast", 10], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": [{"timestamp": 1234, "method": "tx.broadcast", "params": ["base64encodedTX"], "caller": "3f1a9c0b2d4e5f60", "result": {"ok": "TxHash"}}], "id": 1}
+    async fn get_audit_log(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() > 2 ||
+            (!params.is_empty() && !params[0].is_string()) ||
+            (params.len() == 2 && !params[1].is_number())
+        {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        }
+
+        let Some(ref audit_log) = self.audit_log else {
+            return server_error(RpcError::AuditLogDisabled, id, None)
+        };
+
+        let method = params.first().map(|m| m.get::<String>().unwrap().as_str());
+        let limit = match params.get(1) {
+            Some(l) => *l.get::<f64>().unwrap() as usize,
+            None => 100,
+        };
+
+        let entries = match audit_log.entries(method, limit).await {
+            Ok(e) => e,
+            Err(e) => {
+                error!(target: "darkfid::rpc::get_audit_log", "Failed reading audit log: {}", e);
+                return JsonError::new(ErrorCode::InternalError, None, id).into()
+            }
+        };
+
+        JsonResponse::new(JsonValue::Array(entries), id).into()
+    }
+
     // RPCAPI:
     // Activate or deactivate dnet in the P2P stack.
     // By sending `true`, dnet will be activated, and by sending `false` dnet
@@ -227,3 +464,15 @@ impl HandlerP2p for DarkfiNode {
         self.p2p_handler.p2p.clone()
     }
 }
+
+// RPCAPI:
+// Returns the daemon name, version, RPC API revision, git commit it was
+// built from (or `null` if unknown) and optional features enabled.
+//
+// --> {"jsonrpc": "2.0", "method": "get_version", "params": [], "id": 1}
+// <-- {"jsonrpc": "2.0", "result": {"name": "darkfid", "version": "0.4.1", "api_revision": 2, "git_hash": "abcdef0", "features": []}, "id": 1}
+impl HandlerVersion for DarkfiNode {
+    fn daemon_version(&self) -> DaemonVersion {
+        darkfi::daemon_version!(RPC_API_REVISION)
+    }
+}
@@ -16,7 +16,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{collections::HashMap, str::FromStr};
+use std::collections::HashMap;
 
 use darkfi_sdk::{crypto::ContractId, tx::TransactionHash};
 use darkfi_serial::{deserialize_async, serialize_async};
@@ -25,9 +25,12 @@ use tinyjson::JsonValue;
 
 use darkfi::{
     blockchain::contract_store::SMART_CONTRACT_ZKAS_DB_NAME,
-    rpc::jsonrpc::{
-        ErrorCode::{InternalError, InvalidParams, ParseError},
-        JsonError, JsonResponse, JsonResult,
+    rpc::{
+        jsonrpc::{
+            ErrorCode::{InternalError, InvalidParams, ParseError},
+            JsonError, JsonResponse, JsonResult,
+        },
+        util::{decode_typed, CONTRACT_ID_PREFIX, TX_HASH_PREFIX},
     },
     util::encoding::base64,
 };
@@ -80,13 +83,13 @@ impl DarkfiNode {
     // Returns a serialized `Transaction` object.
     //
     // **Params:**
-    // * `array[0]`: Hex-encoded transaction hash string
+    // * `array[0]`: Canonical (or bare hex-encoded) transaction hash string
     //
     // **Returns:**
     // * Serialized [`Transaction`](https://darkrenaissance.github.io/darkfi/dev/darkfi/tx/struct.Transaction.html)
     //   object encoded with base64
     //
-    // --> {"jsonrpc": "2.0", "method": "blockchain.get_tx", "params": ["TxHash"], "id": 1}
+    // --> {"jsonrpc": "2.0", "method": "blockchain.get_tx", "params": ["tx_TxHash"], "id": 1}
     // <-- {"jsonrpc": "2.0", "result": "ABCD...", "id": 1}
     pub async fn blockchain_get_tx(&self, id: u16, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
@@ -95,7 +98,7 @@ impl DarkfiNode {
         }
 
         let tx_hash = params[0].get::<String>().unwrap();
-        let tx_hash = match TransactionHash::from_str(tx_hash) {
+        let tx_hash: TransactionHash = match decode_typed(TX_HASH_PREFIX, tx_hash) {
             Ok(v) => v,
             Err(_) => return JsonError::new(ParseError, None, id).into(),
         };
@@ -243,7 +246,7 @@ impl DarkfiNode {
     // them, including their namespace.
     //
     // **Params:**
-    // * `array[0]`: base58-encoded contract ID string
+    // * `array[0]`: Canonical (or bare base58-encoded) contract ID string
     //
     // **Returns:**
     // * `array[n]`: Pairs of: `zkas_namespace` string, serialized
@@ -259,7 +262,7 @@ impl DarkfiNode {
         }
 
         let contract_id = params[0].get::<String>().unwrap();
-        let contract_id = match ContractId::from_str(contract_id) {
+        let contract_id: ContractId = match decode_typed(CONTRACT_ID_PREFIX, contract_id) {
             Ok(v) => v,
             Err(e) => {
                 error!(target: "darkfid::rpc::blockchain_lookup_zkas", "Error decoding string to ContractId: {}", e);
@@ -0,0 +1,141 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use log::error;
+use tinyjson::JsonValue;
+
+use darkfi::{
+    rpc::{
+        jsonrpc::{
+            ErrorCode::{InvalidParams, ParseError},
+            JsonError, JsonResponse, JsonResult,
+        },
+        util::{decode_typed, encode_typed, PUBLIC_KEY_PREFIX, TOKEN_ID_PREFIX},
+    },
+    util::parse::{decode_base10, encode_base10},
+};
+use darkfi_money_contract::client::payment_request::{PaymentRequest, PAYMENT_REQUEST_DECIMALS};
+
+use crate::DarkfiNode;
+
+impl DarkfiNode {
+    // RPCAPI:
+    // Creates a `darkfi:` payment request URI for the given address.
+    // The token, amount and memo are optional, but an amount requires a
+    // token. Empty strings are treated as omitted.
+    //
+    // **Params:**
+    // * `array[0]`: Recipient address string
+    // * `array[1]`: Token ID string (optional)
+    // * `array[2]`: Decimal amount string (optional)
+    // * `array[3]`: Memo string (optional)
+    //
+    // --> {"jsonrpc": "2.0", "method": "create_payment_request", "params": ["pk_Address", "tk_TokenId", "1.5", "Invoice 42"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "darkfi:Address?token=TokenId&amount=1.5&memo=Invoice%2042", "id": 1}
+    pub async fn create_payment_request(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.is_empty() || params.len() > 4 || params.iter().any(|p| !p.is_string()) {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+        let params: Vec<&str> =
+            params.iter().map(|p| p.get::<String>().unwrap().as_str()).collect();
+        let param = |i: usize| params.get(i).copied().filter(|p| !p.is_empty());
+
+        let mut request = match decode_typed(PUBLIC_KEY_PREFIX, params[0]) {
+            Ok(recipient) => PaymentRequest::new(recipient),
+            Err(e) => {
+                error!(target: "darkfid::rpc::create_payment_request", "Failed parsing address: {}", e);
+                return JsonError::new(InvalidParams, None, id).into()
+            }
+        };
+
+        if let Some(token_id) = param(1) {
+            match decode_typed(TOKEN_ID_PREFIX, token_id) {
+                Ok(token_id) => request.token_id = Some(token_id),
+                Err(e) => {
+                    error!(target: "darkfid::rpc::create_payment_request", "Failed parsing token: {}", e);
+                    return JsonError::new(InvalidParams, None, id).into()
+                }
+            }
+        }
+
+        if let Some(amount) = param(2) {
+            match decode_base10(amount, PAYMENT_REQUEST_DECIMALS, true) {
+                Ok(amount) if amount > 0 && request.token_id.is_some() => {
+                    request.amount = Some(amount)
+                }
+                _ => return JsonError::new(InvalidParams, None, id).into(),
+            }
+        }
+
+        request.memo = param(3).map(String::from);
+
+        JsonResponse::new(JsonValue::String(request.to_string()), id).into()
+    }
+
+    // RPCAPI:
+    // Parses a `darkfi:` payment request URI into its fields. Omitted
+    // fields are returned as `null`.
+    //
+    // **Params:**
+    // * `array[0]`: Payment request URI string
+    //
+    // --> {"jsonrpc": "2.0", "method": "parse_payment_request", "params": ["darkfi:Address?token=TokenId&amount=1.5"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"address": "pk_Address", "token": "tk_TokenId", "amount": "1.5", "memo": null}, "id": 1}
+    pub async fn parse_payment_request(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let request: PaymentRequest = match params[0].get::<String>().unwrap().parse() {
+            Ok(r) => r,
+            Err(e) => {
+                error!(target: "darkfid::rpc::parse_payment_request", "Failed parsing payment request: {}", e);
+                return JsonError::new(ParseError, None, id).into()
+            }
+        };
+
+        let token = match request.token_id {
+            Some(token_id) => JsonValue::String(encode_typed(TOKEN_ID_PREFIX, &token_id)),
+            None => JsonValue::Null,
+        };
+        let amount = match request.amount {
+            Some(amount) => JsonValue::String(encode_base10(amount, PAYMENT_REQUEST_DECIMALS)),
+            None => JsonValue::Null,
+        };
+        let memo = match request.memo {
+            Some(memo) => JsonValue::String(memo),
+            None => JsonValue::Null,
+        };
+
+        let request = HashMap::from([
+            (
+                "address".to_string(),
+                JsonValue::String(encode_typed(PUBLIC_KEY_PREFIX, &request.recipient)),
+            ),
+            ("token".to_string(), token),
+            ("amount".to_string(), amount),
+            ("memo".to_string(), memo),
+        ]);
+
+        JsonResponse::new(JsonValue::Object(request), id).into()
+    }
+}
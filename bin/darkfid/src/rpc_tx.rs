@@ -16,14 +16,20 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::collections::HashMap;
+
+use darkfi_sdk::tx::TransactionHash;
 use darkfi_serial::deserialize_async;
 use log::{error, warn};
 use tinyjson::JsonValue;
 
 use darkfi::{
-    rpc::jsonrpc::{
-        ErrorCode::{InternalError, InvalidParams},
-        JsonError, JsonResponse, JsonResult,
+    rpc::{
+        jsonrpc::{
+            ErrorCode::{InternalError, InvalidParams, ParseError},
+            JsonError, JsonResponse, JsonResult,
+        },
+        util::{decode_typed, json_typed, TX_HASH_PREFIX},
     },
     tx::Transaction,
     util::encoding::base64,
@@ -89,7 +95,7 @@ impl DarkfiNode {
     // error if this is the case. Otherwise, a transaction ID will be returned.
     //
     // --> {"jsonrpc": "2.0", "method": "tx.broadcast", "params": ["base64encodedTX"], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": "txID...", "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "tx_TxHash", "id": 1}
     pub async fn tx_broadcast(&self, id: u16, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
         if params.len() != 1 || !params[0].is_string() {
@@ -138,16 +144,68 @@ impl DarkfiNode {
             warn!(target: "darkfid::rpc::tx_broadcast", "No connected channels to broadcast tx");
         }
 
-        let tx_hash = tx.hash().to_string();
-        JsonResponse::new(JsonValue::String(tx_hash), id).into()
+        JsonResponse::new(json_typed(TX_HASH_PREFIX, &tx.hash()), id).into()
+    }
+
+    // RPCAPI:
+    // Queries the node for the status of a given transaction.
+    // Returns `pending` if the transaction is in the node's pending
+    // transactions store, `confirmed` along with the height of the block
+    // containing it if it's in the blockchain, and `unknown` otherwise.
+    //
+    // **Params:**
+    // * `array[0]`: Canonical (or bare hex-encoded) transaction hash string
+    //
+    // --> {"jsonrpc": "2.0", "method": "tx.get_status", "params": ["tx_TxHash"], "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": {"status": "confirmed", "height": 42}, "id": 1}
+    pub async fn tx_get_status(&self, id: u16, params: JsonValue) -> JsonResult {
+        let params = params.get::<Vec<JsonValue>>().unwrap();
+        if params.len() != 1 || !params[0].is_string() {
+            return JsonError::new(InvalidParams, None, id).into()
+        }
+
+        let tx_hash = params[0].get::<String>().unwrap();
+        let tx_hash: TransactionHash = match decode_typed(TX_HASH_PREFIX, tx_hash) {
+            Ok(v) => v,
+            Err(_) => return JsonError::new(ParseError, None, id).into(),
+        };
+
+        let txs = &self.validator.blockchain.transactions;
+        let location = match txs.get_location(&[tx_hash], false) {
+            Ok(v) => v[0],
+            Err(e) => {
+                error!(target: "darkfid::rpc::tx_get_status", "Failed fetching tx location: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        if let Some((height, _)) = location {
+            let status = HashMap::from([
+                ("status".to_string(), JsonValue::String("confirmed".to_string())),
+                ("height".to_string(), JsonValue::Number(height as f64)),
+            ]);
+            return JsonResponse::new(JsonValue::Object(status), id).into()
+        }
+
+        let status = match txs.contains_pending(&tx_hash) {
+            Ok(true) => "pending",
+            Ok(false) => "unknown",
+            Err(e) => {
+                error!(target: "darkfid::rpc::tx_get_status", "Failed checking pending txs: {}", e);
+                return JsonError::new(InternalError, None, id).into()
+            }
+        };
+
+        let status = HashMap::from([("status".to_string(), JsonValue::String(status.to_string()))]);
+        JsonResponse::new(JsonValue::Object(status), id).into()
     }
 
     // RPCAPI:
     // Queries the node pending transactions store to retrieve all transactions.
-    // Returns a vector of hex-encoded transaction hashes.
+    // Returns a vector of canonical transaction hashes.
     //
     // --> {"jsonrpc": "2.0", "method": "tx.pending", "params": [], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": "[TxHash,...]", "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "[tx_TxHash,...]", "id": 1}
     pub async fn tx_pending(&self, id: u16, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
         if !params.is_empty() {
@@ -168,17 +226,17 @@ impl DarkfiNode {
         };
 
         let pending_txs: Vec<JsonValue> =
-            pending_txs.iter().map(|x| JsonValue::String(x.hash().to_string())).collect();
+            pending_txs.iter().map(|x| json_typed(TX_HASH_PREFIX, &x.hash())).collect();
 
         JsonResponse::new(JsonValue::Array(pending_txs), id).into()
     }
 
     // RPCAPI:
     // Queries the node pending transactions store to remove all transactions.
-    // Returns a vector of hex-encoded transaction hashes.
+    // Returns a vector of canonical transaction hashes.
     //
     // --> {"jsonrpc": "2.0", "method": "tx.clean_pending", "params": [], "id": 1}
-    // <-- {"jsonrpc": "2.0", "result": "[TxHash,...]", "id": 1}
+    // <-- {"jsonrpc": "2.0", "result": "[tx_TxHash,...]", "id": 1}
     pub async fn tx_clean_pending(&self, id: u16, params: JsonValue) -> JsonResult {
         let params = params.get::<Vec<JsonValue>>().unwrap();
         if !params.is_empty() {
@@ -204,7 +262,7 @@ impl DarkfiNode {
         };
 
         let pending_txs: Vec<JsonValue> =
-            pending_txs.iter().map(|x| JsonValue::String(x.hash().to_string())).collect();
+            pending_txs.iter().map(|x| json_typed(TX_HASH_PREFIX, &x.hash())).collect();
 
         JsonResponse::new(JsonValue::Array(pending_txs), id).into()
     }
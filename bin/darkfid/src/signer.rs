@@ -0,0 +1,147 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Block producer signing keys, either derived in-process or held by an
+//! external signer process.
+//!
+//! An external signer is configured with `signer_endpoint`, and is spoken
+//! to over JSON-RPC, usually through a `unix://` socket, so the keys can
+//! live in a separately sandboxed or hardware-bridged process. The signer
+//! must serve the following methods:
+//!
+//! * `ping`, with no params, returning `true`.
+//! * `public_key`, with params `[height]`, returning the base58 encoded
+//!   public key used to sign the block at `height`.
+//! * `sign`, with params `[height, message]`, where `message` is base64
+//!   encoded, returning the base64 encoded serialized signature of
+//!   `message` with the key of `height`.
+//!
+//! The signer must use the same key for both calls of a given height,
+//! as the block signature is verified against the public key of its
+//! reward transaction. Using a different key per height keeps proposals
+//! from being linked to each other.
+
+use std::str::FromStr;
+
+use log::debug;
+use rand::rngs::OsRng;
+use tinyjson::JsonValue;
+
+use darkfi::{rpc::jsonrpc::JsonRequest, util::encoding::base64, Error, Result};
+use darkfi_sdk::{
+    crypto::{
+        poseidon_hash,
+        schnorr::{SchnorrSecret, Signature},
+        PublicKey, SecretKey,
+    },
+    pasta::pallas,
+};
+use darkfi_serial::deserialize_async;
+
+use crate::DarkfiNode;
+
+/// Signer of the blocks we produce and of their reward transactions
+pub enum BlockSigner {
+    /// Keys derived in-process from a random master secret key
+    Local(SecretKey),
+    /// Keys held by the external signer configured in the node
+    External,
+}
+
+impl BlockSigner {
+    /// Use the external signer of the node if one is configured, otherwise
+    /// generate a random master secret key to derive all signing keys from.
+    pub fn new(node: &DarkfiNode) -> Self {
+        if node.signer_client.is_some() {
+            return Self::External
+        }
+
+        // This enables us to deanonimize proposals from reward recipient(miner).
+        // TODO: maybe miner wants to keep this master secret so they can
+        //       verify their signature in the future?
+        Self::Local(SecretKey::random(&mut OsRng))
+    }
+
+    /// Grab the public key to sign the block at `height` with. Local keys
+    /// are derived here, so this must be called before signing for
+    /// `height`.
+    pub async fn public_key(&mut self, node: &DarkfiNode, height: u32) -> Result<PublicKey> {
+        match self {
+            Self::Local(secret) => {
+                // We are deriving the next secret key for optimization.
+                // Next secret is the poseidon hash of:
+                //  [prefix, current(previous) secret, signing(block) height].
+                let prefix = pallas::Base::from_raw([4, 0, 0, 0]);
+                let next_secret = poseidon_hash([prefix, secret.inner(), (height as u64).into()]);
+                *secret = SecretKey::from(next_secret);
+                Ok(PublicKey::from_secret(*secret))
+            }
+            Self::External => {
+                let params = JsonValue::Array(vec![JsonValue::Number(height as f64)]);
+                let rep = node.signer_request("public_key", &params).await?;
+                let Some(public_key) = rep.get::<String>() else {
+                    return Err(Error::ParseFailed("Invalid signer public key reply"))
+                };
+                Ok(PublicKey::from_str(public_key)?)
+            }
+        }
+    }
+
+    /// Sign `message` with the key of `height`
+    pub async fn sign(&self, node: &DarkfiNode, height: u32, message: &[u8]) -> Result<Signature> {
+        match self {
+            Self::Local(secret) => Ok(secret.sign(message)),
+            Self::External => {
+                let params = JsonValue::Array(vec![
+                    JsonValue::Number(height as f64),
+                    JsonValue::String(base64::encode(message)),
+                ]);
+                let rep = node.signer_request("sign", &params).await?;
+                let Some(signature) = rep.get::<String>() else {
+                    return Err(Error::ParseFailed("Invalid signer signature reply"))
+                };
+                let Some(bytes) = base64::decode(signature) else {
+                    return Err(Error::ParseFailed("Invalid signer signature encoding"))
+                };
+                Ok(deserialize_async(&bytes).await?)
+            }
+        }
+    }
+}
+
+impl DarkfiNode {
+    /// Auxiliary function to execute a request towards the configured
+    /// external signer. If the request fails, the connection is
+    /// re-established once and the request retried.
+    pub async fn signer_request(&self, method: &str, params: &JsonValue) -> Result<JsonValue> {
+        let Some(ref signer_client) = self.signer_client else {
+            return Err(Error::RpcClientStopped)
+        };
+        debug!(target: "darkfid::signer::signer_request", "Executing request {} with params: {:?}", method, params);
+
+        let mut signer_client = signer_client.lock().await;
+        let req = JsonRequest::new(method, params.clone());
+        if let Ok(rep) = signer_client.client.request(req.clone()).await {
+            return Ok(rep)
+        }
+
+        debug!(target: "darkfid::signer::signer_request", "Request failed, reconnecting to signer");
+        signer_client.reconnect().await?;
+        signer_client.client.request(req).await
+    }
+}
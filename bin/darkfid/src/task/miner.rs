@@ -34,17 +34,19 @@ use darkfi_money_contract::{
     client::pow_reward_v1::PoWRewardCallBuilder, MoneyFunction, MONEY_CONTRACT_ZKAS_MINT_NS_V1,
 };
 use darkfi_sdk::{
-    crypto::{poseidon_hash, FuncId, PublicKey, SecretKey, MONEY_CONTRACT_ID},
+    crypto::{FuncId, PublicKey, MONEY_CONTRACT_ID},
     pasta::pallas,
     ContractCall,
 };
 use darkfi_serial::{serialize_async, Encodable};
 use log::{error, info};
 use num_bigint::BigUint;
-use rand::rngs::OsRng;
 use smol::channel::{Receiver, Sender};
 
-use crate::{proto::ProposalMessage, task::garbage_collect_task, DarkfiNodePtr};
+use crate::{
+    proto::ProposalMessage, signer::BlockSigner, task::garbage_collect_task, DarkfiNode,
+    DarkfiNodePtr,
+};
 
 /// Auxiliary structure representing node miner rewards recipient configuration
 pub struct MinerRewardsRecipientConfig {
@@ -83,12 +85,10 @@ pub async fn miner_task(
     let circuit = ZkCircuit::new(empty_witnesses(&zkbin)?, &zkbin);
     let pk = ProvingKey::build(zkbin.k, &circuit);
 
-    // Generate a random master secret key, to derive all signing keys from.
-    // This enables us to deanonimize proposals from reward recipient(miner).
-    // TODO: maybe miner wants to keep this master secret so they can
-    //       verify their signature in the future?
+    // Setup the block signer, deriving all signing keys from a random
+    // master secret key, unless an external signer is configured.
     info!(target: "darkfid::task::miner_task", "Generating signing key...");
-    let mut secret = SecretKey::random(&mut OsRng);
+    let mut signer = BlockSigner::new(node);
 
     // Grab blocks subscriber
     let block_sub = node.subscribers.get("blocks").unwrap();
@@ -166,7 +166,7 @@ pub async fn miner_task(
             mine(
                 node,
                 &extended_fork,
-                &mut secret,
+                &mut signer,
                 recipient_config,
                 &zkbin,
                 &pk,
@@ -274,7 +274,7 @@ async fn listen_to_network(
 async fn mine(
     node: &DarkfiNodePtr,
     extended_fork: &Fork,
-    secret: &mut SecretKey,
+    signer: &mut BlockSigner,
     recipient_config: &MinerRewardsRecipientConfig,
     zkbin: &ZkBinary,
     pk: &ProvingKey,
@@ -283,7 +283,7 @@ async fn mine(
 ) -> Result<()> {
     smol::future::or(
         wait_stop_signal(stop_signal),
-        mine_next_block(node, extended_fork, secret, recipient_config, zkbin, pk, skip_sync),
+        mine_next_block(node, extended_fork, signer, recipient_config, zkbin, pk, skip_sync),
     )
     .await
 }
@@ -305,7 +305,7 @@ pub async fn wait_stop_signal(stop_signal: &Receiver<()>) -> Result<()> {
 async fn mine_next_block(
     node: &DarkfiNodePtr,
     extended_fork: &Fork,
-    secret: &mut SecretKey,
+    signer: &mut BlockSigner,
     recipient_config: &MinerRewardsRecipientConfig,
     zkbin: &ZkBinary,
     pk: &ProvingKey,
@@ -313,8 +313,9 @@ async fn mine_next_block(
 ) -> Result<()> {
     // Grab next target and block
     let (next_target, mut next_block) = generate_next_block(
+        node,
         extended_fork,
-        secret,
+        signer,
         recipient_config,
         zkbin,
         pk,
@@ -331,7 +332,8 @@ async fn mine_next_block(
     next_block.header.nonce = *response.get::<f64>().unwrap() as u64;
 
     // Sign the mined block
-    next_block.sign(secret);
+    next_block.signature =
+        signer.sign(node, next_block.header.height, next_block.hash().inner()).await?;
 
     // Verify it
     extended_fork.module.verify_current_block(&next_block)?;
@@ -353,9 +355,11 @@ async fn mine_next_block(
 }
 
 /// Auxiliary function to generate next block in an atomic manner.
+#[allow(clippy::too_many_arguments)]
 async fn generate_next_block(
+    node: &DarkfiNode,
     extended_fork: &Fork,
-    secret: &mut SecretKey,
+    signer: &mut BlockSigner,
     recipient_config: &MinerRewardsRecipientConfig,
     zkbin: &ZkBinary,
     pk: &ProvingKey,
@@ -373,15 +377,21 @@ async fn generate_next_block(
         .unproposed_txs(&extended_fork.blockchain, next_block_height, block_target, verify_fees)
         .await?;
 
-    // We are deriving the next secret key for optimization.
-    // Next secret is the poseidon hash of:
-    //  [prefix, current(previous) secret, signing(block) height].
-    let prefix = pallas::Base::from_raw([4, 0, 0, 0]);
-    let next_secret = poseidon_hash([prefix, secret.inner(), (next_block_height as u64).into()]);
-    *secret = SecretKey::from(next_secret);
+    // Grab the signing key of the next block
+    let signature_public = signer.public_key(node, next_block_height).await?;
 
     // Generate reward transaction
-    let tx = generate_transaction(next_block_height, fees, secret, recipient_config, zkbin, pk)?;
+    let tx = generate_transaction(
+        node,
+        next_block_height,
+        fees,
+        signer,
+        signature_public,
+        recipient_config,
+        zkbin,
+        pk,
+    )
+    .await?;
     txs.push(tx);
 
     // Generate the new header
@@ -400,17 +410,20 @@ async fn generate_next_block(
 }
 
 /// Auxiliary function to generate a Money::PoWReward transaction.
-fn generate_transaction(
+#[allow(clippy::too_many_arguments)]
+async fn generate_transaction(
+    node: &DarkfiNode,
     block_height: u32,
     fees: u64,
-    secret: &SecretKey,
+    signer: &BlockSigner,
+    signature_public: PublicKey,
     recipient_config: &MinerRewardsRecipientConfig,
     zkbin: &ZkBinary,
     pk: &ProvingKey,
 ) -> Result<Transaction> {
     // Build the transaction debris
     let debris = PoWRewardCallBuilder {
-        signature_public: PublicKey::from_secret(*secret),
+        signature_public,
         block_height,
         fees,
         recipient: Some(recipient_config.recipient),
@@ -428,8 +441,8 @@ fn generate_transaction(
     let mut tx_builder =
         TransactionBuilder::new(ContractCallLeaf { call, proofs: debris.proofs }, vec![])?;
     let mut tx = tx_builder.build()?;
-    let sigs = tx.create_sigs(&[*secret])?;
-    tx.signatures = vec![sigs];
+    let signature = signer.sign(node, block_height, tx.signing_hash()?.as_bytes()).await?;
+    tx.signatures = vec![vec![signature]];
 
     Ok(tx)
 }
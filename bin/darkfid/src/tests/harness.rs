@@ -244,9 +244,19 @@ pub async fn generate_node(
     subscribers.insert("dnet", JsonSubscriber::new("dnet.subscribe_events"));
 
     let p2p_handler = DarkfidP2pHandler::init(settings, ex).await?;
-    let node =
-        DarkfiNode::new(p2p_handler.clone(), validator.clone(), 50, subscribers.clone(), None)
-            .await;
+    let node = DarkfiNode::new(
+        p2p_handler.clone(),
+        validator.clone(),
+        50,
+        subscribers.clone(),
+        None,
+        None,
+        false,
+        HashMap::new(),
+        None,
+        None,
+    )
+    .await;
 
     p2p_handler.clone().start(ex, &validator, &subscribers).await?;
 
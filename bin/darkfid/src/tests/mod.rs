@@ -265,6 +265,12 @@ fn darkfid_programmatic_control() -> Result<()> {
                     &darkfi::net::Settings::default(),
                     &None,
                     &None,
+                    &None,
+                    false,
+                    &[],
+                    &[],
+                    &None,
+                    false,
                     &ex,
                 )
                 .await
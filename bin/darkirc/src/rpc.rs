@@ -43,8 +43,13 @@ impl RequestHandler for DarkIrc {
             "ping" => self.pong(req.id, req.params).await,
             "dnet.switch" => self.dnet_switch(req.id, req.params).await,
             "dnet.subscribe_events" => self.dnet_subscribe_events(req.id, req.params).await,
+            "dnet.get_recent_events" => self.dnet_get_recent_events(req.id, req.params).await,
             // TODO: Make this optional
             "p2p.get_info" => self.p2p_get_info(req.id, req.params).await,
+            "p2p.disconnect_peer" => self.p2p_disconnect_peer(req.id, req.params).await,
+            "p2p.blacklist_peer" => self.p2p_blacklist_peer(req.id, req.params).await,
+            "p2p.connect_peer" => self.p2p_connect_peer(req.id, req.params).await,
+            "p2p.get_refinery_stats" => self.p2p_get_refinery_stats(req.id, req.params).await,
 
             "deg.switch" => self.deg_switch(req.id, req.params).await,
             "deg.subscribe_events" => self.deg_subscribe_events(req.id, req.params).await,
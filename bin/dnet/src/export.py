@@ -0,0 +1,119 @@
+# This file is part of DarkFi (https://dark.fi)
+#
+# Copyright (C) 2020-2024 Dyne.org foundation
+#
+# This program is free software: you can redistribute it and/or modify
+# it under the terms of the GNU Affero General Public License as
+# published by the Free Software Foundation, either version 3 of the
+# License, or (at your option) any later version.
+#
+# This program is distributed in the hope that it will be useful,
+# but WITHOUT ANY WARRANTY; without even the implied warranty of
+# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+# GNU Affero General Public License for more details.
+#
+# You should have received a copy of the GNU Affero General Public License
+# along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+import json, time
+
+from src.model import NodeStatus
+
+# Bump when the layout of the snapshot changes, so archived snapshots
+# can still be told apart.
+SNAPSHOT_VERSION = 1
+
+# Serialize the current state of the model: each node with its status
+# and the slots of its sessions, the lilith spawns and the summary.
+def snapshot(model):
+    nodes = {}
+    for name, info in model.nodes.items():
+        status = model.status.get(name, {})
+        node = {
+            'status': status.get('state', NodeStatus.OFFLINE),
+            'error': status.get('error'),
+            'last_seen': status.get('last_seen'),
+            'outbound': [],
+            'inbound': [],
+            'manual': [],
+            'seed': [],
+        }
+
+        if info:
+            events = info.get('event', {})
+            for slot, (addr, id) in info.get('outbound', {}).items():
+                if id != 0:
+                    state = 'connected'
+                else:
+                    addr = None
+                    state = events.get((name, slot), ['empty'])[0]
+                node['outbound'].append({'slot': int(slot), 'channel_id': id,
+                                         'addr': addr, 'state': state})
+
+            for session in ['inbound', 'manual', 'seed']:
+                for id, addr in info.get(session, {}).items():
+                    # Disconnected inbounds are left empty until removed
+                    if not addr:
+                        continue
+                    node[session].append({'channel_id': int(id), 'addr': addr,
+                                          'state': 'connected'})
+
+        nodes[name] = node
+
+    return {
+        'version': SNAPSHOT_VERSION,
+        'timestamp': int(time.time()),
+        'nodes': nodes,
+        'liliths': {name: info.get('spawns', {}) if info else {}
+                    for name, info in model.liliths.items()},
+        'summary': model.summary,
+    }
+
+def to_json(model):
+    return json.dumps(snapshot(model), indent=2)
+
+def quote(s):
+    return '"' + str(s).replace('\\', '\\\\').replace('"', '\\"') + '"'
+
+# Graphviz DOT graph of the nodes and their connected peers. Edges point
+# in the direction the connection was opened. Offline nodes are dashed,
+# empty outbound slots are left out.
+def to_dot(model):
+    snap = snapshot(model)
+    lines = ['digraph dnet {', '    rankdir=LR;']
+
+    for name, node in snap['nodes'].items():
+        style = 'solid' if node['status'] == NodeStatus.ONLINE else 'dashed'
+        label = quote(f'{name} ({node["status"]})')
+        lines.append(f'    {quote(name)} [shape=box, style={style}, label={label}];')
+
+        for session in ['outbound', 'manual', 'seed']:
+            for slot in node[session]:
+                if slot['state'] != 'connected':
+                    continue
+                lines.append(f'    {quote(name)} -> {quote(slot["addr"])} '
+                             f'[label={session}];')
+
+        for slot in node['inbound']:
+            lines.append(f'    {quote(slot["addr"])} -> {quote(name)} '
+                         f'[label=inbound];')
+
+    for name, spawns in snap['liliths'].items():
+        lines.append(f'    {quote(name)} [shape=box, style=rounded];')
+        for spawn in spawns:
+            lines.append(f'    {quote(name)} -> {quote(name + "/" + spawn)} '
+                         f'[label=spawn];')
+
+    lines.append('}')
+    return '\n'.join(lines) + '\n'
+
+# Write a snapshot of the model to the given path, as DOT if it ends in
+# `.dot` or `.gv`, and as JSON otherwise.
+def write(model, path):
+    if path.endswith(('.dot', '.gv')):
+        data = to_dot(model)
+    else:
+        data = to_json(model)
+
+    with open(path, 'w') as f:
+        f.write(data)
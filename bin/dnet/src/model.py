@@ -19,18 +19,61 @@ import logging, time
 import datetime as dt
 from collections import defaultdict as dd
 
+# Highest p2p.get_info schema version we understand. Must match
+# NODE_INFO_VERSION in src/rpc/introspection.rs.
+NODE_INFO_VERSION = 1
+
+# Peers with at least this many protocol errors get flagged in the view.
+PROTOCOL_ERRORS_WARN = 3
+
+class NodeStatus:
+    CONNECTING = 'Connecting'
+    ONLINE = 'Online'
+    OFFLINE = 'Offline'
 
 class Model:
 
     def __init__(self):
         self.nodes = {}
         self.liliths = {}
+        self.status = {}
+        self.summary = {}
+        # Formatted tail of recent events, by node name
+        self.recent = {}
+        self.update_summary()
+
+    # Track the connection state of a monitored node. The last error is
+    # kept while reconnecting, and only cleared once the node is back.
+    def set_status(self, name, state, error=None, retry_at=None):
+        status = self.status.setdefault(name, {
+            'state': state,
+            'error': None,
+            'last_seen': None,
+            'retry_at': None,
+        })
+        status['state'] = state
+        status['retry_at'] = retry_at
+        if error is not None:
+            status['error'] = error
+        if state == NodeStatus.ONLINE:
+            status['error'] = None
+            status['last_seen'] = time.time()
+
+    # Record that we just heard from a node.
+    def seen(self, name):
+        if name in self.status:
+            self.status[name]['last_seen'] = time.time()
 
     def add_node(self, node):
         channel_lookup = {}
         name = list(node.keys())[0]
         values = list(node.values())[0]
         info = values['result']
+        version = info.get('version', 0)
+        if version > NODE_INFO_VERSION:
+            logging.warning(f'{name}: unsupported get_info version {version}, '
+                            f'expected at most {NODE_INFO_VERSION}')
+            return
         channels = info['channels']
         
         self.nodes[name] = {}
@@ -40,11 +83,16 @@ class Model:
         self.nodes[name]['event'] = {}
         self.nodes[name]['seed'] = {}
         self.nodes[name]['msgs'] = dd(list)
+        self.nodes[name]['flagged'] = set()
 
         for channel in channels:
             id = channel['id']
             channel_lookup[id] = channel
 
+            errors = channel.get('errors', {})
+            if sum(errors.values()) >= PROTOCOL_ERRORS_WARN:
+                self.nodes[name]['flagged'].add(channel['url'])
+
         for channel in channels:
             if channel['session'] != 'inbound':
                 continue
@@ -52,6 +100,11 @@ class Model:
             url = channel_lookup[id]['url']
             self.nodes[name]['inbound'][f'{id}'] = url
 
+        # Outbound slots reserved to transports, as (transport, slots,
+        # connected)
+        self.nodes[name]['quotas'] = [(q['transport'], q['slots'], q['connected'])
+                                      for q in info.get('transport_quotas', [])]
+
         for i, id in enumerate(info['outbound_slots']):
             if id == 0:
                 outbounds = self.nodes[name]['outbound'][f'{i}'] = ['none', 0]
@@ -73,6 +126,8 @@ class Model:
             id = channel['id']
             url = channel['url']
             self.nodes[name]['manual'][f'{id}'] = url
+
+        self.update_summary()
     
     def add_offline(self, node, is_lilith: bool):
         name = list(node.keys())[0]
@@ -81,6 +136,7 @@ class Model:
             self.liliths[name] = values
         else:
             self.nodes[name] = values
+        self.update_summary()
 
     def add_event(self, event):
         name = list(event.keys())[0]
@@ -146,6 +202,12 @@ class Model:
                 event = self.nodes[name]['event']
                 event[(f'{name}', f'{slot}')] = [f'disconnected: {err}', 0]
                 logging.debug(f'{current_time}  slot {slot}: disconnected err={err}')
+            case 'outbound_slot_stalled':
+                slot = info['slot']
+                addr = info['addr']
+                event = self.nodes[name]['event']
+                event[(f'{name}', f'{slot}')] = [f'stalled: addr={addr}', 0]
+                logging.debug(f'{current_time}  slot {slot}: stalled      addr={addr}')
             case 'outbound_peer_discovery':
                 attempt = info['attempt']
                 state = info['state']
@@ -154,6 +216,67 @@ class Model:
                 event[key] = f'peer discovery: {state} (attempt {attempt})'
                 logging.debug(f'{current_time}  peer_discovery: {state} (attempt {attempt})')
 
+        self.update_summary()
+
+    # Keep the recent events fetched from a node with
+    # dnet.get_recent_events, formatted for display.
+    def set_recent(self, name, events):
+        lines = []
+        for (stamp, event) in events:
+            t = dt.datetime.fromtimestamp(stamp).strftime('%H:%M:%S')
+            fields = []
+            for key, value in event.get('info', {}).items():
+                if key == 'time':
+                    continue
+                if key == 'chan':
+                    value = value.get('addr')
+                fields.append(f'{key}={value}')
+            lines.append(f"{t}: {event.get('event')}: {' '.join(fields)}")
+        self.recent[name] = lines
+
+    # Aggregate counts across all monitored nodes, shown in the top bar.
+    def update_summary(self):
+        online = 0
+        offline = 0
+        inbound = 0
+        outbound = 0
+        empty_slots = 0
+        peers = set()
+
+        for name, info in self.nodes.items():
+            if not info:
+                offline += 1
+                continue
+            online += 1
+
+            for id, addr in info.get('outbound', {}).items():
+                url, channel_id = addr
+                if channel_id == 0:
+                    empty_slots += 1
+                    continue
+                outbound += 1
+                peers.add(url)
+
+            for id, addr in info.get('inbound', {}).items():
+                # Disconnected inbounds are left empty until removed
+                if not addr:
+                    continue
+                inbound += 1
+                peers.add(addr)
+
+            for session in ['manual', 'seed']:
+                for id, addr in info.get(session, {}).items():
+                    peers.add(addr)
+
+        self.summary = {
+            'online': online,
+            'offline': offline,
+            'inbound': inbound,
+            'outbound': outbound,
+            'peers': len(peers),
+            'empty_slots': empty_slots,
+        }
+
 
     def add_lilith(self, lilith):
         key = list(lilith.keys())[0]
@@ -15,18 +15,62 @@
 # You should have received a copy of the GNU Affero General Public License
 # along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+import os
+import ssl
 import json
 import time
 import random
 import logging
 import asyncio
+from urllib.parse import urlparse
+
+
+def node_endpoint(node):
+    """Return the RPC endpoint of a configured node as a URL string.
+    Nodes are given either by `url` (tcp://, tcp+tls:// or unix://) or
+    by the legacy `host` and `port` keys."""
+    if 'url' in node:
+        return node['url']
+    return f"tcp://{node['host']}:{node['port']}"
+
+
+def tls_context(node):
+    """Build the TLS context used to connect to a tcp+tls:// endpoint.
+    darkfi RPC servers use ephemeral self-signed certificates, so the
+    server is only verified when a `tls_ca` file is configured. A client
+    certificate is presented when `tls_cert` and `tls_key` are given."""
+    path = lambda key: os.path.expanduser(node[key])
+    ctx = ssl.SSLContext(ssl.PROTOCOL_TLS_CLIENT)
+    if 'tls_ca' in node:
+        ctx.load_verify_locations(cafile=path('tls_ca'))
+    else:
+        ctx.check_hostname = False
+        ctx.verify_mode = ssl.CERT_NONE
+    if 'tls_cert' in node:
+        keyfile = path('tls_key') if 'tls_key' in node else None
+        ctx.load_cert_chain(path('tls_cert'), keyfile)
+    return ctx
 
 
 class JsonRpc:
 
-    async def start(self, host, port):
-        #logging.info(f"trying to connect to {host}:{port}")
-        reader, writer = await asyncio.open_connection(host, port)
+    async def start(self, node):
+        endpoint = node_endpoint(node)
+        url = urlparse(endpoint)
+        #logging.info(f"trying to connect to {endpoint}")
+        match url.scheme:
+            case 'tcp':
+                reader, writer = await asyncio.open_connection(
+                    url.hostname, url.port)
+            case 'tcp+tls' | 'tls':
+                reader, writer = await asyncio.open_connection(
+                    url.hostname, url.port, ssl=tls_context(node),
+                    server_hostname=url.hostname)
+            case 'unix':
+                reader, writer = await asyncio.open_unix_connection(
+                    os.path.expanduser(url.path))
+            case _:
+                raise ValueError(f'Unsupported RPC endpoint {endpoint}')
         self.reader = reader
         self.writer = writer
 
@@ -73,3 +117,15 @@ class JsonRpc:
 
     async def dnet_subscribe_events(self):
         return await self._subscribe("dnet.subscribe_events", [])
+
+    async def disconnect_peer(self, url):
+        return await self._make_request("p2p.disconnect_peer", [url])
+
+    async def blacklist_peer(self, url):
+        return await self._make_request("p2p.blacklist_peer", [url])
+
+    async def connect_peer(self, url):
+        return await self._make_request("p2p.connect_peer", [url])
+
+    async def get_recent_events(self, limit):
+        return await self._make_request("dnet.get_recent_events", [limit])
@@ -15,13 +15,14 @@
 # You should have received a copy of the GNU Affero General Public License
 # along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+import time
 import urwid
 import logging
 import asyncio
 import datetime as dt
 from enum import Enum
 
-from src.model import Model
+from src.model import Model, NodeStatus
 
 class DnetWidget(urwid.WidgetWrap):
     def __init__(self, name, kind):
@@ -48,27 +49,34 @@ class Node(DnetWidget):
         self.state = state
         super().__init__(name, kind)
 
-    def set_txt(self):
+    def set_txt(self, status=None):
         if self.state == NodeState.OFF:
-            txt = urwid.Text(f"{self.name} (offline)")
+            label = status['state'].lower() if status else "offline"
+            txt = urwid.AttrMap(urwid.Text(f"{self.name} ({label})"), 'dim')
             super().update(txt)
         else:
             txt = urwid.Text(f"{self.name}")
             super().update(txt)
 
 class Session(DnetWidget):
-    def set_txt(self):
-        txt = urwid.Text(f"  {self.kind}")
+    # Outbound sessions list the usage of their transport quotas.
+    def set_txt(self, quotas=None):
+        usage = ", ".join(f"{t} {c}/{n}" for (t, n, c) in quotas or [])
+        label = f"  {self.kind} ({usage})" if usage else f"  {self.kind}"
+        txt = urwid.Text(label)
         super().update(txt)
 
 class Slot(DnetWidget):
-    def set_txt(self, i, addr):
+    # Flagged peers have elevated protocol error counts and get a badge.
+    def set_txt(self, i, addr, flagged=False):
         self.i = i
+        self.flagged = flagged
+        badge = ('warn', ' [!]') if flagged else ''
         match self.kind:
             case "outbound-slot":
                 self.addr = addr[0]
                 self.id = addr[1]
-                txt = urwid.Text(f"    {self.i}: {self.addr}")
+                txt = urwid.Text([f"    {self.i}: {self.addr}", badge])
                 super().update(txt)
             case "spawn-slot":
                 self.id = addr
@@ -76,14 +84,20 @@ class Slot(DnetWidget):
                 super().update(txt)
             case "manual-slot" | "seed-slot" | "inbound-slot":
                 self.addr = addr
-                txt = urwid.Text(f"    {self.addr}")
+                txt = urwid.Text([f"    {self.addr}", badge])
                 super().update(txt)
     
 
+# Shown in the bottom bar until an action reports its outcome
+KEY_HINTS = (" d: disconnect peer | b: blacklist peer | c: connect peer"
+             " | l: event tail | e/g: export JSON/DOT | q: quit")
+
 class View():
     palette = [
               ('body','light gray','default', 'standout'),
               ('line','dark cyan','default','standout'),
+              ('dim','dark gray','default'),
+              ('warn','light red','default'),
               ]
 
     def __init__(self, model):
@@ -96,7 +110,15 @@ class View():
         self.list = urwid.ListBox(self.listwalker)
         leftbox = urwid.LineBox(self.list)
         columns = urwid.Columns([leftbox, rightbox], focus_column=0)
-        self.ui = urwid.Frame(urwid.AttrWrap(columns, 'body'))
+        self.summary = urwid.Text("")
+        header = urwid.AttrWrap(self.summary, 'line')
+        self.status = urwid.Text(KEY_HINTS)
+        self.ui = urwid.Frame(urwid.AttrWrap(columns, 'body'), header=header,
+                              footer=self.status)
+        # (node name, input widget) while asking for a peer to connect to
+        self.prompt = None
+        # Name of the node whose recent events replace the right box
+        self.tail = None
         self.sessions = set()
         self.nodes = set()
         self.refresh_needed = False
@@ -104,7 +126,7 @@ class View():
     def add_node(self, name, info, state):
         logging.debug(f"Adding node: {name} {info} {state}")
         node = Node(name, "node", state)
-        node.set_txt()
+        node.set_txt(self.model.status.get(name))
         self.nodes.add(name)
         self.listwalker.append(node)
         self.add_sessions(name, info)
@@ -113,14 +135,17 @@ class View():
         for session in ['outbound', 'inbound', 'manual', 'seed']:
             if session in info and info[session]:
                 session_widget = Session(name, session)
-                session_widget.set_txt()
+                quotas = info.get('quotas') if session == 'outbound' else None
+                session_widget.set_txt(quotas)
                 self.listwalker.append(session_widget)
                 self.add_slots(name, session, info[session])
 
     def add_slots(self, name, session, slots):
+        flagged = self.model.nodes[name].get('flagged', set())
         for i, addr in slots.items():
             slot = Slot(name, f"{session}-slot")
-            slot.set_txt(i, addr)
+            url = addr[0] if session == 'outbound' else addr
+            slot.set_txt(i, addr, url in flagged)
             self.listwalker.append(slot)
             match session:
                 case "outbound":
@@ -132,7 +157,7 @@ class View():
     def add_lilith(self, name, info, state):
         logging.debug(f"Adding lilith: {name} {info} {state}")
         node = Node(name, "lilith-node", state)
-        node.set_txt()
+        node.set_txt(self.model.status.get(name))
         self.nodes.add(name)
         self.listwalker.append(node)
         if state == NodeState.OFF:
@@ -152,6 +177,8 @@ class View():
                 # Online node has gone offline
                 elif widget.state == NodeState.ON and not info:
                         self.refresh_needed = True
+                else:
+                    widget.set_txt(self.model.status.get(name))
 
     def update_node(self, name, info):
         for index, widget in enumerate(self.listwalker):
@@ -163,7 +190,7 @@ class View():
                 elif widget.state == NodeState.ON and not info:
                         self.refresh_needed = True
                 else:
-                    widget.set_txt()
+                    widget.set_txt(self.model.status.get(name))
                     return index + 1
         return None
 
@@ -184,22 +211,71 @@ class View():
                 key = (f"{widget.name}", f"{widget.i}")
                 if key in self.model.nodes[widget.name]['event']:
                     info = self.model.nodes[widget.name]['event'].get(key)
-                    widget.set_txt(i, info)
+                    widget.set_txt(i, info, widget.flagged)
                     self.listwalker[index] = widget
                     break
     
+    #-----------------------------------------------------------------
+    # Peer management actions
+    # Bottom bar only
+    #-----------------------------------------------------------------
+    def selected(self):
+        return self.list.get_focus()[0]
+
+    def set_status(self, txt):
+        self.status.set_text(f" {txt}")
+
+    def open_prompt(self, name, caption):
+        self.prompt = (name, urwid.Edit(f" {caption}"))
+        self.ui.footer = self.prompt[1]
+        self.ui.focus_position = 'footer'
+
+    # Returns the node name and the text entered
+    def close_prompt(self):
+        name, edit = self.prompt
+        self.prompt = None
+        self.ui.footer = self.status
+        self.ui.focus_position = 'body'
+        return name, edit.edit_text.strip()
+
     #-----------------------------------------------------------------
     # Render dnet.subscribe_events() RPC call
     # Right hand menu only
     #-----------------------------------------------------------------
     def update_right_box(self):
         self.pile.contents.clear()
+        if self.tail is not None:
+            self.pile.contents.append((urwid.Text(
+                f" Recent events of {self.tail}:"), self.pile.options()))
+            for line in self.model.recent.get(self.tail, []):
+                self.pile.contents.append((urwid.Text(f" {line}"),
+                                           self.pile.options()))
+            return
+
         focus_w = self.list.get_focus()
         if focus_w[0] is None:
             return
         kind = focus_w[0].kind
 
         match kind:
+            case "node" | "lilith-node":
+                status = self.model.status.get(focus_w[0].name)
+                if status is None:
+                    return
+                lines = [f"Status: {status['state']}"]
+                if status['last_seen']:
+                    seen = dt.datetime.fromtimestamp(status['last_seen'])
+                    lines.append(f"Last seen: {seen.strftime('%H:%M:%S')}")
+                else:
+                    lines.append("Last seen: never")
+                if status['error']:
+                    lines.append(f"Last error: {status['error']}")
+                if status['state'] == NodeStatus.OFFLINE and status['retry_at']:
+                    retry_in = max(0, int(status['retry_at'] - time.time()))
+                    lines.append(f"Retrying in {retry_in}s")
+                for line in lines:
+                    self.pile.contents.append((urwid.Text(f" {line}"),
+                                               self.pile.options()))
             case "outbound":
                 key = (focus_w[0].name, "outbound")
                 info = self.model.nodes.get(focus_w[0].name)
@@ -270,6 +346,19 @@ class View():
                             f"  {host}"),
                             self.pile.options()))
 
+    #-----------------------------------------------------------------
+    # Render aggregate counts across all nodes
+    # Top bar only
+    #-----------------------------------------------------------------
+    def update_summary(self):
+        s = self.model.summary
+        self.summary.set_text(
+            f" nodes: {s['online']} online, {s['offline']} offline"
+            f" | inbound: {s['inbound']}"
+            f" | outbound: {s['outbound']}"
+            f" | unique peers: {s['peers']}"
+            f" | empty slots: {s['empty_slots']}")
+
     def update_node_state(self, info):
         if info:
             logging.debug(f"update_node_state(): Returning {NodeState.ON}")
@@ -351,5 +440,6 @@ class View():
                         self.update_lilith(name, info)
 
 
+            self.update_summary()
             self.update_right_box()
             evloop.call_soon(loop.draw_screen)
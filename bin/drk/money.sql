@@ -7,12 +7,12 @@ CREATE TABLE IF NOT EXISTS BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o_money_in
 );
 
 -- The Merkle tree containing coins
-CREATE TABLE IF NOT EXISTS BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o_money_tree (
+CREATE TABLE IF NOT EXISTS archive.BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o_money_tree (
 	tree BLOB NOT NULL
 );
 
 -- The Sparse Merkle tree containing coins nullifiers
-CREATE TABLE IF NOT EXISTS BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o_money_smt (
+CREATE TABLE IF NOT EXISTS archive.BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o_money_smt (
 	smt_key BLOB PRIMARY KEY NOT NULL,
 	smt_value BLOB NOT NULL
 );
@@ -25,6 +25,16 @@ CREATE TABLE IF NOT EXISTS BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o_money_ke
 	secret BLOB NOT NULL
 );
 
+-- Keypairs removed from our wallet, kept until purged so they can be restored
+CREATE TABLE IF NOT EXISTS BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o_money_keys_archived (
+	archive_id INTEGER PRIMARY KEY NOT NULL,
+	key_id INTEGER NOT NULL,
+	is_default INTEGER NOT NULL,
+	public BLOB NOT NULL,
+	secret BLOB NOT NULL,
+	archived_at INTEGER NOT NULL
+);
+
 -- The coins we have the information to and can spend
 CREATE TABLE IF NOT EXISTS BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o_money_coins (
 	coin BLOB PRIMARY KEY NOT NULL,
@@ -39,7 +49,8 @@ CREATE TABLE IF NOT EXISTS BZHKGQ26bzmBithTQYTJtjo2QdCqpkR9tjSBopT4yf4o_money_co
 	secret BLOB NOT NULL,
 	leaf_position BLOB NOT NULL,
 	memo BLOB,
-	spent_tx_hash TEXT DEFAULT '-'
+	spent_tx_hash TEXT DEFAULT '-',
+	creation_height INTEGER NOT NULL DEFAULT 0
 );
 
 -- Arbitrary tokens
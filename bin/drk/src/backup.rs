@@ -0,0 +1,124 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use darkfi::{
+    util::{path::expand_path, time::Timestamp},
+    Error, Result,
+};
+
+use crate::Drk;
+
+/// Prefix of wallet backup file names
+const BACKUP_PREFIX: &str = "wallet-";
+/// Extension of wallet backup file names
+const BACKUP_EXTENSION: &str = ".db";
+
+/// Configuration of scheduled wallet backups
+#[derive(Clone, Debug)]
+pub struct BackupSchedule {
+    /// Directory backups are written to
+    pub dir: PathBuf,
+    /// Password the backups are encrypted with
+    pub password: String,
+    /// Seconds between backups
+    pub interval: u64,
+    /// Number of backups to keep, 0 to keep all
+    pub retention: usize,
+    /// UNIX timestamp of the last successful backup
+    pub last_success: Option<u64>,
+}
+
+impl BackupSchedule {
+    /// Create a new backup schedule, picking up the last successful
+    /// backup time from the backups already present in `dir`.
+    pub fn new(dir: &str, password: String, interval: u64, retention: usize) -> Result<Self> {
+        let dir = expand_path(dir)?;
+        let last_success = list_backups(&dir)?.last().map(|(ts, _)| *ts);
+        Ok(Self { dir, password, interval, retention, last_success })
+    }
+
+    /// Returns true if a backup should be made now
+    pub fn is_due(&self) -> bool {
+        match self.last_success {
+            Some(ts) => Timestamp::current_time().inner() >= ts + self.interval,
+            None => true,
+        }
+    }
+}
+
+/// List the wallet backups present in `dir`, along with their creation
+/// timestamps, ordered from oldest to newest.
+pub fn list_backups(dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    if !dir.exists() {
+        return Ok(vec![])
+    }
+
+    let mut backups = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+        let Some(ts) = name
+            .strip_prefix(BACKUP_PREFIX)
+            .and_then(|n| n.strip_suffix(BACKUP_EXTENSION))
+            .and_then(|n| n.parse::<u64>().ok())
+        else {
+            continue
+        };
+        backups.push((ts, path));
+    }
+    backups.sort();
+
+    Ok(backups)
+}
+
+impl Drk {
+    /// Write an encrypted copy of the wallet database into the schedule
+    /// directory and remove the oldest backups exceeding its retention.
+    /// Returns the path of the new backup.
+    pub fn backup_wallet(&self, schedule: &mut BackupSchedule) -> Result<PathBuf> {
+        fs::create_dir_all(&schedule.dir)?;
+
+        let ts = Timestamp::current_time().inner();
+        let path = schedule.dir.join(format!("{BACKUP_PREFIX}{ts}{BACKUP_EXTENSION}"));
+        if path.exists() {
+            return Err(Error::Custom(format!("Wallet backup {path:?} already exists")))
+        }
+
+        if let Err(e) = self.wallet.backup(&path, &schedule.password) {
+            // Don't leave a partial backup behind
+            let _ = fs::remove_file(&path);
+            return Err(Error::DatabaseError(format!("[backup_wallet] Wallet backup failed: {e:?}")))
+        }
+        schedule.last_success = Some(ts);
+
+        if schedule.retention > 0 {
+            let backups = list_backups(&schedule.dir)?;
+            let excess = backups.len().saturating_sub(schedule.retention);
+            for (_, old) in backups.iter().take(excess) {
+                fs::remove_file(old)?;
+            }
+        }
+
+        Ok(path)
+    }
+}
@@ -31,11 +31,33 @@ use darkfi::{
     util::{encoding::base64, parse::decode_base10},
     Error, Result,
 };
-use darkfi_money_contract::model::TokenId;
+use darkfi_money_contract::model::{Coin, TokenId};
+use darkfi_sdk::pasta::{group::ff::PrimeField, pallas};
 use darkfi_serial::deserialize_async;
 
 use crate::{money::BALANCE_BASE10_DECIMALS, Drk};
 
+/// Auxiliary function to parse a base58 encoded coin.
+pub fn parse_coin(s: &str) -> Result<Coin> {
+    let bytes: [u8; 32] = match bs58::decode(s).into_vec()?.try_into() {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Invalid coin: {e:?}");
+            exit(2);
+        }
+    };
+
+    let elem: pallas::Base = match pallas::Base::from_repr(bytes).into() {
+        Some(v) => v,
+        None => {
+            eprintln!("Invalid coin");
+            exit(2);
+        }
+    };
+
+    Ok(Coin::from(elem))
+}
+
 /// Auxiliary function to parse a base64 encoded transaction from stdin.
 pub async fn parse_tx_from_stdin() -> Result<Transaction> {
     let mut buf = String::new();
@@ -181,17 +203,36 @@ pub fn generate_completions(shell: &str) -> Result<()> {
 
     let unspend = SubCommand::with_name("unspend").about("Unspend a coin").arg(coin);
 
+    // Freeze
+    let coin = Arg::with_name("coin").help("base58-encoded coin to freeze");
+
+    let freeze = SubCommand::with_name("freeze")
+        .about("Freeze a coin, so it never gets spent by payments")
+        .arg(coin);
+
+    // Unfreeze
+    let coin = Arg::with_name("coin").help("base58-encoded coin to unfreeze");
+
+    let unfreeze = SubCommand::with_name("unfreeze")
+        .about("Unfreeze a coin, so it can be spent by payments again")
+        .arg(coin);
+
     // Transfer
     let amount = Arg::with_name("amount").help("Amount to send");
 
     let token = Arg::with_name("token").help("Token ID to send");
 
-    let recipient = Arg::with_name("recipient").help("Recipient address");
+    let recipient = Arg::with_name("recipient").help("Recipient address or contact name");
 
     let spend_hook = Arg::with_name("spend-hook").help("Optional contract spend hook to use");
 
     let user_data = Arg::with_name("user-data").help("Optional user data to use");
 
+    let memo = Arg::with_name("memo")
+        .long("memo")
+        .takes_value(true)
+        .help("Memo to attach to the payment, readable only by the recipient");
+
     let half_split = Arg::with_name("half-split")
         .long("half-split")
         .help("Split the output coin into two equal halves");
@@ -203,9 +244,18 @@ pub fn generate_completions(shell: &str) -> Result<()> {
             recipient.clone(),
             spend_hook.clone(),
             user_data.clone(),
+            memo,
             half_split,
         ]);
 
+    // TransferMany
+    let outputs =
+        Arg::with_name("outputs").multiple(true).help("Outputs to pay, as recipient:token:amount");
+
+    let transfer_many = SubCommand::with_name("transfer-many")
+        .about("Create a single transaction paying multiple recipients")
+        .arg(outputs);
+
     // Otc
     let value_pair = Arg::with_name("value-pair")
         .short("v")
@@ -430,6 +480,85 @@ pub fn generate_completions(shell: &str) -> Result<()> {
         .about("Manage Token aliases")
         .subcommands(vec![add, show, remove]);
 
+    // Schedule
+    let recipient =
+        Arg::with_name("recipient").help("Recipient address or contact name of the payments");
+
+    let token = Arg::with_name("token").help("Token to pay with");
+
+    let amount = Arg::with_name("amount").help("Amount of each payment");
+
+    let interval = Arg::with_name("interval").help("Seconds between two payments");
+
+    let delay = Arg::with_name("delay")
+        .short("d")
+        .long("delay")
+        .takes_value(true)
+        .help("Seconds until the first payment");
+
+    let duration = Arg::with_name("duration")
+        .short("D")
+        .long("duration")
+        .takes_value(true)
+        .help("Seconds after which payments stop, 0 for never");
+
+    let add = SubCommand::with_name("add")
+        .about("Schedule a recurring payment")
+        .args(&vec![recipient, token, amount, interval, delay, duration]);
+
+    let list = SubCommand::with_name("list").about("List all the scheduled payments in the wallet");
+
+    let schedule_id = Arg::with_name("schedule-id").help("Scheduled payment ID to cancel");
+
+    let cancel =
+        SubCommand::with_name("cancel").about("Cancel a scheduled payment").arg(schedule_id);
+
+    let run = SubCommand::with_name("run").about("Pay all the scheduled payments that are due");
+
+    let schedule = SubCommand::with_name("schedule")
+        .about("Manage scheduled recurring payments")
+        .subcommands(vec![add, list, cancel, run]);
+
+    // Contact
+    let name = Arg::with_name("name").help("Contact name");
+
+    let address = Arg::with_name("address").help("Contact address");
+
+    let add = SubCommand::with_name("add")
+        .about("Add a named address to the address book")
+        .args(&vec![name.clone(), address]);
+
+    let list = SubCommand::with_name("list").about("List all the contacts in the address book");
+
+    let remove =
+        SubCommand::with_name("remove").about("Remove a contact from the address book").arg(name);
+
+    let contact = SubCommand::with_name("contact")
+        .about("Manage the address book")
+        .subcommands(vec![add, list, remove]);
+
+    // Archive
+    let key_id = Arg::with_name("key-id").help("Key ID to archive");
+
+    let key = SubCommand::with_name("key").about("Archive a wallet key").arg(key_id);
+
+    let kind = Arg::with_name("kind").help("Kind of records (key, contact or invoice)");
+
+    let list = SubCommand::with_name("list").about("List archived records").arg(kind.clone());
+
+    let archive_id = Arg::with_name("archive-id").help("Archive ID of the record to restore");
+
+    let restore = SubCommand::with_name("restore")
+        .about("Restore an archived record")
+        .args(&vec![kind.clone(), archive_id]);
+
+    let purge =
+        SubCommand::with_name("purge").about("Permanently delete archived records").arg(kind);
+
+    let archive = SubCommand::with_name("archive")
+        .about("Manage archived keys, contacts and invoices")
+        .subcommands(vec![key, list, restore, purge]);
+
     // Token
     let secret_key = Arg::with_name("secret-key").help("Mint authority secret key");
 
@@ -486,7 +615,10 @@ pub fn generate_completions(shell: &str) -> Result<()> {
         wallet,
         spend,
         unspend,
+        freeze,
+        unfreeze,
         transfer,
+        transfer_many,
         otc,
         attach_fee,
         inspect,
@@ -496,6 +628,9 @@ pub fn generate_completions(shell: &str) -> Result<()> {
         scan,
         explorer,
         alias,
+        schedule,
+        contact,
+        archive,
         token,
     ];
 
@@ -504,6 +639,14 @@ pub fn generate_completions(shell: &str) -> Result<()> {
         .long("fun")
         .help("Flag indicating whether you want some fun in your life");
 
+    let json = Arg::with_name("json")
+        .long("json")
+        .help("Print balances and transactions history as JSON instead of tables");
+
+    let perf_stats = Arg::with_name("perf-stats")
+        .long("perf-stats")
+        .help("Print proving keys and proofs timings once done");
+
     let log = Arg::with_name("log")
         .short("l")
         .long("log")
@@ -517,7 +660,7 @@ pub fn generate_completions(shell: &str) -> Result<()> {
 
     let mut app = App::new("drk")
         .about(cli_desc!())
-        .args(&vec![config, network, fun, log, verbose])
+        .args(&vec![config, network, fun, json, perf_stats, log, verbose])
         .subcommands(command);
 
     let shell = match Shell::from_str(shell) {
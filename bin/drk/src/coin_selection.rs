@@ -0,0 +1,109 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{fmt, str::FromStr};
+
+use rand::{rngs::OsRng, seq::SliceRandom};
+
+use darkfi::{Error, Result};
+use darkfi_money_contract::client::OwnCoin;
+
+/// Strategy used to pick the coins spent in a transfer. The transfer
+/// builder spends coins in the given order until the amount is covered,
+/// and sends the remainder back to us as change.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CoinSelection {
+    /// Spend the smallest coins first, consolidating dust
+    SmallestFirst,
+    /// Spend the largest coins first, using as few inputs as possible
+    #[default]
+    LargestFirst,
+    /// Spend coins in random order, so the inputs don't reveal a
+    /// predictable pattern across transfers
+    Random,
+}
+
+impl CoinSelection {
+    /// Order `coins` so that the ones to spend first come first.
+    pub fn order(&self, coins: &mut [OwnCoin]) {
+        self.order_by(coins, |coin| coin.note.value)
+    }
+
+    /// Order `items` by the value returned for each, according to the strategy.
+    fn order_by<T>(&self, items: &mut [T], value: impl Fn(&T) -> u64) {
+        match self {
+            Self::SmallestFirst => items.sort_by_key(value),
+            Self::LargestFirst => items.sort_by_key(|item| std::cmp::Reverse(value(item))),
+            Self::Random => items.shuffle(&mut OsRng),
+        }
+    }
+}
+
+impl FromStr for CoinSelection {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "smallest-first" => Ok(Self::SmallestFirst),
+            "largest-first" => Ok(Self::LargestFirst),
+            "random" => Ok(Self::Random),
+            _ => Err(Error::Custom(format!(
+                "Unknown coin selection strategy: {s}, expected one of \
+                 smallest-first, largest-first, random"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for CoinSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::SmallestFirst => "smallest-first",
+            Self::LargestFirst => "largest-first",
+            Self::Random => "random",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coin_selection() {
+        let mut values = vec![5, 1, 10, 3];
+
+        CoinSelection::SmallestFirst.order_by(&mut values, |v| *v);
+        assert_eq!(values, [1, 3, 5, 10]);
+
+        CoinSelection::LargestFirst.order_by(&mut values, |v| *v);
+        assert_eq!(values, [10, 5, 3, 1]);
+
+        CoinSelection::Random.order_by(&mut values, |v| *v);
+        values.sort();
+        assert_eq!(values, [1, 3, 5, 10]);
+
+        for strategy in
+            [CoinSelection::SmallestFirst, CoinSelection::LargestFirst, CoinSelection::Random]
+        {
+            assert_eq!(CoinSelection::from_str(&strategy.to_string()).unwrap(), strategy);
+        }
+        assert!(CoinSelection::from_str("biggest").is_err());
+    }
+}
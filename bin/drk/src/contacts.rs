@@ -0,0 +1,110 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::str::FromStr;
+
+use rusqlite::types::Value;
+
+use darkfi::{Error, Result};
+use darkfi_sdk::crypto::PublicKey;
+use darkfi_serial::{deserialize_async, serialize_async};
+
+use crate::{convert_named_params, Drk};
+
+// Wallet SQL table constant names. These have to represent the `wallet.sql`
+// SQL schema.
+pub const WALLET_ADDRESS_BOOK_TABLE: &str = "address_book";
+pub const WALLET_ADDRESS_BOOK_COL_NAME: &str = "name";
+pub const WALLET_ADDRESS_BOOK_COL_ADDRESS: &str = "address";
+
+impl Drk {
+    /// Store `address` in the address book under the contact `name`.
+    /// Names that parse as an address, or contain a `:`, are rejected,
+    /// so they can't be mistaken for one in payment commands.
+    pub async fn add_contact(&self, name: String, address: PublicKey) -> Result<()> {
+        if name.is_empty() || name.contains(':') || PublicKey::from_str(&name).is_ok() {
+            return Err(Error::Custom(format!("Invalid contact name: {name}")))
+        }
+
+        let address_bytes = serialize_async(&address).await;
+        if let Err(e) = self.wallet.insert(
+            WALLET_ADDRESS_BOOK_TABLE,
+            convert_named_params! {
+                (WALLET_ADDRESS_BOOK_COL_NAME, name),
+                (WALLET_ADDRESS_BOOK_COL_ADDRESS, address_bytes),
+            },
+            false,
+        ) {
+            return Err(Error::DatabaseError(format!(
+                "[add_contact] Contact insertion failed: {e:?}"
+            )))
+        }
+
+        Ok(())
+    }
+
+    /// Fetch all contacts from the address book, along with their address.
+    pub async fn get_contacts(&self) -> Result<Vec<(String, PublicKey)>> {
+        let rows = match self.wallet.query_multiple(WALLET_ADDRESS_BOOK_TABLE, &[], &[]) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[get_contacts] Contacts retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        let mut contacts = Vec::with_capacity(rows.len());
+        for row in rows {
+            let Value::Text(ref name) = row[0] else {
+                return Err(Error::ParseFailed("[get_contacts] Name parsing failed"))
+            };
+            let Value::Blob(ref address_bytes) = row[1] else {
+                return Err(Error::ParseFailed("[get_contacts] Address bytes parsing failed"))
+            };
+            contacts.push((name.clone(), deserialize_async(address_bytes).await?));
+        }
+
+        Ok(contacts)
+    }
+
+    /// Retrieve recipient address by provided string.
+    /// Input string represents either an address or a contact name.
+    pub async fn get_recipient(&self, input: &str) -> Result<PublicKey> {
+        if let Ok(address) = PublicKey::from_str(input) {
+            return Ok(address)
+        }
+
+        let row = match self.wallet.query_single(
+            WALLET_ADDRESS_BOOK_TABLE,
+            &[WALLET_ADDRESS_BOOK_COL_ADDRESS],
+            convert_named_params! {(WALLET_ADDRESS_BOOK_COL_NAME, input)},
+        ) {
+            Ok(r) => r,
+            Err(_) => {
+                return Err(Error::Custom(format!("Not an address or a known contact: {input}")))
+            }
+        };
+
+        let Value::Blob(ref address_bytes) = row[0] else {
+            return Err(Error::ParseFailed("[get_recipient] Address bytes parsing failed"))
+        };
+
+        Ok(deserialize_async(address_bytes).await?)
+    }
+}
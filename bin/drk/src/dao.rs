@@ -1431,7 +1431,7 @@ impl Drk {
 
         let tree = self.get_money_tree().await?;
         let (fee_call, fee_proofs, fee_secrets) =
-            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None).await?;
+            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None, None).await?;
 
         // Append the fee call to the transaction
         tx_builder.append(ContractCallLeaf { call: fee_call, proofs: fee_proofs }, vec![])?;
@@ -1754,7 +1754,7 @@ impl Drk {
 
         let tree = self.get_money_tree().await?;
         let (fee_call, fee_proofs, fee_secrets) =
-            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None).await?;
+            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None, None).await?;
 
         // Append the fee call to the transaction
         tx_builder.append(ContractCallLeaf { call: fee_call, proofs: fee_proofs }, vec![])?;
@@ -1963,7 +1963,7 @@ impl Drk {
 
         let tree = self.get_money_tree().await?;
         let (fee_call, fee_proofs, fee_secrets) =
-            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None).await?;
+            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None, None).await?;
 
         // Append the fee call to the transaction
         tx_builder.append(ContractCallLeaf { call: fee_call, proofs: fee_proofs }, vec![])?;
@@ -2185,6 +2185,7 @@ impl Drk {
             clear_inputs: vec![],
             inputs,
             outputs,
+            output_memos: vec![],
             mint_zkbin: mint_zkbin.clone(),
             mint_pk: mint_pk.clone(),
             burn_zkbin: burn_zkbin.clone(),
@@ -2276,7 +2277,7 @@ impl Drk {
         tx.signatures = vec![auth_transfer_sigs, transfer_sigs, exec_sigs];
 
         let (fee_call, fee_proofs, fee_secrets) =
-            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None).await?;
+            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None, None).await?;
 
         // Append the fee call to the transaction
         tx_builder.append(ContractCallLeaf { call: fee_call, proofs: fee_proofs }, vec![])?;
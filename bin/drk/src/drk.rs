@@ -16,13 +16,16 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{fs, process::exit, sync::Arc};
+use std::{fs, path::PathBuf, process::exit, sync::Arc};
 
 use url::Url;
 
 use darkfi::{rpc::client::RpcClient, util::path::expand_path, Result};
 
-use crate::walletdb::{WalletDb, WalletPtr};
+use crate::{
+    proving::PROVING_KEYS_DIR,
+    walletdb::{WalletDb, WalletPtr},
+};
 
 /// CLI-util structure
 pub struct Drk {
@@ -32,6 +35,10 @@ pub struct Drk {
     pub rpc_client: Option<RpcClient>,
     /// Flag indicating if fun stuff are enabled
     pub fun: bool,
+    /// Confirmations received coins need before they can be spent
+    pub min_confirmations: u32,
+    /// Directory caching the built circuits proving keys
+    pub proving_keys_dir: Option<PathBuf>,
 }
 
 impl Drk {
@@ -40,6 +47,7 @@ impl Drk {
         wallet_pass: String,
         endpoint: Option<Url>,
         ex: Arc<smol::Executor<'static>>,
+        min_confirmations: u32,
         fun: bool,
     ) -> Result<Self> {
         // Script kiddies protection
@@ -50,6 +58,7 @@ impl Drk {
 
         // Initialize wallet
         let wallet_path = expand_path(&wallet_path)?;
+        let proving_keys_dir = wallet_path.parent().map(|dir| dir.join(PROVING_KEYS_DIR));
         if !wallet_path.exists() {
             if let Some(parent) = wallet_path.parent() {
                 fs::create_dir_all(parent)?;
@@ -70,7 +79,7 @@ impl Drk {
             None
         };
 
-        Ok(Self { wallet, rpc_client, fun })
+        Ok(Self { wallet, rpc_client, fun, min_confirmations, proving_keys_dir })
     }
 
     /// Initialize wallet with tables for drk
@@ -29,6 +29,7 @@ pub enum WalletDbError {
 
     // Configuration related errors
     PragmaUpdateError = -32110,
+    InvalidPassword = -32111,
 
     // Query execution related errors
     QueryPreparationFailed = -32120,
@@ -39,6 +40,9 @@ pub enum WalletDbError {
 
     // Generic error
     GenericError = -32130,
+
+    // Backup related errors
+    BackupFailed = -32140,
 }
 
 impl std::fmt::Display for WalletDbError {
@@ -47,6 +51,7 @@ impl std::fmt::Display for WalletDbError {
             WalletDbError::ConnectionFailed => write!(f, "WalletDbError::ConnectionFailed"),
             WalletDbError::FailedToAquireLock => write!(f, "WalletDbError::FailedToAquireLock"),
             WalletDbError::PragmaUpdateError => write!(f, "WalletDbError::PragmaUpdateError"),
+            WalletDbError::InvalidPassword => write!(f, "WalletDbError::InvalidPassword"),
             WalletDbError::QueryPreparationFailed => {
                 write!(f, "WalletDbError::QueryPreparationFailed")
             }
@@ -59,6 +64,7 @@ impl std::fmt::Display for WalletDbError {
             }
             WalletDbError::RowNotFound => write!(f, "WalletDbError::RowNotFound"),
             WalletDbError::GenericError => write!(f, "WalletDbError::GenericError"),
+            WalletDbError::BackupFailed => write!(f, "WalletDbError::BackupFailed"),
         }
     }
 }
@@ -0,0 +1,86 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use rusqlite::types::Value;
+
+use darkfi::{Error, Result};
+use darkfi_money_contract::model::Coin;
+use darkfi_serial::{deserialize_async, serialize_async};
+
+use crate::{convert_named_params, Drk};
+
+// Wallet SQL table constant names. These have to represent the `wallet.sql`
+// SQL schema.
+pub const WALLET_FROZEN_COINS_TABLE: &str = "frozen_coins";
+pub const WALLET_FROZEN_COINS_COL_COIN: &str = "coin";
+
+impl Drk {
+    /// Freeze an unspent coin of the wallet, so coin selection never
+    /// spends it until it gets unfrozen.
+    pub async fn freeze_coin(&self, coin: &Coin) -> Result<()> {
+        if !self.get_coins(false).await?.iter().any(|(c, _, _)| c.coin == *coin) {
+            return Err(Error::Custom("Coin is not an unspent coin of the wallet".to_string()))
+        }
+
+        if let Err(e) = self.wallet.insert(
+            WALLET_FROZEN_COINS_TABLE,
+            convert_named_params! {(WALLET_FROZEN_COINS_COL_COIN, serialize_async(coin).await)},
+            true,
+        ) {
+            return Err(Error::DatabaseError(format!("[freeze_coin] Coin freezing failed: {e:?}")))
+        }
+
+        Ok(())
+    }
+
+    /// Unfreeze a coin, making it available to coin selection again.
+    pub async fn unfreeze_coin(&self, coin: &Coin) -> Result<()> {
+        if let Err(e) = self.wallet.delete(
+            WALLET_FROZEN_COINS_TABLE,
+            convert_named_params! {(WALLET_FROZEN_COINS_COL_COIN, serialize_async(coin).await)},
+        ) {
+            return Err(Error::DatabaseError(format!(
+                "[unfreeze_coin] Coin unfreezing failed: {e:?}"
+            )))
+        }
+
+        Ok(())
+    }
+
+    /// Fetch all the frozen coins from the wallet.
+    pub async fn get_frozen_coins(&self) -> Result<Vec<Coin>> {
+        let rows = match self.wallet.query_multiple(WALLET_FROZEN_COINS_TABLE, &[], &[]) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[get_frozen_coins] Frozen coins retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        let mut coins = Vec::with_capacity(rows.len());
+        for row in rows {
+            let Value::Blob(ref coin_bytes) = row[0] else {
+                return Err(Error::ParseFailed("[get_frozen_coins] Coin bytes parsing failed"))
+            };
+            coins.push(deserialize_async(coin_bytes).await?);
+        }
+
+        Ok(coins)
+    }
+}
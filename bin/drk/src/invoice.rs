@@ -0,0 +1,318 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fmt;
+
+use rand::rngs::OsRng;
+use rusqlite::types::Value;
+
+use darkfi::{
+    util::{
+        parse::{decode_base10, encode_base10},
+        time::Timestamp,
+    },
+    zk::halo2::Field,
+    Error, Result,
+};
+use darkfi_money_contract::model::TokenId;
+use darkfi_sdk::{
+    crypto::{pasta_prelude::PrimeField, FuncId, PublicKey},
+    pasta::pallas,
+};
+use darkfi_serial::{deserialize_async, serialize_async};
+
+use crate::{convert_named_params, money::BALANCE_BASE10_DECIMALS, Drk};
+
+// Wallet SQL table constant names. These have to represent the `wallet.sql`
+// SQL schema.
+pub const WALLET_INVOICES_TABLE: &str = "invoices";
+pub const WALLET_INVOICES_COL_INVOICE_ID: &str = "invoice_id";
+pub const WALLET_INVOICES_COL_RECIPIENT: &str = "recipient";
+pub const WALLET_INVOICES_COL_TOKEN_ID: &str = "token_id";
+pub const WALLET_INVOICES_COL_AMOUNT: &str = "amount";
+pub const WALLET_INVOICES_COL_MEMO: &str = "memo";
+pub const WALLET_INVOICES_COL_EXPIRY: &str = "expiry";
+pub const WALLET_INVOICES_COL_STATUS: &str = "status";
+
+/// Payment status of an invoice
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvoiceStatus {
+    /// No payment received yet
+    Open,
+    /// Some, but not all, of the amount has been received
+    Partial,
+    /// The full amount has been received
+    Paid,
+    /// The invoice expired before being fully paid
+    Expired,
+}
+
+impl fmt::Display for InvoiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Open => "Open",
+            Self::Partial => "Partial",
+            Self::Paid => "Paid",
+            Self::Expired => "Expired",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Structure representing an invoice record in the wallet.
+///
+/// Payers settle an invoice by sending the requested token to the
+/// recipient address, with the invoice ID as the coin user data.
+#[derive(Clone, Debug)]
+pub struct Invoice {
+    /// Invoice ID, the base58-encoded user data payments must carry
+    pub id: String,
+    /// Address payments should be sent to
+    pub recipient: PublicKey,
+    /// Requested token
+    pub token_id: TokenId,
+    /// Requested amount
+    pub amount: u64,
+    /// Free-form invoice description
+    pub memo: String,
+    /// UNIX timestamp after which the invoice expires, 0 for never
+    pub expiry: u64,
+    /// Amount received so far
+    pub received: u64,
+    /// Current invoice status
+    pub status: InvoiceStatus,
+}
+
+impl Drk {
+    /// Create a new invoice for the provided token and amount, payable to
+    /// our default address. `expiry` is the number of seconds the invoice
+    /// is valid for, or 0 if it never expires. Returns the created invoice.
+    pub async fn create_invoice(
+        &self,
+        token_id: TokenId,
+        amount: &str,
+        memo: String,
+        expiry: u64,
+    ) -> Result<Invoice> {
+        let amount = decode_base10(amount, BALANCE_BASE10_DECIMALS, false)?;
+        if amount == 0 {
+            return Err(Error::Custom("Invoice amount must be greater than zero".to_string()))
+        }
+
+        let recipient = self.default_address().await?;
+        let user_data = pallas::Base::random(&mut OsRng);
+        let id = bs58::encode(user_data.to_repr()).into_string();
+        let expiry = if expiry == 0 { 0 } else { Timestamp::current_time().inner() + expiry };
+
+        let recipient_bytes = serialize_async(&recipient).await;
+        let token_id_bytes = serialize_async(&token_id).await;
+        let amount_bytes = serialize_async(&amount).await;
+        if let Err(e) = self.wallet.insert(
+            WALLET_INVOICES_TABLE,
+            convert_named_params! {
+                (WALLET_INVOICES_COL_INVOICE_ID, id),
+                (WALLET_INVOICES_COL_RECIPIENT, recipient_bytes),
+                (WALLET_INVOICES_COL_TOKEN_ID, token_id_bytes),
+                (WALLET_INVOICES_COL_AMOUNT, amount_bytes),
+                (WALLET_INVOICES_COL_MEMO, memo),
+                (WALLET_INVOICES_COL_EXPIRY, expiry),
+                (WALLET_INVOICES_COL_STATUS, InvoiceStatus::Open.to_string()),
+            },
+            false,
+        ) {
+            return Err(Error::DatabaseError(format!(
+                "[create_invoice] Invoice insertion failed: {e:?}"
+            )))
+        }
+
+        Ok(Invoice {
+            id,
+            recipient,
+            token_id,
+            amount,
+            memo,
+            expiry,
+            received: 0,
+            status: InvoiceStatus::Open,
+        })
+    }
+
+    /// Fetch an invoice record from the wallet, along with its current status.
+    pub async fn get_invoice(&self, id: &str) -> Result<Invoice> {
+        let row = match self.wallet.query_single(
+            WALLET_INVOICES_TABLE,
+            &[],
+            convert_named_params! {(WALLET_INVOICES_COL_INVOICE_ID, id)},
+        ) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[get_invoice] Invoice retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        self.parse_invoice_record(&row).await
+    }
+
+    /// Fetch all invoice records from the wallet, along with their current status.
+    pub async fn get_invoices(&self) -> Result<Vec<Invoice>> {
+        let rows = match self.wallet.query_multiple(WALLET_INVOICES_TABLE, &[], &[]) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[get_invoices] Invoices retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        let mut invoices = Vec::with_capacity(rows.len());
+        for row in rows {
+            invoices.push(self.parse_invoice_record(&row).await?);
+        }
+
+        Ok(invoices)
+    }
+
+    /// Recompute the status of all invoices against our received coins,
+    /// persist any changes and notify about them. Called after each
+    /// scanned block.
+    pub async fn update_invoices(&self) -> Result<()> {
+        let rows = match self.wallet.query_multiple(
+            WALLET_INVOICES_TABLE,
+            &[WALLET_INVOICES_COL_INVOICE_ID, WALLET_INVOICES_COL_STATUS],
+            &[],
+        ) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[update_invoices] Invoices retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        for row in rows {
+            let Value::Text(ref id) = row[0] else {
+                return Err(Error::ParseFailed("[update_invoices] Invoice ID parsing failed"))
+            };
+            let Value::Text(ref previous) = row[1] else {
+                return Err(Error::ParseFailed("[update_invoices] Status parsing failed"))
+            };
+
+            let invoice = self.get_invoice(id).await?;
+            if invoice.status.to_string() == *previous {
+                continue
+            }
+
+            if let Err(e) = self.wallet.update(
+                WALLET_INVOICES_TABLE,
+                convert_named_params! {(WALLET_INVOICES_COL_STATUS, invoice.status.to_string())},
+                convert_named_params! {(WALLET_INVOICES_COL_INVOICE_ID, id)},
+            ) {
+                return Err(Error::DatabaseError(format!(
+                    "[update_invoices] Invoice status update failed: {e:?}"
+                )))
+            }
+
+            println!(
+                "[update_invoices] Invoice {id} is now {}: received {} of {}",
+                invoice.status,
+                encode_base10(invoice.received, BALANCE_BASE10_DECIMALS),
+                encode_base10(invoice.amount, BALANCE_BASE10_DECIMALS),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Auxiliary function to parse a `WALLET_INVOICES_TABLE` record, and
+    /// compute its current status from the coins we have received.
+    async fn parse_invoice_record(&self, row: &[Value]) -> Result<Invoice> {
+        let Value::Text(ref id) = row[0] else {
+            return Err(Error::ParseFailed("[parse_invoice_record] Invoice ID parsing failed"))
+        };
+
+        let Value::Blob(ref recipient_bytes) = row[1] else {
+            return Err(Error::ParseFailed("[parse_invoice_record] Recipient bytes parsing failed"))
+        };
+        let recipient: PublicKey = deserialize_async(recipient_bytes).await?;
+
+        let Value::Blob(ref token_id_bytes) = row[2] else {
+            return Err(Error::ParseFailed("[parse_invoice_record] Token ID bytes parsing failed"))
+        };
+        let token_id: TokenId = deserialize_async(token_id_bytes).await?;
+
+        let Value::Blob(ref amount_bytes) = row[3] else {
+            return Err(Error::ParseFailed("[parse_invoice_record] Amount bytes parsing failed"))
+        };
+        let amount: u64 = deserialize_async(amount_bytes).await?;
+
+        let Value::Text(ref memo) = row[4] else {
+            return Err(Error::ParseFailed("[parse_invoice_record] Memo parsing failed"))
+        };
+
+        let Value::Integer(expiry) = row[5] else {
+            return Err(Error::ParseFailed("[parse_invoice_record] Expiry parsing failed"))
+        };
+        let Ok(expiry) = u64::try_from(expiry) else {
+            return Err(Error::ParseFailed("[parse_invoice_record] Expiry parsing failed"))
+        };
+
+        // Sum up every coin we received for this invoice, including
+        // the ones we have already spent.
+        let Some(user_data) = bs58::decode(id)
+            .into_vec()
+            .ok()
+            .and_then(|b| <[u8; 32]>::try_from(b).ok())
+            .and_then(|b| Option::<pallas::Base>::from(pallas::Base::from_repr(b)))
+        else {
+            return Err(Error::ParseFailed("[parse_invoice_record] Invoice ID decoding failed"))
+        };
+
+        let mut received = 0;
+        for (coin, _, _) in self.get_coins(true).await? {
+            if coin.note.token_id == token_id &&
+                coin.note.spend_hook == FuncId::none() &&
+                coin.note.user_data == user_data
+            {
+                received += coin.note.value;
+            }
+        }
+
+        let status = if received >= amount {
+            InvoiceStatus::Paid
+        } else if expiry != 0 && Timestamp::current_time().inner() > expiry {
+            InvoiceStatus::Expired
+        } else if received > 0 {
+            InvoiceStatus::Partial
+        } else {
+            InvoiceStatus::Open
+        };
+
+        Ok(Invoice {
+            id: id.clone(),
+            recipient,
+            token_id,
+            amount,
+            memo: memo.clone(),
+            expiry,
+            received,
+            status,
+        })
+    }
+}
@@ -29,6 +29,9 @@ pub mod rpc;
 /// Payment methods
 pub mod transfer;
 
+/// Coin selection strategies for payments
+pub mod coin_selection;
+
 /// Swap methods
 pub mod swap;
 
@@ -50,5 +53,35 @@ pub mod deploy;
 /// Wallet functionality related to transactions history
 pub mod txs_history;
 
+/// Wallet functionality related to invoices
+pub mod invoice;
+
+/// Wallet backups
+pub mod backup;
+
+/// Wallet merging
+pub mod merge;
+
+/// Watch-only wallet exports
+pub mod watch_only;
+
+/// Wallet privacy audits
+pub mod privacy_audit;
+
+/// Scheduled recurring payments
+pub mod schedule;
+
+/// Wallet address book
+pub mod contacts;
+
+/// Coins frozen out of coin selection
+pub mod freeze;
+
+/// Soft deletion of wallet keys, contacts and invoices
+pub mod soft_delete;
+
+/// Proving keys caching and off-executor proof generation
+pub mod proving;
+
 /// Wallet database operations handler
 pub mod walletdb;
@@ -17,6 +17,7 @@
  */
 
 use std::{
+    collections::HashMap,
     io::{stdin, Read},
     process::exit,
     str::FromStr,
@@ -31,6 +32,7 @@ use url::Url;
 
 use darkfi::{
     async_daemonize, cli_desc,
+    rpc::util::JsonValue,
     util::{
         encoding::base64,
         parse::{decode_base10, encode_base10},
@@ -40,7 +42,10 @@ use darkfi::{
     Error, Result,
 };
 use darkfi_dao_contract::{blockwindow, model::DaoProposalBulla, DaoFunction};
-use darkfi_money_contract::model::{Coin, CoinAttributes, TokenId};
+use darkfi_money_contract::{
+    client::{transfer_v1::MAX_MEMO_SIZE, OwnCoin},
+    model::{CoinAttributes, TokenId},
+};
 use darkfi_sdk::{
     crypto::{
         note::AeadEncryptedNote, BaseBlind, FuncId, FuncRef, PublicKey, SecretKey, DAO_CONTRACT_ID,
@@ -51,11 +56,17 @@ use darkfi_sdk::{
 use darkfi_serial::{deserialize_async, serialize_async};
 
 use drk::{
+    backup::BackupSchedule,
     cli_util::{
-        generate_completions, kaching, parse_token_pair, parse_tx_from_stdin, parse_value_pair,
+        generate_completions, kaching, parse_coin, parse_token_pair, parse_tx_from_stdin,
+        parse_value_pair,
     },
+    coin_selection::CoinSelection,
     dao::{DaoParams, ProposalRecord},
     money::BALANCE_BASE10_DECIMALS,
+    proving::PERF_STATS,
+    schedule::PaymentBroadcast,
+    soft_delete::ArchivedKind,
     swap::PartialSwapData,
     Drk,
 };
@@ -85,6 +96,14 @@ struct Args {
     /// Flag indicating whether you want some fun in your life
     fun: bool,
 
+    #[structopt(long)]
+    /// Print balances and transactions history as JSON instead of tables
+    json: bool,
+
+    #[structopt(long)]
+    /// Print proving keys and proofs timings once done
+    perf_stats: bool,
+
     #[structopt(short, long)]
     /// Set log file to ouput into
     log: Option<String>,
@@ -144,6 +163,31 @@ enum Subcmd {
         /// Import secret keys from stdin into the wallet, separated by newlines
         import_secrets: bool,
 
+        #[structopt(long)]
+        /// Print all the unspent coins from the wallet, in importable form
+        export_coins: bool,
+
+        #[structopt(long)]
+        /// Import coins from stdin into the wallet, separated by newlines
+        import_coins: bool,
+
+        #[structopt(long)]
+        /// Re-encrypt the wallet with a new password read from stdin
+        change_password: bool,
+
+        #[structopt(long)]
+        /// Write an encrypted backup of the wallet to the configured backup path
+        backup: bool,
+
+        #[structopt(long)]
+        /// Merge the wallet at the given path into ours, reading its password from stdin
+        merge: Option<String>,
+
+        #[structopt(long)]
+        /// Export a watch-only copy of the wallet, without any secret keys,
+        /// to the given path, reading its password from stdin
+        export_watch_only: Option<String>,
+
         #[structopt(long)]
         /// Print the Merkle tree in the wallet
         tree: bool,
@@ -151,6 +195,10 @@ enum Subcmd {
         #[structopt(long)]
         /// Print all the coins in the wallet
         coins: bool,
+
+        #[structopt(long)]
+        /// Audit the wallet history for behavior hurting unlinkability
+        privacy_audit: bool,
     },
 
     /// Read a transaction from stdin and mark its input coins as spent
@@ -162,6 +210,18 @@ enum Subcmd {
         coin: String,
     },
 
+    /// Freeze a coin, so it never gets spent by payments
+    Freeze {
+        /// base58-encoded coin to freeze
+        coin: String,
+    },
+
+    /// Unfreeze a coin, so it can be spent by payments again
+    Unfreeze {
+        /// base58-encoded coin to unfreeze
+        coin: String,
+    },
+
     /// Create a payment transaction
     Transfer {
         /// Amount to send
@@ -170,7 +230,7 @@ enum Subcmd {
         /// Token ID to send
         token: String,
 
-        /// Recipient address
+        /// Recipient address or contact name
         recipient: String,
 
         /// Optional contract spend hook to use
@@ -179,9 +239,48 @@ enum Subcmd {
         /// Optional user data to use
         user_data: Option<String>,
 
+        #[structopt(long)]
+        /// Memo to attach to the payment, readable only by the recipient
+        memo: Option<String>,
+
         #[structopt(long)]
         /// Split the output coin into two equal halves
         half_split: bool,
+
+        #[structopt(long)]
+        /// Coin selection strategy: smallest-first, largest-first or random
+        strategy: Option<String>,
+
+        #[structopt(long)]
+        /// Fee to pay instead of the estimated one, in native tokens
+        fee: Option<String>,
+
+        #[structopt(long)]
+        /// Only print the estimated fee, without creating the transaction
+        estimate_fee: bool,
+
+        #[structopt(long)]
+        /// Build and simulate the transaction, printing its hash, inputs
+        /// and fee instead of the transaction itself
+        simulate: bool,
+    },
+
+    /// Create a single transaction paying multiple recipients
+    TransferMany {
+        /// Outputs to pay, as recipient:token:amount
+        outputs: Vec<String>,
+
+        #[structopt(long)]
+        /// Coin selection strategy: smallest-first, largest-first or random
+        strategy: Option<String>,
+
+        #[structopt(long)]
+        /// Fee to pay instead of the estimated one, in native tokens
+        fee: Option<String>,
+
+        #[structopt(long)]
+        /// Only print the estimated fee, without creating the transaction
+        estimate_fee: bool,
     },
 
     /// OTC atomic swap
@@ -234,6 +333,34 @@ enum Subcmd {
         command: AliasSubcmd,
     },
 
+    /// Manage payment invoices
+    Invoice {
+        #[structopt(subcommand)]
+        /// Sub command to execute
+        command: InvoiceSubcmd,
+    },
+
+    /// Manage scheduled recurring payments
+    Schedule {
+        #[structopt(subcommand)]
+        /// Sub command to execute
+        command: ScheduleSubcmd,
+    },
+
+    /// Manage the address book
+    Contact {
+        #[structopt(subcommand)]
+        /// Sub command to execute
+        command: ContactSubcmd,
+    },
+
+    /// Manage archived keys, contacts and invoices
+    Archive {
+        #[structopt(subcommand)]
+        /// Sub command to execute
+        command: ArchiveSubcmd,
+    },
+
     /// Token functionalities
     Token {
         #[structopt(subcommand)]
@@ -327,7 +454,7 @@ enum DaoSubcmd {
         /// Token ID to send
         token: String,
 
-        /// Recipient address
+        /// Recipient address or contact name
         recipient: String,
 
         /// Optional contract spend hook to use
@@ -398,6 +525,12 @@ enum ExplorerSubcmd {
         encode: bool,
     },
 
+    /// Fetch the status of a transaction from darkfid
+    TxStatus {
+        /// Transaction hash
+        tx_hash: String,
+    },
+
     /// Read a transaction from stdin and simulate it
     SimulateTx,
 
@@ -442,6 +575,129 @@ enum AliasSubcmd {
     },
 }
 
+#[derive(Clone, Debug, Deserialize, StructOpt)]
+enum InvoiceSubcmd {
+    /// Create an invoice payable to the default address
+    Create {
+        /// Token to request
+        token: String,
+
+        /// Amount to request
+        amount: String,
+
+        #[structopt(short, long, default_value = "")]
+        /// Invoice description
+        memo: String,
+
+        #[structopt(short, long, default_value = "0")]
+        /// Seconds until the invoice expires, 0 for never
+        expiry: u64,
+    },
+
+    /// Print invoice info. If no invoice ID is provided,
+    /// list all the invoices in the wallet.
+    Show {
+        /// Invoice ID to search for
+        invoice_id: Option<String>,
+    },
+
+    /// Remove an invoice, keeping it archived until purged
+    Remove {
+        /// Invoice ID to remove
+        invoice_id: String,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, StructOpt)]
+enum ScheduleSubcmd {
+    /// Schedule a recurring payment
+    Add {
+        /// Recipient address or contact name of the payments
+        recipient: String,
+
+        /// Token to pay with
+        token: String,
+
+        /// Amount of each payment
+        amount: String,
+
+        /// Seconds between two payments
+        interval: u64,
+
+        #[structopt(short, long, default_value = "0")]
+        /// Seconds until the first payment
+        delay: u64,
+
+        #[structopt(short = "D", long, default_value = "0")]
+        /// Seconds after which payments stop, 0 for never
+        duration: u64,
+    },
+
+    /// List all the scheduled payments in the wallet
+    List,
+
+    /// Cancel a scheduled payment
+    Cancel {
+        /// Scheduled payment ID to cancel
+        schedule_id: u32,
+    },
+
+    /// Pay all the scheduled payments that are due.
+    /// Meant to be run periodically when not using `subscribe`.
+    Run,
+}
+
+#[derive(Clone, Debug, Deserialize, StructOpt)]
+enum ContactSubcmd {
+    /// Add a named address to the address book
+    Add {
+        /// Contact name
+        name: String,
+
+        /// Contact address
+        address: String,
+    },
+
+    /// List all the contacts in the address book
+    List,
+
+    /// Remove a contact from the address book, keeping it archived until purged
+    Remove {
+        /// Contact name to remove
+        name: String,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, StructOpt)]
+enum ArchiveSubcmd {
+    /// Archive a wallet key, so it's no longer scanned for
+    Key {
+        /// Key ID to archive, as printed by `wallet --addresses`
+        key_id: u64,
+    },
+
+    /// List archived records, optionally only the ones of given kind
+    List {
+        /// Kind of records to list (key, contact or invoice)
+        kind: Option<String>,
+    },
+
+    /// Restore an archived record
+    Restore {
+        /// Kind of the record to restore (key, contact or invoice)
+        kind: String,
+
+        /// Archive ID of the record to restore
+        archive_id: u64,
+    },
+
+    /// Permanently delete archived records, optionally only the ones of given kind
+    Purge {
+        /// Kind of records to purge (key, contact or invoice)
+        kind: Option<String>,
+    },
+}
+
 #[derive(Clone, Debug, Deserialize, StructOpt)]
 enum TokenSubcmd {
     /// Import a mint authority
@@ -527,6 +783,61 @@ struct BlockchainNetwork {
     #[structopt(short, long, default_value = "tcp://127.0.0.1:8240")]
     /// darkfid JSON-RPC endpoint
     endpoint: Url,
+
+    #[structopt(long)]
+    /// Directory to write wallet backups to
+    backup_path: Option<String>,
+
+    #[structopt(long)]
+    /// Password for the wallet backups, defaults to the wallet password
+    backup_pass: Option<String>,
+
+    #[structopt(long, default_value = "0")]
+    /// Seconds between wallet backups while subscribed, 0 to disable
+    backup_interval: u64,
+
+    #[structopt(long, default_value = "7")]
+    /// Number of wallet backups to keep, 0 to keep all
+    backup_retention: usize,
+
+    #[structopt(long, default_value = "1")]
+    /// Confirmations received coins need before they can be spent
+    min_confirmations: u32,
+
+    #[structopt(long, default_value = "largest-first")]
+    /// Coin selection strategy for payments: smallest-first, largest-first or random
+    coin_selection: String,
+
+    #[structopt(long)]
+    /// Additional darkfid JSON-RPC endpoints transactions are broadcast to
+    broadcast_endpoints: Vec<Url>,
+
+    #[structopt(long, default_value = "500")]
+    /// Milliseconds to wait between broadcasts to each endpoint
+    broadcast_stagger: u64,
+}
+
+impl BlockchainNetwork {
+    /// Auxiliary function to build the configured wallet backup schedule,
+    /// if a backup path is set.
+    fn backup_schedule(&self) -> Result<Option<BackupSchedule>> {
+        let Some(ref dir) = self.backup_path else { return Ok(None) };
+        let password = self.backup_pass.clone().unwrap_or(self.wallet_pass.clone());
+        let schedule =
+            BackupSchedule::new(dir, password, self.backup_interval, self.backup_retention)?;
+        Ok(Some(schedule))
+    }
+
+    /// Auxiliary function to build the broadcast configuration used to
+    /// pay scheduled payments.
+    fn payment_broadcast(&self) -> Result<PaymentBroadcast> {
+        Ok(PaymentBroadcast {
+            endpoint: self.endpoint.clone(),
+            gossip: self.broadcast_endpoints.clone(),
+            stagger: self.broadcast_stagger,
+            strategy: CoinSelection::from_str(&self.coin_selection)?,
+        })
+    }
 }
 
 /// Auxiliary function to parse darkfid configuration file and extract requested
@@ -585,7 +896,8 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         }
     };
 
-    match args.command {
+    let perf_stats = args.perf_stats;
+    let result = match args.command {
         Subcmd::Kaching => {
             if !args.fun {
                 println!("Apparently you don't like fun...");
@@ -601,6 +913,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 blockchain_config.wallet_pass,
                 Some(blockchain_config.endpoint),
                 ex,
+                blockchain_config.min_confirmations,
                 args.fun,
             )
             .await?;
@@ -619,8 +932,15 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
             default_address,
             secrets,
             import_secrets,
+            export_coins,
+            import_coins,
+            change_password,
+            backup,
+            merge,
+            export_watch_only,
             tree,
             coins,
+            privacy_audit,
         } => {
             if !initialize &&
                 !keygen &&
@@ -631,18 +951,29 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 !secrets &&
                 !tree &&
                 !coins &&
-                !import_secrets
+                !privacy_audit &&
+                !import_secrets &&
+                !export_coins &&
+                !import_coins &&
+                !change_password &&
+                !backup &&
+                merge.is_none() &&
+                export_watch_only.is_none()
             {
                 eprintln!("Error: You must use at least one flag for this subcommand");
                 eprintln!("Run with \"wallet -h\" to see the subcommand usage.");
                 exit(2);
             }
 
+            // Grab the backup schedule before the config gets consumed
+            let backup_schedule = if backup { blockchain_config.backup_schedule()? } else { None };
+
             let drk = Drk::new(
                 blockchain_config.wallet_path,
                 blockchain_config.wallet_pass,
                 None,
                 ex,
+                blockchain_config.min_confirmations,
                 args.fun,
             )
             .await?;
@@ -677,11 +1008,36 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
 
                 let aliases_map = drk.get_aliases_mapped_by_token().await?;
 
+                if args.json {
+                    let mut balances = vec![];
+                    for (token_id, (balance, immature)) in balmap.iter() {
+                        let aliases = aliases_map.get(token_id).cloned().unwrap_or_default();
+                        balances.push(JsonValue::Object(HashMap::from([
+                            ("token_id".to_string(), JsonValue::String(token_id.to_string())),
+                            ("aliases".to_string(), JsonValue::String(aliases)),
+                            (
+                                "balance".to_string(),
+                                JsonValue::String(encode_base10(*balance, BALANCE_BASE10_DECIMALS)),
+                            ),
+                            (
+                                "immature".to_string(),
+                                JsonValue::String(encode_base10(
+                                    *immature,
+                                    BALANCE_BASE10_DECIMALS,
+                                )),
+                            ),
+                        ])));
+                    }
+                    println!("{}", JsonValue::Array(balances).stringify()?);
+
+                    return Ok(())
+                }
+
                 // Create a prettytable with the new data:
                 let mut table = Table::new();
                 table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-                table.set_titles(row!["Token ID", "Aliases", "Balance"]);
-                for (token_id, balance) in balmap.iter() {
+                table.set_titles(row!["Token ID", "Aliases", "Balance", "Immature"]);
+                for (token_id, (balance, immature)) in balmap.iter() {
                     let aliases = match aliases_map.get(token_id) {
                         Some(a) => a,
                         None => "-",
@@ -690,7 +1046,8 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     table.add_row(row![
                         token_id,
                         aliases,
-                        encode_base10(*balance, BALANCE_BASE10_DECIMALS)
+                        encode_base10(*balance, BALANCE_BASE10_DECIMALS),
+                        encode_base10(*immature, BALANCE_BASE10_DECIMALS)
                     ]);
                 }
 
@@ -788,6 +1145,103 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 return Ok(())
             }
 
+            if export_coins {
+                for (coin, _, _) in drk.get_coins(false).await? {
+                    println!("{}", bs58::encode(&serialize_async(&coin).await).into_string());
+                }
+
+                return Ok(())
+            }
+
+            if import_coins {
+                let lines = stdin().lines();
+                for (i, line) in lines.enumerate() {
+                    if let Ok(line) = line {
+                        let bytes = bs58::decode(&line.trim()).into_vec()?;
+                        let Ok(coin) = deserialize_async::<OwnCoin>(&bytes).await else {
+                            println!("Warning: Failed to deserialize coin on line {i}");
+                            continue
+                        };
+
+                        if let Err(e) = drk.import_coin(&coin).await {
+                            println!("Warning: Failed to import coin on line {i}: {e:?}");
+                            continue
+                        }
+                        println!("{:?}", coin.coin);
+                    }
+                }
+
+                return Ok(())
+            }
+
+            if change_password {
+                let mut password = String::new();
+                stdin().read_line(&mut password)?;
+                let password = password.trim();
+
+                // Script kiddies protection
+                if password.is_empty() || password == "changeme" {
+                    eprintln!("Please don't use an empty or default wallet password...");
+                    exit(2);
+                }
+
+                if let Err(e) = drk.wallet.change_password(password) {
+                    eprintln!("Failed to change wallet password: {e:?}");
+                    exit(2);
+                }
+
+                println!("Wallet password changed, remember to update your config");
+                return Ok(())
+            }
+
+            if backup {
+                let mut schedule = match backup_schedule {
+                    Some(s) => s,
+                    None => {
+                        eprintln!("No backup path configured");
+                        exit(2);
+                    }
+                };
+
+                match drk.backup_wallet(&mut schedule) {
+                    Ok(path) => println!("Wallet backed up to {path:?}"),
+                    Err(e) => {
+                        eprintln!("Failed to backup wallet: {e:?}");
+                        exit(2);
+                    }
+                }
+
+                return Ok(())
+            }
+
+            if let Some(path) = merge {
+                let mut password = String::new();
+                stdin().read_line(&mut password)?;
+
+                match drk.merge_wallet(&path, password.trim()).await {
+                    Ok(summary) => println!("{summary}"),
+                    Err(e) => {
+                        eprintln!("Failed to merge wallet: {e:?}");
+                        exit(2);
+                    }
+                }
+
+                return Ok(())
+            }
+
+            if let Some(path) = export_watch_only {
+                let mut password = String::new();
+                stdin().read_line(&mut password)?;
+
+                if let Err(e) = drk.export_watch_only(&path, password.trim()).await {
+                    eprintln!("Failed to export watch-only wallet: {e:?}");
+                    exit(2);
+                }
+                println!("Watch-only wallet exported to {path}");
+
+                return Ok(())
+            }
+
             if tree {
                 let tree = drk.get_money_tree().await?;
 
@@ -798,6 +1252,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
 
             if coins {
                 let coins = drk.get_coins(true).await?;
+                let frozen_coins = drk.get_frozen_coins().await?;
 
                 let aliases_map = drk.get_aliases_mapped_by_token().await?;
 
@@ -810,6 +1265,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 table.set_titles(row![
                     "Coin",
                     "Spent",
+                    "Frozen",
                     "Token ID",
                     "Aliases",
                     "Value",
@@ -842,6 +1298,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                             .into_string()
                             .to_string(),
                         coin.1,
+                        frozen_coins.contains(&coin.0.coin),
                         coin.0.note.token_id,
                         aliases,
                         format!(
@@ -860,6 +1317,26 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 return Ok(())
             }
 
+            if privacy_audit {
+                let findings = drk.privacy_audit().await?;
+
+                if findings.is_empty() {
+                    println!("No privacy issues found");
+                    return Ok(())
+                }
+
+                let mut table = Table::new();
+                table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+                table.set_titles(row!["Severity", "Finding"]);
+                for finding in findings {
+                    table.add_row(row![finding.severity, finding.description]);
+                }
+
+                println!("{table}");
+
+                return Ok(())
+            }
+
             unreachable!()
         }
 
@@ -871,6 +1348,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 blockchain_config.wallet_pass,
                 None,
                 ex,
+                blockchain_config.min_confirmations,
                 args.fun,
             )
             .await?;
@@ -884,28 +1362,13 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         }
 
         Subcmd::Unspend { coin } => {
-            let bytes: [u8; 32] = match bs58::decode(&coin).into_vec()?.try_into() {
-                Ok(b) => b,
-                Err(e) => {
-                    eprintln!("Invalid coin: {e:?}");
-                    exit(2);
-                }
-            };
-
-            let elem: pallas::Base = match pallas::Base::from_repr(bytes).into() {
-                Some(v) => v,
-                None => {
-                    eprintln!("Invalid coin");
-                    exit(2);
-                }
-            };
-
-            let coin = Coin::from(elem);
+            let coin = parse_coin(&coin)?;
             let drk = Drk::new(
                 blockchain_config.wallet_path,
                 blockchain_config.wallet_pass,
                 None,
                 ex,
+                blockchain_config.min_confirmations,
                 args.fun,
             )
             .await?;
@@ -917,33 +1380,99 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
             Ok(())
         }
 
-        Subcmd::Transfer { amount, token, recipient, spend_hook, user_data, half_split } => {
+        Subcmd::Freeze { coin } => {
+            let coin = parse_coin(&coin)?;
             let drk = Drk::new(
                 blockchain_config.wallet_path,
                 blockchain_config.wallet_pass,
-                Some(blockchain_config.endpoint),
+                None,
                 ex,
+                blockchain_config.min_confirmations,
                 args.fun,
             )
             .await?;
+            if let Err(e) = drk.freeze_coin(&coin).await {
+                eprintln!("Failed to freeze coin: {e:?}");
+                exit(2);
+            }
 
-            if let Err(e) = f64::from_str(&amount) {
-                eprintln!("Invalid amount: {e:?}");
+            Ok(())
+        }
+
+        Subcmd::Unfreeze { coin } => {
+            let coin = parse_coin(&coin)?;
+            let drk = Drk::new(
+                blockchain_config.wallet_path,
+                blockchain_config.wallet_pass,
+                None,
+                ex,
+                blockchain_config.min_confirmations,
+                args.fun,
+            )
+            .await?;
+            if let Err(e) = drk.unfreeze_coin(&coin).await {
+                eprintln!("Failed to unfreeze coin: {e:?}");
                 exit(2);
             }
 
-            let rcpt = match PublicKey::from_str(&recipient) {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("Invalid recipient: {e:?}");
-                    exit(2);
-                }
-            };
+            Ok(())
+        }
 
-            let token_id = match drk.get_token(token).await {
-                Ok(t) => t,
+        Subcmd::Transfer {
+            amount,
+            token,
+            recipient,
+            spend_hook,
+            user_data,
+            memo,
+            half_split,
+            strategy,
+            fee,
+            estimate_fee,
+            simulate,
+        } => {
+            let memo = memo.map(String::into_bytes).unwrap_or_default();
+            if memo.len() > MAX_MEMO_SIZE {
+                eprintln!("Memo exceeds {MAX_MEMO_SIZE} bytes");
+                exit(2);
+            }
+
+            let strategy = strategy.unwrap_or(blockchain_config.coin_selection.clone());
+            let strategy = match CoinSelection::from_str(&strategy) {
+                Ok(s) => s,
                 Err(e) => {
-                    eprintln!("Invalid token alias: {e:?}");
+                    eprintln!("Invalid coin selection strategy: {e:?}");
+                    exit(2);
+                }
+            };
+
+            let drk = Drk::new(
+                blockchain_config.wallet_path,
+                blockchain_config.wallet_pass,
+                Some(blockchain_config.endpoint),
+                ex,
+                blockchain_config.min_confirmations,
+                args.fun,
+            )
+            .await?;
+
+            if let Err(e) = f64::from_str(&amount) {
+                eprintln!("Invalid amount: {e:?}");
+                exit(2);
+            }
+
+            let rcpt = match drk.get_recipient(&recipient).await {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("Invalid recipient: {e:?}");
+                    exit(2);
+                }
+            };
+
+            let token_id = match drk.get_token(token).await {
+                Ok(t) => t,
+                Err(e) => {
+                    eprintln!("Invalid token alias: {e:?}");
                     exit(2);
                 }
             };
@@ -980,8 +1509,70 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 None => None,
             };
 
+            if estimate_fee {
+                let fee = match drk
+                    .estimate_transfer_fee(
+                        &amount, token_id, rcpt, spend_hook, user_data, memo, half_split, strategy,
+                    )
+                    .await
+                {
+                    Ok(f) => f,
+                    Err(e) => {
+                        eprintln!("Failed to estimate payment transaction fee: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                println!("{}", encode_base10(fee, BALANCE_BASE10_DECIMALS));
+
+                return drk.stop_rpc_client().await
+            }
+
+            let fee = match fee {
+                Some(f) => match decode_base10(&f, BALANCE_BASE10_DECIMALS, false) {
+                    Ok(f) => Some(f),
+                    Err(e) => {
+                        eprintln!("Invalid fee: {e:?}");
+                        exit(2);
+                    }
+                },
+                None => None,
+            };
+
+            if simulate {
+                let simulation = match drk
+                    .simulate_transfer(
+                        &amount, token_id, rcpt, spend_hook, user_data, memo, half_split, strategy,
+                        fee,
+                    )
+                    .await
+                {
+                    Ok(s) => s,
+                    Err(e) => {
+                        eprintln!("Failed to simulate payment transaction: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                println!("Transaction: {}", simulation.tx_hash);
+                println!("Inputs:");
+                for coin in &simulation.inputs {
+                    println!(
+                        "  {} {} {}",
+                        bs58::encode(&serialize_async(&coin.coin.inner()).await).into_string(),
+                        encode_base10(coin.note.value, BALANCE_BASE10_DECIMALS),
+                        coin.note.token_id
+                    );
+                }
+                println!("Fee: {}", encode_base10(simulation.fee, BALANCE_BASE10_DECIMALS));
+
+                return drk.stop_rpc_client().await
+            }
+
             let tx = match drk
-                .transfer(&amount, token_id, rcpt, spend_hook, user_data, half_split)
+                .transfer(
+                    &amount, token_id, rcpt, spend_hook, user_data, memo, half_split, strategy, fee,
+                )
                 .await
             {
                 Ok(t) => t,
@@ -996,6 +1587,100 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
             drk.stop_rpc_client().await
         }
 
+        Subcmd::TransferMany { outputs, strategy, fee, estimate_fee } => {
+            if outputs.is_empty() {
+                eprintln!("No outputs provided");
+                exit(2);
+            }
+
+            let strategy = strategy.unwrap_or(blockchain_config.coin_selection.clone());
+            let strategy = match CoinSelection::from_str(&strategy) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("Invalid coin selection strategy: {e:?}");
+                    exit(2);
+                }
+            };
+
+            let drk = Drk::new(
+                blockchain_config.wallet_path,
+                blockchain_config.wallet_pass,
+                Some(blockchain_config.endpoint),
+                ex,
+                blockchain_config.min_confirmations,
+                args.fun,
+            )
+            .await?;
+
+            let mut parsed = Vec::with_capacity(outputs.len());
+            for output in outputs {
+                let [recipient, token, amount] = output.split(':').collect::<Vec<_>>()[..] else {
+                    eprintln!("Invalid output, expected recipient:token:amount: {output}");
+                    exit(2);
+                };
+
+                let rcpt = match drk.get_recipient(recipient).await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        eprintln!("Invalid recipient: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                let token_id = match drk.get_token(token.to_string()).await {
+                    Ok(t) => t,
+                    Err(e) => {
+                        eprintln!("Invalid token alias: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                if let Err(e) = f64::from_str(amount) {
+                    eprintln!("Invalid amount: {e:?}");
+                    exit(2);
+                }
+
+                parsed.push((rcpt, token_id, amount.to_string()));
+            }
+
+            if estimate_fee {
+                let fee = match drk.estimate_transfer_many_fee(parsed, strategy).await {
+                    Ok(f) => f,
+                    Err(e) => {
+                        eprintln!("Failed to estimate payment transaction fee: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                println!("{}", encode_base10(fee, BALANCE_BASE10_DECIMALS));
+
+                return drk.stop_rpc_client().await
+            }
+
+            let fee = match fee {
+                Some(f) => match decode_base10(&f, BALANCE_BASE10_DECIMALS, false) {
+                    Ok(f) => Some(f),
+                    Err(e) => {
+                        eprintln!("Invalid fee: {e:?}");
+                        exit(2);
+                    }
+                },
+                None => None,
+            };
+
+            let tx = match drk.transfer_many(parsed, strategy, fee).await {
+                Ok(t) => t,
+                Err(e) => {
+                    eprintln!("Failed to create payment transaction: {e:?}");
+                    exit(2);
+                }
+            };
+
+            println!("{}", base64::encode(&serialize_async(&tx).await));
+
+            drk.stop_rpc_client().await
+        }
+
         Subcmd::Otc { command } => match command {
             OtcSubcmd::Init { value_pair, token_pair } => {
                 let drk = Drk::new(
@@ -1003,6 +1688,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1036,6 +1722,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1064,6 +1751,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1083,6 +1771,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1123,6 +1812,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1174,6 +1864,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1191,6 +1882,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1208,6 +1900,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1259,6 +1952,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1288,6 +1982,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1297,7 +1992,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     exit(2);
                 }
 
-                let rcpt = match PublicKey::from_str(&recipient) {
+                let rcpt = match drk.get_recipient(&recipient).await {
                     Ok(r) => r,
                     Err(e) => {
                         eprintln!("Invalid recipient: {e:?}");
@@ -1369,6 +2064,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1395,6 +2091,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1586,6 +2283,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1645,6 +2343,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1674,6 +2373,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1717,6 +2417,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 blockchain_config.wallet_pass,
                 Some(blockchain_config.endpoint),
                 ex,
+                blockchain_config.min_confirmations,
                 args.fun,
             )
             .await?;
@@ -1744,8 +2445,9 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
             let drk = Drk::new(
                 blockchain_config.wallet_path,
                 blockchain_config.wallet_pass,
-                Some(blockchain_config.endpoint),
-                ex,
+                Some(blockchain_config.endpoint.clone()),
+                ex.clone(),
+                blockchain_config.min_confirmations,
                 args.fun,
             )
             .await?;
@@ -1760,7 +2462,16 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 exit(2);
             };
 
-            let txid = match drk.broadcast_tx(&tx).await {
+            let txid = match drk
+                .broadcast_tx(
+                    &tx,
+                    &blockchain_config.endpoint,
+                    &blockchain_config.broadcast_endpoints,
+                    blockchain_config.broadcast_stagger,
+                    ex,
+                )
+                .await
+            {
                 Ok(t) => t,
                 Err(e) => {
                     eprintln!("Failed to broadcast transaction: {e:?}");
@@ -1774,16 +2485,31 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
         }
 
         Subcmd::Subscribe => {
+            // Only schedule backups when an interval is configured
+            let backup = match blockchain_config.backup_schedule()? {
+                Some(s) if s.interval > 0 => Some(s),
+                _ => None,
+            };
+            let payments = blockchain_config.payment_broadcast()?;
+
             let drk = Drk::new(
                 blockchain_config.wallet_path,
                 blockchain_config.wallet_pass,
                 Some(blockchain_config.endpoint.clone()),
                 ex.clone(),
+                blockchain_config.min_confirmations,
                 args.fun,
             )
             .await?;
 
-            if let Err(e) = drk.subscribe_blocks(blockchain_config.endpoint, ex).await {
+            if drk.is_watch_only().await? {
+                eprintln!("Watch-only wallets can't pick up new coins by scanning");
+                exit(2);
+            }
+
+            if let Err(e) =
+                drk.subscribe_blocks(blockchain_config.endpoint, ex, backup, payments).await
+            {
                 eprintln!("Block subscription failed: {e:?}");
                 exit(2);
             }
@@ -1797,10 +2523,16 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 blockchain_config.wallet_pass,
                 Some(blockchain_config.endpoint),
                 ex,
+                blockchain_config.min_confirmations,
                 args.fun,
             )
             .await?;
 
+            if drk.is_watch_only().await? {
+                eprintln!("Watch-only wallets can't pick up new coins by scanning");
+                exit(2);
+            }
+
             if reset {
                 println!("Reset requested.");
                 if let Err(e) = drk.scan_blocks(true).await {
@@ -1830,6 +2562,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1863,6 +2596,36 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 drk.stop_rpc_client().await
             }
 
+            ExplorerSubcmd::TxStatus { tx_hash } => {
+                let tx_hash = TransactionHash(*blake3::Hash::from_hex(&tx_hash)?.as_bytes());
+
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    Some(blockchain_config.endpoint),
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+
+                let (status, height) = match drk.get_node_tx_status(&tx_hash).await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        eprintln!("Failed to fetch transaction status: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                println!("Transaction ID: {tx_hash}");
+                println!("Status: {status}");
+                if let Some(height) = height {
+                    println!("Block height: {height}");
+                }
+
+                drk.stop_rpc_client().await
+            }
+
             ExplorerSubcmd::SimulateTx => {
                 let tx = parse_tx_from_stdin().await?;
 
@@ -1871,6 +2634,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1895,6 +2659,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1909,6 +2674,30 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
 
                     println!("Transaction ID: {tx_hash}");
                     println!("Status: {status}");
+                    match drk.get_schedule_payments() {
+                        Ok(payments) => {
+                            if let Some(schedule_id) = payments.get(&tx_hash) {
+                                println!("Scheduled payment: {schedule_id}");
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!(
+                                "Failed to retrieve scheduled payments history records: {e:?}"
+                            );
+                            exit(2);
+                        }
+                    }
+                    let (_, propagation) = drk.get_tx_status(&tx_hash).await?;
+                    for (endpoint, endpoint_status) in propagation {
+                        println!("Propagation: {endpoint}: {endpoint_status}");
+                    }
+                    for (coin, memo) in drk.get_tx_memos(&tx).await? {
+                        println!(
+                            "Memo: {}: {}",
+                            bs58::encode(&serialize_async(&coin.inner()).await).into_string(),
+                            String::from_utf8_lossy(&memo)
+                        );
+                    }
                     println!("{tx:?}");
 
                     return Ok(())
@@ -1922,12 +2711,46 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     }
                 };
 
+                let schedule_payments = match drk.get_schedule_payments() {
+                    Ok(p) => p,
+                    Err(e) => {
+                        eprintln!("Failed to retrieve scheduled payments history records: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                if args.json {
+                    let records = map
+                        .iter()
+                        .map(|(tx_hash, status)| {
+                            let mut record = HashMap::from([
+                                ("tx_hash".to_string(), JsonValue::String(tx_hash.clone())),
+                                ("status".to_string(), JsonValue::String(status.clone())),
+                            ]);
+                            if let Some(schedule_id) = schedule_payments.get(tx_hash) {
+                                record.insert(
+                                    "schedule_id".to_string(),
+                                    JsonValue::Number(*schedule_id as f64),
+                                );
+                            }
+                            JsonValue::Object(record)
+                        })
+                        .collect();
+                    println!("{}", JsonValue::Array(records).stringify()?);
+
+                    return Ok(())
+                }
+
                 // Create a prettytable with the new data:
                 let mut table = Table::new();
                 table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-                table.set_titles(row!["Transaction Hash", "Status"]);
+                table.set_titles(row!["Transaction Hash", "Status", "Scheduled Payment"]);
                 for (txs_hash, status) in map.iter() {
-                    table.add_row(row![txs_hash, status]);
+                    let schedule_id = match schedule_payments.get(txs_hash) {
+                        Some(id) => id.to_string(),
+                        None => "-".to_string(),
+                    };
+                    table.add_row(row![txs_hash, status, schedule_id]);
                 }
 
                 if table.is_empty() {
@@ -1960,6 +2783,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -1988,6 +2812,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2016,6 +2841,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2028,6 +2854,463 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
             }
         },
 
+        Subcmd::Invoice { command } => match command {
+            InvoiceSubcmd::Create { token, amount, memo, expiry } => {
+                if let Err(e) = f64::from_str(&amount) {
+                    eprintln!("Invalid amount: {e:?}");
+                    exit(2);
+                }
+
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    None,
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+
+                let token_id = match drk.get_token(token).await {
+                    Ok(t) => t,
+                    Err(e) => {
+                        eprintln!("Invalid token alias: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                let invoice = match drk.create_invoice(token_id, &amount, memo, expiry).await {
+                    Ok(i) => i,
+                    Err(e) => {
+                        eprintln!("Failed to create invoice: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                println!("Invoice ID: {}", invoice.id);
+                println!("Recipient: {}", invoice.recipient);
+                println!("Payments must use the invoice ID as their user data");
+
+                Ok(())
+            }
+
+            InvoiceSubcmd::Show { invoice_id } => {
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    None,
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+
+                let invoices = match invoice_id {
+                    Some(id) => match drk.get_invoice(&id).await {
+                        Ok(i) => vec![i],
+                        Err(e) => {
+                            eprintln!("Failed to fetch invoice: {e:?}");
+                            exit(2);
+                        }
+                    },
+                    None => match drk.get_invoices().await {
+                        Ok(i) => i,
+                        Err(e) => {
+                            eprintln!("Failed to fetch invoices: {e:?}");
+                            exit(2);
+                        }
+                    },
+                };
+
+                // Create a prettytable with the new data:
+                let mut table = Table::new();
+                table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+                table.set_titles(row![
+                    "Invoice ID",
+                    "Token ID",
+                    "Amount",
+                    "Received",
+                    "Expiry",
+                    "Status",
+                    "Memo"
+                ]);
+                for invoice in invoices {
+                    table.add_row(row![
+                        invoice.id,
+                        invoice.token_id,
+                        encode_base10(invoice.amount, BALANCE_BASE10_DECIMALS),
+                        encode_base10(invoice.received, BALANCE_BASE10_DECIMALS),
+                        invoice.expiry,
+                        invoice.status,
+                        invoice.memo
+                    ]);
+                }
+
+                if table.is_empty() {
+                    println!("No invoices found");
+                } else {
+                    println!("{table}");
+                }
+
+                Ok(())
+            }
+
+            InvoiceSubcmd::Remove { invoice_id } => {
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    None,
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+                if let Err(e) = drk.archive_invoice(&invoice_id) {
+                    eprintln!("Failed to remove invoice: {e:?}");
+                    exit(2);
+                }
+
+                Ok(())
+            }
+        },
+
+        Subcmd::Schedule { command } => match command {
+            ScheduleSubcmd::Add { recipient, token, amount, interval, delay, duration } => {
+                if let Err(e) = f64::from_str(&amount) {
+                    eprintln!("Invalid amount: {e:?}");
+                    exit(2);
+                }
+
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    None,
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+
+                let recipient = match drk.get_recipient(&recipient).await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        eprintln!("Invalid recipient: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                let token_id = match drk.get_token(token).await {
+                    Ok(t) => t,
+                    Err(e) => {
+                        eprintln!("Invalid token alias: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                let schedule = match drk
+                    .add_schedule(recipient, token_id, &amount, interval, delay, duration)
+                    .await
+                {
+                    Ok(s) => s,
+                    Err(e) => {
+                        eprintln!("Failed to schedule payment: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                println!("Scheduled payment ID: {}", schedule.id);
+                println!("First payment due at: {}", schedule.next_payment);
+
+                Ok(())
+            }
+
+            ScheduleSubcmd::List => {
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    None,
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+
+                let schedules = match drk.get_schedules().await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        eprintln!("Failed to fetch scheduled payments: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                // Create a prettytable with the new data:
+                let mut table = Table::new();
+                table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+                table.set_titles(row![
+                    "Schedule ID",
+                    "Recipient",
+                    "Token ID",
+                    "Amount",
+                    "Interval",
+                    "Next Payment",
+                    "End",
+                    "Last Result"
+                ]);
+                for schedule in schedules {
+                    let next_payment = if schedule.is_finished() {
+                        "-".to_string()
+                    } else {
+                        schedule.next_payment.to_string()
+                    };
+                    table.add_row(row![
+                        schedule.id,
+                        schedule.recipient,
+                        schedule.token_id,
+                        encode_base10(schedule.amount, BALANCE_BASE10_DECIMALS),
+                        schedule.interval,
+                        next_payment,
+                        schedule.end,
+                        schedule.last_result
+                    ]);
+                }
+
+                if table.is_empty() {
+                    println!("No scheduled payments found");
+                } else {
+                    println!("{table}");
+                }
+
+                Ok(())
+            }
+
+            ScheduleSubcmd::Cancel { schedule_id } => {
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    None,
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+                if let Err(e) = drk.cancel_schedule(schedule_id) {
+                    eprintln!("Failed to cancel scheduled payment: {e:?}");
+                    exit(2);
+                }
+
+                Ok(())
+            }
+
+            ScheduleSubcmd::Run => {
+                let payments = blockchain_config.payment_broadcast()?;
+
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    Some(blockchain_config.endpoint),
+                    ex.clone(),
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+
+                if drk.is_watch_only().await? {
+                    eprintln!("Watch-only wallets can't make payments");
+                    exit(2);
+                }
+
+                if let Err(e) = drk.pay_due_schedules(&payments, ex).await {
+                    eprintln!("Failed to pay scheduled payments: {e:?}");
+                    exit(2);
+                }
+
+                drk.stop_rpc_client().await
+            }
+        },
+
+        Subcmd::Contact { command } => match command {
+            ContactSubcmd::Add { name, address } => {
+                let address = match PublicKey::from_str(&address) {
+                    Ok(a) => a,
+                    Err(e) => {
+                        eprintln!("Invalid address: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    None,
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+                if let Err(e) = drk.add_contact(name, address).await {
+                    eprintln!("Failed to add contact: {e:?}");
+                    exit(2);
+                }
+
+                Ok(())
+            }
+
+            ContactSubcmd::List => {
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    None,
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+                let contacts = match drk.get_contacts().await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        eprintln!("Failed to fetch contacts: {e:?}");
+                        exit(2);
+                    }
+                };
+
+                // Create a prettytable with the new data:
+                let mut table = Table::new();
+                table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+                table.set_titles(row!["Name", "Address"]);
+                for (name, address) in contacts {
+                    table.add_row(row![name, address]);
+                }
+
+                if table.is_empty() {
+                    println!("No contacts found");
+                } else {
+                    println!("{table}");
+                }
+
+                Ok(())
+            }
+
+            ContactSubcmd::Remove { name } => {
+                let drk = Drk::new(
+                    blockchain_config.wallet_path,
+                    blockchain_config.wallet_pass,
+                    None,
+                    ex,
+                    blockchain_config.min_confirmations,
+                    args.fun,
+                )
+                .await?;
+                if let Err(e) = drk.archive_contact(&name) {
+                    eprintln!("Failed to remove contact: {e:?}");
+                    exit(2);
+                }
+
+                Ok(())
+            }
+        },
+
+        Subcmd::Archive { command } => {
+            let drk = Drk::new(
+                blockchain_config.wallet_path,
+                blockchain_config.wallet_pass,
+                None,
+                ex,
+                blockchain_config.min_confirmations,
+                args.fun,
+            )
+            .await?;
+
+            match command {
+                ArchiveSubcmd::Key { key_id } => {
+                    if let Err(e) = drk.archive_key(key_id) {
+                        eprintln!("Failed to archive key: {e:?}");
+                        exit(2);
+                    }
+
+                    Ok(())
+                }
+
+                ArchiveSubcmd::List { kind } => {
+                    let kinds = match kind {
+                        Some(kind) => {
+                            let kind = match ArchivedKind::from_str(&kind) {
+                                Ok(k) => k,
+                                Err(e) => {
+                                    eprintln!("Invalid record kind: {e:?}");
+                                    exit(2);
+                                }
+                            };
+                            vec![kind]
+                        }
+                        None => ArchivedKind::ALL.to_vec(),
+                    };
+
+                    let mut table = Table::new();
+                    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+                    table.set_titles(row!["Kind", "Archive ID", "Record", "Archived At"]);
+                    for kind in kinds {
+                        let records = match drk.get_archived(kind).await {
+                            Ok(r) => r,
+                            Err(e) => {
+                                eprintln!("Failed to fetch archived records: {e:?}");
+                                exit(2);
+                            }
+                        };
+
+                        for (archive_id, label, archived_at) in records {
+                            table.add_row(row![kind, archive_id, label, archived_at]);
+                        }
+                    }
+
+                    if table.is_empty() {
+                        println!("No archived records found");
+                    } else {
+                        println!("{table}");
+                    }
+
+                    Ok(())
+                }
+
+                ArchiveSubcmd::Restore { kind, archive_id } => {
+                    let kind = match ArchivedKind::from_str(&kind) {
+                        Ok(k) => k,
+                        Err(e) => {
+                            eprintln!("Invalid record kind: {e:?}");
+                            exit(2);
+                        }
+                    };
+                    if let Err(e) = drk.restore_archived(kind, archive_id) {
+                        eprintln!("Failed to restore archived {kind}: {e:?}");
+                        exit(2);
+                    }
+
+                    Ok(())
+                }
+
+                ArchiveSubcmd::Purge { kind } => {
+                    let kind = match kind {
+                        Some(kind) => {
+                            let kind = match ArchivedKind::from_str(&kind) {
+                                Ok(k) => k,
+                                Err(e) => {
+                                    eprintln!("Invalid record kind: {e:?}");
+                                    exit(2);
+                                }
+                            };
+                            Some(kind)
+                        }
+                        None => None,
+                    };
+                    if let Err(e) = drk.purge_archived(kind) {
+                        eprintln!("Failed to purge archived records: {e:?}");
+                        exit(2);
+                    }
+
+                    Ok(())
+                }
+            }
+        }
+
         Subcmd::Token { command } => match command {
             TokenSubcmd::Import { secret_key, token_blind } => {
                 let mint_authority = match SecretKey::from_str(&secret_key) {
@@ -2051,6 +3334,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2066,6 +3350,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2083,6 +3368,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2129,6 +3415,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2138,7 +3425,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     exit(2);
                 }
 
-                let rcpt = match PublicKey::from_str(&recipient) {
+                let rcpt = match drk.get_recipient(&recipient).await {
                     Ok(r) => r,
                     Err(e) => {
                         eprintln!("Invalid recipient: {e:?}");
@@ -2206,6 +3493,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2238,6 +3526,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2256,6 +3545,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     None,
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2288,6 +3578,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2316,6 +3607,7 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                     blockchain_config.wallet_pass,
                     Some(blockchain_config.endpoint),
                     ex,
+                    blockchain_config.min_confirmations,
                     args.fun,
                 )
                 .await?;
@@ -2338,5 +3630,11 @@ async fn realmain(args: Args, ex: Arc<smol::Executor<'static>>) -> Result<()> {
                 drk.stop_rpc_client().await
             }
         },
+    };
+
+    if perf_stats {
+        eprint!("{}", PERF_STATS);
     }
+
+    result
 }
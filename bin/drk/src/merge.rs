@@ -0,0 +1,170 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashSet, fmt};
+
+use darkfi::{util::path::expand_path, Error, Result};
+
+use crate::{walletdb::WalletDb, Drk};
+
+/// Summary of a wallet merge
+#[derive(Clone, Debug, Default)]
+pub struct MergeSummary {
+    /// Secret keys imported
+    pub keys_imported: usize,
+    /// Secret keys we already had
+    pub keys_skipped: usize,
+    /// Coins imported
+    pub coins_imported: usize,
+    /// Coins we already had
+    pub coins_skipped: usize,
+    /// Coins that couldn't be imported because our Merkle tree doesn't
+    /// witness them. These are picked up by rescanning from scratch.
+    pub coins_pending: usize,
+    /// Token mint authorities imported
+    pub tokens_imported: usize,
+    /// Token aliases imported
+    pub aliases_imported: usize,
+    /// Transaction history records imported
+    pub txs_imported: usize,
+}
+
+impl fmt::Display for MergeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Keys: {} imported, {} already known", self.keys_imported, self.keys_skipped)?;
+        writeln!(
+            f,
+            "Coins: {} imported, {} already known, {} pending rescan",
+            self.coins_imported, self.coins_skipped, self.coins_pending
+        )?;
+        writeln!(f, "Token mint authorities: {} imported", self.tokens_imported)?;
+        writeln!(f, "Token aliases: {} imported", self.aliases_imported)?;
+        write!(f, "Transactions history records: {} imported", self.txs_imported)
+    }
+}
+
+impl Drk {
+    /// Import the keys, coins, tokens, aliases and transactions history
+    /// of the wallet at `path` into ours. Records we already have are
+    /// skipped, so merging the same wallet twice is harmless.
+    ///
+    /// Coins are only imported if our Merkle tree witnesses them. If some
+    /// coins end up pending, a `scan --reset` picks them up using the
+    /// imported keys.
+    pub async fn merge_wallet(&self, path: &str, password: &str) -> Result<MergeSummary> {
+        let path = expand_path(path)?;
+        if !path.exists() {
+            return Err(Error::Custom(format!("Wallet {path:?} does not exist")))
+        }
+        let wallet = match WalletDb::new(Some(path), Some(password)) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[merge_wallet] Opening wallet failed: {e:?}"
+                )))
+            }
+        };
+        let other = Drk {
+            wallet,
+            rpc_client: None,
+            fun: self.fun,
+            min_confirmations: self.min_confirmations,
+            proving_keys_dir: self.proving_keys_dir.clone(),
+        };
+
+        let mut summary = MergeSummary::default();
+
+        // Keys first, so the coins they own are ours
+        let secrets = other.get_money_secrets().await?;
+        let imported = self.import_money_secrets(secrets.clone()).await?;
+        summary.keys_imported = imported.len();
+        summary.keys_skipped = secrets.len() - imported.len();
+
+        let our_coins: Vec<_> =
+            self.get_coins(true).await?.into_iter().map(|(c, _, _)| c.coin).collect();
+        for (coin, _, _) in other.get_coins(true).await? {
+            if our_coins.contains(&coin.coin) {
+                summary.coins_skipped += 1;
+                continue
+            }
+
+            match self.import_coin(&coin).await {
+                Ok(()) => summary.coins_imported += 1,
+                Err(e) => {
+                    println!("Coin {:?} pending rescan: {e}", coin.coin);
+                    summary.coins_pending += 1;
+                }
+            }
+        }
+
+        let our_tokens: Vec<_> =
+            self.get_mint_authorities().await?.into_iter().map(|(id, _, _, _)| id).collect();
+        for (token_id, mint_authority, token_blind, _) in other.get_mint_authorities().await? {
+            if our_tokens.contains(&token_id) {
+                continue
+            }
+            self.import_mint_authority(mint_authority, token_blind).await?;
+            summary.tokens_imported += 1;
+        }
+
+        // Our own aliases take precedence
+        let our_aliases = self.get_aliases(None, None).await?;
+        for (alias, token_id) in other.get_aliases(None, None).await? {
+            if our_aliases.contains_key(&alias) {
+                continue
+            }
+            if let Err(e) = self.add_alias(alias, token_id).await {
+                return Err(Error::DatabaseError(format!(
+                    "[merge_wallet] Inserting alias failed: {e:?}"
+                )))
+            }
+            summary.aliases_imported += 1;
+        }
+
+        let our_txs: HashSet<_> = match self.get_txs_history() {
+            Ok(txs) => txs.into_iter().map(|(hash, _)| hash).collect(),
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[merge_wallet] Transactions history retrieval failed: {e:?}"
+                )))
+            }
+        };
+        let other_txs = match other.get_txs_history() {
+            Ok(txs) => txs,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[merge_wallet] Transactions history retrieval failed: {e:?}"
+                )))
+            }
+        };
+        for (tx_hash, _) in other_txs {
+            if our_txs.contains(&tx_hash) {
+                continue
+            }
+            let (_, status, tx) = other.get_tx_history_record(&tx_hash).await?;
+            if let Err(e) = self.put_tx_history_record(&tx, &status).await {
+                return Err(Error::DatabaseError(format!(
+                    "[merge_wallet] Inserting transaction history record failed: {e:?}"
+                )))
+            }
+            summary.txs_imported += 1;
+        }
+
+        Ok(summary)
+    }
+}
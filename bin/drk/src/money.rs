@@ -25,7 +25,8 @@ use rusqlite::types::Value;
 
 use darkfi::{
     tx::Transaction,
-    zk::{halo2::Field, proof::ProvingKey, vm::ZkCircuit, vm_heap::empty_witnesses, Proof},
+    util::parse::encode_base10,
+    zk::{halo2::Field, proof::ProvingKey, Proof},
     zkas::ZkBinary,
     Error, Result,
 };
@@ -36,8 +37,8 @@ use darkfi_money_contract::{
         MoneyNote, OwnCoin,
     },
     model::{
-        Coin, Input, MoneyAuthTokenFreezeParamsV1, MoneyAuthTokenMintParamsV1, MoneyFeeParamsV1,
-        MoneyGenesisMintParamsV1, MoneyPoWRewardParamsV1, MoneyTokenMintParamsV1,
+        Coin, CoinAttributes, Input, MoneyAuthTokenFreezeParamsV1, MoneyAuthTokenMintParamsV1,
+        MoneyFeeParamsV1, MoneyGenesisMintParamsV1, MoneyPoWRewardParamsV1, MoneyTokenMintParamsV1,
         MoneyTransferParamsV1, Nullifier, Output, TokenId, DARK_TOKEN_ID,
     },
     MoneyFunction, MONEY_CONTRACT_ZKAS_FEE_NS_V1,
@@ -75,6 +76,8 @@ lazy_static! {
     pub static ref MONEY_SMT_TABLE: String = format!("{}_money_smt", MONEY_CONTRACT_ID.to_string());
     pub static ref MONEY_KEYS_TABLE: String =
         format!("{}_money_keys", MONEY_CONTRACT_ID.to_string());
+    pub static ref MONEY_KEYS_ARCHIVED_TABLE: String =
+        format!("{}_money_keys_archived", MONEY_CONTRACT_ID.to_string());
     pub static ref MONEY_COINS_TABLE: String =
         format!("{}_money_coins", MONEY_CONTRACT_ID.to_string());
     pub static ref MONEY_TOKENS_TABLE: String =
@@ -113,6 +116,7 @@ pub const MONEY_COINS_COL_SECRET: &str = "secret";
 pub const MONEY_COINS_COL_LEAF_POSITION: &str = "leaf_position";
 pub const MONEY_COINS_COL_MEMO: &str = "memo";
 pub const MONEY_COINS_COL_SPENT_TX_HASH: &str = "spent_tx_hash";
+pub const MONEY_COINS_COL_CREATION_HEIGHT: &str = "creation_height";
 
 // MONEY_TOKENS_TABLE
 pub const MONEY_TOKENS_COL_TOKEN_ID: &str = "token_id";
@@ -212,6 +216,9 @@ impl Drk {
             return Err(Error::ParseFailed("[default_secret] Key bytes parsing failed"))
         };
         let secret_key: SecretKey = deserialize_async(key_bytes).await?;
+        if secret_key.inner() == pallas::Base::ZERO {
+            return Err(Error::Custom("Wallet is watch-only, secret keys are not available".into()))
+        }
 
         Ok(secret_key)
     }
@@ -366,26 +373,57 @@ impl Drk {
     }
 
     /// Fetch known unspent balances from the wallet and return them as a hashmap.
-    pub async fn money_balance(&self) -> Result<HashMap<String, u64>> {
-        let mut coins = self.get_coins(false).await?;
-        coins.retain(|x| x.0.note.spend_hook == FuncId::none());
+    /// Each balance is split into the available and immature amounts, the latter
+    /// being coins that don't have enough confirmations to be spent yet.
+    pub async fn money_balance(&self) -> Result<HashMap<String, (u64, u64)>> {
+        let rows = match self.wallet.query_multiple(
+            &MONEY_COINS_TABLE,
+            &[],
+            convert_named_params! {(MONEY_COINS_COL_IS_SPENT, false), (MONEY_COINS_COL_SPEND_HOOK, serialize_async(&FuncId::none()).await)},
+        ) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[money_balance] Coins retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        let spendable_height = self.spendable_height()?;
 
         // Fill this map with balances
-        let mut balmap: HashMap<String, u64> = HashMap::new();
+        let mut balmap: HashMap<String, (u64, u64)> = HashMap::new();
 
-        for coin in coins {
-            let mut value = coin.0.note.value;
+        for row in rows {
+            let (coin, _, _) = self.parse_coin_record(&row).await?;
+            let balance = balmap.entry(coin.note.token_id.to_string()).or_default();
 
-            if let Some(prev) = balmap.get(&coin.0.note.token_id.to_string()) {
-                value += prev;
+            if parse_coin_height(&row)? <= spendable_height {
+                balance.0 += coin.note.value;
+            } else {
+                balance.1 += coin.note.value;
             }
-
-            balmap.insert(coin.0.note.token_id.to_string(), value);
         }
 
         Ok(balmap)
     }
 
+    /// Returns the highest block height a coin can have been created at in order
+    /// to have `min_confirmations` confirmations, and therefore be spendable.
+    pub fn spendable_height(&self) -> Result<u32> {
+        let last_scanned_block = match self.last_scanned_block() {
+            Ok(h) => h,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[spendable_height] Last scanned block retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        // A coin found in the last scanned block has one confirmation
+        Ok((last_scanned_block + 1).saturating_sub(self.min_confirmations))
+    }
+
     /// Fetch all coins and their metadata related to the Money contract from the wallet.
     /// Optionally also fetch spent ones.
     /// The boolean in the returned tuple notes if the coin was marked as spent.
@@ -418,6 +456,8 @@ impl Drk {
     }
 
     /// Fetch provided token unspend balances from the wallet.
+    /// Only coins with at least `min_confirmations` confirmations, that
+    /// aren't frozen, are returned.
     pub async fn get_token_coins(&self, token_id: &TokenId) -> Result<Vec<OwnCoin>> {
         let query = self.wallet.query_multiple(
             &MONEY_COINS_TABLE,
@@ -434,15 +474,27 @@ impl Drk {
             }
         };
 
+        let spendable_height = self.spendable_height()?;
+        let frozen_coins = self.get_frozen_coins().await?;
+
         let mut owncoins = Vec::with_capacity(rows.len());
         for row in rows {
-            owncoins.push(self.parse_coin_record(&row).await?.0)
+            if parse_coin_height(&row)? > spendable_height {
+                continue
+            }
+            let owncoin = self.parse_coin_record(&row).await?.0;
+            if frozen_coins.contains(&owncoin.coin) {
+                continue
+            }
+            owncoins.push(owncoin)
         }
 
         Ok(owncoins)
     }
 
     /// Fetch provided contract specified token unspend balances from the wallet.
+    /// Only coins with at least `min_confirmations` confirmations, that
+    /// aren't frozen, are returned.
     pub async fn get_contract_token_coins(
         &self,
         token_id: &TokenId,
@@ -464,9 +516,19 @@ impl Drk {
             }
         };
 
+        let spendable_height = self.spendable_height()?;
+        let frozen_coins = self.get_frozen_coins().await?;
+
         let mut owncoins = Vec::with_capacity(rows.len());
         for row in rows {
-            owncoins.push(self.parse_coin_record(&row).await?.0)
+            if parse_coin_height(&row)? > spendable_height {
+                continue
+            }
+            let owncoin = self.parse_coin_record(&row).await?.0;
+            if frozen_coins.contains(&owncoin.coin) {
+                continue
+            }
+            owncoins.push(owncoin)
         }
 
         Ok(owncoins)
@@ -533,9 +595,10 @@ impl Drk {
         };
         let leaf_position: bridgetree::Position = deserialize_async(leaf_position_bytes).await?;
 
-        let Value::Blob(ref memo) = row[11] else {
+        let Value::Blob(ref memo_bytes) = row[11] else {
             return Err(Error::ParseFailed("[parse_coin_record] Memo parsing failed"))
         };
+        let memo: Vec<u8> = deserialize_async(memo_bytes).await?;
 
         let Value::Text(ref spent_tx_hash) = row[12] else {
             return Err(Error::ParseFailed(
@@ -551,7 +614,7 @@ impl Drk {
             coin_blind,
             value_blind,
             token_blind,
-            memo: memo.clone(),
+            memo,
         };
 
         Ok((OwnCoin { coin, note, secret, leaf_position }, is_spent, spent_tx_hash.clone()))
@@ -820,11 +883,13 @@ impl Drk {
     }
 
     /// Append data related to Money contract transactions into the wallet database.
+    /// `block_height` is the height of the block containing the transaction.
     pub async fn apply_tx_money_data(
         &self,
         call_idx: usize,
         calls: &[DarkLeaf<ContractCall>],
         tx_hash: &String,
+        block_height: u32,
     ) -> Result<()> {
         let (nullifiers, coins, notes, freezes) = self.parse_money_call(call_idx, calls).await?;
         let secrets = self.get_money_secrets().await?;
@@ -860,44 +925,10 @@ impl Drk {
         self.smt_insert(&nullifiers)?;
         self.mark_spent_coins(&nullifiers, tx_hash).await?;
 
-        // This is the SQL query we'll be executing to insert new coins
-        // into the wallet
-        let query = format!(
-            "INSERT INTO {} ({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12);",
-            *MONEY_COINS_TABLE,
-            MONEY_COINS_COL_COIN,
-            MONEY_COINS_COL_IS_SPENT,
-            MONEY_COINS_COL_VALUE,
-            MONEY_COINS_COL_TOKEN_ID,
-            MONEY_COINS_COL_SPEND_HOOK,
-            MONEY_COINS_COL_USER_DATA,
-            MONEY_COINS_COL_COIN_BLIND,
-            MONEY_COINS_COL_VALUE_BLIND,
-            MONEY_COINS_COL_TOKEN_BLIND,
-            MONEY_COINS_COL_SECRET,
-            MONEY_COINS_COL_LEAF_POSITION,
-            MONEY_COINS_COL_MEMO,
-        );
-
         println!("Found {} OwnCoin(s) in transaction", owncoins.len());
         for owncoin in &owncoins {
             println!("OwnCoin: {:?}", owncoin.coin);
-            let params = rusqlite::params![
-                serialize_async(&owncoin.coin).await,
-                0, // <-- is_spent
-                serialize_async(&owncoin.note.value).await,
-                serialize_async(&owncoin.note.token_id).await,
-                serialize_async(&owncoin.note.spend_hook).await,
-                serialize_async(&owncoin.note.user_data).await,
-                serialize_async(&owncoin.note.coin_blind).await,
-                serialize_async(&owncoin.note.value_blind).await,
-                serialize_async(&owncoin.note.token_blind).await,
-                serialize_async(&owncoin.secret).await,
-                serialize_async(&owncoin.leaf_position).await,
-                serialize_async(&owncoin.note.memo).await,
-            ];
-
-            if let Err(e) = self.wallet.exec_sql(&query, params) {
+            if let Err(e) = self.insert_owncoin(owncoin, block_height).await {
                 return Err(Error::DatabaseError(format!(
                     "[apply_tx_money_data] Inserting Money coin failed: {e:?}"
                 )))
@@ -926,8 +957,107 @@ impl Drk {
         Ok(())
     }
 
+    /// Auxiliary function to insert an unspent `OwnCoin`, created at the provided
+    /// block height, into the wallet.
+    async fn insert_owncoin(&self, owncoin: &OwnCoin, creation_height: u32) -> WalletDbResult<()> {
+        let query = format!(
+            "INSERT INTO {} ({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13);",
+            *MONEY_COINS_TABLE,
+            MONEY_COINS_COL_COIN,
+            MONEY_COINS_COL_IS_SPENT,
+            MONEY_COINS_COL_VALUE,
+            MONEY_COINS_COL_TOKEN_ID,
+            MONEY_COINS_COL_SPEND_HOOK,
+            MONEY_COINS_COL_USER_DATA,
+            MONEY_COINS_COL_COIN_BLIND,
+            MONEY_COINS_COL_VALUE_BLIND,
+            MONEY_COINS_COL_TOKEN_BLIND,
+            MONEY_COINS_COL_SECRET,
+            MONEY_COINS_COL_LEAF_POSITION,
+            MONEY_COINS_COL_MEMO,
+            MONEY_COINS_COL_CREATION_HEIGHT,
+        );
+
+        let params = rusqlite::params![
+            serialize_async(&owncoin.coin).await,
+            0, // <-- is_spent
+            serialize_async(&owncoin.note.value).await,
+            serialize_async(&owncoin.note.token_id).await,
+            serialize_async(&owncoin.note.spend_hook).await,
+            serialize_async(&owncoin.note.user_data).await,
+            serialize_async(&owncoin.note.coin_blind).await,
+            serialize_async(&owncoin.note.value_blind).await,
+            serialize_async(&owncoin.note.token_blind).await,
+            serialize_async(&owncoin.secret).await,
+            serialize_async(&owncoin.leaf_position).await,
+            serialize_async(&owncoin.note.memo).await,
+            creation_height,
+        ];
+
+        self.wallet.exec_sql(&query, params)
+    }
+
+    /// Import an `OwnCoin` obtained through external means, e.g. exported
+    /// from an old backup or another wallet, without rescanning the chain.
+    ///
+    /// The coin must match its note and secret, and its leaf position must
+    /// be witnessed in our Merkle tree so it can be spent. If the coin's
+    /// nullifier is already in our nullifiers tree, it gets marked as spent.
+    /// Since its creation height is unknown, the coin is considered to have
+    /// enough confirmations to be spent.
+    pub async fn import_coin(&self, owncoin: &OwnCoin) -> Result<()> {
+        let coin = CoinAttributes {
+            public_key: PublicKey::from_secret(owncoin.secret),
+            value: owncoin.note.value,
+            token_id: owncoin.note.token_id,
+            spend_hook: owncoin.note.spend_hook,
+            user_data: owncoin.note.user_data,
+            blind: owncoin.note.coin_blind,
+        }
+        .to_coin();
+        if coin != owncoin.coin {
+            return Err(Error::Custom("Coin does not match its note and secret".to_string()))
+        }
+
+        if self.get_coins(true).await?.iter().any(|(c, _, _)| c.coin == owncoin.coin) {
+            return Err(Error::Custom("Coin already exists in the wallet".to_string()))
+        }
+
+        // We can only spend coins whose leaf position is marked in our tree
+        let tree = self.get_money_tree().await?;
+        if tree.witness(owncoin.leaf_position, 0).is_none() {
+            return Err(Error::Custom(
+                "Coin leaf position is not witnessed in the wallet Merkle tree".to_string(),
+            ))
+        }
+
+        if let Err(e) = self.insert_owncoin(owncoin, 0).await {
+            return Err(Error::DatabaseError(format!(
+                "[import_coin] Inserting Money coin failed: {e:?}"
+            )))
+        }
+
+        // Check if the coin was already spent on chain
+        let store = WalletStorage::new(
+            &self.wallet,
+            &MONEY_SMT_TABLE,
+            MONEY_SMT_COL_KEY,
+            MONEY_SMT_COL_VALUE,
+        );
+        let smt = WalletSmt::new(store, PoseidonFp::new(), &EMPTY_NODES_FP);
+        let nullifier = owncoin.nullifier();
+        if smt.get_leaf(&nullifier.inner()) != pallas::Base::ZERO {
+            self.mark_spent_coins(&[nullifier], &String::from("-")).await?;
+        }
+
+        Ok(())
+    }
+
     /// Auxiliary function to  grab all the nullifiers from a transaction money call.
-    async fn money_call_nullifiers(&self, call: &DarkLeaf<ContractCall>) -> Result<Vec<Nullifier>> {
+    pub async fn money_call_nullifiers(
+        &self,
+        call: &DarkLeaf<ContractCall>,
+    ) -> Result<Vec<Nullifier>> {
         let mut nullifiers: Vec<Nullifier> = vec![];
 
         let data = &call.data.data;
@@ -956,6 +1086,29 @@ impl Drk {
         Ok(nullifiers)
     }
 
+    /// Auxiliary function to grab all the output coins from a transaction money call.
+    pub async fn money_call_coins(&self, call: &DarkLeaf<ContractCall>) -> Result<Vec<Coin>> {
+        let mut coins: Vec<Coin> = vec![];
+
+        let data = &call.data.data;
+        match MoneyFunction::try_from(data[0])? {
+            MoneyFunction::FeeV1 => {
+                let params: MoneyFeeParamsV1 = deserialize_async(&data[9..]).await?;
+                coins.push(params.output.coin);
+            }
+            MoneyFunction::TransferV1 | MoneyFunction::OtcSwapV1 => {
+                let params: MoneyTransferParamsV1 = deserialize_async(&data[1..]).await?;
+
+                for output in params.outputs {
+                    coins.push(output.coin);
+                }
+            }
+            _ => { /* Do nothing */ }
+        }
+
+        Ok(coins)
+    }
+
     /// Mark provided transaction input coins as spent.
     pub async fn mark_tx_spend(&self, tx: &Transaction) -> Result<()> {
         let tx_hash = tx.hash().to_string();
@@ -1086,15 +1239,30 @@ impl Drk {
         fee_pk: &ProvingKey,
         fee_zkbin: &ZkBinary,
         spent_coins: Option<&[OwnCoin]>,
+        fee: Option<u64>,
     ) -> Result<(ContractCall, Vec<Proof>, Vec<SecretKey>)> {
         // First we verify the fee-less transaction to see how much gas it uses for execution
         // and verification.
-        let gas_used = FEE_CALL_GAS + self.get_tx_gas(tx, false).await?;
+        let gas_used = self.estimate_fee(tx).await?;
+
+        // An explicit fee can only pay more than the required one, otherwise
+        // the transaction would get rejected by the validators.
+        let fee = match fee {
+            Some(fee) if fee < gas_used => {
+                return Err(Error::Custom(format!(
+                    "Fee {} is below the required fee {}",
+                    encode_base10(fee, BALANCE_BASE10_DECIMALS),
+                    encode_base10(gas_used, BALANCE_BASE10_DECIMALS)
+                )))
+            }
+            Some(fee) => fee,
+            None => gas_used,
+        };
 
-        // Knowing the total gas, we can now find an OwnCoin of enough value
+        // Knowing the total fee, we can now find an OwnCoin of enough value
         // so that we can create a valid Money::Fee call.
         let mut available_coins = self.get_token_coins(&DARK_TOKEN_ID).await?;
-        available_coins.retain(|x| x.note.value > gas_used);
+        available_coins.retain(|x| x.note.value > fee);
         if let Some(spent_coins) = spent_coins {
             available_coins.retain(|x| !spent_coins.contains(x));
         }
@@ -1103,7 +1271,7 @@ impl Drk {
         }
 
         let coin = &available_coins[0];
-        let change_value = coin.note.value - gas_used;
+        let change_value = coin.note.value - fee;
 
         // Input and output setup
         let input = FeeCallInput {
@@ -1180,13 +1348,19 @@ impl Drk {
 
         // Encode the contract call
         let mut data = vec![MoneyFunction::FeeV1 as u8];
-        gas_used.encode_async(&mut data).await?;
+        fee.encode_async(&mut data).await?;
         params.encode_async(&mut data).await?;
         let call = ContractCall { contract_id: *MONEY_CONTRACT_ID, data };
 
         Ok((call, vec![proof], vec![signature_secret]))
     }
 
+    /// Estimate the fee required to pay for given fee-less transaction,
+    /// including the gas used by the fee call itself.
+    pub async fn estimate_fee(&self, tx: &Transaction) -> Result<u64> {
+        Ok(FEE_CALL_GAS + self.get_tx_gas(tx, false).await?)
+    }
+
     /// Create and attach the fee call to given transaction.
     pub async fn attach_fee(&self, tx: &mut Transaction) -> Result<()> {
         // Grab spent coins nullifiers of the transactions and check no other fee call exists
@@ -1226,18 +1400,14 @@ impl Drk {
             return Err(Error::Custom("Fee circuit not found".to_string()))
         };
 
-        let fee_zkbin = ZkBinary::decode(&fee_zkbin.1)?;
-
-        let fee_circuit = ZkCircuit::new(empty_witnesses(&fee_zkbin)?, &fee_zkbin);
-
-        // Creating Fee circuits proving keys
-        let fee_pk = ProvingKey::build(fee_zkbin.k, &fee_circuit);
+        // Retrieve the Fee circuit proving key
+        let (fee_zkbin, fee_pk) = self.proving_key(&fee_zkbin.1).await?;
 
         // We first have to execute the fee-less tx to gather its used gas, and then we feed
         // it into the fee-creating function.
         let tree = self.get_money_tree().await?;
         let (fee_call, fee_proofs, fee_secrets) =
-            self.append_fee_call(tx, &tree, &fee_pk, &fee_zkbin, Some(&spent_coins)).await?;
+            self.append_fee_call(tx, &tree, &fee_pk, &fee_zkbin, Some(&spent_coins), None).await?;
 
         // Append the fee call to the transaction
         tx.calls.push(DarkLeaf { data: fee_call, parent_index: None, children_indexes: vec![] });
@@ -1248,3 +1418,15 @@ impl Drk {
         Ok(())
     }
 }
+
+/// Auxiliary function to parse the creation height of a `MONEY_COINS_TABLE` record.
+fn parse_coin_height(row: &[Value]) -> Result<u32> {
+    let Value::Integer(height) = row[13] else {
+        return Err(Error::ParseFailed("[parse_coin_height] Creation height parsing failed"))
+    };
+    let Ok(height) = u32::try_from(height) else {
+        return Err(Error::ParseFailed("[parse_coin_height] Creation height parsing failed"))
+    };
+
+    Ok(height)
+}
@@ -0,0 +1,163 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use darkfi::{util::parse::encode_base10, Error, Result};
+use darkfi_sdk::crypto::{PublicKey, MONEY_CONTRACT_ID};
+
+use crate::{money::BALANCE_BASE10_DECIMALS, Drk};
+
+/// Received values that are a multiple of this, a whole token, are
+/// considered round amounts.
+const ROUND_AMOUNT: u64 = 100_000_000;
+
+/// Severity of a privacy audit finding
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Low => write!(f, "Low"),
+            Self::Medium => write!(f, "Medium"),
+            Self::High => write!(f, "High"),
+        }
+    }
+}
+
+/// A past wallet behavior affecting unlinkability
+#[derive(Clone, Debug)]
+pub struct Finding {
+    /// How much the behavior hurts unlinkability
+    pub severity: Severity,
+    /// Description of the behavior and the records involved
+    pub description: String,
+}
+
+impl Drk {
+    /// Scan the wallet coins and transactions history for privacy
+    /// footguns: addresses receiving several payments, payments of round
+    /// amounts, change returned to the address it was spent from, and
+    /// transactions spending coins of different addresses together.
+    /// Findings are returned most severe first.
+    pub async fn privacy_audit(&self) -> Result<Vec<Finding>> {
+        let coins = self.get_coins(true).await?;
+
+        // Coins created by our own transactions are change, everything
+        // else was received from someone else.
+        let history = match self.get_txs_history() {
+            Ok(h) => h,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[privacy_audit] Transactions history retrieval failed: {e:?}"
+                )))
+            }
+        };
+        let mut change = HashMap::new();
+        for (tx_hash, _) in history {
+            let (_, _, tx) = self.get_tx_history_record(&tx_hash).await?;
+            for call in tx.calls.iter() {
+                if call.data.contract_id != *MONEY_CONTRACT_ID {
+                    continue
+                }
+
+                for coin in self.money_call_coins(call).await? {
+                    change.insert(coin.to_string(), tx_hash.clone());
+                }
+            }
+        }
+
+        // Addresses each transaction spent coins from
+        let mut inputs: HashMap<String, HashSet<String>> = HashMap::new();
+        for (coin, is_spent, spent_tx_hash) in coins.iter() {
+            if *is_spent && spent_tx_hash != "-" {
+                let address = PublicKey::from_secret(coin.secret).to_string();
+                inputs.entry(spent_tx_hash.clone()).or_default().insert(address);
+            }
+        }
+
+        let mut findings = vec![];
+        let mut payments: HashMap<String, usize> = HashMap::new();
+        let mut change_to_spender = HashSet::new();
+        for (coin, _, _) in coins.iter() {
+            let address = PublicKey::from_secret(coin.secret).to_string();
+
+            if let Some(tx_hash) = change.get(&coin.coin.to_string()) {
+                if inputs.get(tx_hash).is_some_and(|a| a.contains(&address)) {
+                    change_to_spender.insert((tx_hash.clone(), address));
+                }
+                continue
+            }
+
+            *payments.entry(address).or_default() += 1;
+
+            if coin.note.value > 0 && coin.note.value % ROUND_AMOUNT == 0 {
+                findings.push(Finding {
+                    severity: Severity::Low,
+                    description: format!(
+                        "Coin {} received the round amount {}",
+                        coin.coin,
+                        encode_base10(coin.note.value, BALANCE_BASE10_DECIMALS)
+                    ),
+                });
+            }
+        }
+
+        for (address, count) in payments {
+            if count > 1 {
+                findings.push(Finding {
+                    severity: Severity::Medium,
+                    description: format!("Address {address} received {count} payments"),
+                });
+            }
+        }
+
+        for (tx_hash, address) in change_to_spender {
+            findings.push(Finding {
+                severity: Severity::Medium,
+                description: format!(
+                    "Transaction {tx_hash} returned change to the address {address} it spent from"
+                ),
+            });
+        }
+
+        for (tx_hash, addresses) in inputs {
+            if addresses.len() > 1 {
+                findings.push(Finding {
+                    severity: Severity::High,
+                    description: format!(
+                        "Transaction {tx_hash} spent coins of {} different addresses, linking them",
+                        addresses.len()
+                    ),
+                });
+            }
+        }
+
+        findings.sort_by(|a, b| b.severity.cmp(&a.severity));
+
+        Ok(findings)
+    }
+}
@@ -0,0 +1,191 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    future::Future,
+    io::Cursor,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::Instant,
+};
+
+use log::{debug, info};
+use smol::lock::Mutex;
+
+use darkfi::{
+    zk::{proof::ProvingKey, vm::ZkCircuit, vm_heap::empty_witnesses},
+    zkas::ZkBinary,
+    Result,
+};
+
+use crate::Drk;
+
+/// Directory next to the wallet holding the cached proving keys
+pub const PROVING_KEYS_DIR: &str = "proving_keys";
+
+/// Proving keys already available to this process, mapped by the
+/// hash of their zkas bincode
+static PROVING_KEYS: OnceLock<Mutex<HashMap<blake3::Hash, ProvingKey>>> = OnceLock::new();
+
+/// Timings of the proving operations of this process
+pub static PERF_STATS: PerfStats = PerfStats::new();
+
+/// Number of times an operation ran and its total duration
+pub struct PerfCounter {
+    count: AtomicU64,
+    micros: AtomicU64,
+}
+
+impl PerfCounter {
+    const fn new() -> Self {
+        Self { count: AtomicU64::new(0), micros: AtomicU64::new(0) }
+    }
+
+    fn record(&self, started: Instant) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.micros.fetch_add(started.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Number of times the operation ran and its average duration
+    /// in milliseconds
+    pub fn get(&self) -> (u64, f64) {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return (0, 0.0)
+        }
+
+        (count, self.micros.load(Ordering::Relaxed) as f64 / count as f64 / 1000.0)
+    }
+}
+
+/// Timings of the proving operations of this process
+pub struct PerfStats {
+    /// Proving keys built from their circuit
+    pub key_builds: PerfCounter,
+    /// Proving keys loaded from the cache directory
+    pub key_loads: PerfCounter,
+    /// Contract calls proven
+    pub proofs: PerfCounter,
+}
+
+impl PerfStats {
+    const fn new() -> Self {
+        Self {
+            key_builds: PerfCounter::new(),
+            key_loads: PerfCounter::new(),
+            proofs: PerfCounter::new(),
+        }
+    }
+}
+
+impl fmt::Display for PerfStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counters = [
+            ("Key builds", &self.key_builds),
+            ("Key loads", &self.key_loads),
+            ("Proofs", &self.proofs),
+        ];
+        for (name, counter) in counters {
+            let (count, avg) = counter.get();
+            writeln!(f, "{name}: {count} (avg {avg:.2} ms)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Run a proving function on the blocking thread pool, so it neither
+/// stalls the async executor nor waits for other proofs to finish.
+/// It starts right away, the returned future only yields its result.
+pub fn prove<T, F>(f: F) -> impl Future<Output = T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    smol::unblock(move || {
+        let started = Instant::now();
+        let result = f();
+        PERF_STATS.proofs.record(started);
+        result
+    })
+}
+
+impl Drk {
+    /// Decode given zkas bincode and retrieve its circuit proving key.
+    /// Keys are built once per process, and kept in the proving keys
+    /// directory so later runs only have to load them.
+    pub async fn proving_key(&self, bincode: &[u8]) -> Result<(ZkBinary, ProvingKey)> {
+        let zkbin = ZkBinary::decode(bincode)?;
+        let hash = blake3::hash(bincode);
+
+        let keys = PROVING_KEYS.get_or_init(Default::default);
+        if let Some(pk) = keys.lock().await.get(&hash) {
+            return Ok((zkbin, pk.clone()))
+        }
+
+        let path = self.proving_keys_dir.as_ref().map(|dir| dir.join(hash.to_hex().as_str()));
+        let zkbin_ = zkbin.clone();
+        let pk = smol::unblock(move || load_or_build_proving_key(&zkbin_, path)).await?;
+        keys.lock().await.insert(hash, pk.clone());
+
+        Ok((zkbin, pk))
+    }
+}
+
+/// Load the proving key of given circuit from its cache file, building
+/// and caching it if that fails.
+fn load_or_build_proving_key(zkbin: &ZkBinary, path: Option<PathBuf>) -> Result<ProvingKey> {
+    let circuit = ZkCircuit::new(empty_witnesses(zkbin)?, zkbin);
+
+    if let Some(ref path) = path {
+        if let Ok(bytes) = fs::read(path) {
+            let started = Instant::now();
+            match ProvingKey::read(&mut Cursor::new(bytes), circuit.clone()) {
+                Ok(pk) => {
+                    PERF_STATS.key_loads.record(started);
+                    return Ok(pk)
+                }
+                Err(e) => debug!(
+                    target: "drk::proving",
+                    "Failed reading cached {} proving key: {e}", zkbin.namespace,
+                ),
+            }
+        }
+    }
+
+    info!(target: "drk::proving", "Building {} proving key", zkbin.namespace);
+    let started = Instant::now();
+    let pk = ProvingKey::build(zkbin.k, &circuit);
+    PERF_STATS.key_builds.record(started);
+
+    if let Some(path) = path {
+        let mut buf = vec![];
+        pk.write(&mut buf)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, buf)?;
+    }
+
+    Ok(pk)
+}
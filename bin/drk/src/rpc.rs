@@ -16,7 +16,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{sync::Arc, time::Instant};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 use url::Url;
 
@@ -25,9 +25,9 @@ use darkfi::{
     rpc::{
         client::RpcClient,
         jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResult},
-        util::JsonValue,
+        util::{decode_typed, json_typed, JsonValue, CONTRACT_ID_PREFIX, TX_HASH_PREFIX},
     },
-    system::{Publisher, StoppableTask},
+    system::{msleep, Publisher, StoppableTask},
     tx::Transaction,
     util::encoding::base64,
     Error, Result,
@@ -39,8 +39,10 @@ use darkfi_sdk::{
 use darkfi_serial::{deserialize_async, serialize_async};
 
 use crate::{
+    backup::BackupSchedule,
     error::{WalletDbError, WalletDbResult},
     money::{MONEY_INFO_COL_LAST_SCANNED_BLOCK, MONEY_INFO_TABLE},
+    schedule::PaymentBroadcast,
     Drk,
 };
 
@@ -49,11 +51,16 @@ impl Drk {
     /// new finalized blocks. Upon receiving them, all the transactions are
     /// scanned and we check if any of them call the money contract, and if
     /// the payments are intended for us. If so, we decrypt them and append
-    /// the metadata to our wallet.
+    /// the metadata to our wallet. If a backup schedule is provided, the
+    /// wallet is backed up after a scanned block whenever one is due.
+    /// Scheduled payments that are due get paid after each scanned block,
+    /// using the provided broadcast configuration.
     pub async fn subscribe_blocks(
         &self,
         endpoint: Url,
         ex: Arc<smol::Executor<'static>>,
+        mut backup: Option<BackupSchedule>,
+        payments: PaymentBroadcast,
     ) -> Result<()> {
         let rep = self
             .darkfid_daemon_request("blockchain.last_known_block", &JsonValue::Array(vec![]))
@@ -76,11 +83,19 @@ impl Drk {
             ))
         }
 
+        if let Some(ref backup) = backup {
+            match backup.last_success {
+                Some(ts) => println!("Wallet backups enabled, last backup at {ts}"),
+                None => println!("Wallet backups enabled, no backup made yet"),
+            }
+        }
+
         println!("Subscribing to receive notifications of incoming blocks");
         let publisher = Publisher::new();
         let subscription = publisher.clone().subscribe().await;
         let _publisher = publisher.clone();
         let _ex = ex.clone();
+        let payments_ex = ex.clone();
         StoppableTask::new().start(
             // Weird hack to prevent lifetimes hell
             async move {
@@ -160,6 +175,25 @@ impl Drk {
                             )))
                         }
                     }
+
+                    // A failed backup shouldn't stop us from following the chain,
+                    // so we just report it and retry after the next block.
+                    if let Some(ref mut backup) = backup {
+                        if backup.is_due() {
+                            match self.backup_wallet(backup) {
+                                Ok(path) => println!("Wallet backed up to {path:?}"),
+                                Err(e) => {
+                                    eprintln!("[subscribe_blocks] Wallet backup failed: {e:?}")
+                                }
+                            }
+                        }
+                    }
+
+                    // Same goes for scheduled payments, which get retried after
+                    // the next block if they failed.
+                    if let Err(e) = self.pay_due_schedules(&payments, payments_ex.clone()).await {
+                        eprintln!("[subscribe_blocks] Paying scheduled payments failed: {e:?}")
+                    }
                 }
 
                 JsonResult::Error(e) => {
@@ -193,7 +227,7 @@ impl Drk {
             for (i, call) in tx.calls.iter().enumerate() {
                 if call.data.contract_id == *MONEY_CONTRACT_ID {
                     println!("[scan_block] Found Money contract in call {i}");
-                    self.apply_tx_money_data(i, &tx.calls, &tx_hash).await?;
+                    self.apply_tx_money_data(i, &tx.calls, &tx_hash, block.header.height).await?;
                     continue
                 }
 
@@ -228,6 +262,11 @@ impl Drk {
             )))
         }
 
+        // Check if any of our invoices got paid
+        if let Err(e) = self.update_invoices().await {
+            eprintln!("[scan_block] Updating invoices failed: {e:?}");
+        }
+
         Ok(())
     }
 
@@ -277,7 +316,10 @@ impl Drk {
             }
 
             while height <= last {
-                println!("Requesting block {height}...");
+                // Report progress over the whole chain, so restored wallets
+                // rescanning from genesis can tell how far along they are
+                let progress = (height + 1) as f64 * 100.0 / (last + 1) as f64;
+                println!("Requesting block {height} of {last} ({progress:.1}%)...");
                 let block = match self.get_block_by_height(height).await {
                     Ok(r) => r,
                     Err(e) => {
@@ -312,15 +354,77 @@ impl Drk {
     }
 
     /// Broadcast a given transaction to darkfid and forward onto the network.
-    /// Returns the transaction ID upon success.
-    pub async fn broadcast_tx(&self, tx: &Transaction) -> Result<String> {
+    /// The transaction is also submitted to each of the `gossip` endpoints,
+    /// waiting `stagger` milliseconds between submissions, so a single
+    /// failing or censoring node can't drop it. The outcome for every
+    /// endpoint is recorded and can be retrieved with `get_tx_status`.
+    /// Returns the transaction ID if at least one endpoint accepted it.
+    pub async fn broadcast_tx(
+        &self,
+        tx: &Transaction,
+        endpoint: &Url,
+        gossip: &[Url],
+        stagger: u64,
+        ex: Arc<smol::Executor<'static>>,
+    ) -> Result<String> {
         println!("Broadcasting transaction...");
 
+        let tx_hash = tx.hash().to_string();
         let params =
             JsonValue::Array(vec![JsonValue::String(base64::encode(&serialize_async(tx).await))]);
-        let rep = self.darkfid_daemon_request("tx.broadcast", &params).await?;
 
-        let txid = rep.get::<String>().unwrap().clone();
+        let mut outcomes = vec![];
+        let rep = self.darkfid_daemon_request("tx.broadcast", &params).await;
+        outcomes.push((endpoint.clone(), rep));
+
+        for gossip_endpoint in gossip {
+            msleep(stagger).await;
+            println!("Submitting transaction to {gossip_endpoint}...");
+            let rep = match RpcClient::new(gossip_endpoint.clone(), ex.clone()).await {
+                Ok(rpc_client) => {
+                    let rep = rpc_client.request(JsonRequest::new("tx.broadcast", params.clone()));
+                    let rep = rep.await;
+                    rpc_client.stop().await;
+                    rep
+                }
+                Err(e) => Err(e),
+            };
+            outcomes.push((gossip_endpoint.clone(), rep));
+        }
+
+        let mut accepted = 0;
+        let mut last_error = None;
+        for (endpoint, rep) in outcomes {
+            let status = match rep {
+                Ok(rep) => {
+                    let rep_hash: Option<TransactionHash> =
+                        rep.get::<String>().and_then(|h| decode_typed(TX_HASH_PREFIX, h).ok());
+                    if rep_hash != Some(tx.hash()) {
+                        "Rejected: unexpected transaction ID".to_string()
+                    } else {
+                        accepted += 1;
+                        "Accepted".to_string()
+                    }
+                }
+                Err(e) => {
+                    let status = format!("Rejected: {e}");
+                    last_error = Some(e);
+                    status
+                }
+            };
+
+            if let Err(e) = self.insert_tx_broadcast_record(&tx_hash, &endpoint, &status) {
+                return Err(Error::DatabaseError(format!(
+                    "[broadcast_tx] Inserting transaction broadcast record failed: {e:?}"
+                )))
+            }
+        }
+
+        if accepted == 0 {
+            return Err(last_error.unwrap_or(Error::Custom(
+                "[broadcast_tx] No endpoint accepted the transaction".to_string(),
+            )))
+        }
 
         // Store transactions history record
         if let Err(e) = self.insert_tx_history_record(tx).await {
@@ -329,16 +433,15 @@ impl Drk {
             )))
         }
 
-        Ok(txid)
+        Ok(tx_hash)
     }
 
     /// Queries darkfid for a tx with given hash.
     pub async fn get_tx(&self, tx_hash: &TransactionHash) -> Result<Option<Transaction>> {
-        let tx_hash_str = tx_hash.to_string();
         match self
             .darkfid_daemon_request(
                 "blockchain.get_tx",
-                &JsonValue::Array(vec![JsonValue::String(tx_hash_str)]),
+                &JsonValue::Array(vec![json_typed(TX_HASH_PREFIX, tx_hash)]),
             )
             .await
         {
@@ -352,6 +455,26 @@ impl Drk {
         }
     }
 
+    /// Queries darkfid for the status of a given transaction. Returns the
+    /// status, along with the block height for confirmed transactions.
+    pub async fn get_node_tx_status(
+        &self,
+        tx_hash: &TransactionHash,
+    ) -> Result<(String, Option<u32>)> {
+        let rep = self
+            .darkfid_daemon_request(
+                "tx.get_status",
+                &JsonValue::Array(vec![json_typed(TX_HASH_PREFIX, tx_hash)]),
+            )
+            .await?;
+
+        let rep = rep.get::<HashMap<String, JsonValue>>().unwrap();
+        let status = rep["status"].get::<String>().unwrap().clone();
+        let height = rep.get("height").map(|h| *h.get::<f64>().unwrap() as u32);
+
+        Ok((status, height))
+    }
+
     /// Simulate the transaction with the state machine.
     pub async fn simulate_tx(&self, tx: &Transaction) -> Result<bool> {
         let tx_str = base64::encode(&serialize_async(tx).await);
@@ -368,7 +491,7 @@ impl Drk {
 
     /// Try to fetch zkas bincodes for the given `ContractId`.
     pub async fn lookup_zkas(&self, contract_id: &ContractId) -> Result<Vec<(String, Vec<u8>)>> {
-        let params = JsonValue::Array(vec![JsonValue::String(format!("{contract_id}"))]);
+        let params = JsonValue::Array(vec![json_typed(CONTRACT_ID_PREFIX, contract_id)]);
         let rep = self.darkfid_daemon_request("blockchain.lookup_zkas", &params).await?;
         let params = rep.get::<Vec<JsonValue>>().unwrap();
 
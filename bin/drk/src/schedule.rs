@@ -0,0 +1,380 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, sync::Arc};
+
+use rand::{rngs::OsRng, Rng};
+use rusqlite::types::Value;
+use url::Url;
+
+use darkfi::{
+    util::{
+        parse::{decode_base10, encode_base10},
+        time::Timestamp,
+    },
+    Error, Result,
+};
+use darkfi_money_contract::model::TokenId;
+use darkfi_sdk::crypto::PublicKey;
+use darkfi_serial::{deserialize_async, serialize_async};
+
+use crate::{
+    coin_selection::CoinSelection,
+    convert_named_params,
+    error::{WalletDbError, WalletDbResult},
+    money::BALANCE_BASE10_DECIMALS,
+    Drk,
+};
+
+// Wallet SQL table constant names. These have to represent the `wallet.sql`
+// SQL schema.
+const WALLET_SCHEDULES_TABLE: &str = "scheduled_payments";
+const WALLET_SCHEDULES_COL_SCHEDULE_ID: &str = "schedule_id";
+const WALLET_SCHEDULES_COL_RECIPIENT: &str = "recipient";
+const WALLET_SCHEDULES_COL_TOKEN_ID: &str = "token_id";
+const WALLET_SCHEDULES_COL_AMOUNT: &str = "amount";
+const WALLET_SCHEDULES_COL_INTERVAL: &str = "interval";
+const WALLET_SCHEDULES_COL_NEXT_PAYMENT: &str = "next_payment";
+const WALLET_SCHEDULES_COL_END_TIME: &str = "end_time";
+const WALLET_SCHEDULES_COL_LAST_RESULT: &str = "last_result";
+const WALLET_SCHEDULE_TXS_TABLE: &str = "scheduled_payments_history";
+const WALLET_SCHEDULE_TXS_COL_TX_HASH: &str = "transaction_hash";
+const WALLET_SCHEDULE_TXS_COL_SCHEDULE_ID: &str = "schedule_id";
+
+/// Structure representing a recurring payment registered in the wallet.
+#[derive(Clone, Debug)]
+pub struct ScheduledPayment {
+    /// Schedule ID
+    pub id: u32,
+    /// Address payments are sent to
+    pub recipient: PublicKey,
+    /// Token to pay with
+    pub token_id: TokenId,
+    /// Amount of each payment
+    pub amount: u64,
+    /// Seconds between two payments
+    pub interval: u64,
+    /// UNIX timestamp at which the next payment is due
+    pub next_payment: u64,
+    /// UNIX timestamp after which no more payments are made, 0 for never
+    pub end: u64,
+    /// Outcome of the last payment attempt
+    pub last_result: String,
+}
+
+impl ScheduledPayment {
+    /// Returns true if the schedule won't make any more payments
+    pub fn is_finished(&self) -> bool {
+        self.end != 0 && self.next_payment > self.end
+    }
+
+    /// Returns true if a payment is due at `now`
+    fn is_due(&self, now: u64) -> bool {
+        !self.is_finished() && self.next_payment <= now
+    }
+}
+
+/// Where and how the transactions of due scheduled payments get broadcast
+#[derive(Clone, Debug)]
+pub struct PaymentBroadcast {
+    /// darkfid JSON-RPC endpoint
+    pub endpoint: Url,
+    /// Additional darkfid JSON-RPC endpoints transactions are broadcast to
+    pub gossip: Vec<Url>,
+    /// Milliseconds to wait between broadcasts to each endpoint
+    pub stagger: u64,
+    /// Coin selection strategy for the payments
+    pub strategy: CoinSelection,
+}
+
+impl Drk {
+    /// Register a recurring payment of `amount` of the provided token to
+    /// `recipient`, made every `interval` seconds. The first payment is
+    /// due after `delay` seconds, and payments stop after `duration`
+    /// seconds, or never if it is 0. Returns the created schedule.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn add_schedule(
+        &self,
+        recipient: PublicKey,
+        token_id: TokenId,
+        amount: &str,
+        interval: u64,
+        delay: u64,
+        duration: u64,
+    ) -> Result<ScheduledPayment> {
+        let amount = decode_base10(amount, BALANCE_BASE10_DECIMALS, false)?;
+        if amount == 0 {
+            return Err(Error::Custom("Payment amount must be greater than zero".to_string()))
+        }
+        if interval == 0 {
+            return Err(Error::Custom("Payment interval must be greater than zero".to_string()))
+        }
+
+        let id: u32 = OsRng.gen();
+        let now = Timestamp::current_time().inner();
+        let next_payment = now + delay;
+        let end = if duration == 0 { 0 } else { now + duration };
+        let last_result = "-".to_string();
+
+        let recipient_bytes = serialize_async(&recipient).await;
+        let token_id_bytes = serialize_async(&token_id).await;
+        let amount_bytes = serialize_async(&amount).await;
+        if let Err(e) = self.wallet.insert(
+            WALLET_SCHEDULES_TABLE,
+            convert_named_params! {
+                (WALLET_SCHEDULES_COL_SCHEDULE_ID, id),
+                (WALLET_SCHEDULES_COL_RECIPIENT, recipient_bytes),
+                (WALLET_SCHEDULES_COL_TOKEN_ID, token_id_bytes),
+                (WALLET_SCHEDULES_COL_AMOUNT, amount_bytes),
+                (WALLET_SCHEDULES_COL_INTERVAL, interval),
+                (WALLET_SCHEDULES_COL_NEXT_PAYMENT, next_payment),
+                (WALLET_SCHEDULES_COL_END_TIME, end),
+                (WALLET_SCHEDULES_COL_LAST_RESULT, last_result),
+            },
+            false,
+        ) {
+            return Err(Error::DatabaseError(format!(
+                "[add_schedule] Scheduled payment insertion failed: {e:?}"
+            )))
+        }
+
+        Ok(ScheduledPayment {
+            id,
+            recipient,
+            token_id,
+            amount,
+            interval,
+            next_payment,
+            end,
+            last_result,
+        })
+    }
+
+    /// Fetch all scheduled payment records from the wallet.
+    pub async fn get_schedules(&self) -> Result<Vec<ScheduledPayment>> {
+        let rows = match self.wallet.query_multiple(WALLET_SCHEDULES_TABLE, &[], &[]) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[get_schedules] Scheduled payments retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        let mut schedules = Vec::with_capacity(rows.len());
+        for row in rows {
+            schedules.push(self.parse_schedule_record(&row).await?);
+        }
+
+        Ok(schedules)
+    }
+
+    /// Remove a scheduled payment record from the wallet, cancelling
+    /// its future payments.
+    pub fn cancel_schedule(&self, id: u32) -> WalletDbResult<()> {
+        self.wallet.delete(
+            WALLET_SCHEDULES_TABLE,
+            convert_named_params! {(WALLET_SCHEDULES_COL_SCHEDULE_ID, id)},
+        )
+    }
+
+    /// Make the payments of all the schedules that are due, broadcasting
+    /// their transactions. Each schedule makes at most one payment per
+    /// call: payments missed while the wallet wasn't running are not made
+    /// up for, the schedule just moves on to its next due time. Failed
+    /// payments are reported, recorded in the schedule, and retried on
+    /// the next call. Successful ones get their transaction history record
+    /// tagged with the schedule ID.
+    pub async fn pay_due_schedules(
+        &self,
+        broadcast: &PaymentBroadcast,
+        ex: Arc<smol::Executor<'static>>,
+    ) -> Result<()> {
+        let now = Timestamp::current_time().inner();
+        for schedule in self.get_schedules().await? {
+            if !schedule.is_due(now) {
+                continue
+            }
+
+            let amount = encode_base10(schedule.amount, BALANCE_BASE10_DECIMALS);
+            let (next_payment, last_result) = match self
+                .pay_schedule(&schedule, &amount, broadcast, ex.clone())
+                .await
+            {
+                Ok(tx_hash) => {
+                    println!(
+                            "[pay_due_schedules] Scheduled payment {} of {amount} to {} sent in transaction {tx_hash}",
+                            schedule.id, schedule.recipient,
+                        );
+                    if let Err(e) = self.tag_schedule_payment(&tx_hash, schedule.id) {
+                        eprintln!(
+                            "[pay_due_schedules] Tagging transaction {tx_hash} as scheduled payment failed: {e:?}"
+                        );
+                    }
+                    let periods = (now - schedule.next_payment) / schedule.interval + 1;
+                    (
+                        schedule.next_payment + periods * schedule.interval,
+                        format!("Paid: {tx_hash}"),
+                    )
+                }
+                Err(e) => {
+                    eprintln!(
+                        "[pay_due_schedules] Scheduled payment {} of {amount} to {} failed: {e:?}",
+                        schedule.id, schedule.recipient,
+                    );
+                    (schedule.next_payment, format!("Failed: {e}"))
+                }
+            };
+
+            if let Err(e) = self.wallet.update(
+                WALLET_SCHEDULES_TABLE,
+                convert_named_params! {
+                    (WALLET_SCHEDULES_COL_NEXT_PAYMENT, next_payment),
+                    (WALLET_SCHEDULES_COL_LAST_RESULT, last_result),
+                },
+                convert_named_params! {(WALLET_SCHEDULES_COL_SCHEDULE_ID, schedule.id)},
+            ) {
+                return Err(Error::DatabaseError(format!(
+                    "[pay_due_schedules] Scheduled payment update failed: {e:?}"
+                )))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Auxiliary function to build, mark as spent and broadcast the
+    /// transaction of a scheduled payment. Returns the transaction hash.
+    async fn pay_schedule(
+        &self,
+        schedule: &ScheduledPayment,
+        amount: &str,
+        broadcast: &PaymentBroadcast,
+        ex: Arc<smol::Executor<'static>>,
+    ) -> Result<String> {
+        let tx = self
+            .transfer(
+                amount,
+                schedule.token_id,
+                schedule.recipient,
+                None,
+                None,
+                vec![],
+                false,
+                broadcast.strategy,
+                None,
+            )
+            .await?;
+
+        if !self.simulate_tx(&tx).await? {
+            return Err(Error::Custom("Payment transaction simulation failed".to_string()))
+        }
+        self.mark_tx_spend(&tx).await?;
+        self.broadcast_tx(&tx, &broadcast.endpoint, &broadcast.gossip, broadcast.stagger, ex).await
+    }
+
+    /// Tag the history record of a transaction as a payment made by the
+    /// given schedule.
+    fn tag_schedule_payment(&self, tx_hash: &str, id: u32) -> WalletDbResult<()> {
+        self.wallet.insert(
+            WALLET_SCHEDULE_TXS_TABLE,
+            convert_named_params! {
+                (WALLET_SCHEDULE_TXS_COL_TX_HASH, tx_hash),
+                (WALLET_SCHEDULE_TXS_COL_SCHEDULE_ID, id),
+            },
+            true,
+        )
+    }
+
+    /// Fetch the IDs of the schedules that made transactions of the
+    /// history, by transaction hash. Schedules that got cancelled since
+    /// are still included.
+    pub fn get_schedule_payments(&self) -> WalletDbResult<HashMap<String, u32>> {
+        let rows = self.wallet.query_multiple(WALLET_SCHEDULE_TXS_TABLE, &[], &[])?;
+
+        let mut ret = HashMap::with_capacity(rows.len());
+        for row in rows {
+            let Value::Text(ref tx_hash) = row[0] else {
+                return Err(WalletDbError::ParseColumnValueError)
+            };
+
+            let Value::Integer(id) = row[1] else {
+                return Err(WalletDbError::ParseColumnValueError)
+            };
+            let Ok(id) = u32::try_from(id) else {
+                return Err(WalletDbError::ParseColumnValueError)
+            };
+
+            ret.insert(tx_hash.clone(), id);
+        }
+
+        Ok(ret)
+    }
+
+    /// Auxiliary function to parse a `WALLET_SCHEDULES_TABLE` record.
+    async fn parse_schedule_record(&self, row: &[Value]) -> Result<ScheduledPayment> {
+        let Value::Integer(id) = row[0] else {
+            return Err(Error::ParseFailed("[parse_schedule_record] Schedule ID parsing failed"))
+        };
+        let Ok(id) = u32::try_from(id) else {
+            return Err(Error::ParseFailed("[parse_schedule_record] Schedule ID parsing failed"))
+        };
+
+        let Value::Blob(ref recipient_bytes) = row[1] else {
+            return Err(Error::ParseFailed("[parse_schedule_record] Recipient bytes parsing failed"))
+        };
+        let recipient: PublicKey = deserialize_async(recipient_bytes).await?;
+
+        let Value::Blob(ref token_id_bytes) = row[2] else {
+            return Err(Error::ParseFailed("[parse_schedule_record] Token ID bytes parsing failed"))
+        };
+        let token_id: TokenId = deserialize_async(token_id_bytes).await?;
+
+        let Value::Blob(ref amount_bytes) = row[3] else {
+            return Err(Error::ParseFailed("[parse_schedule_record] Amount bytes parsing failed"))
+        };
+        let amount: u64 = deserialize_async(amount_bytes).await?;
+
+        let mut times = [0; 3];
+        for (i, time) in times.iter_mut().enumerate() {
+            let Value::Integer(value) = row[4 + i] else {
+                return Err(Error::ParseFailed("[parse_schedule_record] Timing parsing failed"))
+            };
+            let Ok(value) = u64::try_from(value) else {
+                return Err(Error::ParseFailed("[parse_schedule_record] Timing parsing failed"))
+            };
+            *time = value;
+        }
+        let [interval, next_payment, end] = times;
+
+        let Value::Text(ref last_result) = row[7] else {
+            return Err(Error::ParseFailed("[parse_schedule_record] Last result parsing failed"))
+        };
+
+        Ok(ScheduledPayment {
+            id,
+            recipient,
+            token_id,
+            amount,
+            interval,
+            next_payment,
+            end,
+            last_result: last_result.clone(),
+        })
+    }
+}
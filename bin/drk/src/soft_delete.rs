@@ -0,0 +1,327 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{fmt, str::FromStr};
+
+use rusqlite::types::{ToSql, Value};
+
+use darkfi::{util::time::Timestamp, Error, Result};
+use darkfi_sdk::crypto::PublicKey;
+use darkfi_serial::deserialize_async;
+
+use crate::{
+    contacts::{
+        WALLET_ADDRESS_BOOK_COL_ADDRESS, WALLET_ADDRESS_BOOK_COL_NAME, WALLET_ADDRESS_BOOK_TABLE,
+    },
+    convert_named_params,
+    invoice::{
+        WALLET_INVOICES_COL_AMOUNT, WALLET_INVOICES_COL_EXPIRY, WALLET_INVOICES_COL_INVOICE_ID,
+        WALLET_INVOICES_COL_MEMO, WALLET_INVOICES_COL_RECIPIENT, WALLET_INVOICES_COL_STATUS,
+        WALLET_INVOICES_COL_TOKEN_ID, WALLET_INVOICES_TABLE,
+    },
+    money::{
+        MONEY_KEYS_ARCHIVED_TABLE, MONEY_KEYS_COL_IS_DEFAULT, MONEY_KEYS_COL_KEY_ID,
+        MONEY_KEYS_COL_PUBLIC, MONEY_KEYS_COL_SECRET, MONEY_KEYS_TABLE,
+    },
+    Drk,
+};
+
+// Wallet SQL table constant names. These have to represent the `wallet.sql`
+// SQL schema.
+pub const WALLET_ADDRESS_BOOK_ARCHIVED_TABLE: &str = "address_book_archived";
+pub const WALLET_INVOICES_ARCHIVED_TABLE: &str = "invoices_archived";
+
+// Columns shared by all archive tables, next to the ones of the archived record
+pub const ARCHIVED_COL_ARCHIVE_ID: &str = "archive_id";
+pub const ARCHIVED_COL_ARCHIVED_AT: &str = "archived_at";
+
+/// Kinds of wallet records that are archived instead of deleted
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArchivedKind {
+    Key,
+    Contact,
+    Invoice,
+}
+
+impl ArchivedKind {
+    /// All the kinds of archived records
+    pub const ALL: [Self; 3] = [Self::Key, Self::Contact, Self::Invoice];
+
+    /// Table holding the live records of this kind, and its archive table
+    fn tables(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::Key => (MONEY_KEYS_TABLE.as_str(), MONEY_KEYS_ARCHIVED_TABLE.as_str()),
+            Self::Contact => (WALLET_ADDRESS_BOOK_TABLE, WALLET_ADDRESS_BOOK_ARCHIVED_TABLE),
+            Self::Invoice => (WALLET_INVOICES_TABLE, WALLET_INVOICES_ARCHIVED_TABLE),
+        }
+    }
+
+    /// Column identifying a live record of this kind
+    fn id_column(&self) -> &'static str {
+        match self {
+            Self::Key => MONEY_KEYS_COL_KEY_ID,
+            Self::Contact => WALLET_ADDRESS_BOOK_COL_NAME,
+            Self::Invoice => WALLET_INVOICES_COL_INVOICE_ID,
+        }
+    }
+
+    /// Column shown when listing archived records of this kind
+    fn label_column(&self) -> &'static str {
+        match self {
+            Self::Key => MONEY_KEYS_COL_PUBLIC,
+            _ => self.id_column(),
+        }
+    }
+
+    /// Columns copied into the archive table
+    fn archived_columns(&self) -> &'static [&'static str] {
+        match self {
+            Self::Key => &[
+                MONEY_KEYS_COL_KEY_ID,
+                MONEY_KEYS_COL_IS_DEFAULT,
+                MONEY_KEYS_COL_PUBLIC,
+                MONEY_KEYS_COL_SECRET,
+            ],
+            Self::Contact => &[WALLET_ADDRESS_BOOK_COL_NAME, WALLET_ADDRESS_BOOK_COL_ADDRESS],
+            Self::Invoice => &[
+                WALLET_INVOICES_COL_INVOICE_ID,
+                WALLET_INVOICES_COL_RECIPIENT,
+                WALLET_INVOICES_COL_TOKEN_ID,
+                WALLET_INVOICES_COL_AMOUNT,
+                WALLET_INVOICES_COL_MEMO,
+                WALLET_INVOICES_COL_EXPIRY,
+                WALLET_INVOICES_COL_STATUS,
+            ],
+        }
+    }
+
+    /// Columns copied back on restore. Keys get a fresh key ID, since
+    /// their old one may have been reused in the meantime.
+    fn restored_columns(&self) -> &'static [&'static str] {
+        match self {
+            Self::Key => &[MONEY_KEYS_COL_IS_DEFAULT, MONEY_KEYS_COL_PUBLIC, MONEY_KEYS_COL_SECRET],
+            _ => self.archived_columns(),
+        }
+    }
+}
+
+impl fmt::Display for ArchivedKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Key => "key",
+            Self::Contact => "contact",
+            Self::Invoice => "invoice",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl FromStr for ArchivedKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "key" => Ok(Self::Key),
+            "contact" => Ok(Self::Contact),
+            "invoice" => Ok(Self::Invoice),
+            _ => Err(Error::Custom(format!("Unknown archived record kind: {s}"))),
+        }
+    }
+}
+
+impl Drk {
+    /// Archive the wallet key with the given key ID. The key is no longer
+    /// used for scanning, so new payments to its address are not picked
+    /// up, while coins already received with it remain spendable. The
+    /// default key can't be archived.
+    pub fn archive_key(&self, key_id: u64) -> Result<()> {
+        let row = match self.wallet.query_single(
+            &MONEY_KEYS_TABLE,
+            &[MONEY_KEYS_COL_IS_DEFAULT],
+            convert_named_params! {(MONEY_KEYS_COL_KEY_ID, key_id)},
+        ) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[archive_key] Key retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        if row[0] == Value::Integer(1) {
+            return Err(Error::Custom(
+                "The default key can't be archived, set another default address first".to_string(),
+            ))
+        }
+
+        self.archive_record(ArchivedKind::Key, &key_id)
+    }
+
+    /// Archive the address book contact with the given name.
+    pub fn archive_contact(&self, name: &str) -> Result<()> {
+        self.archive_record(ArchivedKind::Contact, &name)
+    }
+
+    /// Archive the invoice with the given invoice ID.
+    pub fn archive_invoice(&self, invoice_id: &str) -> Result<()> {
+        self.archive_record(ArchivedKind::Invoice, &invoice_id)
+    }
+
+    /// Auxiliary function to move the record of given kind and ID into
+    /// its archive table.
+    fn archive_record(&self, kind: ArchivedKind, id: &dyn ToSql) -> Result<()> {
+        let (table, archived_table) = kind.tables();
+        let id_column = kind.id_column();
+
+        if let Err(e) =
+            self.wallet.query_single(table, &[id_column], convert_named_params! {(id_column, id)})
+        {
+            return Err(Error::DatabaseError(format!(
+                "[archive_record] {kind} retrieval failed: {e:?}"
+            )))
+        }
+
+        let columns = kind.archived_columns().join(", ");
+        let query = format!(
+            "INSERT INTO {} ({}, {}) SELECT {}, ?1 FROM {} WHERE {} = ?2;",
+            archived_table, columns, ARCHIVED_COL_ARCHIVED_AT, columns, table, id_column,
+        );
+        let archived_at = Timestamp::current_time().inner();
+        if let Err(e) = self.wallet.exec_sql(&query, rusqlite::params![archived_at, id]) {
+            return Err(Error::DatabaseError(format!(
+                "[archive_record] Archiving {kind} failed: {e:?}"
+            )))
+        }
+
+        let query = format!("DELETE FROM {table} WHERE {id_column} = ?1;");
+        if let Err(e) = self.wallet.exec_sql(&query, rusqlite::params![id]) {
+            return Err(Error::DatabaseError(format!(
+                "[archive_record] Removing archived {kind} failed: {e:?}"
+            )))
+        }
+
+        Ok(())
+    }
+
+    /// Fetch all archived records of given kind, as their archive ID,
+    /// label and archival timestamp. Keys are labelled by their address,
+    /// contacts by their name and invoices by their invoice ID.
+    pub async fn get_archived(&self, kind: ArchivedKind) -> Result<Vec<(u64, String, u64)>> {
+        let (_, archived_table) = kind.tables();
+        let rows = match self.wallet.query_multiple(
+            archived_table,
+            &[ARCHIVED_COL_ARCHIVE_ID, kind.label_column(), ARCHIVED_COL_ARCHIVED_AT],
+            &[],
+        ) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[get_archived] Archived {kind} retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        let mut records = Vec::with_capacity(rows.len());
+        for row in rows {
+            let Value::Integer(archive_id) = row[0] else {
+                return Err(Error::ParseFailed("[get_archived] Archive ID parsing failed"))
+            };
+            let Ok(archive_id) = u64::try_from(archive_id) else {
+                return Err(Error::ParseFailed("[get_archived] Archive ID parsing failed"))
+            };
+
+            let label = match row[1] {
+                Value::Blob(ref public_bytes) if kind == ArchivedKind::Key => {
+                    let public_key: PublicKey = deserialize_async(public_bytes).await?;
+                    public_key.to_string()
+                }
+                Value::Text(ref label) => label.clone(),
+                _ => return Err(Error::ParseFailed("[get_archived] Label parsing failed")),
+            };
+
+            let Value::Integer(archived_at) = row[2] else {
+                return Err(Error::ParseFailed("[get_archived] Archival timestamp parsing failed"))
+            };
+            let Ok(archived_at) = u64::try_from(archived_at) else {
+                return Err(Error::ParseFailed("[get_archived] Archival timestamp parsing failed"))
+            };
+
+            records.push((archive_id, label, archived_at));
+        }
+
+        Ok(records)
+    }
+
+    /// Move the archived record of given kind and archive ID back into
+    /// the wallet. Contacts and invoices whose name or ID got reused in
+    /// the meantime can't be restored until the newer record is removed.
+    pub fn restore_archived(&self, kind: ArchivedKind, archive_id: u64) -> Result<()> {
+        let (table, archived_table) = kind.tables();
+
+        if let Err(e) = self.wallet.query_single(
+            archived_table,
+            &[ARCHIVED_COL_ARCHIVE_ID],
+            convert_named_params! {(ARCHIVED_COL_ARCHIVE_ID, archive_id)},
+        ) {
+            return Err(Error::DatabaseError(format!(
+                "[restore_archived] Archived {kind} retrieval failed: {e:?}"
+            )))
+        }
+
+        let columns = kind.restored_columns().join(", ");
+        let query = format!(
+            "INSERT INTO {} ({}) SELECT {} FROM {} WHERE {} = ?1;",
+            table, columns, columns, archived_table, ARCHIVED_COL_ARCHIVE_ID,
+        );
+        if let Err(e) = self.wallet.exec_sql(&query, rusqlite::params![archive_id]) {
+            return Err(Error::DatabaseError(format!(
+                "[restore_archived] Restoring {kind} failed: {e:?}"
+            )))
+        }
+
+        let query = format!("DELETE FROM {archived_table} WHERE {ARCHIVED_COL_ARCHIVE_ID} = ?1;");
+        if let Err(e) = self.wallet.exec_sql(&query, rusqlite::params![archive_id]) {
+            return Err(Error::DatabaseError(format!(
+                "[restore_archived] Removing restored {kind} failed: {e:?}"
+            )))
+        }
+
+        Ok(())
+    }
+
+    /// Permanently delete all archived records of given kind, or of all
+    /// kinds if none is given. Purged keys can't be recovered.
+    pub fn purge_archived(&self, kind: Option<ArchivedKind>) -> Result<()> {
+        let kinds = match kind {
+            Some(kind) => vec![kind],
+            None => ArchivedKind::ALL.to_vec(),
+        };
+
+        for kind in kinds {
+            let (_, archived_table) = kind.tables();
+            let query = format!("DELETE FROM {archived_table};");
+            if let Err(e) = self.wallet.exec_sql(&query, &[]) {
+                return Err(Error::DatabaseError(format!(
+                    "[purge_archived] Purging archived {kind} failed: {e:?}"
+                )))
+            }
+        }
+
+        Ok(())
+    }
+}
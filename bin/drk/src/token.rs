@@ -307,7 +307,7 @@ impl Drk {
 
         let tree = self.get_money_tree().await?;
         let (fee_call, fee_proofs, fee_secrets) =
-            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None).await?;
+            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None, None).await?;
 
         // Append the fee call to the transaction
         tx_builder.append(ContractCallLeaf { call: fee_call, proofs: fee_proofs }, vec![])?;
@@ -388,7 +388,7 @@ impl Drk {
 
         let tree = self.get_money_tree().await?;
         let (fee_call, fee_proofs, fee_secrets) =
-            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None).await?;
+            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, None, None).await?;
 
         // Append the fee call to the transaction
         tx_builder.append(ContractCallLeaf { call: fee_call, proofs: fee_proofs }, vec![])?;
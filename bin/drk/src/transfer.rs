@@ -16,28 +16,85 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use smol::future::try_zip;
+
 use darkfi::{
     tx::{ContractCallLeaf, Transaction, TransactionBuilder},
     util::parse::{decode_base10, encode_base10},
-    zk::{proof::ProvingKey, vm::ZkCircuit, vm_heap::empty_witnesses},
+    zk::proof::ProvingKey,
     zkas::ZkBinary,
     Error, Result,
 };
 use darkfi_money_contract::{
-    client::transfer_v1::make_transfer_call, model::TokenId, MoneyFunction,
-    MONEY_CONTRACT_ZKAS_BURN_NS_V1, MONEY_CONTRACT_ZKAS_FEE_NS_V1, MONEY_CONTRACT_ZKAS_MINT_NS_V1,
+    client::{
+        transfer_v1::{make_transfer_call, make_transfer_many_call},
+        OwnCoin,
+    },
+    model::TokenId,
+    MoneyFunction, MONEY_CONTRACT_ZKAS_BURN_NS_V1, MONEY_CONTRACT_ZKAS_FEE_NS_V1,
+    MONEY_CONTRACT_ZKAS_MINT_NS_V1,
 };
 use darkfi_sdk::{
-    crypto::{contract_id::MONEY_CONTRACT_ID, FuncId, Keypair, PublicKey},
+    crypto::{contract_id::MONEY_CONTRACT_ID, FuncId, Keypair, MerkleTree, PublicKey, SecretKey},
     pasta::pallas,
-    tx::ContractCall,
+    tx::{ContractCall, TransactionHash},
 };
-use darkfi_serial::AsyncEncodable;
+use darkfi_serial::{deserialize_async, AsyncEncodable};
+
+use crate::{coin_selection::CoinSelection, money::BALANCE_BASE10_DECIMALS, proving::prove, Drk};
 
-use crate::{money::BALANCE_BASE10_DECIMALS, Drk};
+/// Outcome of a successfully simulated payment transaction
+pub struct TransferSimulation {
+    /// Hash the transaction would have
+    pub tx_hash: TransactionHash,
+    /// Coins the transaction spends, including the one paying the fee
+    pub inputs: Vec<OwnCoin>,
+    /// Fee the transaction pays
+    pub fee: u64,
+}
+
+/// Circuits and proving keys of the `Money` calls a payment
+/// transaction is made of.
+struct MoneyCircuits {
+    mint_zkbin: ZkBinary,
+    mint_pk: ProvingKey,
+    burn_zkbin: ZkBinary,
+    burn_pk: ProvingKey,
+    fee_zkbin: ZkBinary,
+    fee_pk: ProvingKey,
+}
+
+/// A built, fee-less transaction of one or more transfer calls, along
+/// with everything needed to attach the fee call to it.
+struct TransferCall {
+    tx_builder: TransactionBuilder,
+    signature_secrets: Vec<Vec<SecretKey>>,
+    spent_coins: Vec<OwnCoin>,
+    tree: MerkleTree,
+    fee_pk: ProvingKey,
+    fee_zkbin: ZkBinary,
+}
+
+impl TransferCall {
+    /// Build the fee-less transaction, signed with the transfer keys.
+    fn build_tx(&mut self) -> Result<Transaction> {
+        let mut tx = self.tx_builder.build()?;
+        for secrets in &self.signature_secrets {
+            let sigs = tx.create_sigs(secrets)?;
+            tx.signatures.push(sigs);
+        }
+        Ok(tx)
+    }
+}
 
 impl Drk {
-    /// Create a payment transaction. Returns the transaction object on success.
+    /// Create a payment transaction, spending coins in the order given by
+    /// the coin selection `strategy`. The `memo` is encrypted along with
+    /// the recipient's output, so only they can read it. If `fee` is
+    /// provided, it is paid instead of the estimated one, as long as it
+    /// covers it.
+    /// Returns the transaction object on success.
+    #[allow(clippy::too_many_arguments)]
     pub async fn transfer(
         &self,
         amount: &str,
@@ -45,10 +102,176 @@ impl Drk {
         recipient: PublicKey,
         spend_hook: Option<FuncId>,
         user_data: Option<pallas::Base>,
+        memo: Vec<u8>,
         half_split: bool,
+        strategy: CoinSelection,
+        fee: Option<u64>,
+    ) -> Result<Transaction> {
+        let transfer = self
+            .transfer_call(
+                amount, token_id, recipient, spend_hook, user_data, memo, half_split, strategy,
+            )
+            .await?;
+
+        self.attach_fee_call(transfer, fee).await
+    }
+
+    /// Create a single transaction paying all given `outputs`, as
+    /// `(recipient, token, amount)` tuples. Outputs of the same token are
+    /// paid by one `Money::Transfer` call, spending coins in the order
+    /// given by the coin selection `strategy`, and the whole transaction
+    /// pays a single fee. If `fee` is provided, it is paid instead of the
+    /// estimated one, as long as it covers it.
+    /// Returns the transaction object on success.
+    pub async fn transfer_many(
+        &self,
+        outputs: Vec<(PublicKey, TokenId, String)>,
+        strategy: CoinSelection,
+        fee: Option<u64>,
     ) -> Result<Transaction> {
+        let transfer = self.transfer_many_calls(outputs, strategy).await?;
+        self.attach_fee_call(transfer, fee).await
+    }
+
+    /// Estimate the fee a [`Drk::transfer_many`] transaction with the
+    /// given outputs would pay, without creating the fee call.
+    pub async fn estimate_transfer_many_fee(
+        &self,
+        outputs: Vec<(PublicKey, TokenId, String)>,
+        strategy: CoinSelection,
+    ) -> Result<u64> {
+        let mut transfer = self.transfer_many_calls(outputs, strategy).await?;
+        let tx = transfer.build_tx()?;
+        self.estimate_fee(&tx).await
+    }
+
+    /// Append the fee call to a fee-less payment transaction and build
+    /// the final transaction, signed with all necessary keys.
+    async fn attach_fee_call(
+        &self,
+        mut transfer: TransferCall,
+        fee: Option<u64>,
+    ) -> Result<Transaction> {
+        // We first have to execute the fee-less tx to gather its used gas, and then we feed
+        // it into the fee-creating function.
+        // We also tell it about any spent coins so we don't accidentally reuse them in the
+        // fee call.
+        let tx = transfer.build_tx()?;
+        let (fee_call, fee_proofs, fee_secrets) = self
+            .append_fee_call(
+                &tx,
+                &transfer.tree,
+                &transfer.fee_pk,
+                &transfer.fee_zkbin,
+                Some(&transfer.spent_coins),
+                fee,
+            )
+            .await?;
+
+        // Append the fee call to the transaction
+        transfer
+            .tx_builder
+            .append(ContractCallLeaf { call: fee_call, proofs: fee_proofs }, vec![])?;
+
+        // Now build the actual transaction and sign it with all necessary keys.
+        let mut tx = transfer.build_tx()?;
+        let sigs = tx.create_sigs(&fee_secrets)?;
+        tx.signatures.push(sigs);
+
+        Ok(tx)
+    }
+
+    /// Create a payment transaction like [`Drk::transfer`] and run it
+    /// through darkfid's state transition without broadcasting it.
+    /// Returns what the transaction would do on success.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn simulate_transfer(
+        &self,
+        amount: &str,
+        token_id: TokenId,
+        recipient: PublicKey,
+        spend_hook: Option<FuncId>,
+        user_data: Option<pallas::Base>,
+        memo: Vec<u8>,
+        half_split: bool,
+        strategy: CoinSelection,
+        fee: Option<u64>,
+    ) -> Result<TransferSimulation> {
+        let tx = self
+            .transfer(
+                amount, token_id, recipient, spend_hook, user_data, memo, half_split, strategy, fee,
+            )
+            .await?;
+
+        if let Err(e) = self.simulate_tx(&tx).await {
+            return Err(Error::Custom(format!("Transaction simulation failed: {e:?}")))
+        }
+
+        // Grab the nullifiers of the coins the transaction spends, along
+        // with the fee it pays
+        let mut nullifiers = vec![];
+        let mut fee = 0;
+        for call in &tx.calls {
+            if call.data.contract_id != *MONEY_CONTRACT_ID {
+                continue
+            }
+
+            if call.data.data[0] == MoneyFunction::FeeV1 as u8 {
+                fee = deserialize_async(&call.data.data[1..9]).await?;
+            }
+
+            nullifiers.extend_from_slice(&self.money_call_nullifiers(call).await?);
+        }
+
+        let inputs = self
+            .get_coins(false)
+            .await?
+            .into_iter()
+            .map(|(coin, _, _)| coin)
+            .filter(|coin| nullifiers.contains(&coin.nullifier()))
+            .collect();
+
+        Ok(TransferSimulation { tx_hash: tx.hash(), inputs, fee })
+    }
+
+    /// Estimate the fee a payment transaction with the given parameters
+    /// would pay, without creating the fee call.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn estimate_transfer_fee(
+        &self,
+        amount: &str,
+        token_id: TokenId,
+        recipient: PublicKey,
+        spend_hook: Option<FuncId>,
+        user_data: Option<pallas::Base>,
+        memo: Vec<u8>,
+        half_split: bool,
+        strategy: CoinSelection,
+    ) -> Result<u64> {
+        let mut transfer = self
+            .transfer_call(
+                amount, token_id, recipient, spend_hook, user_data, memo, half_split, strategy,
+            )
+            .await?;
+        let tx = transfer.build_tx()?;
+        self.estimate_fee(&tx).await
+    }
+
+    /// Build the fee-less `Money::Transfer` call of a payment transaction.
+    #[allow(clippy::too_many_arguments)]
+    async fn transfer_call(
+        &self,
+        amount: &str,
+        token_id: TokenId,
+        recipient: PublicKey,
+        spend_hook: Option<FuncId>,
+        user_data: Option<pallas::Base>,
+        memo: Vec<u8>,
+        half_split: bool,
+        strategy: CoinSelection,
+    ) -> Result<TransferCall> {
         // First get all unspent OwnCoins to see what our balance is
-        let owncoins = self.get_token_coins(&token_id).await?;
+        let mut owncoins = self.get_token_coins(&token_id).await?;
         if owncoins.is_empty() {
             return Err(Error::Custom(format!(
                 "Did not find any unspent coins with token ID: {token_id}"
@@ -68,6 +291,83 @@ impl Drk {
             )))
         }
 
+        // Coins are spent in order until the amount is covered, with the
+        // remainder sent back to us as change
+        strategy.order(&mut owncoins);
+
+        // Fetch our default secret
+        let secret = self.default_secret().await?;
+        let keypair = Keypair::new(secret);
+
+        // We'll also need our Merkle tree
+        let tree = self.get_money_tree().await?;
+
+        // Now we need the circuits and proving keys so we can build the transaction
+        let circuits = self.money_circuits().await?;
+
+        // Building transaction parameters
+        let tree_ = tree.clone();
+        let (params, secrets, spent_coins) = prove(move || {
+            make_transfer_call(
+                keypair,
+                recipient,
+                amount,
+                token_id,
+                owncoins,
+                tree_,
+                spend_hook,
+                user_data,
+                memo,
+                circuits.mint_zkbin,
+                circuits.mint_pk,
+                circuits.burn_zkbin,
+                circuits.burn_pk,
+                half_split,
+            )
+        })
+        .await?;
+
+        // Encode the call
+        let mut data = vec![MoneyFunction::TransferV1 as u8];
+        params.encode_async(&mut data).await?;
+        let call = ContractCall { contract_id: *MONEY_CONTRACT_ID, data };
+
+        // Create the TransactionBuilder containing the `Transfer` call
+        let tx_builder =
+            TransactionBuilder::new(ContractCallLeaf { call, proofs: secrets.proofs }, vec![])?;
+
+        Ok(TransferCall {
+            tx_builder,
+            signature_secrets: vec![secrets.signature_secrets],
+            spent_coins,
+            tree,
+            fee_pk: circuits.fee_pk,
+            fee_zkbin: circuits.fee_zkbin,
+        })
+    }
+
+    /// Build the fee-less transaction of a [`Drk::transfer_many`] call,
+    /// containing one `Money::Transfer` call per token.
+    async fn transfer_many_calls(
+        &self,
+        outputs: Vec<(PublicKey, TokenId, String)>,
+        strategy: CoinSelection,
+    ) -> Result<TransferCall> {
+        if outputs.is_empty() {
+            return Err(Error::Custom("No transfer outputs provided".to_string()))
+        }
+
+        // Group the recipients by token, keeping the order each token
+        // first appeared in, so the calls are deterministic.
+        let mut groups: Vec<(TokenId, Vec<(PublicKey, u64)>)> = vec![];
+        for (recipient, token_id, amount) in outputs {
+            let amount = decode_base10(&amount, BALANCE_BASE10_DECIMALS, false)?;
+            match groups.iter_mut().find(|(id, _)| *id == token_id) {
+                Some((_, recipients)) => recipients.push((recipient, amount)),
+                None => groups.push((token_id, vec![(recipient, amount)])),
+            }
+        }
+
         // Fetch our default secret
         let secret = self.default_secret().await?;
         let keypair = Keypair::new(secret);
@@ -75,9 +375,83 @@ impl Drk {
         // We'll also need our Merkle tree
         let tree = self.get_money_tree().await?;
 
-        // Now we need to do a lookup for the zkas proof bincodes, and create
-        // the circuit objects and proving keys so we can build the transaction.
-        // We also do this through the RPC.
+        // Now we need the circuits and proving keys so we can build the transaction
+        let circuits = self.money_circuits().await?;
+
+        let mut calls = Vec::with_capacity(groups.len());
+        for (token_id, recipients) in groups {
+            let mut owncoins = self.get_token_coins(&token_id).await?;
+            if owncoins.is_empty() {
+                return Err(Error::Custom(format!(
+                    "Did not find any unspent coins with token ID: {token_id}"
+                )))
+            }
+
+            let Some(amount) =
+                recipients.iter().try_fold(0_u64, |sum, (_, amount)| sum.checked_add(*amount))
+            else {
+                return Err(Error::Custom(format!(
+                    "Transfer amount overflow for token ID: {token_id}"
+                )))
+            };
+            let balance: u64 = owncoins.iter().map(|coin| coin.note.value).sum();
+            if balance < amount {
+                return Err(Error::Custom(format!(
+                    "Not enough balance for token ID: {token_id}, found: {}",
+                    encode_base10(balance, BALANCE_BASE10_DECIMALS)
+                )))
+            }
+            strategy.order(&mut owncoins);
+
+            // Building transaction parameters. The calls are proven
+            // concurrently, each one on its own thread.
+            let tree = tree.clone();
+            let mint_zkbin = circuits.mint_zkbin.clone();
+            let mint_pk = circuits.mint_pk.clone();
+            let burn_zkbin = circuits.burn_zkbin.clone();
+            let burn_pk = circuits.burn_pk.clone();
+            calls.push(prove(move || {
+                make_transfer_many_call(
+                    keypair, recipients, token_id, owncoins, tree, mint_zkbin, mint_pk, burn_zkbin,
+                    burn_pk,
+                )
+            }));
+        }
+
+        let mut tx_builder: Option<TransactionBuilder> = None;
+        let mut signature_secrets = vec![];
+        let mut spent_coins = vec![];
+        for call in calls {
+            let (params, secrets, coins) = call.await?;
+
+            // Encode the call
+            let mut data = vec![MoneyFunction::TransferV1 as u8];
+            params.encode_async(&mut data).await?;
+            let call = ContractCall { contract_id: *MONEY_CONTRACT_ID, data };
+            let leaf = ContractCallLeaf { call, proofs: secrets.proofs };
+
+            // Every token gets its own `Transfer` call in the same transaction
+            match tx_builder {
+                Some(ref mut builder) => builder.append(leaf, vec![])?,
+                None => tx_builder = Some(TransactionBuilder::new(leaf, vec![])?),
+            }
+            signature_secrets.push(secrets.signature_secrets);
+            spent_coins.extend(coins);
+        }
+
+        Ok(TransferCall {
+            tx_builder: tx_builder.unwrap(),
+            signature_secrets,
+            spent_coins,
+            tree,
+            fee_pk: circuits.fee_pk,
+            fee_zkbin: circuits.fee_zkbin,
+        })
+    }
+
+    /// Lookup the `Money` zkas bincodes through the RPC and retrieve the
+    /// Mint, Burn and Fee circuits proving keys.
+    async fn money_circuits(&self) -> Result<MoneyCircuits> {
         let zkas_bins = self.lookup_zkas(&MONEY_CONTRACT_ID).await?;
 
         let Some(mint_zkbin) = zkas_bins.iter().find(|x| x.0 == MONEY_CONTRACT_ZKAS_MINT_NS_V1)
@@ -95,69 +469,14 @@ impl Drk {
             return Err(Error::Custom("Fee circuit not found".to_string()))
         };
 
-        let mint_zkbin = ZkBinary::decode(&mint_zkbin.1)?;
-        let burn_zkbin = ZkBinary::decode(&burn_zkbin.1)?;
-        let fee_zkbin = ZkBinary::decode(&fee_zkbin.1)?;
-
-        let mint_circuit = ZkCircuit::new(empty_witnesses(&mint_zkbin)?, &mint_zkbin);
-        let burn_circuit = ZkCircuit::new(empty_witnesses(&burn_zkbin)?, &burn_zkbin);
-        let fee_circuit = ZkCircuit::new(empty_witnesses(&fee_zkbin)?, &fee_zkbin);
+        // Retrieve the Mint, Burn and Fee circuits proving keys, building
+        // the missing ones concurrently
+        let ((mint_zkbin, mint_pk), ((burn_zkbin, burn_pk), (fee_zkbin, fee_pk))) = try_zip(
+            self.proving_key(&mint_zkbin.1),
+            try_zip(self.proving_key(&burn_zkbin.1), self.proving_key(&fee_zkbin.1)),
+        )
+        .await?;
 
-        // Creating Mint, Burn and Fee circuits proving keys
-        let mint_pk = ProvingKey::build(mint_zkbin.k, &mint_circuit);
-        let burn_pk = ProvingKey::build(burn_zkbin.k, &burn_circuit);
-        let fee_pk = ProvingKey::build(fee_zkbin.k, &fee_circuit);
-
-        // Building transaction parameters
-        let (params, secrets, spent_coins) = make_transfer_call(
-            keypair,
-            recipient,
-            amount,
-            token_id,
-            owncoins,
-            tree.clone(),
-            spend_hook,
-            user_data,
-            mint_zkbin,
-            mint_pk,
-            burn_zkbin,
-            burn_pk,
-            half_split,
-        )?;
-
-        // Encode the call
-        let mut data = vec![MoneyFunction::TransferV1 as u8];
-        params.encode_async(&mut data).await?;
-        let call = ContractCall { contract_id: *MONEY_CONTRACT_ID, data };
-
-        // Create the TransactionBuilder containing the `Transfer` call
-        let mut tx_builder =
-            TransactionBuilder::new(ContractCallLeaf { call, proofs: secrets.proofs }, vec![])?;
-
-        // We first have to execute the fee-less tx to gather its used gas, and then we feed
-        // it into the fee-creating function.
-        // We also tell it about any spent coins so we don't accidentally reuse them in the
-        // fee call.
-        // TODO: We have to build a proper coin selection algorithm so that we can utilize
-        // the Money::Transfer to merge any coins which would give us a coin with enough
-        // value for paying the transaction fee.
-        let mut tx = tx_builder.build()?;
-        let sigs = tx.create_sigs(&secrets.signature_secrets)?;
-        tx.signatures.push(sigs);
-
-        let (fee_call, fee_proofs, fee_secrets) =
-            self.append_fee_call(&tx, &tree, &fee_pk, &fee_zkbin, Some(&spent_coins)).await?;
-
-        // Append the fee call to the transaction
-        tx_builder.append(ContractCallLeaf { call: fee_call, proofs: fee_proofs }, vec![])?;
-
-        // Now build the actual transaction and sign it with all necessary keys.
-        let mut tx = tx_builder.build()?;
-        let sigs = tx.create_sigs(&secrets.signature_secrets)?;
-        tx.signatures.push(sigs);
-        let sigs = tx.create_sigs(&fee_secrets)?;
-        tx.signatures.push(sigs);
-
-        Ok(tx)
+        Ok(MoneyCircuits { mint_zkbin, mint_pk, burn_zkbin, burn_pk, fee_zkbin, fee_pk })
     }
 }
@@ -17,8 +17,11 @@
  */
 
 use rusqlite::types::Value;
+use url::Url;
 
 use darkfi::{tx::Transaction, Error, Result};
+use darkfi_money_contract::model::Coin;
+use darkfi_sdk::crypto::MONEY_CONTRACT_ID;
 use darkfi_serial::{deserialize_async, serialize_async};
 
 use crate::{
@@ -33,10 +36,24 @@ const WALLET_TXS_HISTORY_TABLE: &str = "transactions_history";
 const WALLET_TXS_HISTORY_COL_TX_HASH: &str = "transaction_hash";
 const WALLET_TXS_HISTORY_COL_STATUS: &str = "status";
 const WALLET_TXS_HISTORY_COL_TX: &str = "tx";
+const WALLET_TXS_BROADCASTS_TABLE: &str = "transactions_broadcasts";
+const WALLET_TXS_BROADCASTS_COL_TX_HASH: &str = "transaction_hash";
+const WALLET_TXS_BROADCASTS_COL_ENDPOINT: &str = "endpoint";
+const WALLET_TXS_BROADCASTS_COL_STATUS: &str = "status";
 
 impl Drk {
     /// Insert a `Transaction` history record into the wallet.
     pub async fn insert_tx_history_record(&self, tx: &Transaction) -> WalletDbResult<String> {
+        self.put_tx_history_record(tx, "Broadcasted").await
+    }
+
+    /// Insert a `Transaction` history record with the given status into
+    /// the wallet. Existing records are left untouched.
+    pub async fn put_tx_history_record(
+        &self,
+        tx: &Transaction,
+        status: &str,
+    ) -> WalletDbResult<String> {
         let query = format!(
             "INSERT OR IGNORE INTO {} ({}, {}, {}) VALUES (?1, ?2, ?3);",
             WALLET_TXS_HISTORY_TABLE,
@@ -45,10 +62,8 @@ impl Drk {
             WALLET_TXS_HISTORY_COL_TX,
         );
         let tx_hash = tx.hash().to_string();
-        self.wallet.exec_sql(
-            &query,
-            rusqlite::params![tx_hash, "Broadcasted", &serialize_async(tx).await,],
-        )?;
+        self.wallet
+            .exec_sql(&query, rusqlite::params![tx_hash, status, &serialize_async(tx).await,])?;
 
         Ok(tx_hash)
     }
@@ -103,6 +118,28 @@ impl Drk {
         Ok((tx_hash.clone(), status.clone(), tx))
     }
 
+    /// Fetch the memos attached to the coins we received in given
+    /// transaction. Coins without a memo are skipped.
+    pub async fn get_tx_memos(&self, tx: &Transaction) -> Result<Vec<(Coin, Vec<u8>)>> {
+        let mut tx_coins = vec![];
+        for call in &tx.calls {
+            if call.data.contract_id != *MONEY_CONTRACT_ID {
+                continue
+            }
+            tx_coins.extend(self.money_call_coins(call).await?);
+        }
+
+        let memos = self
+            .get_coins(true)
+            .await?
+            .into_iter()
+            .filter(|(coin, _, _)| tx_coins.contains(&coin.coin) && !coin.note.memo.is_empty())
+            .map(|(coin, _, _)| (coin.coin, coin.note.memo))
+            .collect();
+
+        Ok(memos)
+    }
+
     /// Fetch all transactions history records, excluding bytes column.
     pub fn get_txs_history(&self) -> WalletDbResult<Vec<(String, String)>> {
         let rows = self.wallet.query_multiple(
@@ -157,4 +194,56 @@ impl Drk {
         );
         self.wallet.exec_sql(&query, rusqlite::params![status])
     }
+
+    /// Record the outcome of submitting a transaction to an endpoint,
+    /// replacing any previous record for it.
+    pub fn insert_tx_broadcast_record(
+        &self,
+        tx_hash: &str,
+        endpoint: &Url,
+        status: &str,
+    ) -> WalletDbResult<()> {
+        let query = format!(
+            "INSERT OR REPLACE INTO {} ({}, {}, {}) VALUES (?1, ?2, ?3);",
+            WALLET_TXS_BROADCASTS_TABLE,
+            WALLET_TXS_BROADCASTS_COL_TX_HASH,
+            WALLET_TXS_BROADCASTS_COL_ENDPOINT,
+            WALLET_TXS_BROADCASTS_COL_STATUS,
+        );
+        self.wallet.exec_sql(&query, rusqlite::params![tx_hash, endpoint.to_string(), status])
+    }
+
+    /// Get the status of a transaction along with its propagation status,
+    /// the outcome of its submission to each endpoint.
+    pub async fn get_tx_status(&self, tx_hash: &str) -> Result<(String, Vec<(String, String)>)> {
+        let (_, status, _) = self.get_tx_history_record(tx_hash).await?;
+
+        let rows = match self.wallet.query_multiple(
+            WALLET_TXS_BROADCASTS_TABLE,
+            &[WALLET_TXS_BROADCASTS_COL_ENDPOINT, WALLET_TXS_BROADCASTS_COL_STATUS],
+            convert_named_params! {(WALLET_TXS_BROADCASTS_COL_TX_HASH, tx_hash)},
+        ) {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[get_tx_status] Transaction broadcast records retrieval failed: {e:?}"
+                )))
+            }
+        };
+
+        let mut propagation = Vec::with_capacity(rows.len());
+        for row in rows {
+            let Value::Text(ref endpoint) = row[0] else {
+                return Err(Error::ParseFailed("[get_tx_status] Endpoint parsing failed"))
+            };
+
+            let Value::Text(ref endpoint_status) = row[1] else {
+                return Err(Error::ParseFailed("[get_tx_status] Status parsing failed"))
+            };
+
+            propagation.push((endpoint.clone(), endpoint_status.clone()));
+        }
+
+        Ok((status, propagation))
+    }
 }
@@ -17,7 +17,7 @@
  */
 
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
 
@@ -33,13 +33,29 @@ use log::{debug, error};
 use num_bigint::BigUint;
 use rusqlite::{
     types::{ToSql, Value},
-    Connection,
+    Connection, DatabaseName,
 };
 
 use crate::error::{WalletDbError, WalletDbResult};
 
 pub type WalletPtr = Arc<WalletDb>;
 
+/// Name the archive database is attached as. Tables holding no spending
+/// material, which keep growing over time, are created in it, so the
+/// main database file stays small. Queries don't need to name the
+/// database, as table names are resolved across both of them.
+pub const ARCHIVE_DB: &str = "archive";
+
+/// Number of prepared statements kept cached by the connection
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Returns the path of the archive database of the wallet at `path`
+pub fn archive_path(path: &Path) -> PathBuf {
+    let mut archive = path.as_os_str().to_owned();
+    archive.push(".archive");
+    PathBuf::from(archive)
+}
+
 /// Structure representing base wallet database operations.
 pub struct WalletDb {
     /// Connection to the SQLite database
@@ -61,16 +77,95 @@ impl WalletDb {
                 error!(target: "walletdb::new", "[WalletDb] Pragma update failed: {e}");
                 return Err(WalletDbError::PragmaUpdateError);
             };
+
+            // SQLCipher only decrypts pages on first access, so we read the
+            // schema here to fail early if the password is wrong.
+            if let Err(e) = conn.query_row("SELECT count(*) FROM sqlite_master;", (), |_| Ok(())) {
+                error!(target: "walletdb::new", "[WalletDb] Unable to decrypt database: {e}");
+                return Err(WalletDbError::InvalidPassword);
+            };
         }
+
+        // Attach the archive database, kept next to the main one
+        let archive = match path {
+            Some(ref p) => {
+                let archive = archive_path(p);
+                let Some(archive) = archive.to_str() else {
+                    return Err(WalletDbError::ConnectionFailed);
+                };
+                archive.to_string()
+            }
+            None => ":memory:".to_string(),
+        };
+        let attached = match password {
+            Some(password) => conn.execute(
+                &format!("ATTACH DATABASE ?1 AS {ARCHIVE_DB} KEY ?2;"),
+                [archive.as_str(), password],
+            ),
+            None => conn.execute(&format!("ATTACH DATABASE ?1 AS {ARCHIVE_DB};"), [&archive]),
+        };
+        if let Err(e) = attached {
+            error!(target: "walletdb::new", "[WalletDb] Attaching archive failed: {e}");
+            return Err(WalletDbError::ConnectionFailed);
+        };
+        let query = format!("SELECT count(*) FROM {ARCHIVE_DB}.sqlite_master;");
+        if let Err(e) = conn.query_row(&query, (), |_| Ok(())) {
+            error!(target: "walletdb::new", "[WalletDb] Unable to decrypt archive: {e}");
+            return Err(WalletDbError::InvalidPassword);
+        };
+
         if let Err(e) = conn.pragma_update(None, "foreign_keys", "ON") {
             error!(target: "walletdb::new", "[WalletDb] Pragma update failed: {e}");
             return Err(WalletDbError::PragmaUpdateError);
         };
 
+        // Keep the statements of frequent queries prepared, as scanning
+        // runs the same handful of them for every coin and nullifier
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+
         debug!(target: "walletdb::new", "[WalletDb] Opened Sqlite connection at \"{path:?}\"");
         Ok(Arc::new(Self { conn: Mutex::new(conn) }))
     }
 
+    /// Re-encrypt the wallet and archive databases using the provided password.
+    pub fn change_password(&self, password: &str) -> WalletDbResult<()> {
+        debug!(target: "walletdb::change_password", "[WalletDb] Re-encrypting database");
+        let Ok(conn) = self.conn.lock() else { return Err(WalletDbError::FailedToAquireLock) };
+        for db in [DatabaseName::Main, DatabaseName::Attached(ARCHIVE_DB)] {
+            if let Err(e) = conn.pragma_update(Some(db), "rekey", password) {
+                error!(target: "walletdb::change_password", "[WalletDb] Pragma update failed: {e}");
+                return Err(WalletDbError::PragmaUpdateError);
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Export an encrypted copy of the wallet database to `path`, using the
+    /// provided password. The file at `path` must not exist. Only the main
+    /// database is exported, the archive can be rebuilt by rescanning.
+    pub fn backup(&self, path: &Path, password: &str) -> WalletDbResult<()> {
+        debug!(target: "walletdb::backup", "[WalletDb] Exporting database to {path:?}");
+        let Some(path) = path.to_str() else { return Err(WalletDbError::BackupFailed) };
+        let Ok(conn) = self.conn.lock() else { return Err(WalletDbError::FailedToAquireLock) };
+        if let Err(e) = conn.execute("ATTACH DATABASE ?1 AS backup KEY ?2;", [path, password]) {
+            error!(target: "walletdb::backup", "[WalletDb] Attaching backup failed: {e}");
+            return Err(WalletDbError::BackupFailed)
+        };
+
+        let exported = conn.query_row("SELECT sqlcipher_export('backup');", (), |_| Ok(()));
+        if let Err(e) = conn.execute("DETACH DATABASE backup;", ()) {
+            error!(target: "walletdb::backup", "[WalletDb] Detaching backup failed: {e}");
+            return Err(WalletDbError::BackupFailed)
+        };
+        if let Err(e) = exported {
+            error!(target: "walletdb::backup", "[WalletDb] Exporting backup failed: {e}");
+            return Err(WalletDbError::BackupFailed)
+        };
+
+        Ok(())
+    }
+
     /// This function executes a given SQL query that contains multiple SQL statements,
     /// that don't contain any parameters.
     pub fn exec_batch_sql(&self, query: &str) -> WalletDbResult<()> {
@@ -99,8 +194,8 @@ impl WalletDb {
             return Ok(())
         }
 
-        // First we prepare the query
-        let Ok(mut stmt) = conn.prepare(query) else {
+        // First we prepare the query, or grab it from the statement cache
+        let Ok(mut stmt) = conn.prepare_cached(query) else {
             return Err(WalletDbError::QueryPreparationFailed)
         };
 
@@ -110,11 +205,8 @@ impl WalletDb {
             return Err(WalletDbError::QueryExecutionFailed)
         };
 
-        // Finalize query and drop connection lock
-        if let Err(e) = stmt.finalize() {
-            error!(target: "walletdb::exec_sql", "[WalletDb] Query finalization failed: {e}");
-            return Err(WalletDbError::QueryFinalizationFailed)
-        };
+        // Return the statement to the cache and drop connection lock
+        drop(stmt);
         drop(conn);
 
         Ok(())
@@ -134,16 +226,7 @@ impl WalletDb {
         } else {
             format!("SELECT {} FROM {}", col_names.join(", "), table)
         };
-        if params.is_empty() {
-            return query
-        }
-
-        let mut where_str = Vec::with_capacity(params.len());
-        for (k, _) in params {
-            let col = &k[1..];
-            where_str.push(format!("{col} = {k}"));
-        }
-        query.push_str(&format!(" WHERE {}", where_str.join(" AND ")));
+        query.push_str(&self.generate_where_clauses(params));
 
         query
     }
@@ -163,7 +246,7 @@ impl WalletDb {
         // First we prepare the query
         let Ok(conn) = self.conn.lock() else { return Err(WalletDbError::FailedToAquireLock) };
 
-        let Ok(mut stmt) = conn.prepare(&query) else {
+        let Ok(mut stmt) = conn.prepare_cached(&query) else {
             return Err(WalletDbError::QueryPreparationFailed)
         };
 
@@ -214,7 +297,7 @@ impl WalletDb {
 
         // First we prepare the query
         let Ok(conn) = self.conn.lock() else { return Err(WalletDbError::FailedToAquireLock) };
-        let Ok(mut stmt) = conn.prepare(&query) else {
+        let Ok(mut stmt) = conn.prepare_cached(&query) else {
             return Err(WalletDbError::QueryPreparationFailed)
         };
 
@@ -260,6 +343,88 @@ impl WalletDb {
 
         Ok(result)
     }
+
+    /// Generate the `WHERE` clauses string for provided named parameters,
+    /// assuming they follow the normal formatting ":{column_name}".
+    fn generate_where_clauses(&self, params: &[(&str, &dyn ToSql)]) -> String {
+        if params.is_empty() {
+            return String::new()
+        }
+
+        let where_str: Vec<String> =
+            params.iter().map(|(k, _)| format!("{} = {k}", &k[1..])).collect();
+        format!(" WHERE {}", where_str.join(" AND "))
+    }
+
+    /// This function executes a given SQL query using named parameters.
+    fn exec_named_sql(&self, query: &str, params: &[(&str, &dyn ToSql)]) -> WalletDbResult<()> {
+        debug!(target: "walletdb::exec_named_sql", "[WalletDb] Executing SQL query:\n{query}");
+        let Ok(conn) = self.conn.lock() else { return Err(WalletDbError::FailedToAquireLock) };
+
+        // First we prepare the query, or grab it from the statement cache
+        let Ok(mut stmt) = conn.prepare_cached(query) else {
+            return Err(WalletDbError::QueryPreparationFailed)
+        };
+
+        // Execute the query using provided params
+        if let Err(e) = stmt.execute(params) {
+            error!(target: "walletdb::exec_named_sql", "[WalletDb] Query failed: {e}");
+            return Err(WalletDbError::QueryExecutionFailed)
+        };
+
+        Ok(())
+    }
+
+    /// Insert a row into provided table, from the given named column values.
+    /// If `replace` is set, an existing row conflicting with it gets replaced.
+    pub fn insert(
+        &self,
+        table: &str,
+        values: &[(&str, &dyn ToSql)],
+        replace: bool,
+    ) -> WalletDbResult<()> {
+        let cols: Vec<&str> = values.iter().map(|(k, _)| &k[1..]).collect();
+        let keys: Vec<&str> = values.iter().map(|(k, _)| *k).collect();
+        let query = format!(
+            "INSERT{} INTO {} ({}) VALUES ({});",
+            if replace { " OR REPLACE" } else { "" },
+            table,
+            cols.join(", "),
+            keys.join(", "),
+        );
+
+        self.exec_named_sql(&query, values)
+    }
+
+    /// Update provided table rows matching provided `WHERE` clauses, setting
+    /// the given named column values. A column can't be both set and used in
+    /// the `WHERE` clauses, as their named parameters would clash.
+    pub fn update(
+        &self,
+        table: &str,
+        values: &[(&str, &dyn ToSql)],
+        params: &[(&str, &dyn ToSql)],
+    ) -> WalletDbResult<()> {
+        let set_str: Vec<String> =
+            values.iter().map(|(k, _)| format!("{} = {k}", &k[1..])).collect();
+        let query = format!(
+            "UPDATE {} SET {}{};",
+            table,
+            set_str.join(", "),
+            self.generate_where_clauses(params)
+        );
+
+        let mut all_params = values.to_vec();
+        all_params.extend_from_slice(params);
+        self.exec_named_sql(&query, &all_params)
+    }
+
+    /// Delete provided table rows matching provided `WHERE` clauses.
+    /// If no clauses are provided, all the table rows get deleted.
+    pub fn delete(&self, table: &str, params: &[(&str, &dyn ToSql)]) -> WalletDbResult<()> {
+        let query = format!("DELETE FROM {}{};", table, self.generate_where_clauses(params));
+        self.exec_named_sql(&query, params)
+    }
 }
 
 /// Custom implementation of rusqlite::named_params! to use `expr` instead of `literal` as `$param_name`,
@@ -307,13 +472,14 @@ impl<'a> StorageAdapter for WalletStorage<'a> {
     type Value = pallas::Base;
 
     fn put(&mut self, key: BigUint, value: pallas::Base) -> ContractResult {
-        let query = format!(
-            "INSERT OR REPLACE INTO {} ({}, {}) VALUES (?1, ?2);",
-            self.table, self.key_col, self.value_col
-        );
-        if let Err(e) =
-            self.wallet.exec_sql(&query, rusqlite::params![key.to_bytes_le(), value.to_repr()])
-        {
+        if let Err(e) = self.wallet.insert(
+            self.table,
+            convert_named_params! {
+                (self.key_col, key.to_bytes_le()),
+                (self.value_col, value.to_repr()),
+            },
+            true,
+        ) {
             error!(target: "walletdb::StorageAdapter::put", "Inserting key {key:?}, value {value:?} into DB failed: {e:?}");
             return Err(ContractError::SmtPutFailed)
         }
@@ -347,8 +513,10 @@ impl<'a> StorageAdapter for WalletStorage<'a> {
     }
 
     fn del(&mut self, key: &BigUint) -> ContractResult {
-        let query = format!("DELETE FROM {} WHERE {} = ?1;", self.table, self.key_col);
-        if let Err(e) = self.wallet.exec_sql(&query, rusqlite::params![key.to_bytes_le()]) {
+        if let Err(e) = self
+            .wallet
+            .delete(self.table, convert_named_params! {(self.key_col, key.to_bytes_le())})
+        {
             error!(target: "walletdb::StorageAdapter::del", "Removing key {key:?} from DB failed: {e:?}");
             return Err(ContractError::SmtDelFailed)
         }
@@ -367,7 +535,10 @@ mod tests {
     use rand::rngs::OsRng;
     use rusqlite::types::Value;
 
-    use crate::walletdb::{WalletDb, WalletStorage};
+    use crate::{
+        convert_named_params,
+        walletdb::{archive_path, WalletDb, WalletStorage},
+    };
 
     #[test]
     fn test_mem_wallet() {
@@ -381,6 +552,95 @@ mod tests {
         assert_eq!(numba, 42);
     }
 
+    #[test]
+    fn test_query_builder() {
+        let wallet = WalletDb::new(None, Some("foobar")).unwrap();
+        wallet
+            .exec_sql("CREATE TABLE mista ( id INTEGER PRIMARY KEY, numba INTEGER );", &[])
+            .unwrap();
+
+        wallet.insert("mista", convert_named_params! {("id", 1), ("numba", 41)}, false).unwrap();
+        wallet.insert("mista", convert_named_params! {("id", 2), ("numba", 42)}, false).unwrap();
+        assert!(wallet
+            .insert("mista", convert_named_params! {("id", 1), ("numba", 0)}, false)
+            .is_err());
+
+        wallet.insert("mista", convert_named_params! {("id", 1), ("numba", 43)}, true).unwrap();
+        let ret =
+            wallet.query_single("mista", &["numba"], convert_named_params! {("id", 1)}).unwrap();
+        assert_eq!(ret[0], Value::Integer(43));
+
+        wallet
+            .update(
+                "mista",
+                convert_named_params! {("numba", 44)},
+                convert_named_params! {("id", 2)},
+            )
+            .unwrap();
+        let ret =
+            wallet.query_single("mista", &["numba"], convert_named_params! {("id", 2)}).unwrap();
+        assert_eq!(ret[0], Value::Integer(44));
+
+        wallet.delete("mista", convert_named_params! {("id", 1)}).unwrap();
+        assert_eq!(wallet.query_multiple("mista", &[], &[]).unwrap().len(), 1);
+        wallet.delete("mista", &[]).unwrap();
+        assert!(wallet.query_multiple("mista", &[], &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_backup() {
+        let wallet = WalletDb::new(None, Some("foobar")).unwrap();
+        wallet.exec_sql("CREATE TABLE mista ( numba INTEGER );", &[]).unwrap();
+        wallet.exec_sql("INSERT INTO mista ( numba ) VALUES ( 42 );", &[]).unwrap();
+
+        let path = std::env::temp_dir().join(format!("drk_test_backup_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        wallet.backup(&path, "barfoo").unwrap();
+
+        assert!(WalletDb::new(Some(path.clone()), Some("foobar")).is_err());
+        let backup = WalletDb::new(Some(path.clone()), Some("barfoo")).unwrap();
+        let ret = backup.query_single("mista", &["numba"], &[]).unwrap();
+        assert_eq!(ret[0], Value::Integer(42));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(archive_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_archive() {
+        let path = std::env::temp_dir().join(format!("drk_test_archive_{}.db", std::process::id()));
+        let archive = archive_path(&path);
+        let backup_path =
+            std::env::temp_dir().join(format!("drk_test_archive_backup_{}.db", std::process::id()));
+        for p in [&path, &archive, &backup_path] {
+            let _ = std::fs::remove_file(p);
+        }
+
+        let wallet = WalletDb::new(Some(path.clone()), Some("foobar")).unwrap();
+        wallet.exec_sql("CREATE TABLE mista ( numba INTEGER );", &[]).unwrap();
+        wallet.exec_sql("INSERT INTO mista ( numba ) VALUES ( 41 );", &[]).unwrap();
+        wallet.exec_sql("CREATE TABLE archive.history ( numba INTEGER );", &[]).unwrap();
+        wallet.exec_sql("INSERT INTO history ( numba ) VALUES ( 42 );", &[]).unwrap();
+        wallet.backup(&backup_path, "foobar").unwrap();
+        drop(wallet);
+        assert!(archive.exists());
+
+        // Archive tables are resolved without naming the database
+        let wallet = WalletDb::new(Some(path.clone()), Some("foobar")).unwrap();
+        let ret = wallet.query_single("history", &["numba"], &[]).unwrap();
+        assert_eq!(ret[0], Value::Integer(42));
+
+        // Backups only hold the main database
+        let backup = WalletDb::new(Some(backup_path.clone()), Some("foobar")).unwrap();
+        let ret = backup.query_single("mista", &["numba"], &[]).unwrap();
+        assert_eq!(ret[0], Value::Integer(41));
+        assert!(backup.query_single("history", &["numba"], &[]).is_err());
+
+        for p in [&path, &archive, &backup_path, &archive_path(&backup_path)] {
+            let _ = std::fs::remove_file(p);
+        }
+    }
+
     #[test]
     fn test_query_single() {
         let wallet = WalletDb::new(None, None).unwrap();
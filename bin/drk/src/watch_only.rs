@@ -0,0 +1,136 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fs;
+
+use darkfi::{util::path::expand_path, zk::halo2::Field, Error, Result};
+use darkfi_sdk::{crypto::SecretKey, pasta::pallas};
+use darkfi_serial::serialize_async;
+
+use crate::{
+    dao::{DAO_DAOS_COL_BULLA, DAO_DAOS_COL_PARAMS, DAO_DAOS_TABLE},
+    deploy::DEPLOY_AUTH_TABLE,
+    money::{
+        MONEY_COINS_COL_SECRET, MONEY_COINS_TABLE, MONEY_KEYS_ARCHIVED_TABLE,
+        MONEY_KEYS_COL_SECRET, MONEY_KEYS_TABLE, MONEY_TOKENS_COL_MINT_AUTHORITY,
+        MONEY_TOKENS_TABLE,
+    },
+    walletdb::WalletDb,
+    Drk,
+};
+
+impl Drk {
+    /// Write a watch-only copy of the wallet to `path`, encrypted with
+    /// `password`. The copy keeps the public keys, coins, tokens, DAOs and
+    /// transactions history, but all secret keys are zeroed and deploy
+    /// authorities removed, so nothing can be spent or signed with it.
+    ///
+    /// Since notes are decrypted with the same secret keys, the copy is
+    /// a snapshot: it can't pick up new coins by scanning.
+    pub async fn export_watch_only(&self, path: &str, password: &str) -> Result<()> {
+        let path = expand_path(path)?;
+        if path.exists() {
+            return Err(Error::Custom(format!("Wallet {path:?} already exists")))
+        }
+
+        if let Err(e) = self.wallet.backup(&path, password) {
+            let _ = fs::remove_file(&path);
+            return Err(Error::DatabaseError(format!(
+                "[export_watch_only] Wallet export failed: {e:?}"
+            )))
+        }
+
+        if let Err(e) = self.strip_secrets(&path, password).await {
+            // Never leave a copy containing secrets behind
+            let _ = fs::remove_file(&path);
+            return Err(e)
+        }
+
+        Ok(())
+    }
+
+    /// Auxiliary function to zero all secret keys of the wallet at `path`.
+    async fn strip_secrets(&self, path: &std::path::Path, password: &str) -> Result<()> {
+        let wallet = match WalletDb::new(Some(path.to_path_buf()), Some(password)) {
+            Ok(w) => w,
+            Err(e) => {
+                return Err(Error::DatabaseError(format!(
+                    "[export_watch_only] Opening exported wallet failed: {e:?}"
+                )))
+            }
+        };
+        let copy = Drk {
+            wallet,
+            rpc_client: None,
+            fun: self.fun,
+            min_confirmations: self.min_confirmations,
+            proving_keys_dir: self.proving_keys_dir.clone(),
+        };
+
+        let zero = serialize_async(&SecretKey::from(pallas::Base::ZERO)).await;
+        for (table, column) in [
+            (&*MONEY_KEYS_TABLE, MONEY_KEYS_COL_SECRET),
+            (&*MONEY_KEYS_ARCHIVED_TABLE, MONEY_KEYS_COL_SECRET),
+            (&*MONEY_COINS_TABLE, MONEY_COINS_COL_SECRET),
+            (&*MONEY_TOKENS_TABLE, MONEY_TOKENS_COL_MINT_AUTHORITY),
+        ] {
+            let query = format!("UPDATE {table} SET {column} = ?1;");
+            if let Err(e) = copy.wallet.exec_sql(&query, rusqlite::params![zero]) {
+                return Err(Error::DatabaseError(format!(
+                    "[export_watch_only] Stripping {table} secrets failed: {e:?}"
+                )))
+            }
+        }
+
+        // Deploy authorities are unique, so they can't all be zeroed
+        let query = format!("DELETE FROM {};", *DEPLOY_AUTH_TABLE);
+        if let Err(e) = copy.wallet.exec_sql(&query, &[]) {
+            return Err(Error::DatabaseError(format!(
+                "[export_watch_only] Removing deploy authorities failed: {e:?}"
+            )))
+        }
+
+        for mut dao in copy.get_daos().await? {
+            dao.params.secret_key = SecretKey::from(pallas::Base::ZERO);
+            let query = format!(
+                "UPDATE {} SET {} = ?1 WHERE {} = ?2;",
+                *DAO_DAOS_TABLE, DAO_DAOS_COL_PARAMS, DAO_DAOS_COL_BULLA
+            );
+            if let Err(e) = copy.wallet.exec_sql(
+                &query,
+                rusqlite::params![
+                    serialize_async(&dao.params).await,
+                    serialize_async(&dao.bulla()).await,
+                ],
+            ) {
+                return Err(Error::DatabaseError(format!(
+                    "[export_watch_only] Stripping DAO secrets failed: {e:?}"
+                )))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if this wallet is a watch-only copy, created by
+    /// [`Drk::export_watch_only`].
+    pub async fn is_watch_only(&self) -> Result<bool> {
+        let secrets = self.get_money_secrets().await?;
+        Ok(!secrets.is_empty() && secrets.iter().all(|s| s.inner() == pallas::Base::ZERO))
+    }
+}
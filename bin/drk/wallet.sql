@@ -1,9 +1,80 @@
 -- Wallet definitions for drk.
 -- We store data that is needed for wallet operations.
+-- Tables holding no spending material are created in the archive
+-- database, kept next to the wallet file.
 
 -- Transactions history
-CREATE TABLE IF NOT EXISTS transactions_history (
+CREATE TABLE IF NOT EXISTS archive.transactions_history (
     transaction_hash TEXT PRIMARY KEY NOT NULL,
     status TEXT NOT NULL,
 	tx BLOB NOT NULL
 );
+
+-- Transactions made by scheduled payments, tagging their history records
+CREATE TABLE IF NOT EXISTS archive.scheduled_payments_history (
+    transaction_hash TEXT PRIMARY KEY NOT NULL,
+    schedule_id INTEGER NOT NULL
+);
+
+-- Outcome of transaction broadcasts, per submitted endpoint
+CREATE TABLE IF NOT EXISTS archive.transactions_broadcasts (
+    transaction_hash TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    status TEXT NOT NULL,
+    PRIMARY KEY (transaction_hash, endpoint)
+);
+
+-- Invoices, settled by payments carrying the invoice ID as user data
+CREATE TABLE IF NOT EXISTS invoices (
+    invoice_id TEXT PRIMARY KEY NOT NULL,
+    recipient BLOB NOT NULL,
+    token_id BLOB NOT NULL,
+    amount BLOB NOT NULL,
+    memo TEXT NOT NULL,
+    expiry INTEGER NOT NULL,
+    status TEXT NOT NULL
+);
+
+-- Invoices removed from the wallet, kept until purged so they can be restored
+CREATE TABLE IF NOT EXISTS invoices_archived (
+    archive_id INTEGER PRIMARY KEY NOT NULL,
+    invoice_id TEXT NOT NULL,
+    recipient BLOB NOT NULL,
+    token_id BLOB NOT NULL,
+    amount BLOB NOT NULL,
+    memo TEXT NOT NULL,
+    expiry INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    archived_at INTEGER NOT NULL
+);
+
+-- Recurring payments made by the wallet
+CREATE TABLE IF NOT EXISTS scheduled_payments (
+    schedule_id INTEGER PRIMARY KEY NOT NULL,
+    recipient BLOB NOT NULL,
+    token_id BLOB NOT NULL,
+    amount BLOB NOT NULL,
+    interval INTEGER NOT NULL,
+    next_payment INTEGER NOT NULL,
+    end_time INTEGER NOT NULL,
+    last_result TEXT NOT NULL
+);
+
+-- Coins excluded from coin selection until unfrozen
+CREATE TABLE IF NOT EXISTS frozen_coins (
+    coin BLOB PRIMARY KEY NOT NULL
+);
+
+-- Address book of named recipient addresses
+CREATE TABLE IF NOT EXISTS address_book (
+    name TEXT PRIMARY KEY NOT NULL,
+    address BLOB NOT NULL
+);
+
+-- Contacts removed from the address book, kept until purged so they can be restored
+CREATE TABLE IF NOT EXISTS address_book_archived (
+    archive_id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    address BLOB NOT NULL,
+    archived_at INTEGER NOT NULL
+);
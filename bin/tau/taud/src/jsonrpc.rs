@@ -85,6 +85,9 @@ impl RequestHandler for JsonRpcInterface {
 
             "ping" => return self.pong(req.id, req.params).await,
             "dnet.subscribe_events" => return self.dnet_subscribe_events(req.id, req.params).await,
+            "dnet.get_recent_events" => {
+                return self.dnet_get_recent_events(req.id, req.params).await
+            }
             "dnet.switch" => self.dnet_switch(req.params).await,
 
             "deg.switch" => self.deg_switch(req.id, req.params).await,
@@ -93,6 +96,12 @@ impl RequestHandler for JsonRpcInterface {
 
             // TODO: make this optional
             "p2p.get_info" => return self.p2p_get_info(req.id, req.params).await,
+            "p2p.disconnect_peer" => return self.p2p_disconnect_peer(req.id, req.params).await,
+            "p2p.blacklist_peer" => return self.p2p_blacklist_peer(req.id, req.params).await,
+            "p2p.connect_peer" => return self.p2p_connect_peer(req.id, req.params).await,
+            "p2p.get_refinery_stats" => {
+                return self.p2p_get_refinery_stats(req.id, req.params).await
+            }
             _ => return JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         };
 
@@ -0,0 +1,396 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Load testing harness for the P2P stack. Spawns the requested number
+//! of nodes in a single process, wired together over the in-process
+//! memory transport, lets them form a network, then has each of them
+//! broadcast messages at a fixed rate. Once done, it reports message
+//! throughput, delivery latency and channel churn.
+
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use structopt::StructOpt;
+use structopt_toml::StructOptToml;
+use url::Url;
+
+use darkfi::{
+    cli_desc, impl_p2p_message,
+    net::{
+        protocol::protocol_generic::{
+            ProtocolGenericAction, ProtocolGenericHandler, ProtocolGenericHandlerPtr,
+        },
+        session::SESSION_DEFAULT,
+        settings::SettingsOpt,
+        Message, P2p, P2pPtr, Settings,
+    },
+    system::{msleep, sleep, ExecutorPtr},
+    util::{
+        cli::{get_log_config, get_log_level},
+        path::expand_path,
+    },
+    Error, Result,
+};
+use darkfi_serial::{SerialDecodable, SerialEncodable};
+
+/// First port of the node addresses, node `i` listening on `BASE_PORT + i`
+const BASE_PORT: usize = 10000;
+
+#[derive(Clone, Debug, serde::Deserialize, StructOpt, StructOptToml)]
+#[serde(default)]
+#[structopt(name = "p2p-loadtest", about = cli_desc!())]
+struct Args {
+    #[structopt(short, long)]
+    /// Settings template file to use
+    config: Option<String>,
+
+    #[structopt(short, parse(from_occurrences))]
+    /// Increase verbosity (-vvv supported)
+    verbose: u8,
+
+    #[structopt(long, default_value = "200")]
+    /// Number of nodes to spawn
+    nodes: usize,
+
+    #[structopt(long, default_value = "4")]
+    /// Number of nodes acting as seeds for the rest
+    seed_nodes: usize,
+
+    #[structopt(long, default_value = "10")]
+    /// Seconds to let the network form before driving load
+    warmup: u64,
+
+    #[structopt(long, default_value = "60")]
+    /// Seconds to drive load for
+    duration: u64,
+
+    #[structopt(long, default_value = "1.0")]
+    /// Messages each node broadcasts per second
+    rate: f64,
+
+    #[structopt(long, default_value = "256")]
+    /// Payload size of each message, in bytes
+    payload_size: usize,
+
+    #[structopt(long, default_value = "0")]
+    /// Seed of the RNG jittering the send schedule and filling payloads
+    rng_seed: u64,
+
+    /// P2P network settings template
+    #[structopt(flatten)]
+    net: SettingsOpt,
+}
+
+/// Message broadcasted by the nodes under load
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+struct LoadMessage {
+    /// Microseconds since the UNIX epoch at which the message was sent
+    sent_at: u64,
+    /// Filler bytes
+    payload: Vec<u8>,
+}
+
+impl_p2p_message!(LoadMessage, "loadtest");
+
+/// Statistics gathered over all the nodes
+#[derive(Default)]
+struct Stats {
+    /// Messages broadcasted
+    sent: AtomicU64,
+    /// Copies of the messages handed to channels
+    fanout: AtomicU64,
+    /// Messages received
+    received: AtomicU64,
+    /// Payload bytes received
+    received_bytes: AtomicU64,
+    /// Delivery latency of each received message, in microseconds
+    latencies: Mutex<Vec<u64>>,
+    /// Channels opened while under load
+    opened: AtomicU64,
+    /// Channels closed while under load
+    closed: AtomicU64,
+}
+
+/// A spawned node along with its load message handler
+struct Node {
+    p2p: P2pPtr,
+    handler: ProtocolGenericHandlerPtr<LoadMessage, LoadMessage>,
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64
+}
+
+fn node_addr(index: usize) -> Url {
+    Url::parse(&format!("memory://127.0.0.1:{}", BASE_PORT + index)).unwrap()
+}
+
+/// Settings of node `index`, built from the template. Seed nodes are
+/// the ones without seeds of their own.
+fn node_settings(template: &Settings, index: usize, seeds: &[Url]) -> Settings {
+    let addr = node_addr(index);
+
+    let mut settings = template.clone();
+    settings.node_id = format!("loadtest-{}", index);
+    settings.inbound_addrs = vec![addr.clone()];
+    settings.external_addrs = vec![addr.clone()];
+    settings.seeds = if seeds.contains(&addr) { vec![] } else { seeds.to_vec() };
+    settings.peers = vec![];
+    settings.allowed_transports = vec!["memory".to_string()];
+    settings.localnet = true;
+    settings.p2p_datastore = None;
+    settings.hostlist = None;
+    settings
+}
+
+/// Record every received message, without relaying it any further
+async fn receive_loop(
+    handler: ProtocolGenericHandlerPtr<LoadMessage, LoadMessage>,
+    stats: Arc<Stats>,
+) -> Result<()> {
+    loop {
+        let (channel, msg) = match handler.receiver.recv().await {
+            Ok(r) => r,
+            Err(e) => {
+                debug!(target: "p2p_loadtest::receive_loop", "recv fail: {}", e);
+                continue
+            }
+        };
+
+        stats.received.fetch_add(1, Ordering::Relaxed);
+        stats.received_bytes.fetch_add(msg.payload.len() as u64, Ordering::Relaxed);
+        stats.latencies.lock().unwrap().push(now_micros().saturating_sub(msg.sent_at));
+
+        handler.send_action(channel, ProtocolGenericAction::Skip).await;
+    }
+}
+
+/// Broadcast a message every `interval` until `deadline`, starting
+/// after `offset` so the nodes don't all send at once
+async fn send_loop(
+    p2p: P2pPtr,
+    stats: Arc<Stats>,
+    payload: Vec<u8>,
+    interval: Duration,
+    offset: Duration,
+    deadline: Instant,
+) {
+    smol::Timer::after(offset).await;
+
+    while Instant::now() < deadline {
+        let msg = LoadMessage { sent_at: now_micros(), payload: payload.clone() };
+        let peers = p2p.hosts().peers().len() as u64;
+        p2p.broadcast(&msg).await;
+
+        stats.sent.fetch_add(1, Ordering::Relaxed);
+        stats.fanout.fetch_add(peers, Ordering::Relaxed);
+
+        smol::Timer::after(interval).await;
+    }
+}
+
+/// Sample the channels of every node each second until `deadline`,
+/// counting the ones opened and closed in between samples
+async fn churn_loop(nodes: Vec<P2pPtr>, stats: Arc<Stats>, deadline: Instant) {
+    let channel_ids = |p2p: &P2pPtr| -> HashSet<u32> {
+        p2p.hosts().channels().iter().map(|c| c.info.id).collect()
+    };
+
+    let mut known: Vec<HashSet<u32>> = nodes.iter().map(channel_ids).collect();
+
+    while Instant::now() < deadline {
+        msleep(1000).await;
+
+        for (p2p, known) in nodes.iter().zip(known.iter_mut()) {
+            let current = channel_ids(p2p);
+            stats.opened.fetch_add(current.difference(known).count() as u64, Ordering::Relaxed);
+            stats.closed.fetch_add(known.difference(&current).count() as u64, Ordering::Relaxed);
+            *known = current;
+        }
+    }
+}
+
+/// Latency at the given percentile of the sorted samples, in milliseconds
+fn percentile(sorted: &[u64], pct: usize) -> f64 {
+    if sorted.is_empty() {
+        return 0.0
+    }
+
+    let index = (sorted.len() * pct / 100).min(sorted.len() - 1);
+    sorted[index] as f64 / 1000.0
+}
+
+fn report(stats: &Stats, nodes: &[Node], elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    let sent = stats.sent.load(Ordering::Relaxed);
+    let fanout = stats.fanout.load(Ordering::Relaxed);
+    let received = stats.received.load(Ordering::Relaxed);
+    let received_bytes = stats.received_bytes.load(Ordering::Relaxed);
+    let opened = stats.opened.load(Ordering::Relaxed);
+    let closed = stats.closed.load(Ordering::Relaxed);
+
+    let mut latencies = stats.latencies.lock().unwrap().clone();
+    latencies.sort_unstable();
+
+    let connections: Vec<usize> = nodes.iter().map(|n| n.p2p.hosts().peers().len()).collect();
+    let min_conns = connections.iter().min().unwrap_or(&0);
+    let max_conns = connections.iter().max().unwrap_or(&0);
+    let avg_conns = connections.iter().sum::<usize>() as f64 / connections.len().max(1) as f64;
+
+    println!("Load test over {} nodes, {:.1}s", nodes.len(), secs);
+    println!("  Messages broadcasted: {} ({} channel sends)", sent, fanout);
+    println!(
+        "  Messages received:    {} ({:.1} msg/s, {:.1} KiB/s)",
+        received,
+        received as f64 / secs,
+        received_bytes as f64 / 1024.0 / secs,
+    );
+    println!(
+        "  Latency (ms):         p50 {:.2}, p90 {:.2}, p99 {:.2}, max {:.2}",
+        percentile(&latencies, 50),
+        percentile(&latencies, 90),
+        percentile(&latencies, 99),
+        percentile(&latencies, 100),
+    );
+    println!(
+        "  Channel churn:        {} opened, {} closed ({:.2}/s)",
+        opened,
+        closed,
+        (opened + closed) as f64 / secs,
+    );
+    println!("  Peers per node:       min {}, avg {:.1}, max {}", min_conns, avg_conns, max_conns);
+}
+
+async fn realmain(args: Args, ex: ExecutorPtr) -> Result<()> {
+    if args.seed_nodes == 0 || args.seed_nodes >= args.nodes {
+        return Err(Error::Custom("Seed nodes must be between 1 and the node count".to_string()))
+    }
+
+    if args.nodes > u16::MAX as usize - BASE_PORT {
+        return Err(Error::Custom("Too many nodes requested".to_string()))
+    }
+
+    if args.rate <= 0.0 {
+        return Err(Error::Custom("Message rate must be positive".to_string()))
+    }
+
+    let template: Settings = args.net.clone().into();
+    let seeds: Vec<Url> = (0..args.seed_nodes).map(node_addr).collect();
+    let stats = Arc::new(Stats::default());
+
+    info!(target: "p2p_loadtest", "Spawning {} nodes...", args.nodes);
+    let mut nodes = Vec::with_capacity(args.nodes);
+    for index in 0..args.nodes {
+        let p2p = P2p::new(node_settings(&template, index, &seeds), ex.clone()).await?;
+
+        let handler = ProtocolGenericHandler::new(&p2p, "ProtocolLoad", SESSION_DEFAULT).await;
+        handler.task.clone().start(
+            receive_loop(handler.clone(), stats.clone()),
+            |_| async {},
+            Error::DetachedTaskStopped,
+            ex.clone(),
+        );
+
+        p2p.clone().start().await?;
+        nodes.push(Node { p2p, handler });
+    }
+
+    info!(target: "p2p_loadtest", "Waiting {}s for the network to form...", args.warmup);
+    sleep(args.warmup).await;
+
+    info!(target: "p2p_loadtest", "Driving load for {}s...", args.duration);
+    let mut rng = StdRng::seed_from_u64(args.rng_seed);
+    let interval = Duration::from_secs_f64(1.0 / args.rate);
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(args.duration);
+
+    let p2ps = nodes.iter().map(|n| n.p2p.clone()).collect();
+    let churn_task = ex.spawn(churn_loop(p2ps, stats.clone(), deadline));
+
+    let mut send_tasks = Vec::with_capacity(nodes.len());
+    for node in &nodes {
+        let offset = interval.mul_f64(rng.gen());
+        let mut payload = vec![0u8; args.payload_size];
+        rng.fill_bytes(&mut payload);
+
+        send_tasks.push(ex.spawn(send_loop(
+            node.p2p.clone(),
+            stats.clone(),
+            payload,
+            interval,
+            offset,
+            deadline,
+        )));
+    }
+
+    for task in send_tasks {
+        task.await;
+    }
+    churn_task.await;
+
+    report(&stats, &nodes, start.elapsed());
+
+    info!(target: "p2p_loadtest", "Stopping nodes...");
+    for node in nodes {
+        node.handler.task.stop().await;
+        node.p2p.stop().await;
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::from_args_with_toml("").unwrap();
+    let args = match args.config {
+        Some(ref path) => {
+            let template = std::fs::read_to_string(expand_path(path)?)?;
+            Args::from_args_with_toml(&template).unwrap()
+        }
+        None => args,
+    };
+
+    simplelog::TermLogger::init(
+        get_log_level(args.verbose),
+        get_log_config(args.verbose),
+        simplelog::TerminalMode::Mixed,
+        simplelog::ColorChoice::Auto,
+    )?;
+
+    let n_threads = std::thread::available_parallelism().unwrap().get();
+    let ex = Arc::new(smol::Executor::new());
+    let (signal, shutdown) = smol::channel::unbounded::<()>();
+    let (_, result) = easy_parallel::Parallel::new()
+        .each(0..n_threads, |_| smol::future::block_on(ex.run(shutdown.recv())))
+        .finish(|| {
+            smol::future::block_on(async {
+                realmain(args, ex.clone()).await?;
+                drop(signal);
+                Ok::<(), Error>(())
+            })
+        });
+
+    result
+}
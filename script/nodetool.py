@@ -120,6 +120,10 @@ async def main(argv):
                 slot = info["slot"]
                 err = info["err"]
                 print(f"{current_time}  slot {slot}: disconnected err='{err}'")
+            case "outbound_slot_stalled":
+                slot = info["slot"]
+                addr = info["addr"]
+                print(f"{current_time}  slot {slot}: stalled      addr={addr}")
             case "outbound_peer_discovery":
                 attempt = info["attempt"]
                 state = info["state"]
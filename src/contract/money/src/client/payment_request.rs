@@ -0,0 +1,172 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Payment requests exchanged between wallets and merchants as
+//! `darkfi:` URIs, in the form:
+//!
+//! `darkfi:<address>?token=<token_id>&amount=<amount>&memo=<memo>`
+//!
+//! All parameters are optional, but an amount requires a token. Amounts
+//! are decimal strings with up to [`PAYMENT_REQUEST_DECIMALS`] decimal
+//! places, and the memo is percent-encoded. Unknown parameters are
+//! ignored, unless they are prefixed with `req-`, in which case the
+//! request is rejected, as the payer can't know what it requires.
+
+use std::{fmt, str::FromStr};
+
+use darkfi::{
+    util::parse::{decode_base10, encode_base10},
+    Error, Result,
+};
+use darkfi_sdk::crypto::PublicKey;
+
+use crate::model::TokenId;
+
+/// URI scheme of payment requests
+pub const PAYMENT_REQUEST_SCHEME: &str = "darkfi";
+
+/// Decimal places of the amounts in payment request URIs
+pub const PAYMENT_REQUEST_DECIMALS: usize = 8;
+
+/// A structured request for a payment to `recipient`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaymentRequest {
+    /// Address the payment should be sent to
+    pub recipient: PublicKey,
+    /// Requested token
+    pub token_id: Option<TokenId>,
+    /// Requested amount of the token
+    pub amount: Option<u64>,
+    /// Free-form payment description
+    pub memo: Option<String>,
+}
+
+impl PaymentRequest {
+    /// Create a payment request for `recipient`, without any parameters
+    pub fn new(recipient: PublicKey) -> Self {
+        Self { recipient, token_id: None, amount: None, memo: None }
+    }
+}
+
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{PAYMENT_REQUEST_SCHEME}:{}", self.recipient)?;
+
+        let mut params = vec![];
+        if let Some(token_id) = self.token_id {
+            params.push(format!("token={token_id}"));
+        }
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={}", encode_base10(amount, PAYMENT_REQUEST_DECIMALS)));
+        }
+        if let Some(ref memo) = self.memo {
+            params.push(format!("memo={}", percent_encode(memo)));
+        }
+
+        if !params.is_empty() {
+            write!(f, "?{}", params.join("&"))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for PaymentRequest {
+    type Err = Error;
+
+    fn from_str(uri: &str) -> Result<Self> {
+        let Some((scheme, rest)) = uri.trim().split_once(':') else {
+            return Err(Error::ParseFailed("Missing payment request scheme"))
+        };
+        if !scheme.eq_ignore_ascii_case(PAYMENT_REQUEST_SCHEME) {
+            return Err(Error::ParseFailed("Invalid payment request scheme"))
+        }
+
+        let (recipient, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let Ok(recipient) = PublicKey::from_str(recipient) else {
+            return Err(Error::ParseFailed("Invalid payment request address"))
+        };
+
+        let mut request = Self::new(recipient);
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            match key {
+                "token" => {
+                    let Ok(token_id) = TokenId::from_str(value) else {
+                        return Err(Error::ParseFailed("Invalid payment request token"))
+                    };
+                    request.token_id = Some(token_id);
+                }
+                "amount" => {
+                    let amount = decode_base10(value, PAYMENT_REQUEST_DECIMALS, true)?;
+                    if amount == 0 {
+                        return Err(Error::ParseFailed("Invalid payment request amount"))
+                    }
+                    request.amount = Some(amount);
+                }
+                "memo" => request.memo = Some(percent_decode(value)?),
+                _ if key.starts_with("req-") => {
+                    return Err(Error::ParseFailed("Unsupported required payment request parameter"))
+                }
+                _ => {}
+            }
+        }
+
+        if request.amount.is_some() && request.token_id.is_none() {
+            return Err(Error::ParseFailed("Payment request amount without a token"))
+        }
+
+        Ok(request)
+    }
+}
+
+/// Percent-encode everything but the URI unreserved characters
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Decode a percent-encoded string, treating `+` as a space
+fn percent_decode(s: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'%' => {
+                let hex = [iter.next().unwrap_or(0), iter.next().unwrap_or(0)];
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return Err(Error::ParseFailed("Invalid percent-encoding"))
+                }
+                // Safe to unwrap as we checked these are hex digits
+                let hex = std::str::from_utf8(&hex).unwrap();
+                bytes.push(u8::from_str_radix(hex, 16).unwrap());
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| Error::ParseFailed("Invalid UTF-8 in percent-encoding"))
+}
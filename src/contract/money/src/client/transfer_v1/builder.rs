@@ -38,6 +38,9 @@ use crate::{
     model::{CoinAttributes, Input, MoneyTransferParamsV1, Output},
 };
 
+/// Maximum size in bytes of a memo attached to a transfer output
+pub const MAX_MEMO_SIZE: usize = 256;
+
 /// Struct holding necessary information to build a `Money::TransferV1` contract call.
 pub struct TransferCallBuilder {
     /// Clear inputs
@@ -46,6 +49,10 @@ pub struct TransferCallBuilder {
     pub inputs: Vec<TransferCallInput>,
     /// Anonymous outputs
     pub outputs: Vec<TransferCallOutput>,
+    /// Memos attached to the outputs, by output index. Outputs without
+    /// a memo here get an empty one. Memos are encrypted along with the
+    /// output note, so only its recipient can read them.
+    pub output_memos: Vec<Vec<u8>>,
     /// `Mint_V1` zkas circuit ZkBinary
     pub mint_zkbin: ZkBinary,
     /// Proving key for the `Mint_V1` zk circuit
@@ -128,6 +135,19 @@ impl TransferCallBuilder {
             )
         }
 
+        if self.output_memos.len() > self.outputs.len() {
+            return Err(ClientFailed::VerifyError("More memos than outputs".to_string()).into())
+        }
+
+        if let Some(memo) = self.output_memos.iter().find(|m| m.len() > MAX_MEMO_SIZE) {
+            return Err(ClientFailed::VerifyError(format!(
+                "Memo of {} bytes exceeds the maximum of {} bytes",
+                memo.len(),
+                MAX_MEMO_SIZE
+            ))
+            .into())
+        }
+
         let mut output_notes = vec![];
 
         for (i, output) in self.outputs.iter().enumerate() {
@@ -162,7 +182,7 @@ impl TransferCallBuilder {
                 coin_blind: output.blind,
                 value_blind,
                 token_blind,
-                memo: vec![],
+                memo: self.output_memos.get(i).cloned().unwrap_or_default(),
             };
 
             let encrypted_note = AeadEncryptedNote::encrypt(&note, &output.public_key, &mut OsRng)?;
@@ -33,7 +33,7 @@ use crate::{
 mod builder;
 pub use builder::{
     TransferCallBuilder, TransferCallClearInput, TransferCallInput, TransferCallOutput,
-    TransferCallSecrets,
+    TransferCallSecrets, MAX_MEMO_SIZE,
 };
 
 pub(crate) mod proof;
@@ -76,6 +76,8 @@ pub fn select_coins(coins: Vec<OwnCoin>, min_value: u64) -> Result<(Vec<OwnCoin>
 ///    the output, not applicable to the change
 /// * `output_user_data: Optional user data to use in the output,
 ///    not applicable to the change
+/// * `memo`: Memo encrypted to the recipient along with the output,
+///    not applicable to the change
 /// * `mint_zkbin`: `Mint_V1` zkas circuit ZkBinary
 /// * `mint_pk`: Proving key for the `Mint_V1` zk circuit
 /// * `burn_zkbin`: `Burn_V1` zkas circuit ZkBinary
@@ -98,6 +100,7 @@ pub fn make_transfer_call(
     tree: MerkleTree,
     output_spend_hook: Option<FuncId>,
     output_user_data: Option<pallas::Base>,
+    memo: Vec<u8>,
     mint_zkbin: ZkBinary,
     mint_pk: ProvingKey,
     burn_zkbin: ZkBinary,
@@ -188,6 +191,9 @@ pub fn make_transfer_call(
         });
     }
 
+    // The memo goes to the recipient outputs only
+    let output_memos = vec![memo; outputs.len()];
+
     if change_value > 0 {
         outputs.push(TransferCallOutput {
             public_key: keypair.public,
@@ -208,6 +214,124 @@ pub fn make_transfer_call(
         clear_inputs: vec![],
         inputs,
         outputs,
+        output_memos,
+        mint_zkbin,
+        mint_pk,
+        burn_zkbin,
+        burn_pk,
+    };
+
+    let (params, secrets) = xfer_builder.build()?;
+
+    Ok((params, secrets, spent_coins))
+}
+
+/// Make an anonymous transfer call paying several recipients of the same
+/// token at once, so a batch of payments needs a single call.
+///
+/// * `keypair`: Caller's keypair
+/// * `recipients`: Recipients' public keys along with the amount each
+///    of them receives
+/// * `token_id`: Token ID that we want to send to the recipients
+/// * `coins`: Set of `OwnCoin` we're given to use in this builder
+/// * `tree`: Merkle tree of coins used to create inclusion proofs
+/// * `mint_zkbin`: `Mint_V1` zkas circuit ZkBinary
+/// * `mint_pk`: Proving key for the `Mint_V1` zk circuit
+/// * `burn_zkbin`: `Burn_V1` zkas circuit ZkBinary
+/// * `burn_pk`: Proving key for the `Burn_V1` zk circuit
+///
+/// Returns a tuple of:
+///
+/// * The actual call data
+/// * Secret values such as blinds
+/// * A list of the spent coins
+#[allow(clippy::too_many_arguments)]
+pub fn make_transfer_many_call(
+    keypair: Keypair,
+    recipients: Vec<(PublicKey, u64)>,
+    token_id: TokenId,
+    coins: Vec<OwnCoin>,
+    tree: MerkleTree,
+    mint_zkbin: ZkBinary,
+    mint_pk: ProvingKey,
+    burn_zkbin: ZkBinary,
+    burn_pk: ProvingKey,
+) -> Result<(MoneyTransferParamsV1, TransferCallSecrets, Vec<OwnCoin>)> {
+    debug!(target: "contract::money::client::transfer", "Building Money::TransferV1 contract call with {} recipients", recipients.len());
+    if recipients.is_empty() {
+        return Err(ClientFailed::VerifyError(MoneyError::TransferMissingOutputs.to_string()).into())
+    }
+
+    let mut value: u64 = 0;
+    for (_, amount) in &recipients {
+        if *amount == 0 {
+            return Err(ClientFailed::InvalidAmount(*amount).into())
+        }
+
+        let Some(sum) = value.checked_add(*amount) else {
+            return Err(ClientFailed::InvalidAmount(*amount).into())
+        };
+        value = sum;
+    }
+
+    if token_id.inner() == pallas::Base::ZERO {
+        return Err(ClientFailed::InvalidTokenId(token_id.to_string()).into())
+    }
+
+    if coins.is_empty() {
+        return Err(ClientFailed::VerifyError(MoneyError::TransferMissingInputs.to_string()).into())
+    }
+
+    // Ensure the coins given to us are all of the same token ID.
+    // The money contract base transfer doesn't allow conversions.
+    for coin in &coins {
+        if coin.note.token_id != token_id {
+            return Err(ClientFailed::InvalidTokenId(coin.note.token_id.to_string()).into())
+        }
+    }
+
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+
+    let (spent_coins, change_value) = select_coins(coins, value)?;
+
+    for coin in spent_coins.iter() {
+        let input = TransferCallInput {
+            coin: coin.clone(),
+            merkle_path: tree.witness(coin.leaf_position, 0).unwrap(),
+            user_data_blind: Blind::random(&mut OsRng),
+        };
+
+        inputs.push(input);
+    }
+
+    for (public_key, value) in recipients {
+        outputs.push(TransferCallOutput {
+            public_key,
+            value,
+            token_id,
+            spend_hook: FuncId::none(),
+            user_data: pallas::Base::ZERO,
+            blind: Blind::random(&mut OsRng),
+        });
+    }
+
+    if change_value > 0 {
+        outputs.push(TransferCallOutput {
+            public_key: keypair.public,
+            value: change_value,
+            token_id,
+            spend_hook: FuncId::none(),
+            user_data: pallas::Base::ZERO,
+            blind: Blind::random(&mut OsRng),
+        });
+    }
+
+    let xfer_builder = TransferCallBuilder {
+        clear_inputs: vec![],
+        inputs,
+        outputs,
+        output_memos: vec![],
         mint_zkbin,
         mint_pk,
         burn_zkbin,
@@ -94,6 +94,7 @@ fn delayed_tx() -> Result<()> {
             money_merkle_tree.clone(),
             None,
             None,
+            vec![],
             mint_zkbin.clone(),
             mint_pk.clone(),
             burn_zkbin.clone(),
@@ -0,0 +1,58 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use darkfi_money_contract::{client::payment_request::PaymentRequest, model::DARK_TOKEN_ID};
+use darkfi_sdk::crypto::Keypair;
+use rand::rngs::OsRng;
+
+#[test]
+fn payment_request() {
+    let recipient = Keypair::random(&mut OsRng).public;
+
+    // Bare address
+    let request = PaymentRequest::new(recipient);
+    let uri = request.to_string();
+    assert_eq!(uri, format!("darkfi:{recipient}"));
+    assert_eq!(uri.parse::<PaymentRequest>().unwrap(), request);
+
+    // All parameters, with a memo needing encoding
+    let mut request = PaymentRequest::new(recipient);
+    request.token_id = Some(*DARK_TOKEN_ID);
+    request.amount = Some(150000000);
+    request.memo = Some("Invoice #42 & co".to_string());
+    let uri = request.to_string();
+    assert_eq!(
+        uri,
+        format!(
+            "darkfi:{recipient}?token={}&amount=1.5&memo=Invoice%20%2342%20%26%20co",
+            *DARK_TOKEN_ID
+        )
+    );
+    assert_eq!(uri.parse::<PaymentRequest>().unwrap(), request);
+
+    // Unknown parameters are ignored, unless required
+    let parsed: PaymentRequest = format!("DARKFI:{recipient}?label=shop").parse().unwrap();
+    assert_eq!(parsed, PaymentRequest::new(recipient));
+    assert!(format!("darkfi:{recipient}?req-label=shop").parse::<PaymentRequest>().is_err());
+
+    // Malformed requests
+    assert!(format!("bitcoin:{recipient}").parse::<PaymentRequest>().is_err());
+    assert!("darkfi:notanaddress".parse::<PaymentRequest>().is_err());
+    assert!(format!("darkfi:{recipient}?amount=1").parse::<PaymentRequest>().is_err());
+    assert!(format!("darkfi:{recipient}?memo=%4").parse::<PaymentRequest>().is_err());
+}
@@ -123,6 +123,7 @@ impl TestHarness {
             clear_inputs: vec![],
             inputs,
             outputs,
+            output_memos: vec![],
             mint_zkbin: mint_zkbin.clone(),
             mint_pk: mint_pk.clone(),
             burn_zkbin: burn_zkbin.clone(),
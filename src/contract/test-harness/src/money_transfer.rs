@@ -64,6 +64,7 @@ impl TestHarness {
             wallet.money_merkle_tree.clone(),
             None,
             None,
+            vec![],
             mint_zkbin.clone(),
             mint_pk.clone(),
             burn_zkbin.clone(),
@@ -192,6 +192,18 @@ pub enum Error {
     #[error("Invalid state transition: current_state={0}, end_state={1}")]
     HostStateBlocked(String, String),
 
+    #[error("Peer is not in the private network allowlist")]
+    PeerNotAllowed,
+
+    #[error("Duplicate channel to an already connected peer")]
+    DuplicateChannel,
+
+    #[error("Port mapping failed: {0}")]
+    PortMappingFailed(String),
+
+    #[error("Frame encryption failed")]
+    FrameEncryptionFailed,
+
     // =============
     // Crypto errors
     // =============
@@ -245,8 +257,9 @@ pub enum Error {
     #[error("Unsupported chain")]
     UnsupportedChain,
 
-    #[error("JSON-RPC error: {0:?}")]
-    JsonRpcError((i32, String)),
+    #[cfg(feature = "rpc")]
+    #[error("JSON-RPC error: {0}")]
+    JsonRpcError(crate::rpc::jsonrpc::JsonErrorVal),
 
     #[cfg(feature = "rpc")]
     #[error(transparent)]
@@ -256,6 +269,9 @@ pub enum Error {
     #[error("JSON-RPC connections exhausted")]
     RpcConnectionsExhausted,
 
+    #[error("JSON-RPC connection idle")]
+    RpcConnectionIdle,
+
     #[cfg(feature = "rpc")]
     #[error("JSON-RPC server stopped")]
     RpcServerStopped,
@@ -151,6 +151,18 @@ impl Acceptor {
                         continue
                     }
 
+                    // Refuse new peers once the daily data cap is reached
+                    if self.session.upgrade().unwrap().p2p().data_cap_reached().await {
+                        warn!(target: "net::acceptor::run_accept_loop()", "Daily data cap reached, refusing {}", url);
+                        continue
+                    }
+
+                    // In private network mode, only allowlisted peers get in
+                    if !hosts.is_allowed(&url, true).await {
+                        warn!(target: "net::acceptor::run_accept_loop()", "Peer {} is not allowlisted", url);
+                        continue
+                    }
+
                     // Create the new Channel.
                     let session = self.session.clone();
                     let channel = Channel::new(stream, None, url, session).await;
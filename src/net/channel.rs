@@ -19,30 +19,39 @@
 use std::{
     fmt,
     sync::{
-        atomic::{AtomicBool, Ordering::SeqCst},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering::SeqCst},
+        Arc, Mutex as SyncMutex,
     },
     time::UNIX_EPOCH,
 };
 
 use darkfi_serial::{
-    async_trait, AsyncDecodable, AsyncEncodable, SerialDecodable, SerialEncodable, VarInt,
+    async_trait, deserialize, AsyncDecodable, AsyncEncodable, SerialDecodable, SerialEncodable,
+    VarInt,
 };
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 use rand::{rngs::OsRng, Rng};
 use smol::{
     io::{self, AsyncRead, AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
     lock::Mutex,
-    Executor,
+    Executor, Timer,
 };
 use url::Url;
 
 use super::{
     dnet::{self, dnetev, DnetEvent},
+    encryption::{FrameCipher, KeyExchange},
     hosts::HostColor,
+    identity::PeerId,
     message,
-    message::{SerializedMessage, VersionMessage, MAGIC_BYTES},
+    message::{
+        decompress_payload, sequenced_frame, KeyExchangeMessage, Message, SerializedMessage,
+        VerackMessage, VersionMessage, COMPRESSED_MAGIC_BYTES, COMPRESSION_FEATURE,
+        ENCRYPTED_MAGIC_BYTES, ENCRYPTION_FEATURE, MAGIC_BYTES, MAX_COMMAND_LEN, MAX_PAYLOAD_LEN,
+        SEQUENCED_COMPRESSED_MAGIC_BYTES, SEQUENCED_MAGIC_BYTES, SEQUENCE_FEATURE, SEQUENCE_LEN,
+    },
     message_publisher::{MessageSubscription, MessageSubsystem},
+    metering::{CompressionCounters, CompressionStats, RateLimiter},
     p2p::P2pPtr,
     session::{
         Session, SessionBitFlag, SessionWeakPtr, SESSION_ALL, SESSION_INBOUND, SESSION_REFINE,
@@ -59,6 +68,76 @@ use crate::{
 /// Atomic pointer to async channel
 pub type ChannelPtr = Arc<Channel>;
 
+/// Capacity of the payload receive buffer kept between messages
+const RECV_BUFFER_RETAIN: usize = 1024 * 1024;
+
+/// Largest sealed frame accepted from peers: a payload of the largest
+/// size along with the magic bytes, sequence number, command, length
+/// prefixes and authentication tag wrapping it
+const MAX_SEALED_LEN: u64 = MAX_PAYLOAD_LEN + MAX_COMMAND_LEN + 64;
+
+/// Messages peers may send in cleartext when we require encryption
+const CLEARTEXT_COMMANDS: &[&str] =
+    &[VersionMessage::NAME, VerackMessage::NAME, KeyExchangeMessage::NAME];
+
+/// Reason a channel was stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The peer closed the connection, or the transport failed
+    RemoteClose,
+    /// The peer did not respond in time
+    Timeout,
+    /// The peer violated the protocol
+    ProtocolViolation,
+    /// We closed the channel ourselves
+    LocalShutdown,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Self::RemoteClose => "remote_close",
+            Self::Timeout => "timeout",
+            Self::ProtocolViolation => "protocol_violation",
+            Self::LocalShutdown => "local_shutdown",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Kind of protocol error seen on a channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// A message payload failed to decode
+    Decode,
+    /// A message of a type we have no dispatcher for
+    UnknownMessage,
+    /// The peer didn't reply to a protocol message in time
+    Timeout,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Self::Decode => "decode",
+            Self::UnknownMessage => "unknown_message",
+            Self::Timeout => "timeout",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Header of a frame read from the stream
+struct FrameHeader {
+    command: String,
+    /// Whether the payload is compressed
+    compressed: bool,
+    /// Sequence number, if the frame has one
+    seq: Option<u64>,
+    /// Size of the encrypted frame carrying this one, if it was encrypted
+    encrypted_len: Option<u64>,
+}
+
 /// Channel debug info
 #[derive(Clone, Debug, SerialEncodable, SerialDecodable)]
 pub struct ChannelInfo {
@@ -88,12 +167,55 @@ pub struct Channel {
     receive_task: StoppableTaskPtr,
     /// A boolean marking if this channel is stopped
     stopped: AtomicBool,
+    /// Why this channel is being stopped, if known
+    stop_reason: SyncMutex<Option<StopReason>>,
+    /// Rate limiter for the data we receive from the peer
+    recv_limiter: RateLimiter,
+    /// Rate limiter for the data we send to the peer
+    send_limiter: RateLimiter,
+    /// Minimum payload size of compressed messages, `None` if we don't
+    /// do compression
+    compression_threshold: Option<usize>,
+    /// Set once the peer advertised support for compressed payloads
+    compression: AtomicBool,
+    /// Counters of the messages sent and received compressed
+    compression_counters: CompressionCounters,
+    /// Whether we accept sequenced messages
+    sequencing: bool,
+    /// Set once the peer advertised support for sequence numbers. From
+    /// then on, the messages we send are numbered.
+    send_sequenced: AtomicBool,
+    /// Sequence number of the next message we send
+    send_seq: AtomicU64,
+    /// Our ephemeral key for channel encryption, `None` if we don't do
+    /// encryption
+    key_exchange: Option<KeyExchange>,
+    /// Whether we refuse cleartext messages after the version exchange
+    require_encryption: bool,
+    /// Set once the peer advertised support for encryption
+    encryption: AtomicBool,
+    /// Set once our key exchange message went out. The frames we send
+    /// from then on are encrypted, as soon as the keys are agreed.
+    key_sent: AtomicBool,
+    /// Cipher of the frames we send, once the keys are agreed
+    send_cipher: SyncMutex<Option<FrameCipher>>,
+    /// Cipher of the frames we receive, once the keys are agreed
+    recv_cipher: SyncMutex<Option<FrameCipher>>,
+    /// Key exchange transcripts signed by us and by the peer respectively
+    /// to prove their identity, once the keys are agreed
+    transcripts: SyncMutex<Option<([u8; 32], [u8; 32])>>,
     /// Weak pointer to respective session
     pub(in crate::net) session: SessionWeakPtr,
     /// The version message of the node we are connected to.
     /// Some if the version exchange has already occurred, None
     /// otherwise.
     pub version: Mutex<Option<Arc<VersionMessage>>>,
+    /// Peer ID the node we are connected to proved over the encrypted
+    /// channel, `None` until then or if it didn't
+    peer_id: SyncMutex<Option<PeerId>>,
+    /// Nonce identifying the node we are connected to, `None` until the
+    /// version exchange is done or if the peer doesn't send one
+    peer_nonce: SyncMutex<Option<u64>>,
     /// Channel debug info
     pub info: ChannelInfo,
 }
@@ -119,6 +241,17 @@ impl Channel {
         let start_time = UNIX_EPOCH.elapsed().unwrap().as_secs();
         let info = ChannelInfo::new(resolve_addr, connect_addr.clone(), start_time);
 
+        let settings = session.upgrade().unwrap().p2p().settings().read_arc().await;
+        let recv_limiter = RateLimiter::new(settings.channel_recv_rate_limit);
+        let send_limiter = RateLimiter::new(settings.channel_send_rate_limit);
+        let compression_threshold =
+            settings.channel_compression.then_some(settings.channel_compression_threshold);
+        let sequencing = settings.channel_sequencing;
+        let key_exchange = (settings.channel_encryption || settings.channel_require_encryption)
+            .then(KeyExchange::new);
+        let require_encryption = settings.channel_require_encryption;
+        drop(settings);
+
         Arc::new(Self {
             reader,
             writer,
@@ -126,8 +259,26 @@ impl Channel {
             stop_publisher: Publisher::new(),
             receive_task: StoppableTask::new(),
             stopped: AtomicBool::new(false),
+            stop_reason: SyncMutex::new(None),
+            recv_limiter,
+            send_limiter,
+            compression_threshold,
+            compression: AtomicBool::new(false),
+            compression_counters: CompressionCounters::default(),
+            sequencing,
+            send_sequenced: AtomicBool::new(false),
+            send_seq: AtomicU64::new(0),
+            key_exchange,
+            require_encryption,
+            encryption: AtomicBool::new(false),
+            key_sent: AtomicBool::new(false),
+            send_cipher: SyncMutex::new(None),
+            recv_cipher: SyncMutex::new(None),
+            transcripts: SyncMutex::new(None),
             session,
             version,
+            peer_id: SyncMutex::new(None),
+            peer_nonce: SyncMutex::new(None),
             info,
         })
     }
@@ -136,6 +287,8 @@ impl Channel {
     async fn setup_dispatchers(subsystem: &MessageSubsystem) {
         subsystem.add_dispatch::<message::VersionMessage>().await;
         subsystem.add_dispatch::<message::VerackMessage>().await;
+        subsystem.add_dispatch::<message::NodeNonceMessage>().await;
+        subsystem.add_dispatch::<message::IdentityMessage>().await;
         subsystem.add_dispatch::<message::PingMessage>().await;
         subsystem.add_dispatch::<message::PongMessage>().await;
         subsystem.add_dispatch::<message::GetAddrsMessage>().await;
@@ -166,6 +319,27 @@ impl Channel {
         debug!(target: "net::channel::stop()", "END {:?}", self);
     }
 
+    /// Stops the channel, recording why it was stopped in the hosts.
+    /// Channels stopped without a reason are recorded as a local shutdown.
+    pub async fn stop_with_reason(&self, reason: StopReason) {
+        self.set_stop_reason(reason);
+        self.stop().await;
+    }
+
+    /// Record a protocol error seen on this channel in the hosts, where
+    /// they are kept per peer across reconnections.
+    pub(in crate::net) fn record_protocol_error(&self, error: ProtocolError) {
+        if let Some(session) = self.session.upgrade() {
+            session.p2p().hosts().record_protocol_error(&self.stats_addr(), error);
+        }
+    }
+
+    /// Record why this channel is being stopped. Only the first reason
+    /// is kept.
+    fn set_stop_reason(&self, reason: StopReason) {
+        self.stop_reason.lock().unwrap().get_or_insert(reason);
+    }
+
     /// Creates a subscription to a stopped signal.
     /// If the channel is stopped then this will return a ChannelStopped error.
     pub async fn subscribe_stop(&self) -> Result<Subscription<Error>> {
@@ -214,7 +388,7 @@ impl Channel {
                     self, e
                 );
             }
-            self.stop().await;
+            self.stop_with_reason(StopReason::RemoteClose).await;
             return Err(Error::ChannelStopped)
         }
 
@@ -226,13 +400,19 @@ impl Channel {
         Ok(())
     }
 
-    /// Sends the encoded payload of provided `SerializedMessage` by writing
-    /// the data to the channel async stream.
+    /// Sends the encoded frame of provided `SerializedMessage` by writing
+    /// the data to the channel async stream. The frame is written with a
+    /// single call, so it doesn't get split into several transport writes.
+    /// Payloads above the compression threshold are sent compressed if the
+    /// peer supports it, and cleartext frames are numbered if the peer
+    /// supports sequencing. Numbers are taken while holding the writer lock,
+    /// so they go out in order. The same goes for the nonces of encrypted
+    /// frames, which are sent once the keys are agreed with the peer and
+    /// take the place of sequence numbers.
     async fn send_message(&self, message: &SerializedMessage) -> Result<()> {
         assert!(!message.command.is_empty());
 
         let stream = &mut *self.writer.lock().await;
-        let mut written: usize = 0;
 
         dnetev!(self, SendMessage, {
             chan: self.info.clone(),
@@ -240,52 +420,149 @@ impl Channel {
             time: NanoTimestamp::current_time(),
         });
 
-        trace!(target: "net::channel::send_message()", "Sending magic...");
-        written += MAGIC_BYTES.encode_async(stream).await?;
-        trace!(target: "net::channel::send_message()", "Sent magic");
+        let mut frame = message.frame();
+        if self.compression.load(SeqCst) &&
+            message.payload().len() >= self.compression_threshold.unwrap_or(usize::MAX)
+        {
+            if let Some(compressed) = message.compressed_frame() {
+                self.compression_counters.add_sent(frame.len() as u64, compressed.len() as u64);
+                frame = compressed;
+            }
+        }
+
+        // Decided once, as the keys may get agreed on in the meantime
+        let encrypting = self.is_encrypted();
 
-        trace!(target: "net::channel::send_message()", "Sending command...");
-        written += message.command.encode_async(stream).await?;
-        trace!(target: "net::channel::send_message()", "Sent command: {}", message.command);
+        let sequenced;
+        if self.send_sequenced.load(SeqCst) && !encrypting {
+            sequenced = sequenced_frame(frame, self.send_seq.fetch_add(1, SeqCst));
+            frame = &sequenced;
+        }
 
-        trace!(target: "net::channel::send_message()", "Sending payload...");
-        // First extract the length of the payload as a VarInt and write it to the stream.
-        written += VarInt(message.payload.len() as u64).encode_async(stream).await?;
-        // Then write the encoded payload itself to the stream.
-        stream.write_all(&message.payload).await?;
-        written += message.payload.len();
+        let encrypted;
+        if encrypting {
+            if let Some(cipher) = self.send_cipher.lock().unwrap().as_mut() {
+                encrypted = cipher.seal(frame)?;
+                frame = &encrypted;
+            }
+        }
 
-        trace!(target: "net::channel::send_message()", "Sent payload {} bytes, total bytes {}",
-            message.payload.len(), written);
+        // Stay within the configured send rate
+        let delay = self.send_limiter.consume(frame.len() as u64);
+        if !delay.is_zero() {
+            Timer::after(delay).await;
+        }
 
+        trace!(target: "net::channel::send_message()", "Sending command {}...", message.command);
+        stream.write_all(frame).await?;
         stream.flush().await?;
+        trace!(target: "net::channel::send_message()", "Sent payload {} bytes, total bytes {}",
+            message.payload().len(), frame.len());
+
+        self.p2p().data_meter().add_sent(frame.len() as u64);
+
+        // The peer gets our key in cleartext, and everything after it
+        // encrypted
+        if message.command == KeyExchangeMessage::NAME {
+            self.key_sent.store(true, SeqCst);
+        }
 
         Ok(())
     }
 
-    /// Returns a decoded Message command. We start by extracting the length
-    /// from the stream, then allocate the precise buffer for this length
-    /// using stream.take(). This manual deserialization provides a basic
-    /// DDOS protection, since it prevents nodes from sending an arbitarily
-    /// large payload.
-    pub async fn read_command<R: AsyncRead + Unpin + Send + Sized>(
+    /// Reads the next frame from the stream into its header and `buf`,
+    /// opening it first if it's encrypted.
+    async fn read_frame<R: AsyncRead + Unpin + Send + Sized>(
         &self,
         stream: &mut R,
-    ) -> Result<String> {
+        buf: &mut Vec<u8>,
+    ) -> Result<FrameHeader> {
         // Messages should have a 4 byte header of magic digits.
         // This is used for network debugging.
         let mut magic = [0u8; 4];
-        trace!(target: "net::channel::read_command()", "Reading magic...");
+        trace!(target: "net::channel::read_frame()", "Reading magic...");
         stream.read_exact(&mut magic).await?;
 
+        if magic != ENCRYPTED_MAGIC_BYTES {
+            let (command, compressed, seq) = self.read_command(magic, stream).await?;
+            self.check_command(&command).await?;
+            self.read_payload(stream, buf, MAX_PAYLOAD_LEN).await?;
+            return Ok(FrameHeader { command, compressed, seq, encrypted_len: None })
+        }
+
+        // The sealed frame is read like a payload, and opened with
+        // the keys agreed with the peer
+        self.read_payload(stream, buf, MAX_SEALED_LEN).await?;
+        let encrypted_len = frame_len_of(ENCRYPTED_MAGIC_BYTES.len(), buf.len());
+        let frame = match self.recv_cipher.lock().unwrap().as_mut() {
+            Some(cipher) => cipher.open(buf)?,
+            None => {
+                error!(target: "net::channel::read_frame", "Error: Unexpected encrypted frame");
+                return Err(Error::MalformedPacket)
+            }
+        };
+
+        // The frame it carries is read the same way, from memory. Being
+        // authenticated, any garbage in there is a protocol violation.
+        let mut frame = &frame[..];
+        let read = async {
+            let mut magic = [0u8; 4];
+            frame.read_exact(&mut magic).await?;
+            let command = self.read_command(magic, &mut frame).await?;
+            self.check_command(&command.0).await?;
+            self.read_payload(&mut frame, buf, MAX_PAYLOAD_LEN).await?;
+            Ok::<_, Error>(command)
+        };
+        let (command, compressed, seq) = match read.await {
+            Ok(header) => header,
+            Err(Error::MissingDispatcher) => return Err(Error::MissingDispatcher),
+            Err(_) => return Err(Error::MalformedPacket),
+        };
+        if !frame.is_empty() {
+            return Err(Error::MalformedPacket)
+        }
+
+        Ok(FrameHeader { command, compressed, seq, encrypted_len: Some(encrypted_len) })
+    }
+
+    /// Returns a decoded Message command of a frame with the given magic
+    /// bytes, whether its payload is compressed, and its sequence number
+    /// if it has one. We start by extracting the length from the stream,
+    /// then allocate the precise buffer for this length using stream.take().
+    /// This manual deserialization provides a basic DDOS protection, since
+    /// it prevents nodes from sending an arbitarily large payload.
+    pub async fn read_command<R: AsyncRead + Unpin + Send + Sized>(
+        &self,
+        magic: [u8; 4],
+        stream: &mut R,
+    ) -> Result<(String, bool, Option<u64>)> {
         trace!(target: "net::channel::read_command()", "Read magic {:?}", magic);
-        if magic != MAGIC_BYTES {
-            error!(target: "net::channel::read_command", "Error: Magic bytes mismatch");
+        let (compressed, sequenced) = match magic {
+            MAGIC_BYTES => (false, false),
+            COMPRESSED_MAGIC_BYTES => (true, false),
+            SEQUENCED_MAGIC_BYTES => (false, true),
+            SEQUENCED_COMPRESSED_MAGIC_BYTES => (true, true),
+            _ => {
+                error!(target: "net::channel::read_command", "Error: Magic bytes mismatch");
+                return Err(Error::MalformedPacket)
+            }
+        };
+
+        // Compressed and sequenced frames are only valid if we advertised
+        // support for them
+        if (compressed && self.compression_threshold.is_none()) || (sequenced && !self.sequencing) {
+            error!(target: "net::channel::read_command", "Error: Unsupported frame {:?}", magic);
             return Err(Error::MalformedPacket)
         }
 
+        let seq = if sequenced { Some(u64::decode_async(stream).await?) } else { None };
+
         // First extract the length from the stream
         let cmd_len = VarInt::decode_async(stream).await?.0;
+        if cmd_len > MAX_COMMAND_LEN {
+            error!(target: "net::channel::read_command", "Error: Command too long");
+            return Err(Error::MalformedPacket)
+        }
 
         // Then extract precisely `cmd_len` items from the stream.
         let mut take = stream.take(cmd_len);
@@ -296,7 +573,57 @@ impl Channel {
 
         let command = String::from_utf8(bytes)?;
 
-        Ok(command)
+        Ok((command, compressed, seq))
+    }
+
+    /// Refuse messages we have no dispatcher for before reading their
+    /// payload, so peers can't make us buffer messages we'd drop anyway.
+    async fn check_command(&self, command: &str) -> Result<()> {
+        // Key exchange messages are handled by the channel itself
+        if command == KeyExchangeMessage::NAME ||
+            self.message_subsystem.has_dispatcher(command).await
+        {
+            return Ok(())
+        }
+
+        warn!(
+            target: "net::channel::check_command()",
+            "Command '{}' from {} did not find a dispatcher", command, self.address(),
+        );
+        Err(Error::MissingDispatcher)
+    }
+
+    /// Reads a message payload of at most `max_len` bytes from the stream
+    /// into `buf`, reusing its allocation across messages. The payload is
+    /// read in one go and then decoded from memory, instead of issuing a
+    /// transport read per field. Payloads announcing more than `max_len`
+    /// are refused before anything gets read.
+    async fn read_payload<R: AsyncRead + Unpin + Send + Sized>(
+        &self,
+        stream: &mut R,
+        buf: &mut Vec<u8>,
+        max_len: u64,
+    ) -> Result<()> {
+        buf.clear();
+        // Don't hold on to the memory of an occasional large message
+        buf.shrink_to(RECV_BUFFER_RETAIN);
+
+        let len = VarInt::decode_async(stream).await?.0;
+        if len > max_len {
+            error!(
+                target: "net::channel::read_payload()",
+                "Error: Payload of {} bytes from {} is too large", len, self.address(),
+            );
+            return Err(Error::MalformedPacket)
+        }
+
+        stream.take(len).read_to_end(buf).await?;
+
+        if buf.len() as u64 != len {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof))
+        }
+
+        Ok(())
     }
 
     /// Subscribe to a message on the message subsystem.
@@ -323,6 +650,13 @@ impl Channel {
 
         self.stopped.store(true, SeqCst);
 
+        let reason = self.stop_reason.lock().unwrap().unwrap_or(StopReason::LocalShutdown);
+        if let Some(session) = self.session.upgrade() {
+            let (hosts, stats_addr) = (session.p2p().hosts(), self.stats_addr());
+            hosts.record_stop(&stats_addr, reason);
+            hosts.remove_pings(&stats_addr);
+        }
+
         match result {
             Ok(()) => panic!("Channel task should never complete without error status"),
             // Send this error to all channel subscribers
@@ -342,11 +676,29 @@ impl Channel {
         // Acquire reader lock
         let reader = &mut *self.reader.lock().await;
 
+        // Receive buffer for message payloads
+        let mut payload = vec![];
+
+        // Sequence number of the last message received, once the peer
+        // started numbering them
+        let mut last_seq: Option<u64> = None;
+
+        // Set once the peer started encrypting its messages
+        let mut recv_encrypted = false;
+
         // Run loop
         loop {
-            let command = match self.read_command(reader).await {
-                Ok(command) => command,
+            let header = match self.read_frame(reader, &mut payload).await {
+                Ok(header) => header,
+                // If we're getting messages without dispatchers, it's spam.
+                Err(Error::MissingDispatcher) => return self.reject_unknown_message().await,
                 Err(err) => {
+                    if let Error::MalformedPacket = err {
+                        self.set_stop_reason(StopReason::ProtocolViolation);
+                    } else {
+                        self.set_stop_reason(StopReason::RemoteClose);
+                    }
+
                     if Self::is_eof_error(&err) {
                         info!(
                             target: "net::channel::main_receive_loop()",
@@ -372,69 +724,184 @@ impl Channel {
                 }
             };
 
+            let FrameHeader { command, compressed, seq, encrypted_len } = header;
+
             dnetev!(self, RecvMessage, {
                 chan: self.info.clone(),
                 cmd: command.clone(),
                 time: NanoTimestamp::current_time(),
             });
 
-            // Send result to our publishers
-            match self.message_subsystem.notify(&command, reader).await {
-                Ok(()) => {}
-                // If we're getting messages without dispatchers, it's spam.
-                Err(Error::MissingDispatcher) => {
-                    debug!(target: "net::channel::main_receive_loop()", "Stopping channel {:?}", self);
-                    if let BanPolicy::Strict = self.p2p().settings().read().await.ban_policy {
-                        self.ban(self.address()).await;
+            // Once a peer numbers its cleartext messages, it must keep
+            // doing so, one by one. Encrypted frames are ordered by the
+            // nonce of the cipher instead, which also refuses replays.
+            let valid_seq = match (encrypted_len, last_seq, seq) {
+                (Some(_), _, seq) => seq.is_none(),
+                (None, None, _) => true,
+                (None, Some(_), None) => false,
+                (None, Some(last), Some(seq)) => last.checked_add(1) == Some(seq),
+            };
+            if !valid_seq {
+                warn!(
+                    target: "net::channel::main_receive_loop()",
+                    "[P2P] Out of sequence message from {}, disconnecting",
+                    self.address(),
+                );
+                self.set_stop_reason(StopReason::ProtocolViolation);
+                return Err(Error::ChannelStopped)
+            }
+            if seq.is_some() {
+                last_seq = seq;
+            }
+
+            // Likewise, once a peer encrypts its messages it must keep doing
+            // so. If we require encryption, only the handshake may be sent
+            // in cleartext.
+            let valid_cleartext = !recv_encrypted &&
+                (!self.require_encryption || CLEARTEXT_COMMANDS.contains(&command.as_str()));
+            if encrypted_len.is_none() && !valid_cleartext {
+                warn!(
+                    target: "net::channel::main_receive_loop()",
+                    "[P2P] Cleartext message from {}, disconnecting",
+                    self.address(),
+                );
+                self.set_stop_reason(StopReason::ProtocolViolation);
+                return Err(Error::ChannelStopped)
+            }
+            if encrypted_len.is_some() {
+                recv_encrypted = true;
+            }
+
+            // Account for the magic bytes, sequence number, command and payload,
+            // or the whole encrypted frame carrying them
+            let received = encrypted_len.unwrap_or_else(|| {
+                frame_len(&command, payload.len()) + seq.map_or(0, |_| SEQUENCE_LEN as u64)
+            });
+            self.p2p().data_meter().add_received(received);
+
+            // Throttle peers sending faster than the configured rate. They
+            // can't know our limit, so reads are just paced.
+            let delay = self.recv_limiter.consume(received);
+            if !delay.is_zero() {
+                Timer::after(delay).await;
+            }
+
+            if compressed {
+                payload = match decompress_payload(&payload) {
+                    Ok(p) => p,
+                    Err(_) => {
+                        warn!(
+                            target: "net::channel::main_receive_loop()",
+                            "[P2P] Invalid compressed payload from {}, disconnecting",
+                            self.address(),
+                        );
+                        self.set_stop_reason(StopReason::ProtocolViolation);
+                        return Err(Error::ChannelStopped)
                     }
+                };
+                self.compression_counters
+                    .add_received(frame_len(&command, payload.len()), received);
+            }
 
+            // Key exchange messages are handled by the channel itself, so
+            // the keys are in place before the next frame is read.
+            if command == KeyExchangeMessage::NAME {
+                if let Err(e) = self.agree_keys(&payload) {
+                    warn!(
+                        target: "net::channel::main_receive_loop()",
+                        "[P2P] Invalid key exchange from {}: {}, disconnecting",
+                        self.address(), e,
+                    );
+                    self.set_stop_reason(StopReason::ProtocolViolation);
                     return Err(Error::ChannelStopped)
                 }
-                Err(_) => unreachable!("You added a new error in notify()"),
+                continue
+            }
+
+            // Send result to our publishers
+            match self.message_subsystem.notify(&command, &payload).await {
+                Ok(()) => {}
+                Err(Error::MissingDispatcher) => return self.reject_unknown_message().await,
+                // Messages failing to decode are dropped
+                Err(_) => self.record_protocol_error(ProtocolError::Decode),
             }
         }
     }
 
+    /// Stop the channel of a peer sending messages we have no dispatcher
+    /// for, banning it under the strict ban policy.
+    async fn reject_unknown_message(&self) -> Result<()> {
+        debug!(target: "net::channel::main_receive_loop()", "Stopping channel {:?}", self);
+        self.record_protocol_error(ProtocolError::UnknownMessage);
+        self.set_stop_reason(StopReason::ProtocolViolation);
+        if let BanPolicy::Strict = self.p2p().settings().read().await.ban_policy {
+            self.ban(self.address()).await;
+        }
+
+        Err(Error::ChannelStopped)
+    }
+
     /// Ban a malicious peer and stop the channel.
     pub async fn ban(&self, peer: &Url) {
         debug!(target: "net::channel::ban()", "START {:?}", self);
         debug!(target: "net::channel::ban()", "Peer: {:?}", peer);
 
+        let Some(peer) = self.ban_addr(peer) else { return };
+
+        let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        self.p2p().hosts().move_host(&peer, last_seen, HostColor::Black).unwrap();
+        self.stop_with_reason(StopReason::ProtocolViolation).await;
+        debug!(target: "net::channel::ban()", "STOP {:?}", self);
+    }
+
+    /// Report misbehavior of this channel's peer. Once the peer's
+    /// misbehavior score crosses the configured threshold, it gets
+    /// banned and the channel is stopped.
+    pub async fn report_misbehavior(&self, score: u64, reason: &str) {
+        let Some(peer) = self.ban_addr(self.address()) else { return };
+
+        if self.p2p().hosts().report_misbehavior(&peer, score, reason).await {
+            self.stop_with_reason(StopReason::ProtocolViolation).await;
+        }
+    }
+
+    /// Returns the address to blacklist in order to ban the given peer,
+    /// or `None` if the peer can't be banned.
+    pub(in crate::net) fn ban_addr(&self, peer: &Url) -> Option<Url> {
         // Just store the hostname if this is an inbound session.
         // This will block all ports from this peer by setting
         // `hosts.block_all_ports()` to true.
-        let peer = {
-            if self.session_type_id() & SESSION_INBOUND != 0 {
-                if peer.host().is_none() {
-                    error!("[P2P] ban() caught Url without host: {:?}", peer);
-                    return
-                }
+        if self.session_type_id() & SESSION_INBOUND == 0 {
+            return Some(peer.clone())
+        }
 
-                // An inbound Tor connection can't really be banned :)
-                #[cfg(feature = "p2p-tor")]
-                if (peer.scheme() == "tor" || peer.scheme() == "tor+tls") &&
-                    self.p2p().hosts().is_local_host(peer)
-                {
-                    return
-                }
+        if peer.host().is_none() {
+            error!("[P2P] ban() caught Url without host: {:?}", peer);
+            return None
+        }
 
-                #[cfg(feature = "p2p-unix")]
-                if peer.scheme() == "unix" {
-                    return
-                }
+        // An inbound Tor connection can't really be banned :)
+        #[cfg(feature = "p2p-tor")]
+        if (peer.scheme() == "tor" || peer.scheme() == "tor+tls") &&
+            self.p2p().hosts().is_local_host(peer)
+        {
+            return None
+        }
 
-                let mut addr = peer.clone();
-                addr.set_port(None).unwrap();
-                addr
-            } else {
-                peer.clone()
-            }
-        };
+        #[cfg(feature = "p2p-unix")]
+        if peer.scheme() == "unix" {
+            return None
+        }
 
-        let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
-        self.p2p().hosts().move_host(&peer, last_seen, HostColor::Black).unwrap();
-        self.stop().await;
-        debug!(target: "net::channel::ban()", "STOP {:?}", self);
+        // In-process peers get a fresh address on every connection
+        #[cfg(feature = "p2p-memory")]
+        if peer.scheme() == "memory" {
+            return None
+        }
+
+        let mut addr = peer.clone();
+        addr.set_port(None).unwrap();
+        Some(addr)
     }
 
     /// Returns the relevant socket address for this connection.  If this is
@@ -449,6 +916,17 @@ impl Channel {
         }
     }
 
+    /// Returns the address per-peer statistics of this channel are kept
+    /// under in the hosts. Inbound peers connect from ephemeral ports, so
+    /// they are keyed by host, like in [`Channel::ban_addr`].
+    pub fn stats_addr(&self) -> Url {
+        let mut addr = self.address().clone();
+        if self.session_type_id() & SESSION_INBOUND != 0 && addr.host().is_some() {
+            let _ = addr.set_port(None);
+        }
+        addr
+    }
+
     /// Returns the socket address that has undergone transport
     /// processing, if it exists. Returns None otherwise.
     pub fn resolve_addr(&self) -> Option<Url> {
@@ -463,9 +941,113 @@ impl Channel {
     /// Set the VersionMessage of the node this channel is connected
     /// to. Called on receiving a version message in `ProtocolVersion`.
     pub(crate) async fn set_version(&self, version: Arc<VersionMessage>) {
+        if self.compression_threshold.is_some() && version.has_feature(COMPRESSION_FEATURE) {
+            self.compression.store(true, SeqCst);
+        }
+
+        if self.sequencing && version.has_feature(SEQUENCE_FEATURE) {
+            self.send_sequenced.store(true, SeqCst);
+        }
+
+        if self.key_exchange.is_some() && version.has_feature(ENCRYPTION_FEATURE) {
+            self.encryption.store(true, SeqCst);
+        }
+
         *self.version.lock().await = Some(version);
     }
 
+    /// Set the peer ID the node we are connected to proved. Called by
+    /// `ProtocolVersion` once the peer's identity proof checks out.
+    pub(in crate::net) fn set_peer_id(&self, peer_id: PeerId) {
+        *self.peer_id.lock().unwrap() = Some(peer_id);
+    }
+
+    /// Returns the peer ID of the node we are connected to, once it was
+    /// proven over the encrypted channel. Peers on cleartext channels, or
+    /// not supporting identity proofs, have none.
+    pub fn peer_id(&self) -> Option<PeerId> {
+        *self.peer_id.lock().unwrap()
+    }
+
+    /// Set the nonce identifying the node we are connected to. Called by
+    /// `ProtocolVersion` once the peer sent it.
+    pub(in crate::net) fn set_peer_nonce(&self, nonce: u64) {
+        *self.peer_nonce.lock().unwrap() = Some(nonce);
+    }
+
+    /// Returns the nonce identifying the node we are connected to, if it
+    /// sent one during the version exchange
+    pub fn peer_nonce(&self) -> Option<u64> {
+        *self.peer_nonce.lock().unwrap()
+    }
+
+    /// Returns true if the peer advertised the given feature, in the given
+    /// version or a newer one, in its version message. Protocols use this
+    /// to only enable extensions with peers supporting them, and fall back
+    /// to the base protocol otherwise. Always false before the handshake.
+    pub async fn has_feature(&self, feature: (&str, u32)) -> bool {
+        match *self.version.lock().await {
+            Some(ref version) => version.has_feature(feature),
+            None => false,
+        }
+    }
+
+    /// Start encrypting the channel if the peer supports it, by sending
+    /// it our ephemeral key. Called by `ProtocolVersion` once the peer's
+    /// version was received.
+    pub(in crate::net) async fn start_encryption(&self) -> Result<()> {
+        let Some(ref key_exchange) = self.key_exchange else { return Ok(()) };
+        if !self.encryption.load(SeqCst) {
+            return Ok(())
+        }
+
+        let message = KeyExchangeMessage {
+            public_key: key_exchange.public_key(),
+            nonce: key_exchange.nonce(),
+        };
+        self.send(&message).await
+    }
+
+    /// Agree on the channel keys with the payload of the peer's key
+    /// exchange message. Peers may only send one, and only if we
+    /// advertised encryption support.
+    fn agree_keys(&self, payload: &[u8]) -> Result<()> {
+        let Some(ref key_exchange) = self.key_exchange else { return Err(Error::MalformedPacket) };
+        let message: KeyExchangeMessage = deserialize(payload)?;
+
+        let mut recv_cipher = self.recv_cipher.lock().unwrap();
+        if recv_cipher.is_some() {
+            return Err(Error::MalformedPacket)
+        }
+
+        let (send, recv) = key_exchange.agree(&message.public_key)?;
+        *recv_cipher = Some(recv);
+        *self.send_cipher.lock().unwrap() = Some(send);
+        *self.transcripts.lock().unwrap() =
+            Some(key_exchange.transcripts(&message.public_key, &message.nonce));
+        Ok(())
+    }
+
+    /// Returns the key exchange transcripts signed by us and by the peer
+    /// respectively, once the channel is encrypted
+    pub(in crate::net) fn transcripts(&self) -> Option<([u8; 32], [u8; 32])> {
+        if !self.is_encrypted() {
+            return None
+        }
+        *self.transcripts.lock().unwrap()
+    }
+
+    /// Returns true once the messages we send on this channel are encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.key_sent.load(SeqCst) && self.send_cipher.lock().unwrap().is_some()
+    }
+
+    /// Returns the counters of the messages sent and received compressed
+    /// on this channel
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.compression_counters.stats()
+    }
+
     /// Returns the inner [`MessageSubsystem`] reference
     pub fn message_subsystem(&self) -> &MessageSubsystem {
         &self.message_subsystem
@@ -492,6 +1074,23 @@ impl Channel {
     }
 }
 
+/// Size of a frame made of a header of the given length, followed by a
+/// length-prefixed body
+fn frame_len_of(header_len: usize, body_len: usize) -> u64 {
+    (header_len + VarInt(body_len as u64).length() + body_len) as u64
+}
+
+/// Size of a message frame with the given command and payload length
+fn frame_len(command: &str, payload_len: usize) -> u64 {
+    let cmd_len = command.len() as u64;
+    let payload_len = payload_len as u64;
+    MAGIC_BYTES.len() as u64 +
+        VarInt(cmd_len).length() as u64 +
+        cmd_len +
+        VarInt(payload_len).length() as u64 +
+        payload_len
+}
+
 impl fmt::Debug for Channel {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "<Channel addr='{}' id={}>", self.address(), self.info.id)
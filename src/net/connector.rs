@@ -27,7 +27,7 @@ use futures::{
 };
 use log::warn;
 use smol::lock::RwLock as AsyncRwLock;
-use url::Url;
+use url::{Host, Url};
 
 use super::{
     channel::{Channel, ChannelPtr},
@@ -36,7 +36,10 @@ use super::{
     settings::Settings,
     transport::Dialer,
 };
-use crate::{system::CondVar, Error, Result};
+use crate::{
+    system::{io_timeout, CondVar},
+    Error, Result,
+};
 
 /// Create outbound socket connections
 pub struct Connector {
@@ -56,17 +59,26 @@ impl Connector {
 
     /// Establish an outbound connection
     pub async fn connect(&self, url: &Url) -> Result<(Url, ChannelPtr)> {
-        let hosts = self.session.upgrade().unwrap().p2p().hosts();
+        let p2p = self.session.upgrade().unwrap().p2p();
+        let hosts = p2p.hosts();
         if hosts.container.contains(HostColor::Black as usize, url) || hosts.block_all_ports(url) {
             warn!(target: "net::connector::connect", "Peer {} is blacklisted", url);
             return Err(Error::ConnectFailed)
         }
 
+        if !hosts.is_allowed(url, false).await {
+            warn!(target: "net::connector::connect", "Peer {} is not allowlisted", url);
+            return Err(Error::PeerNotAllowed)
+        }
+
         let settings = self.settings.read().await;
         let transports = settings.allowed_transports.clone();
         let transport_mixing = settings.transport_mixing;
         let datastore = settings.p2p_datastore.clone();
+        let socks5_proxy = settings.socks5_proxy.clone();
         let outbound_connect_timeout = settings.outbound_connect_timeout;
+        let dns_cache_ttl = settings.dns_cache_ttl;
+        let dns_pin_addresses = settings.dns_pin_addresses;
         drop(settings);
 
         let mut endpoint = url.clone();
@@ -81,16 +93,44 @@ impl Connector {
                 endpoint.set_scheme("nym")?;
             } else if transports.contains(&"nym+tls".to_string()) && scheme == "tcp+tls" {
                 endpoint.set_scheme("nym+tls")?;
+            } else if transports.contains(&"socks5".to_string()) &&
+                (scheme == "tcp" || scheme == "tor")
+            {
+                endpoint.set_scheme("socks5")?;
+            } else if transports.contains(&"socks5+tls".to_string()) &&
+                (scheme == "tcp+tls" || scheme == "tor+tls")
+            {
+                endpoint.set_scheme("socks5+tls")?;
             }
         }
 
-        let dialer = Dialer::new(endpoint.clone(), datastore).await?;
+        let mut dialer = Dialer::new(endpoint.clone(), datastore).await?;
+        if let Some(proxy) = socks5_proxy {
+            dialer.set_socks5_proxy(proxy).await?;
+        }
         let timeout = Duration::from_secs(outbound_connect_timeout);
 
         let stop_fut = async {
             self.stop_signal.wait().await;
         };
-        let dial_fut = async { dialer.dial(Some(timeout)).await };
+
+        // Hostnames of TCP endpoints are resolved through the resolver
+        // cache. Proxied transports resolve them on the remote end.
+        let domain = match (endpoint.scheme(), endpoint.host(), endpoint.port()) {
+            ("tcp" | "tcp+tls", Some(Host::Domain(host)), Some(port)) => {
+                Some((host.to_string(), port))
+            }
+            _ => None,
+        };
+
+        let dial_fut = async {
+            if let Some((ref host, port)) = domain {
+                let resolve_fut =
+                    p2p.resolver().resolve(host, port, dns_cache_ttl, dns_pin_addresses);
+                dialer.set_resolved_addrs(io_timeout(timeout, resolve_fut).await?);
+            }
+            dialer.dial(Some(timeout)).await
+        };
 
         pin_mut!(stop_fut);
         pin_mut!(dial_fut);
@@ -110,14 +150,15 @@ impl Connector {
             Either::Left((Err(e), _)) => {
                 // If we get ENETUNREACH, we don't have IPv6 connectivity so note it down.
                 if e.raw_os_error() == Some(libc::ENETUNREACH) {
-                    self.session
-                        .upgrade()
-                        .unwrap()
-                        .p2p()
-                        .hosts()
-                        .ipv6_available
-                        .store(false, Ordering::SeqCst);
+                    hosts.ipv6_available.store(false, Ordering::SeqCst);
                 }
+
+                // The hostname records might have changed, so resolve it
+                // again on the next attempt.
+                if let Some((ref host, port)) = domain {
+                    p2p.resolver().invalidate(host, port);
+                }
+
                 Err(e.into())
             }
 
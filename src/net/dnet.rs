@@ -21,6 +21,9 @@ use url::Url;
 use super::channel::ChannelInfo;
 use crate::util::time::NanoTimestamp;
 
+/// Number of recent dnet events kept by a node, for `dnet.get_recent_events`
+pub const DNET_RECENT_EVENTS: usize = 256;
+
 macro_rules! dnetev {
     ($self:expr, $event_name:ident, $($code:tt)*) => {
         {
@@ -77,6 +80,12 @@ pub struct OutboundSlotDisconnected {
     pub err: String,
 }
 
+#[derive(Clone, Debug)]
+pub struct OutboundSlotStalled {
+    pub slot: u32,
+    pub addr: Url,
+}
+
 #[derive(Clone, Debug)]
 pub struct OutboundPeerDiscovery {
     pub attempt: u32,
@@ -93,5 +102,6 @@ pub enum DnetEvent {
     OutboundSlotConnecting(OutboundSlotConnecting),
     OutboundSlotConnected(OutboundSlotConnected),
     OutboundSlotDisconnected(OutboundSlotDisconnected),
+    OutboundSlotStalled(OutboundSlotStalled),
     OutboundPeerDiscovery(OutboundPeerDiscovery),
 }
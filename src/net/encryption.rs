@@ -0,0 +1,222 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use darkfi_serial::{Encodable, VarInt};
+use ed25519_compact::x25519;
+use rand::{rngs::OsRng, Rng};
+
+use super::message::ENCRYPTED_MAGIC_BYTES;
+use crate::{Error, Result};
+
+/// Context string of the frame keys derivation
+const FRAME_KEY_CONTEXT: &str = "darkfi 2024-10-15 net channel frame key";
+
+/// Context string of the key exchange transcript hash
+const TRANSCRIPT_CONTEXT: &str = "darkfi 2024-10-15 net channel key exchange transcript";
+
+/// Size of the authentication tag of encrypted frames
+pub(in crate::net) const TAG_LEN: usize = 16;
+
+/// Ephemeral X25519 key pair of a channel, used to agree on the keys
+/// encrypting its frames with the peer. A fresh one is generated for
+/// every channel, so recorded traffic can't be decrypted later on.
+/// By itself this protects against eavesdropping and tampering, but not
+/// against an active man in the middle. Peers signing the transcript of
+/// the exchange with their identity key are authenticated as well.
+pub(in crate::net) struct KeyExchange {
+    keypair: x25519::KeyPair,
+    /// Random nonce sent along with our public key
+    nonce: [u8; 32],
+}
+
+impl KeyExchange {
+    pub fn new() -> Self {
+        Self { keypair: x25519::KeyPair::generate(), nonce: OsRng.gen() }
+    }
+
+    /// Our ephemeral public key, sent to the peer
+    pub fn public_key(&self) -> [u8; 32] {
+        *self.keypair.pk
+    }
+
+    /// Our nonce, sent to the peer
+    pub fn nonce(&self) -> [u8; 32] {
+        self.nonce
+    }
+
+    /// Hashes of the transcript of the exchange with the peer's public
+    /// key and nonce, as signed by us and by the peer respectively. Both
+    /// cover the two keys and nonces, ordered by signer, so a signature
+    /// can't be relayed to another channel nor reflected back.
+    pub fn transcripts(&self, peer_key: &[u8; 32], peer_nonce: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+        let our_key = self.public_key();
+        (
+            Self::transcript(&our_key, peer_key, &self.nonce, peer_nonce),
+            Self::transcript(peer_key, &our_key, peer_nonce, &self.nonce),
+        )
+    }
+
+    /// Hash of the transcript of an exchange, as signed by `signer`
+    fn transcript(
+        signer_key: &[u8; 32],
+        other_key: &[u8; 32],
+        signer_nonce: &[u8; 32],
+        other_nonce: &[u8; 32],
+    ) -> [u8; 32] {
+        let material = [&signer_key[..], other_key, signer_nonce, other_nonce].concat();
+        blake3::derive_key(TRANSCRIPT_CONTEXT, &material)
+    }
+
+    /// Agree on the frame keys with the peer's ephemeral public key.
+    /// Returns the ciphers of the frames we send and receive respectively.
+    pub fn agree(&self, peer_key: &[u8; 32]) -> Result<(FrameCipher, FrameCipher)> {
+        let Ok(peer) = x25519::PublicKey::from_slice(peer_key) else {
+            return Err(Error::MalformedPacket)
+        };
+        // Fails on low order points, which would give a known secret
+        let Ok(shared) = peer.dh(&self.keypair.sk) else { return Err(Error::MalformedPacket) };
+
+        // Each direction gets its own key, so both ends can count
+        // nonces from zero.
+        let our_key = self.public_key();
+        let send = Self::frame_key(&shared[..], &our_key, peer_key);
+        let recv = Self::frame_key(&shared[..], peer_key, &our_key);
+
+        Ok((FrameCipher::new(&send), FrameCipher::new(&recv)))
+    }
+
+    /// Derive the key of the frames sent from `sender` to `receiver`
+    fn frame_key(shared: &[u8], sender: &[u8; 32], receiver: &[u8; 32]) -> [u8; 32] {
+        let mut material = Vec::with_capacity(shared.len() + 64);
+        material.extend_from_slice(shared);
+        material.extend_from_slice(sender);
+        material.extend_from_slice(receiver);
+        blake3::derive_key(FRAME_KEY_CONTEXT, &material)
+    }
+}
+
+/// Cipher of one direction of an encrypted channel. Frames are sealed
+/// with ChaCha20-Poly1305 under a counter nonce, so a frame that was
+/// tampered with, dropped, replayed or reordered fails to open.
+pub(in crate::net) struct FrameCipher {
+    cipher: ChaCha20Poly1305,
+    /// Nonce of the next frame
+    counter: u64,
+}
+
+impl FrameCipher {
+    fn new(key: &[u8; 32]) -> Self {
+        Self { cipher: ChaCha20Poly1305::new(Key::from_slice(key)), counter: 0 }
+    }
+
+    /// Returns the nonce of the next frame
+    fn next_nonce(&mut self) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.counter.to_le_bytes());
+        self.counter += 1;
+        *Nonce::from_slice(&nonce)
+    }
+
+    /// Seal an encoded frame, returning the encrypted frame carrying it
+    pub fn seal(&mut self, frame: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.next_nonce();
+        let sealed =
+            self.cipher.encrypt(&nonce, frame).map_err(|_| Error::FrameEncryptionFailed)?;
+
+        let mut encrypted = Vec::with_capacity(
+            ENCRYPTED_MAGIC_BYTES.len() + VarInt(sealed.len() as u64).length() + sealed.len(),
+        );
+        encrypted.extend_from_slice(&ENCRYPTED_MAGIC_BYTES);
+        VarInt(sealed.len() as u64).encode(&mut encrypted).unwrap();
+        encrypted.extend_from_slice(&sealed);
+        Ok(encrypted)
+    }
+
+    /// Open the sealed body of a received encrypted frame, returning the
+    /// frame it carries
+    pub fn open(&mut self, sealed: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.next_nonce();
+        self.cipher.decrypt(&nonce, sealed).map_err(|_| Error::MalformedPacket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use darkfi_serial::Decodable;
+
+    use super::*;
+
+    /// Returns the sealed body of an encrypted frame
+    fn sealed_body(encrypted: &[u8]) -> &[u8] {
+        assert_eq!(encrypted[..4], ENCRYPTED_MAGIC_BYTES);
+        let mut cursor = Cursor::new(&encrypted[4..]);
+        let len = VarInt::decode(&mut cursor).unwrap().0 as usize;
+        let body = &encrypted[4 + cursor.position() as usize..];
+        assert_eq!(body.len(), len);
+        body
+    }
+
+    #[test]
+    fn test_frame_encryption() {
+        let alice = KeyExchange::new();
+        let bob = KeyExchange::new();
+        let (mut alice_send, mut alice_recv) = alice.agree(&bob.public_key()).unwrap();
+        let (mut bob_send, mut bob_recv) = bob.agree(&alice.public_key()).unwrap();
+
+        let frame = b"frame".to_vec();
+        let encrypted = alice_send.seal(&frame).unwrap();
+        assert_eq!(encrypted.len(), 4 + 1 + frame.len() + TAG_LEN);
+        assert_eq!(bob_recv.open(sealed_body(&encrypted)).unwrap(), frame);
+
+        let encrypted = bob_send.seal(&frame).unwrap();
+        assert_eq!(alice_recv.open(sealed_body(&encrypted)).unwrap(), frame);
+
+        // Replayed frames fail to open
+        assert!(alice_recv.open(sealed_body(&encrypted)).is_err());
+
+        // So do tampered ones
+        let mut encrypted = alice_send.seal(&frame).unwrap();
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(bob_recv.open(sealed_body(&encrypted)).is_err());
+
+        // A third party agreeing with either side gets other keys
+        let eve = KeyExchange::new();
+        let (_, mut eve_recv) = eve.agree(&alice.public_key()).unwrap();
+        let encrypted = alice_send.seal(&frame).unwrap();
+        assert!(eve_recv.open(sealed_body(&encrypted)).is_err());
+
+        // Low order points are refused
+        assert!(alice.agree(&[0u8; 32]).is_err());
+
+        // Each side signs what the other one verifies, and nothing else
+        let (alice_signs, alice_verifies) = alice.transcripts(&bob.public_key(), &bob.nonce());
+        let (bob_signs, bob_verifies) = bob.transcripts(&alice.public_key(), &alice.nonce());
+        assert_eq!(alice_signs, bob_verifies);
+        assert_eq!(bob_signs, alice_verifies);
+        assert_ne!(alice_signs, bob_signs);
+        let (eve_signs, _) = eve.transcripts(&bob.public_key(), &bob.nonce());
+        assert_ne!(eve_signs, bob_verifies);
+    }
+}
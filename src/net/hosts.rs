@@ -17,14 +17,15 @@
  */
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt, fs,
     fs::File,
+    str::FromStr,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Mutex, RwLock,
     },
-    time::{Instant, UNIX_EPOCH},
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 
 use log::{debug, error, info, trace, warn};
@@ -33,7 +34,9 @@ use smol::lock::RwLock as AsyncRwLock;
 use url::Url;
 
 use super::{
-    session::{SESSION_REFINE, SESSION_SEED},
+    channel::{ProtocolError, StopReason},
+    identity::PeerId,
+    session::{SESSION_OUTBOUND, SESSION_REFINE, SESSION_SEED},
     settings::Settings,
     ChannelPtr,
 };
@@ -90,6 +93,16 @@ pub const LOCAL_HOST_STRS: [&str; 2] = ["localhost", "localhost.localdomain"];
 const WHITELIST_MAX_LEN: usize = 5000;
 const GREYLIST_MAX_LEN: usize = 2000;
 const DARKLIST_MAX_LEN: usize = 1000;
+const IDENTITIES_MAX_LEN: usize = 5000;
+const PEER_STATS_MAX_LEN: usize = 5000;
+
+/// Header of hostlist files in the current format. Files without it are
+/// in the original format, lacking host metadata, and get migrated on
+/// the next save.
+const HOSTLIST_HEADER: &str = "#hostlist\tv2";
+
+/// Number of ping round-trip times averaged into a peer's ping latency
+const PING_SAMPLES: usize = 8;
 
 /// Atomic pointer to hosts object
 pub type HostsPtr = Arc<Hosts>;
@@ -335,11 +348,285 @@ impl TryFrom<usize> for HostColor {
     }
 }
 
+/// How we first learned about a host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HostOrigin {
+    /// Hosts loaded from a hostlist predating origin tracking.
+    #[default]
+    Unknown,
+    /// Addresses sent to us by a seed node.
+    Seed,
+    /// Addresses sent to us by peers during address exchange.
+    Discovery,
+    /// Nodes found on the local network.
+    Lan,
+    /// Peers we were asked to connect to manually.
+    Manual,
+}
+
+impl fmt::Display for HostOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let origin = match self {
+            Self::Unknown => "unknown",
+            Self::Seed => "seed",
+            Self::Discovery => "discovery",
+            Self::Lan => "lan",
+            Self::Manual => "manual",
+        };
+        write!(f, "{}", origin)
+    }
+}
+
+impl FromStr for HostOrigin {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "unknown" => Ok(Self::Unknown),
+            "seed" => Ok(Self::Seed),
+            "discovery" => Ok(Self::Discovery),
+            "lan" => Ok(Self::Lan),
+            "manual" => Ok(Self::Manual),
+            _ => Err(Error::ParseFailed("Invalid host origin")),
+        }
+    }
+}
+
+/// Metadata kept for each host alongside the hostlists, and saved
+/// along with them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostMetadata {
+    /// Handshake round-trip time last measured by the refinery
+    pub latency: Option<Duration>,
+    /// Failed outbound connection attempts since we were last connected
+    pub failures: u32,
+    /// How we first learned about the host
+    pub origin: HostOrigin,
+}
+
+/// A temporary ban of a peer that misbehaved. Banned peers are kept on
+/// the blacklist until the ban expires.
+#[derive(Clone, Debug)]
+pub struct HostBan {
+    /// UNIX timestamp at which the ban expires
+    pub until: u64,
+    /// Misbehavior that got the peer banned
+    pub reason: String,
+}
+
+/// Last address each authenticated outbound peer was reached at, indexed
+/// both by peer ID and by address.
+#[derive(Default)]
+pub(in crate::net) struct PeerIdentities {
+    by_peer: HashMap<PeerId, Url>,
+    by_addr: HashMap<Url, PeerId>,
+}
+
+impl PeerIdentities {
+    /// Record that `peer_id` was reached at `addr`, replacing whatever was
+    /// recorded for either. Returns the previous address of the peer. A
+    /// random entry is evicted if there are already too many.
+    fn insert(&mut self, peer_id: PeerId, addr: Url) -> Option<Url> {
+        let previous = self.by_peer.remove(&peer_id);
+        if let Some(ref previous) = previous {
+            self.by_addr.remove(previous);
+        }
+        if let Some(other) = self.by_addr.remove(&addr) {
+            self.by_peer.remove(&other);
+        }
+
+        if self.by_addr.len() >= IDENTITIES_MAX_LEN {
+            let evicted = self.by_addr.keys().choose(&mut OsRng).cloned();
+            if let Some(evicted) = evicted {
+                self.remove(&evicted);
+            }
+        }
+
+        self.by_peer.insert(peer_id, addr.clone());
+        self.by_addr.insert(addr, peer_id);
+        previous
+    }
+
+    /// Forget the peer ID recorded for an address.
+    fn remove(&mut self, addr: &Url) {
+        if let Some(peer_id) = self.by_addr.remove(addr) {
+            self.by_peer.remove(&peer_id);
+        }
+    }
+
+    /// Returns the peer ID recorded for an address, if any.
+    fn get(&self, addr: &Url) -> Option<PeerId> {
+        self.by_addr.get(addr).copied()
+    }
+}
+
+/// Misbehavior score of a peer, decaying over time.
+#[derive(Clone, Debug)]
+struct MisbehaviorScore {
+    /// Score at the time of the last update
+    score: f64,
+    /// UNIX timestamp of the last update
+    updated: u64,
+}
+
+impl MisbehaviorScore {
+    /// Returns the score decayed until `now`, halving every `half_life`
+    /// seconds. A half life of 0 disables decay.
+    fn decayed(&self, now: u64, half_life: u64) -> f64 {
+        if half_life == 0 {
+            return self.score
+        }
+
+        let elapsed = now.saturating_sub(self.updated) as f64;
+        self.score * 0.5_f64.powf(elapsed / half_life as f64)
+    }
+}
+
+/// Number of times channels with a host were stopped, by reason.
+#[derive(Clone, Debug, Default)]
+pub struct StopCounts {
+    /// The peer closed the connection, or the transport failed
+    pub remote_close: u64,
+    /// The peer did not respond in time
+    pub timeout: u64,
+    /// The peer violated the protocol
+    pub protocol_violation: u64,
+    /// We closed the channel ourselves
+    pub local_shutdown: u64,
+}
+
+impl StopCounts {
+    /// Count a channel stop with the given reason
+    fn add(&mut self, reason: StopReason) {
+        match reason {
+            StopReason::RemoteClose => self.remote_close += 1,
+            StopReason::Timeout => self.timeout += 1,
+            StopReason::ProtocolViolation => self.protocol_violation += 1,
+            StopReason::LocalShutdown => self.local_shutdown += 1,
+        }
+    }
+
+    /// Sum up the counts of another host
+    fn merge(&mut self, other: &Self) {
+        self.remote_close += other.remote_close;
+        self.timeout += other.timeout;
+        self.protocol_violation += other.protocol_violation;
+        self.local_shutdown += other.local_shutdown;
+    }
+}
+
+/// Number of protocol errors seen on channels with a host, by kind.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProtocolErrors {
+    /// Messages whose payload failed to decode
+    pub decode_failures: u64,
+    /// Messages of a type we have no dispatcher for
+    pub unknown_messages: u64,
+    /// Protocol replies the peer didn't send in time
+    pub timeouts: u64,
+}
+
+impl ProtocolErrors {
+    /// Count a protocol error of the given kind
+    fn add(&mut self, error: ProtocolError) {
+        match error {
+            ProtocolError::Decode => self.decode_failures += 1,
+            ProtocolError::UnknownMessage => self.unknown_messages += 1,
+            ProtocolError::Timeout => self.timeouts += 1,
+        }
+    }
+
+    /// Returns the number of errors of all kinds
+    pub fn total(&self) -> u64 {
+        self.decode_failures + self.unknown_messages + self.timeouts
+    }
+}
+
+/// Distribution of a set of hosts across address families, transports
+/// and subnets. Used to detect a lack of diversity in our peers, which
+/// makes eclipse attacks easier.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostDiversity {
+    /// Number of hosts per address family: ipv4, ipv6, onion or dns
+    pub families: HashMap<String, usize>,
+    /// Number of hosts per transport scheme
+    pub transports: HashMap<String, usize>,
+    /// Number of hosts per subnet, /16 for IPv4 and /32 for IPv6. Hosts
+    /// not given by IP address are not part of any subnet.
+    pub subnets: HashMap<String, usize>,
+}
+
+impl HostDiversity {
+    /// Compute the distribution of the given hosts
+    pub fn new<'a>(addrs: impl IntoIterator<Item = &'a Url>) -> Self {
+        let mut diversity = Self::default();
+
+        for addr in addrs {
+            let Some(host) = addr.host() else { continue };
+            let family = match host {
+                url::Host::Ipv4(_) => "ipv4",
+                url::Host::Ipv6(_) => "ipv6",
+                url::Host::Domain(d) if d.ends_with(".onion") => "onion",
+                url::Host::Domain(_) => "dns",
+            };
+
+            *diversity.families.entry(family.to_string()).or_default() += 1;
+            *diversity.transports.entry(addr.scheme().to_string()).or_default() += 1;
+            if let Some(subnet) = Self::subnet(addr) {
+                *diversity.subnets.entry(subnet).or_default() += 1;
+            }
+        }
+
+        diversity
+    }
+
+    /// Subnet of the given host, /16 for IPv4 and /32 for IPv6. Hosts not
+    /// given by IP address are not part of any subnet.
+    pub fn subnet(addr: &Url) -> Option<String> {
+        match addr.host()? {
+            url::Host::Ipv4(ip) => {
+                let [a, b, _, _] = ip.octets();
+                Some(format!("{a}.{b}.0.0/16"))
+            }
+            url::Host::Ipv6(ip) => {
+                let segments = ip.segments();
+                Some(format!("{:x}:{:x}::/32", segments[0], segments[1]))
+            }
+            url::Host::Domain(_) => None,
+        }
+    }
+
+    /// Total number of hosts
+    pub fn total(&self) -> usize {
+        self.families.values().sum()
+    }
+
+    /// Percentage of the hosts sharing the most common subnet
+    pub fn largest_subnet_share(&self) -> usize {
+        let total = self.total();
+        if total == 0 {
+            return 0
+        }
+        self.subnets.values().max().cloned().unwrap_or(0) * 100 / total
+    }
+}
+
 /// A Container for managing Grey, White, Gold and Black hostlists. Exposes
 /// a common interface for writing to and querying hostlists.
 // TODO: Benchmark hostlist operations when the hostlist is at max size.
 pub struct HostContainer {
     pub(in crate::net) hostlists: [RwLock<Vec<(Url, u64)>>; 5],
+    /// Temporary bans of blacklisted hosts. Blacklist entries without a
+    /// ban last for the duration of the program.
+    pub(in crate::net) bans: RwLock<HashMap<Url, HostBan>>,
+    /// Outbound peers we were connected to on shutdown. These are tried
+    /// first on startup, to quickly get back to our previous topology.
+    pub(in crate::net) anchors: RwLock<Vec<(Url, u64)>>,
+    /// Last address each authenticated outbound peer was reached at.
+    /// Lets us recognize peers changing their address.
+    pub(in crate::net) identities: RwLock<PeerIdentities>,
+    /// Latency, failures and origin of the hosts we know about.
+    pub(in crate::net) metadata: RwLock<HashMap<Url, HostMetadata>>,
 }
 
 impl HostContainer {
@@ -352,7 +639,52 @@ impl HostContainer {
             RwLock::new(Vec::new()),
         ];
 
-        Self { hostlists }
+        Self {
+            hostlists,
+            bans: RwLock::new(HashMap::new()),
+            anchors: RwLock::new(Vec::new()),
+            identities: RwLock::new(PeerIdentities::default()),
+            metadata: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Replace the anchors with the given peers.
+    pub(in crate::net) fn set_anchors(&self, anchors: Vec<(Url, u64)>) {
+        *self.anchors.write().unwrap() = anchors;
+    }
+
+    /// Take the next anchor to connect to. Each anchor is only tried once.
+    pub(in crate::net) fn pop_anchor(&self) -> Option<(Url, u64)> {
+        let mut anchors = self.anchors.write().unwrap();
+        if anchors.is_empty() {
+            return None
+        }
+        Some(anchors.remove(0))
+    }
+
+    /// Check if there are anchors left to connect to.
+    pub(in crate::net) fn has_anchors(&self) -> bool {
+        !self.anchors.read().unwrap().is_empty()
+    }
+
+    /// Return the metadata of a host, or the defaults for unknown hosts.
+    pub fn metadata(&self, addr: &Url) -> HostMetadata {
+        self.metadata.read().unwrap().get(addr).cloned().unwrap_or_default()
+    }
+
+    /// Record how we learned about a host, unless we already know.
+    pub(in crate::net) fn record_origin(&self, addr: &Url, origin: HostOrigin) {
+        let mut metadata = self.metadata.write().unwrap();
+        let entry = metadata.entry(addr.clone()).or_default();
+        if entry.origin == HostOrigin::Unknown {
+            entry.origin = origin;
+        }
+    }
+
+    /// Forget the metadata and peer ID of a host.
+    pub(in crate::net) fn remove_metadata(&self, addr: &Url) {
+        self.metadata.write().unwrap().remove(addr);
+        self.identities.write().unwrap().remove(addr);
     }
 
     /// Append host to a hostlist. Called when initalizing the hostlist in load_hosts().
@@ -426,6 +758,8 @@ impl HostContainer {
         // If transport mixing is enabled, then for example we're allowed to
         // use tor:// to connect to tcp:// and tor+tls:// to connect to tcp+tls://.
         // However, **do not** mix tor:// and tcp+tls://, nor tor+tls:// and tcp://.
+        // A SOCKS5 proxy such as a local Tor daemon can reach both TCP and
+        // onion addresses, so socks5:// mixes with tcp:// and tor://.
         macro_rules! mix_transport {
             ($a:expr, $b:expr) => {
                 if transports.contains(&$a.to_string()) && transport_mixing {
@@ -442,6 +776,10 @@ impl HostContainer {
         mix_transport!("tor+tls", "tcp+tls");
         mix_transport!("nym", "tcp");
         mix_transport!("nym+tls", "tcp+tls");
+        mix_transport!("socks5", "tcp");
+        mix_transport!("socks5", "tor");
+        mix_transport!("socks5+tls", "tcp+tls");
+        mix_transport!("socks5+tls", "tor+tls");
 
         // And now the actual requested transports
         for (addr, last_seen) in self.fetch_with_schemes(index, transports, None) {
@@ -701,6 +1039,7 @@ impl HostContainer {
                 if size == max_size {
                     let mut list = self.hostlists[color.clone() as usize].write().unwrap();
                     let last_entry = list.pop().unwrap();
+                    self.remove_metadata(&last_entry.0);
 
                     debug!(
                         target: "net::hosts::resize()",
@@ -739,6 +1078,7 @@ impl HostContainer {
         for item in old_items {
             debug!(target: "net::hosts::refresh()", "Removing {:?}", item);
             self.remove_if_exists(color.clone(), &item);
+            self.remove_metadata(&item);
         }
     }
 
@@ -760,7 +1100,23 @@ impl HostContainer {
             return Ok(())
         }
 
-        for line in contents.unwrap().lines() {
+        let contents = contents.unwrap();
+        let mut lines = contents.lines().peekable();
+        let migrating = match lines.peek() {
+            Some(&HOSTLIST_HEADER) => {
+                lines.next();
+                false
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if migrating {
+            info!(target: "net::hosts::load_hosts()", "Migrating hosts file {:?} to the current format",
+                  path);
+        }
+
+        for line in lines {
             let data: Vec<&str> = line.split('\t').collect();
 
             let url = match Url::parse(data[1]) {
@@ -771,6 +1127,19 @@ impl HostContainer {
                 }
             };
 
+            // For identities, this field holds the peer ID.
+            if data[0] == "identity" {
+                match PeerId::from_str(data[2]) {
+                    Ok(peer_id) => {
+                        self.identities.write().unwrap().insert(peer_id, url);
+                    }
+                    Err(e) => {
+                        debug!(target: "net::hosts::load_hosts()", "Skipping malformed peer ID {}", e);
+                    }
+                }
+                continue
+            }
+
             let last_seen = match data[2].parse::<u64>() {
                 Ok(t) => t,
                 Err(e) => {
@@ -779,6 +1148,19 @@ impl HostContainer {
                 }
             };
 
+            // Entries of the original format have no metadata yet.
+            if !migrating && ["gold", "white", "grey", "dark"].contains(&data[0]) {
+                match parse_metadata(&data[3..]) {
+                    Some(metadata) => {
+                        self.metadata.write().unwrap().insert(url.clone(), metadata);
+                    }
+                    None => {
+                        debug!(target: "net::hosts::load_hosts()",
+                               "Skipping malformed metadata of {}", url);
+                    }
+                }
+            }
+
             match data[0] {
                 "gold" => {
                     self.store(HostColor::Gold as usize, url, last_seen);
@@ -803,6 +1185,20 @@ impl HostContainer {
                     let day = 86400;
                     self.refresh(HostColor::Dark, day);
                 }
+                "anchor" => {
+                    self.anchors.write().unwrap().push((url, last_seen));
+                }
+                "banned" => {
+                    // For banned hosts, this field holds the ban expiry.
+                    let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+                    if last_seen <= now {
+                        continue
+                    }
+
+                    let reason = data.get(3).unwrap_or(&"").to_string();
+                    self.store(HostColor::Black as usize, url.clone(), now);
+                    self.bans.write().unwrap().insert(url, HostBan { until: last_seen, reason });
+                }
                 _ => {
                     debug!(target: "net::hosts::load_hosts()", "Malformed list name...");
                 }
@@ -824,15 +1220,38 @@ impl HostContainer {
         hostlist.insert("white".to_string(), self.fetch_all(HostColor::White));
         hostlist.insert("gold".to_string(), self.fetch_all(HostColor::Gold));
 
+        let metadata = self.metadata.read().unwrap();
         for (name, list) in hostlist {
             for (url, last_seen) in list {
-                tsv.push_str(&format!("{}\t{}\t{}\n", name, url, last_seen));
+                let meta = metadata.get(&url).cloned().unwrap_or_default();
+                let latency = match meta.latency {
+                    Some(latency) => latency.as_millis().to_string(),
+                    None => "-".to_string(),
+                };
+                tsv.push_str(&format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\n",
+                    name, url, last_seen, latency, meta.failures, meta.origin
+                ));
             }
         }
+        drop(metadata);
+
+        for (url, ban) in self.bans.read().unwrap().iter() {
+            tsv.push_str(&format!("banned\t{}\t{}\t{}\n", url, ban.until, ban.reason));
+        }
+
+        for (url, last_seen) in self.anchors.read().unwrap().iter() {
+            tsv.push_str(&format!("anchor\t{}\t{}\n", url, last_seen));
+        }
+
+        for (peer_id, url) in self.identities.read().unwrap().by_peer.iter() {
+            tsv.push_str(&format!("identity\t{}\t{}\n", url, peer_id));
+        }
 
         if !tsv.is_empty() {
             info!(target: "net::hosts::save_hosts()", "Saving hosts to: {:?}",
                   path);
+            let tsv = format!("{}\n{}", HOSTLIST_HEADER, tsv);
             if let Err(e) = save_file(&path, &tsv) {
                 error!(target: "net::hosts::save_hosts()", "Failed saving hosts: {}", e);
             }
@@ -872,6 +1291,21 @@ pub struct Hosts {
     /// Marker for IPv6 availability
     pub(in crate::net) ipv6_available: AtomicBool,
 
+    /// Misbehavior scores of peers
+    scores: Mutex<HashMap<Url, MisbehaviorScore>>,
+
+    /// Reasons channels with each peer were stopped
+    stop_counts: Mutex<HashMap<Url, StopCounts>>,
+
+    /// Protocol errors seen on channels with each peer
+    protocol_errors: Mutex<HashMap<Url, ProtocolErrors>>,
+
+    /// Last ping round-trip times of each connected peer, oldest first
+    pings: Mutex<HashMap<Url, VecDeque<Duration>>>,
+
+    /// Marker for a lack of diversity in our outbound peers
+    diversity_skewed: AtomicBool,
+
     /// Pointer to configured P2P settings
     settings: Arc<AsyncRwLock<Settings>>,
 }
@@ -887,19 +1321,45 @@ impl Hosts {
             disconnect_publisher: Publisher::new(),
             last_connection: Mutex::new(Instant::now()),
             ipv6_available: AtomicBool::new(true),
+            scores: Mutex::new(HashMap::new()),
+            stop_counts: Mutex::new(HashMap::new()),
+            protocol_errors: Mutex::new(HashMap::new()),
+            pings: Mutex::new(HashMap::new()),
+            diversity_skewed: AtomicBool::new(false),
             settings,
         })
     }
 
     /// Safely insert into the HostContainer. Filters the addresses first before storing and
     /// notifies the publisher. Must be called when first receiving greylist addresses.
-    pub(in crate::net) async fn insert(&self, color: HostColor, addrs: &[(Url, u64)]) {
+    pub(in crate::net) async fn insert(
+        &self,
+        color: HostColor,
+        addrs: &[(Url, u64)],
+        origin: HostOrigin,
+    ) {
+        self.insert_filtered(color, addrs, origin, false).await
+    }
+
+    /// Insert addresses of nodes found on the local network into the greylist.
+    /// Unlike [`Hosts::insert`], these are allowed to be in non-global ranges.
+    pub(in crate::net) async fn insert_lan(&self, addrs: &[(Url, u64)]) {
+        self.insert_filtered(HostColor::Grey, addrs, HostOrigin::Lan, true).await
+    }
+
+    async fn insert_filtered(
+        &self,
+        color: HostColor,
+        addrs: &[(Url, u64)],
+        origin: HostOrigin,
+        allow_local: bool,
+    ) {
         trace!(target: "net::hosts:insert()", "[START]");
 
         // First filter these address to ensure this peer doesn't exist in our black, gold or
         // whitelist and apply transport filtering. If we don't support this transport,
         // store the peer on our dark list to broadcast to other nodes.
-        let filtered_addrs = self.filter_addresses(addrs).await;
+        let filtered_addrs = self.filter_addresses(addrs, allow_local).await;
         let mut addrs_len = 0;
 
         if filtered_addrs.is_empty() {
@@ -917,6 +1377,7 @@ impl Hosts {
 
             addrs_len += i + 1;
 
+            self.container.record_origin(addr, origin);
             self.container.store_or_update(color.clone(), addr.clone(), *last_seen);
             self.container.sort_by_last_seen(color.clone() as usize);
             self.container.resize(color.clone());
@@ -979,11 +1440,18 @@ impl Hosts {
 
     // Loop through hosts selected by Outbound Session and see if any of them are
     // free to connect to.
-    pub(in crate::net) async fn check_addrs(&self, hosts: Vec<(Url, u64)>) -> Option<(Url, u64)> {
+    pub(in crate::net) async fn check_addrs(
+        &self,
+        mut hosts: Vec<(Url, u64)>,
+    ) -> Option<(Url, u64)> {
         trace!(target: "net::hosts::check_addrs()", "[START]");
 
         let seeds = self.settings.read().await.seeds.clone();
         let external_addrs = self.settings.read().await.external_addrs.clone();
+        let max_per_subnet = self.settings.read().await.outbound_max_per_subnet;
+
+        // Try the hosts in subnets we aren't connected to much first
+        self.sort_by_subnet_diversity(&mut hosts, max_per_subnet);
 
         for (host, last_seen) in hosts {
             // Print a warning if we are trying to connect to a seed node in
@@ -1202,6 +1670,361 @@ impl Hosts {
         Ok(())
     }
 
+    /// Report misbehavior of a peer, adding `score` to its misbehavior
+    /// score. Scores halve every `ban_score_half_life` seconds, and once
+    /// a peer's score reaches `ban_score_threshold` it gets blacklisted
+    /// for `ban_duration` seconds. Returns true if the peer got banned.
+    pub async fn report_misbehavior(&self, addr: &Url, score: u64, reason: &str) -> bool {
+        let settings = self.settings.read().await;
+        let threshold = settings.ban_score_threshold;
+        let half_life = settings.ban_score_half_life;
+        let ban_duration = settings.ban_duration;
+        drop(settings);
+
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        let total = {
+            let mut scores = self.scores.lock().unwrap();
+            let entry =
+                scores.entry(addr.clone()).or_insert(MisbehaviorScore { score: 0.0, updated: now });
+            entry.score = entry.decayed(now, half_life) + score as f64;
+            entry.updated = now;
+            entry.score
+        };
+
+        warn!(
+            target: "net::hosts::report_misbehavior()",
+            "[P2P] Misbehavior by {}: {} (score={:.0})", addr, reason, total,
+        );
+
+        if total < threshold as f64 {
+            return false
+        }
+
+        self.scores.lock().unwrap().remove(addr);
+        self.ban_host(addr, now + ban_duration, reason)
+    }
+
+    /// Blacklist a host for the configured `ban_duration`, for the given
+    /// reason. Local hosts are never blacklisted, in which case false is
+    /// returned.
+    pub async fn ban_peer(&self, addr: &Url, reason: &str) -> bool {
+        let ban_duration = self.settings.read().await.ban_duration;
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        self.ban_host(addr, now + ban_duration, reason)
+    }
+
+    /// Blacklist a host until the given UNIX timestamp. Local hosts are
+    /// never blacklisted, in which case false is returned.
+    fn ban_host(&self, addr: &Url, until: u64, reason: &str) -> bool {
+        if addr.host_str().is_some() && self.is_local_host(addr) {
+            return false
+        }
+
+        warn!(
+            target: "net::hosts::ban_host()",
+            "[P2P] Banning {} for {}s: {}",
+            addr, until.saturating_sub(UNIX_EPOCH.elapsed().unwrap().as_secs()), reason,
+        );
+
+        self.container.remove_if_exists(HostColor::Grey, addr);
+        self.container.remove_if_exists(HostColor::White, addr);
+        self.container.remove_if_exists(HostColor::Gold, addr);
+
+        let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        self.container.store_or_update(HostColor::Black, addr.clone(), last_seen);
+        self.container
+            .bans
+            .write()
+            .unwrap()
+            .insert(addr.clone(), HostBan { until, reason: reason.to_string() });
+
+        true
+    }
+
+    /// Record that the outbound peer reached at `addr` proved the given
+    /// peer ID. If the peer was known under another address, the
+    /// reputation gathered there carries over: its hostlist entries,
+    /// misbehavior score, stop and error counts and metadata move to the
+    /// new address, and if it was banned the new address gets banned as
+    /// well. Returns false in that case.
+    pub(in crate::net) fn register_identity(&self, addr: &Url, peer_id: PeerId) -> bool {
+        let previous = self.container.identities.write().unwrap().insert(peer_id, addr.clone());
+        let Some(previous) = previous else { return true };
+        if previous == *addr {
+            return true
+        }
+
+        info!(
+            target: "net::hosts::register_identity()",
+            "[P2P] Peer {} moved from {} to {}", peer_id, previous, addr,
+        );
+
+        let ban = self.container.bans.read().unwrap().get(&previous).cloned();
+        if let Some(ban) = ban {
+            return !self.ban_host(addr, ban.until, &ban.reason)
+        }
+
+        for color in [HostColor::Gold, HostColor::White] {
+            let Some(last_seen) = self.container.get_last_seen(color.clone() as usize, &previous)
+            else {
+                continue
+            };
+            self.container.remove_if_exists(color.clone(), &previous);
+            self.container.store_or_update(color, addr.clone(), last_seen);
+        }
+
+        move_entry(&self.scores, &previous, addr);
+        move_entry(&self.stop_counts, &previous, addr);
+        move_entry(&self.protocol_errors, &previous, addr);
+
+        let mut metadata = self.container.metadata.write().unwrap();
+        if let Some(entry) = metadata.remove(&previous) {
+            metadata.insert(addr.clone(), entry);
+        }
+
+        true
+    }
+
+    /// Returns the peer ID last proven by the outbound peer reached at
+    /// the given address, if any.
+    pub fn peer_id(&self, addr: &Url) -> Option<PeerId> {
+        self.container.identities.read().unwrap().get(addr)
+    }
+
+    /// Lift expired bans and forget misbehavior scores that have decayed
+    /// away. Called periodically by the greylist refinery.
+    pub(in crate::net) async fn refresh_bans(&self) {
+        let half_life = self.settings.read().await.ban_score_half_life;
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+
+        let mut expired = vec![];
+        self.container.bans.write().unwrap().retain(|addr, ban| {
+            if ban.until > now {
+                return true
+            }
+            expired.push(addr.clone());
+            false
+        });
+
+        for addr in expired {
+            info!(target: "net::hosts::refresh_bans()", "[P2P] Ban of {} expired", addr);
+            self.container.remove_if_exists(HostColor::Black, &addr);
+        }
+
+        self.scores.lock().unwrap().retain(|_, score| score.decayed(now, half_life) >= 1.0);
+    }
+
+    /// Returns the currently banned hosts, along with their ban.
+    pub fn bans(&self) -> Vec<(Url, HostBan)> {
+        self.container.bans.read().unwrap().iter().map(|(u, b)| (u.clone(), b.clone())).collect()
+    }
+
+    /// Record why a channel with the given peer was stopped.
+    pub(in crate::net) fn record_stop(&self, addr: &Url, reason: StopReason) {
+        debug!(target: "net::hosts::record_stop()", "Channel with {} stopped: {}", addr, reason);
+        bounded_entry(&mut self.stop_counts.lock().unwrap(), addr).add(reason);
+    }
+
+    /// Returns the reasons channels with the given peer were stopped.
+    pub fn stop_counts(&self, addr: &Url) -> StopCounts {
+        self.stop_counts.lock().unwrap().get(addr).cloned().unwrap_or_default()
+    }
+
+    /// Returns the reasons channels were stopped, summed up over all peers.
+    pub fn total_stop_counts(&self) -> StopCounts {
+        let mut total = StopCounts::default();
+        for counts in self.stop_counts.lock().unwrap().values() {
+            total.merge(counts);
+        }
+        total
+    }
+
+    /// Record a protocol error seen on a channel with the given peer.
+    pub(in crate::net) fn record_protocol_error(&self, addr: &Url, error: ProtocolError) {
+        debug!(target: "net::hosts::record_protocol_error()", "Protocol error with {}: {}", addr, error);
+        bounded_entry(&mut self.protocol_errors.lock().unwrap(), addr).add(error);
+    }
+
+    /// Returns the protocol errors seen on channels with the given peer.
+    pub fn protocol_errors(&self, addr: &Url) -> ProtocolErrors {
+        self.protocol_errors.lock().unwrap().get(addr).cloned().unwrap_or_default()
+    }
+
+    /// Record the round-trip time of a ping answered by a peer, keeping
+    /// the last [`PING_SAMPLES`] of them.
+    pub(in crate::net) fn record_ping(&self, addr: &Url, rtt: Duration) {
+        let mut pings = self.pings.lock().unwrap();
+        let samples = pings.entry(addr.clone()).or_default();
+        if samples.len() == PING_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(rtt);
+    }
+
+    /// Forget the ping round-trip times of a peer. Called when a channel
+    /// with it stops, so only connected peers are kept.
+    pub(in crate::net) fn remove_pings(&self, addr: &Url) {
+        self.pings.lock().unwrap().remove(addr);
+    }
+
+    /// Returns the average round-trip time of the last pings answered by
+    /// a peer.
+    pub fn ping_latency(&self, addr: &Url) -> Option<Duration> {
+        let pings = self.pings.lock().unwrap();
+        let samples = pings.get(addr)?;
+        Some(samples.iter().sum::<Duration>() / samples.len() as u32)
+    }
+
+    /// Record the handshake round-trip time of a peer, replacing any
+    /// previous measurement.
+    pub(in crate::net) fn record_latency(&self, addr: &Url, rtt: Duration) {
+        let mut metadata = self.container.metadata.write().unwrap();
+        metadata.entry(addr.clone()).or_default().latency = Some(rtt);
+    }
+
+    /// Returns the last measured handshake round-trip time of a peer.
+    pub fn latency(&self, addr: &Url) -> Option<Duration> {
+        self.container.metadata(addr).latency
+    }
+
+    /// Record a failed outbound connection attempt to a peer.
+    pub(in crate::net) fn record_failure(&self, addr: &Url) {
+        let mut metadata = self.container.metadata.write().unwrap();
+        let entry = metadata.entry(addr.clone()).or_default();
+        entry.failures = entry.failures.saturating_add(1);
+    }
+
+    /// Reset the failed connection attempts of a peer we connected to.
+    pub(in crate::net) fn clear_failures(&self, addr: &Url) {
+        if let Some(entry) = self.container.metadata.write().unwrap().get_mut(addr) {
+            entry.failures = 0;
+        }
+    }
+
+    /// Sort the given hosts by ascending failed connection attempts, then
+    /// by ascending handshake round-trip time. Hosts without a measurement
+    /// are placed last among equals, keeping their order.
+    pub(in crate::net) fn sort_by_reliability(&self, hosts: &mut [(Url, u64)]) {
+        let metadata = self.container.metadata.read().unwrap();
+        hosts.sort_by_key(|(addr, _)| match metadata.get(addr) {
+            Some(meta) => (meta.failures, meta.latency.unwrap_or(Duration::MAX)),
+            None => (0, Duration::MAX),
+        });
+    }
+
+    /// Move the given hosts in subnets already holding `max_per_subnet` of
+    /// the peers we're connected or connecting to after the other ones,
+    /// keeping their order otherwise. Hosts in crowded subnets are still
+    /// tried when there's nothing else, so a small hostlist doesn't leave
+    /// slots empty. A `max_per_subnet` of 0 leaves the hosts untouched.
+    pub(in crate::net) fn sort_by_subnet_diversity(
+        &self,
+        hosts: &mut [(Url, u64)],
+        max_per_subnet: usize,
+    ) {
+        if max_per_subnet == 0 {
+            return
+        }
+
+        let mut subnets: HashMap<String, usize> = HashMap::new();
+        for (addr, state) in self.registry.lock().unwrap().iter() {
+            let counted = match state {
+                HostState::Connect => true,
+                HostState::Connected(c) => c.session_type_id() == SESSION_OUTBOUND,
+                _ => false,
+            };
+            if let (true, Some(subnet)) = (counted, HostDiversity::subnet(addr)) {
+                *subnets.entry(subnet).or_default() += 1;
+            }
+        }
+
+        hosts.sort_by_key(|(addr, _)| {
+            HostDiversity::subnet(addr)
+                .is_some_and(|subnet| subnets.get(&subnet).is_some_and(|n| *n >= max_per_subnet))
+        });
+    }
+
+    /// Returns the diversity of the whitelisted hosts
+    pub fn whitelist_diversity(&self) -> HostDiversity {
+        let whitelist = self.container.fetch_all(HostColor::White);
+        HostDiversity::new(whitelist.iter().map(|(addr, _)| addr))
+    }
+
+    /// Returns the diversity of the connected peers. Seed and refinery
+    /// connections are not taken into account.
+    pub fn peers_diversity(&self) -> HostDiversity {
+        let peers = self.peers();
+        HostDiversity::new(peers.iter().map(|c| c.address()))
+    }
+
+    /// Returns the diversity of the outbound peers
+    pub fn outbound_diversity(&self) -> HostDiversity {
+        let peers = self.peers();
+        HostDiversity::new(
+            peers.iter().filter(|c| c.session_type_id() == SESSION_OUTBOUND).map(|c| c.address()),
+        )
+    }
+
+    /// Remember our current outbound peers as anchors, up to the configured
+    /// anchor count, preferring the ones we stayed connected to the longest.
+    /// If we have no outbound peers, the previous anchors are kept, so a node
+    /// shut down while offline doesn't forget them.
+    pub(in crate::net) async fn record_anchors(&self) {
+        let anchor_count = self.settings.read().await.anchor_connect_count;
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+
+        let mut peers: Vec<ChannelPtr> =
+            self.peers().into_iter().filter(|c| c.session_type_id() == SESSION_OUTBOUND).collect();
+        peers.sort_by_key(|c| c.info.start_time);
+
+        let anchors: Vec<(Url, u64)> =
+            peers.iter().take(anchor_count).map(|c| (c.address().clone(), now)).collect();
+
+        if anchors.is_empty() && anchor_count > 0 {
+            return
+        }
+
+        debug!(target: "net::hosts::record_anchors()", "Recording {} anchors", anchors.len());
+        self.container.set_anchors(anchors);
+    }
+
+    /// Returns true if our outbound peers were found to be concentrated
+    /// in too few subnets on the last diversity check.
+    pub fn diversity_skewed(&self) -> bool {
+        self.diversity_skewed.load(Ordering::SeqCst)
+    }
+
+    /// Check the diversity of our outbound peers against the configured
+    /// thresholds, and warn when it drops below them.
+    pub(in crate::net) async fn check_diversity(&self) {
+        let settings = self.settings.read().await;
+        let min_subnets = settings.outbound_min_subnets;
+        let max_subnet_share = settings.outbound_max_subnet_share;
+        drop(settings);
+
+        let diversity = self.outbound_diversity();
+        let total = diversity.total();
+        let subnets = diversity.subnets.len();
+        let share = diversity.largest_subnet_share();
+
+        // Only judge once we have enough peers to meet the thresholds
+        let skewed =
+            total >= min_subnets.max(2) && (subnets < min_subnets || share > max_subnet_share);
+
+        if skewed && !self.diversity_skewed.swap(true, Ordering::SeqCst) {
+            warn!(
+                target: "net::hosts::check_diversity()",
+                "[P2P] Low outbound peer diversity: {} peers in {} subnets, {}% in a single subnet",
+                total, subnets, share,
+            );
+        } else if !skewed && self.diversity_skewed.swap(false, Ordering::SeqCst) {
+            info!(
+                target: "net::hosts::check_diversity()",
+                "[P2P] Outbound peer diversity restored: {} peers in {} subnets", total, subnets,
+            );
+        }
+    }
+
     /// To block a peer trying to access by all ports, simply store its
     /// hostname in the blacklist. This method will check if a host is
     /// stored in the blacklist without a port, and if so, it will return
@@ -1215,9 +2038,28 @@ impl Hosts {
             .any(|(u, _t)| u.host().unwrap() == host && u.port().is_none())
     }
 
+    /// Check if a peer is allowed to interact with us. This always returns
+    /// true unless we are running as a private network, in which case the
+    /// peer must either be a configured manual peer, or match an allowlist
+    /// entry. Allowlist entries without a port match every port of the host.
+    /// Inbound peers connect from ephemeral ports, so only their host is
+    /// checked.
+    pub(in crate::net) async fn is_allowed(&self, url: &Url, inbound: bool) -> bool {
+        let settings = self.settings.read().await;
+        if !settings.private_network {
+            return true
+        }
+
+        let host = url.host();
+        let matches =
+            |u: &Url| u.host() == host && (inbound || u.port().is_none() || u.port() == url.port());
+        settings.peers.iter().any(matches) || settings.allowlist.iter().any(matches)
+    }
+
     /// Filter given addresses based on certain rulesets and validity. Strictly called only on
-    /// the first time learning of new peers.
-    async fn filter_addresses(&self, addrs: &[(Url, u64)]) -> Vec<(Url, u64)> {
+    /// the first time learning of new peers. Non-global ranges are only kept on localnet,
+    /// or if `allow_local` is set.
+    async fn filter_addresses(&self, addrs: &[(Url, u64)], allow_local: bool) -> Vec<(Url, u64)> {
         debug!(target: "net::hosts::filter_addresses", "Filtering addrs: {:?}", addrs);
         let mut ret = vec![];
 
@@ -1293,7 +2135,7 @@ impl Hosts {
             // Filter non-global ranges if we're not allowing localnet.
             // Should never be allowed in production, so we don't really care
             // about some of them (e.g. 0.0.0.0, or broadcast, etc.).
-            if !settings.localnet && self.is_local_host(addr_) {
+            if !settings.localnet && !allow_local && self.is_local_host(addr_) {
                 debug!(
                     target: "net::hosts::filter_addresses",
                     "[{}] Filtering non-global ranges", addr_,
@@ -1326,6 +2168,14 @@ impl Hosts {
                     );
                 }
 
+                #[cfg(feature = "p2p-memory")]
+                "memory" => {
+                    trace!(
+                        target: "net::hosts::filter_addresses",
+                        "[Memory] Valid: {}", host_str,
+                    );
+                }
+
                 _ => continue,
             }
 
@@ -1479,6 +2329,37 @@ impl Hosts {
     }
 }
 
+/// Parse the latency, failures and origin fields of a hostlist entry
+fn parse_metadata(fields: &[&str]) -> Option<HostMetadata> {
+    let [latency, failures, origin] = fields else { return None };
+    let latency = match *latency {
+        "-" => None,
+        millis => Some(Duration::from_millis(millis.parse().ok()?)),
+    };
+
+    Some(HostMetadata { latency, failures: failures.parse().ok()?, origin: origin.parse().ok()? })
+}
+
+/// Returns the entry kept for `addr` in a per-address map, making room
+/// for it by evicting a random entry if the map is full
+fn bounded_entry<'a, V: Default>(map: &'a mut HashMap<Url, V>, addr: &Url) -> &'a mut V {
+    if !map.contains_key(addr) && map.len() >= PEER_STATS_MAX_LEN {
+        let evicted = map.keys().choose(&mut OsRng).cloned();
+        if let Some(evicted) = evicted {
+            map.remove(&evicted);
+        }
+    }
+    map.entry(addr.clone()).or_default()
+}
+
+/// Move the entry kept for `from` in a per-address map over to `to`
+fn move_entry<V>(map: &Mutex<HashMap<Url, V>>, from: &Url, to: &Url) {
+    let mut map = map.lock().unwrap();
+    if let Some(value) = map.remove(from) {
+        map.insert(to.clone(), value);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1519,6 +2400,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_is_allowed() {
+        smol::block_on(async {
+            let settings = Settings {
+                private_network: true,
+                allowlist: vec![
+                    Url::parse("tcp://10.0.0.2:8442").unwrap(),
+                    Url::parse("tcp://10.0.0.3").unwrap(),
+                ],
+                ..Default::default()
+            };
+            let hosts = Hosts::new(Arc::new(AsyncRwLock::new(settings)));
+
+            // Ports are only checked when dialing
+            let dialed = Url::parse("tcp://10.0.0.2:8442").unwrap();
+            let ephemeral = Url::parse("tcp://10.0.0.2:53124").unwrap();
+            assert!(hosts.is_allowed(&dialed, false).await);
+            assert!(!hosts.is_allowed(&ephemeral, false).await);
+            assert!(hosts.is_allowed(&ephemeral, true).await);
+
+            let any_port = Url::parse("tcp://10.0.0.3:1234").unwrap();
+            assert!(hosts.is_allowed(&any_port, false).await);
+
+            let stranger = Url::parse("tcp://10.0.0.4:8442").unwrap();
+            assert!(!hosts.is_allowed(&stranger, false).await);
+            assert!(!hosts.is_allowed(&stranger, true).await);
+        });
+    }
+
     #[test]
     fn test_is_ipv6() {
         let settings = Settings { ..Default::default() };
@@ -1683,4 +2593,349 @@ mod tests {
             println!("last entry: {} {}", entry.0, entry.1);
         });
     }
+
+    #[test]
+    fn test_misbehavior_ban() {
+        smol::block_on(async {
+            let settings = Settings {
+                ban_score_threshold: 100,
+                ban_score_half_life: 0,
+                ban_duration: 3600,
+                ..Default::default()
+            };
+            let hosts = Hosts::new(Arc::new(AsyncRwLock::new(settings)));
+            let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
+
+            let peer = Url::parse("tcp://peer.example.org:123").unwrap();
+            hosts.container.store(HostColor::White as usize, peer.clone(), last_seen);
+
+            // Scores accumulate until they reach the threshold
+            assert!(!hosts.report_misbehavior(&peer, 60, "spam").await);
+            assert!(hosts.bans().is_empty());
+            assert!(hosts.report_misbehavior(&peer, 40, "more spam").await);
+            assert!(!hosts.container.contains(HostColor::White as usize, &peer));
+            assert!(hosts.container.contains(HostColor::Black as usize, &peer));
+
+            let bans = hosts.bans();
+            assert_eq!(bans.len(), 1);
+            assert_eq!(bans[0].0, peer);
+            assert_eq!(bans[0].1.reason, "more spam");
+
+            // Local hosts never get banned
+            let local = Url::parse("tcp://127.0.0.1:123").unwrap();
+            assert!(!hosts.report_misbehavior(&local, 100, "spam").await);
+            assert!(!hosts.container.contains(HostColor::Black as usize, &local));
+
+            // Bans persist across restarts
+            let path = std::env::temp_dir()
+                .join(format!("darkfi_test_misbehavior_ban_{}.tsv", std::process::id()));
+            let path = path.to_str().unwrap();
+            hosts.container.save_all(path).unwrap();
+            let restarted = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+            restarted.container.load_all(path).unwrap();
+            fs::remove_file(path).unwrap();
+            assert!(restarted.container.contains(HostColor::Black as usize, &peer));
+            assert_eq!(restarted.bans()[0].1.until, bans[0].1.until);
+
+            // Expired bans get lifted
+            restarted.container.bans.write().unwrap().get_mut(&peer).unwrap().until = last_seen;
+            restarted.refresh_bans().await;
+            assert!(restarted.bans().is_empty());
+            assert!(!restarted.container.contains(HostColor::Black as usize, &peer));
+        });
+    }
+
+    #[test]
+    fn test_misbehavior_decay() {
+        let score = MisbehaviorScore { score: 80.0, updated: 1000 };
+        assert_eq!(score.decayed(1000, 3600), 80.0);
+        assert_eq!(score.decayed(4600, 3600), 40.0);
+        assert_eq!(score.decayed(8200, 3600), 20.0);
+        assert_eq!(score.decayed(8200, 0), 80.0);
+    }
+
+    #[test]
+    fn test_identity_moves() {
+        let hosts = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+        let peer_id = PeerId([1; 32]);
+        let old_addr = Url::parse("tcp://old.example.org:123").unwrap();
+        let new_addr = Url::parse("tcp://new.example.org:123").unwrap();
+
+        assert!(hosts.register_identity(&old_addr, peer_id));
+        hosts.container.store(HostColor::Gold as usize, old_addr.clone(), 1000);
+        hosts.record_stop(&old_addr, StopReason::Timeout);
+
+        // Reputation follows the peer to its new address
+        assert!(hosts.register_identity(&new_addr, peer_id));
+        assert_eq!(hosts.peer_id(&new_addr), Some(peer_id));
+        assert_eq!(hosts.peer_id(&old_addr), None);
+        assert!(!hosts.container.contains(HostColor::Gold as usize, &old_addr));
+        assert_eq!(hosts.container.get_last_seen(HostColor::Gold as usize, &new_addr), Some(1000));
+        assert_eq!(hosts.stop_counts(&new_addr).timeout, 1);
+
+        // Identities persist across restarts
+        let path = std::env::temp_dir()
+            .join(format!("darkfi_test_identity_moves_{}.tsv", std::process::id()));
+        let path = path.to_str().unwrap();
+        hosts.container.save_all(path).unwrap();
+        let restarted = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+        restarted.container.load_all(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(restarted.peer_id(&new_addr), Some(peer_id));
+
+        // Bans do too
+        hosts.ban_host(&new_addr, u64::MAX, "spam");
+        assert!(!hosts.register_identity(&old_addr, peer_id));
+        assert!(hosts.container.contains(HostColor::Black as usize, &old_addr));
+
+        // Another peer taking over the address replaces it, and identities
+        // are forgotten along with their hosts
+        let other_id = PeerId([2; 32]);
+        assert!(restarted.register_identity(&new_addr, other_id));
+        assert_eq!(restarted.peer_id(&new_addr), Some(other_id));
+        assert!(restarted.register_identity(&old_addr, peer_id));
+        restarted.container.remove_metadata(&new_addr);
+        assert_eq!(restarted.peer_id(&new_addr), None);
+        assert_eq!(restarted.peer_id(&old_addr), Some(peer_id));
+    }
+
+    #[test]
+    fn test_stop_counts() {
+        let hosts = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+        let peer_a = Url::parse("tcp://peer-a.example.org:123").unwrap();
+        let peer_b = Url::parse("tcp://peer-b.example.org:123").unwrap();
+
+        hosts.record_stop(&peer_a, StopReason::Timeout);
+        hosts.record_stop(&peer_a, StopReason::Timeout);
+        hosts.record_stop(&peer_a, StopReason::ProtocolViolation);
+        hosts.record_stop(&peer_b, StopReason::RemoteClose);
+
+        let counts = hosts.stop_counts(&peer_a);
+        assert_eq!(counts.timeout, 2);
+        assert_eq!(counts.protocol_violation, 1);
+        assert_eq!(counts.remote_close, 0);
+
+        let total = hosts.total_stop_counts();
+        assert_eq!(total.timeout, 2);
+        assert_eq!(total.protocol_violation, 1);
+        assert_eq!(total.remote_close, 1);
+        assert_eq!(total.local_shutdown, 0);
+
+        // The counts kept are bounded
+        for i in 0..PEER_STATS_MAX_LEN + 1 {
+            let peer = Url::parse(&format!("tcp://peer-{}.example.org:123", i)).unwrap();
+            hosts.record_stop(&peer, StopReason::Timeout);
+        }
+        assert_eq!(hosts.stop_counts.lock().unwrap().len(), PEER_STATS_MAX_LEN);
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        let hosts = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+        let peer_a = Url::parse("tcp://peer-a.example.org:123").unwrap();
+        let peer_b = Url::parse("tcp://peer-b.example.org:123").unwrap();
+
+        hosts.record_protocol_error(&peer_a, ProtocolError::Decode);
+        hosts.record_protocol_error(&peer_a, ProtocolError::Decode);
+        hosts.record_protocol_error(&peer_a, ProtocolError::Timeout);
+        hosts.record_protocol_error(&peer_b, ProtocolError::UnknownMessage);
+
+        let errors = hosts.protocol_errors(&peer_a);
+        assert_eq!(errors.decode_failures, 2);
+        assert_eq!(errors.timeouts, 1);
+        assert_eq!(errors.unknown_messages, 0);
+        assert_eq!(errors.total(), 3);
+
+        assert_eq!(hosts.protocol_errors(&peer_b).unknown_messages, 1);
+        assert_eq!(hosts.protocol_errors(&Url::parse("tcp://peer-c:123").unwrap()).total(), 0);
+
+        // The errors kept are bounded
+        for i in 0..PEER_STATS_MAX_LEN + 1 {
+            let peer = Url::parse(&format!("tcp://peer-{}.example.org:123", i)).unwrap();
+            hosts.record_protocol_error(&peer, ProtocolError::Decode);
+        }
+        assert_eq!(hosts.protocol_errors.lock().unwrap().len(), PEER_STATS_MAX_LEN);
+    }
+
+    #[test]
+    fn test_sort_by_reliability() {
+        let hosts = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+        let fast = Url::parse("tcp://fast.example.org:123").unwrap();
+        let slow = Url::parse("tcp://slow.example.org:123").unwrap();
+        let unknown = Url::parse("tcp://unknown.example.org:123").unwrap();
+
+        hosts.record_latency(&slow, Duration::from_millis(800));
+        hosts.record_latency(&fast, Duration::from_millis(50));
+        assert_eq!(hosts.latency(&fast), Some(Duration::from_millis(50)));
+        assert_eq!(hosts.latency(&unknown), None);
+
+        let mut list = vec![(unknown.clone(), 3), (slow.clone(), 2), (fast.clone(), 1)];
+        hosts.sort_by_reliability(&mut list);
+        assert_eq!(list, vec![(fast.clone(), 1), (slow.clone(), 2), (unknown.clone(), 3)]);
+
+        // Failing hosts go last, until we connect to them again
+        hosts.record_failure(&fast);
+        hosts.sort_by_reliability(&mut list);
+        assert_eq!(list, vec![(slow.clone(), 2), (unknown.clone(), 3), (fast.clone(), 1)]);
+
+        hosts.clear_failures(&fast);
+        hosts.sort_by_reliability(&mut list);
+        assert_eq!(list[0].0, fast);
+
+        hosts.container.remove_metadata(&fast);
+        assert_eq!(hosts.latency(&fast), None);
+    }
+
+    #[test]
+    fn test_ping_latency() {
+        let hosts = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+        let peer = Url::parse("tcp://peer.example.org:123").unwrap();
+        assert_eq!(hosts.ping_latency(&peer), None);
+
+        hosts.record_ping(&peer, Duration::from_millis(100));
+        hosts.record_ping(&peer, Duration::from_millis(300));
+        assert_eq!(hosts.ping_latency(&peer), Some(Duration::from_millis(200)));
+
+        // Only the last samples are averaged
+        for _ in 0..PING_SAMPLES {
+            hosts.record_ping(&peer, Duration::from_millis(50));
+        }
+        assert_eq!(hosts.ping_latency(&peer), Some(Duration::from_millis(50)));
+
+        hosts.remove_pings(&peer);
+        assert_eq!(hosts.ping_latency(&peer), None);
+    }
+
+    #[test]
+    fn test_hostlist_metadata() {
+        smol::block_on(async {
+            let hosts = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+            let peer = Url::parse("tcp+tls://1.2.3.4:26661").unwrap();
+            let seed_peer = Url::parse("tcp+tls://5.6.7.8:26661").unwrap();
+
+            hosts.insert(HostColor::Grey, &[(peer.clone(), 1000)], HostOrigin::Discovery).await;
+            hosts.insert(HostColor::Grey, &[(seed_peer.clone(), 2000)], HostOrigin::Seed).await;
+            hosts.insert(HostColor::Grey, &[(peer.clone(), 1000)], HostOrigin::Seed).await;
+            hosts.record_latency(&peer, Duration::from_millis(120));
+            hosts.record_failure(&peer);
+            hosts.record_failure(&peer);
+
+            let metadata = hosts.container.metadata(&peer);
+            assert_eq!(metadata.origin, HostOrigin::Discovery);
+            assert_eq!(metadata.failures, 2);
+
+            // Metadata persists across restarts
+            let path = std::env::temp_dir()
+                .join(format!("darkfi_test_hostlist_metadata_{}.tsv", std::process::id()));
+            let path = path.to_str().unwrap();
+            hosts.container.save_all(path).unwrap();
+            let restarted = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+            restarted.container.load_all(path).unwrap();
+            assert_eq!(restarted.container.metadata(&peer), metadata);
+            assert_eq!(restarted.container.metadata(&seed_peer).origin, HostOrigin::Seed);
+            assert_eq!(restarted.container.metadata(&seed_peer).latency, None);
+
+            // Hostlists in the original format get migrated
+            fs::write(path, format!("grey\t{}\t1000\nwhite\t{}\t2000\n", peer, seed_peer)).unwrap();
+            let migrated = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+            migrated.container.load_all(path).unwrap();
+            assert_eq!(migrated.container.fetch_all(HostColor::Grey), vec![(peer.clone(), 1000)]);
+            assert_eq!(migrated.container.metadata(&seed_peer), HostMetadata::default());
+
+            migrated.container.save_all(path).unwrap();
+            let contents = fs::read_to_string(path).unwrap();
+            fs::remove_file(path).unwrap();
+            assert!(contents.starts_with(HOSTLIST_HEADER));
+            assert!(contents.contains(&format!("white\t{}\t2000\t-\t0\tunknown\n", seed_peer)));
+        });
+    }
+
+    #[test]
+    fn test_host_diversity() {
+        let addrs: Vec<Url> = [
+            "tcp+tls://1.2.3.4:26661",
+            "tcp+tls://1.2.200.1:26661",
+            "tcp://5.6.7.8:26661",
+            "tcp+tls://[2001:db8::1]:26661",
+            "tor://fz6ld6r6zr2g3nmmfvcdbdbl3f7rvaeyeuqqw2rbx4q3jbxhsjaa4fqd.onion:26661",
+            "tcp+tls://dark.fi:26661",
+        ]
+        .iter()
+        .map(|a| Url::parse(a).unwrap())
+        .collect();
+
+        let diversity = HostDiversity::new(addrs.iter());
+        assert_eq!(diversity.total(), 6);
+        assert_eq!(diversity.families["ipv4"], 3);
+        assert_eq!(diversity.families["ipv6"], 1);
+        assert_eq!(diversity.families["onion"], 1);
+        assert_eq!(diversity.families["dns"], 1);
+        assert_eq!(diversity.transports["tcp+tls"], 4);
+        assert_eq!(diversity.subnets.len(), 3);
+        assert_eq!(diversity.subnets["1.2.0.0/16"], 2);
+        assert_eq!(diversity.subnets["2001:db8::/32"], 1);
+        assert_eq!(diversity.largest_subnet_share(), 33);
+
+        assert_eq!(HostDiversity::new([].iter()).largest_subnet_share(), 0);
+    }
+
+    #[test]
+    fn test_sort_by_subnet_diversity() {
+        let hosts = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+        let busy_a = Url::parse("tcp+tls://1.2.3.4:26661").unwrap();
+        let busy_b = Url::parse("tcp+tls://1.2.5.6:26661").unwrap();
+        let crowded = Url::parse("tcp+tls://1.2.7.8:26661").unwrap();
+        let fresh = Url::parse("tcp+tls://9.9.9.9:26661").unwrap();
+        let onion = Url::parse(
+            "tor://fz6ld6r6zr2g3nmmfvcdbdbl3f7rvaeyeuqqw2rbx4q3jbxhsjaa4fqd.onion:26661",
+        )
+        .unwrap();
+
+        hosts.try_register(busy_a, HostState::Connect).unwrap();
+        hosts.try_register(busy_b, HostState::Connect).unwrap();
+
+        let list = vec![(crowded.clone(), 1), (fresh.clone(), 2), (onion.clone(), 3)];
+
+        // Disabled
+        let mut sorted = list.clone();
+        hosts.sort_by_subnet_diversity(&mut sorted, 0);
+        assert_eq!(sorted, list);
+
+        // Still room in the subnet
+        hosts.sort_by_subnet_diversity(&mut sorted, 3);
+        assert_eq!(sorted, list);
+
+        // The crowded subnet is only tried last
+        hosts.sort_by_subnet_diversity(&mut sorted, 2);
+        assert_eq!(sorted, vec![(fresh, 2), (onion, 3), (crowded, 1)]);
+    }
+
+    #[test]
+    fn test_anchors() {
+        smol::block_on(async {
+            let settings = Settings { anchor_connect_count: 1, ..Default::default() };
+            let hosts = Hosts::new(Arc::new(AsyncRwLock::new(settings)));
+            assert!(!hosts.container.has_anchors());
+
+            // Without outbound peers, previous anchors are kept
+            let anchor = Url::parse("tcp://1.2.3.4:26661").unwrap();
+            hosts.container.set_anchors(vec![(anchor.clone(), 1000)]);
+            hosts.record_anchors().await;
+            assert!(hosts.container.has_anchors());
+
+            // Anchors persist across restarts
+            let path = std::env::temp_dir()
+                .join(format!("darkfi_test_anchors_{}.tsv", std::process::id()));
+            let path = path.to_str().unwrap();
+            hosts.container.save_all(path).unwrap();
+            let restarted = Hosts::new(Arc::new(AsyncRwLock::new(Settings::default())));
+            restarted.container.load_all(path).unwrap();
+            fs::remove_file(path).unwrap();
+
+            // Each anchor is only tried once
+            assert_eq!(restarted.container.pop_anchor(), Some((anchor, 1000)));
+            assert_eq!(restarted.container.pop_anchor(), None);
+            assert!(!restarted.container.has_anchors());
+        });
+    }
 }
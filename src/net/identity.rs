@@ -0,0 +1,156 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{fmt, fs, io::Write, os::unix::fs::OpenOptionsExt, path::Path, str::FromStr};
+
+use darkfi_serial::{SerialDecodable, SerialEncodable};
+use ed25519_compact::{KeyPair, PublicKey, Seed, Signature};
+use log::info;
+
+use crate::{Error, Result};
+
+/// Context prepended to the transcripts signed as identity proofs
+const IDENTITY_PROOF_CONTEXT: &[u8] = b"darkfi net identity proof";
+
+/// Name of the file holding the identity key in the P2P datastore
+const IDENTITY_KEY_FILE: &str = "identity.key";
+
+/// Identifier of a node, the public half of its ed25519 identity key
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, SerialEncodable, SerialDecodable)]
+pub struct PeerId(pub [u8; 32]);
+
+impl PeerId {
+    /// Verify that `proof` was made over the key exchange `transcript`
+    /// of a channel with the identity key of this peer.
+    pub fn verify(&self, transcript: &[u8; 32], proof: &[u8]) -> bool {
+        let Ok(signature) = Signature::from_slice(proof) else { return false };
+        PublicKey::new(self.0).verify(proof_message(transcript), &signature).is_ok()
+    }
+}
+
+impl fmt::Display for PeerId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", bs58::encode(self.0).into_string())
+    }
+}
+
+impl FromStr for PeerId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Ok(bytes) = bs58::decode(s).into_vec() else {
+            return Err(Error::ParseFailed("Invalid peer ID encoding"))
+        };
+        let Ok(bytes) = bytes.try_into() else {
+            return Err(Error::ParseFailed("Invalid peer ID length"))
+        };
+        Ok(Self(bytes))
+    }
+}
+
+/// Persistent identity key of a node. Peers prove ownership of theirs
+/// over encrypted channels by signing the key exchange transcript, so
+/// they can be recognized across address changes.
+pub struct Identity {
+    keypair: KeyPair,
+}
+
+impl Identity {
+    /// Generate a fresh identity, lasting for the lifetime of the process
+    pub fn generate() -> Self {
+        Self { keypair: KeyPair::from_seed(Seed::generate()) }
+    }
+
+    /// Load the identity stored in the given P2P datastore, generating
+    /// and storing a new one if there is none yet.
+    pub fn load_or_generate(datastore: &Path) -> Result<Self> {
+        let path = datastore.join(IDENTITY_KEY_FILE);
+
+        if path.exists() {
+            let Ok(seed) = Seed::from_slice(&fs::read(&path)?) else {
+                return Err(Error::ParseFailed("Invalid identity key file"))
+            };
+            return Ok(Self { keypair: KeyPair::from_seed(seed) })
+        }
+
+        // Create the file readable by us only, refusing to reuse one
+        // created in the meantime
+        let seed = Seed::generate();
+        let mut file =
+            fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path)?;
+        file.write_all(&*seed)?;
+        file.sync_all()?;
+
+        let identity = Self { keypair: KeyPair::from_seed(seed) };
+        info!(target: "net::identity", "[P2P] Generated identity {}", identity.peer_id());
+        Ok(identity)
+    }
+
+    /// Our peer ID
+    pub fn peer_id(&self) -> PeerId {
+        PeerId(*self.keypair.pk)
+    }
+
+    /// Prove ownership of our identity key by signing the key exchange
+    /// transcript of a channel, binding the proof to that channel.
+    pub fn prove(&self, transcript: &[u8; 32]) -> Vec<u8> {
+        self.keypair.sk.sign(proof_message(transcript), None).to_vec()
+    }
+}
+
+/// Message signed by identity proofs over `transcript`
+fn proof_message(transcript: &[u8; 32]) -> Vec<u8> {
+    [IDENTITY_PROOF_CONTEXT, &transcript[..]].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_identity_proof() {
+        let identity = Identity::generate();
+        let peer_id = identity.peer_id();
+
+        let transcript = [7u8; 32];
+        let proof = identity.prove(&transcript);
+        assert!(peer_id.verify(&transcript, &proof));
+        assert!(!peer_id.verify(&[8u8; 32], &proof));
+        assert!(!Identity::generate().peer_id().verify(&transcript, &proof));
+        assert!(!peer_id.verify(&transcript, &proof[1..]));
+
+        assert_eq!(PeerId::from_str(&peer_id.to_string()).unwrap(), peer_id);
+    }
+
+    #[test]
+    fn test_identity_persistence() {
+        let datastore =
+            std::env::temp_dir().join(format!("darkfi_test_identity_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&datastore);
+        fs::create_dir_all(&datastore).unwrap();
+
+        let identity = Identity::load_or_generate(&datastore).unwrap();
+        let mode = fs::metadata(datastore.join(IDENTITY_KEY_FILE)).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let reloaded = Identity::load_or_generate(&datastore).unwrap();
+        assert_eq!(identity.peer_id(), reloaded.peer_id());
+
+        fs::remove_dir_all(&datastore).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Discovery of other nodes on the local network.
+//!
+//! Every node with LAN discovery enabled periodically broadcasts a UDP
+//! announcement carrying the transports and ports of its TCP inbound
+//! addresses, and listens for the announcements of others. Announcements
+//! don't carry hosts: the address of a node is built from the source of
+//! its datagram, so a node can only announce itself. Found nodes enter
+//! the greylist, where the refinery checks them like any other host.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Weak},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use darkfi_serial::{deserialize, serialize, SerialDecodable, SerialEncodable};
+use log::{debug, info, warn};
+use smol::{future, lock::Mutex, net::UdpSocket, Timer};
+use socket2::{Domain, Protocol, Socket, Type};
+use url::Url;
+
+use super::p2p::{P2p, P2pPtr};
+use crate::{
+    system::{StoppableTask, StoppableTaskPtr},
+    Error, Result,
+};
+
+/// Magic bytes identifying a LAN announcement
+const LAN_MAGIC: [u8; 4] = *b"DFLN";
+/// Largest announcement we read
+const MAX_ANNOUNCEMENT_SIZE: usize = 512;
+/// Transports announced on the local network
+const LAN_TRANSPORTS: [&str; 2] = ["tcp", "tcp+tls"];
+
+/// Announcement broadcast on the local network
+#[derive(Debug, SerialEncodable, SerialDecodable)]
+struct LanAnnouncement {
+    magic: [u8; 4],
+    /// Nonce of the announcing P2P instance, so we can skip our own
+    nonce: u64,
+    /// Transport and port of each TCP inbound address of the node
+    endpoints: Vec<(String, u16)>,
+}
+
+pub type LanDiscoveryPtr = Arc<LanDiscovery>;
+
+/// Announces us to, and finds, other nodes on the local network
+pub struct LanDiscovery {
+    /// Weak pointer to parent p2p object
+    p2p: Weak<P2p>,
+    /// Task announcing us and listening for announcements
+    task: Mutex<Option<StoppableTaskPtr>>,
+}
+
+impl LanDiscovery {
+    pub fn new(p2p: Weak<P2p>) -> LanDiscoveryPtr {
+        Arc::new(Self { p2p, task: Mutex::new(None) })
+    }
+
+    fn p2p(&self) -> P2pPtr {
+        self.p2p.upgrade().unwrap()
+    }
+
+    /// Bind the discovery socket and start announcing and listening
+    pub(in crate::net) async fn start(self: Arc<Self>) {
+        let port = self.p2p().settings().read().await.lan_discovery_port;
+        let socket = match bind_socket(port) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!(
+                    target: "net::lan_discovery::start()",
+                    "[P2P] Failed binding LAN discovery port {}: {}", port, e,
+                );
+                return
+            }
+        };
+
+        info!(target: "net::lan_discovery::start()", "[P2P] LAN discovery on port {}", port);
+        let task = StoppableTask::new();
+        task.clone().start(
+            self.clone().run(socket),
+            // Ignore stop handler
+            |_| async {},
+            Error::NetworkServiceStopped,
+            self.p2p().executor(),
+        );
+        *self.task.lock().await = Some(task);
+    }
+
+    /// Stop announcing and listening
+    pub(in crate::net) async fn stop(&self) {
+        if let Some(task) = self.task.lock().await.take() {
+            task.stop().await;
+        }
+    }
+
+    async fn run(self: Arc<Self>, socket: UdpSocket) -> Result<()> {
+        let mut buf = [0u8; MAX_ANNOUNCEMENT_SIZE];
+        let mut next_announcement = Instant::now();
+
+        loop {
+            if Instant::now() >= next_announcement {
+                self.announce(&socket).await;
+                let interval = self.p2p().settings().read().await.lan_discovery_interval;
+                next_announcement = Instant::now() + Duration::from_secs(interval);
+            }
+
+            let recv = async { Some(socket.recv_from(&mut buf).await) };
+            let tick = async {
+                Timer::at(next_announcement).await;
+                None
+            };
+
+            let received = future::or(recv, tick).await;
+            match received {
+                Some(Ok((len, from))) => self.handle_announcement(&buf[..len], from).await,
+                Some(Err(e)) => {
+                    debug!(
+                        target: "net::lan_discovery::run()",
+                        "[P2P] Failed receiving LAN announcement: {}", e,
+                    );
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Broadcast our TCP inbound endpoints, if we have any
+    async fn announce(&self, socket: &UdpSocket) {
+        let settings = self.p2p().settings();
+        let settings = settings.read().await;
+
+        let endpoints: Vec<_> = settings
+            .inbound_addrs
+            .iter()
+            .filter(|addr| LAN_TRANSPORTS.contains(&addr.scheme()))
+            .filter_map(|addr| addr.port().map(|port| (addr.scheme().to_string(), port)))
+            .collect();
+        if endpoints.is_empty() {
+            return
+        }
+
+        let announcement =
+            LanAnnouncement { magic: LAN_MAGIC, nonce: self.p2p().nonce(), endpoints };
+        let target = SocketAddr::from((Ipv4Addr::BROADCAST, settings.lan_discovery_port));
+        if let Err(e) = socket.send_to(&serialize(&announcement), target).await {
+            debug!(
+                target: "net::lan_discovery::announce()",
+                "[P2P] Failed broadcasting LAN announcement: {}", e,
+            );
+        }
+    }
+
+    /// Add the node behind a received announcement to the greylist
+    async fn handle_announcement(&self, bytes: &[u8], from: SocketAddr) {
+        let Ok(announcement) = deserialize::<LanAnnouncement>(bytes) else { return };
+        if announcement.magic != LAN_MAGIC || announcement.nonce == self.p2p().nonce() {
+            return
+        }
+
+        let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        let addrs = lan_addrs(&announcement, from)
+            .into_iter()
+            .map(|addr| (addr, last_seen))
+            .collect::<Vec<_>>();
+        if addrs.is_empty() {
+            return
+        }
+
+        debug!(
+            target: "net::lan_discovery::handle_announcement()",
+            "[P2P] Found LAN nodes: {:?}", addrs,
+        );
+        self.p2p().hosts().insert_lan(&addrs).await;
+    }
+}
+
+/// Build the addresses of the node behind an announcement received from `from`
+fn lan_addrs(announcement: &LanAnnouncement, from: SocketAddr) -> Vec<Url> {
+    announcement
+        .endpoints
+        .iter()
+        .filter(|(scheme, _)| LAN_TRANSPORTS.contains(&scheme.as_str()))
+        .filter_map(|(scheme, port)| {
+            Url::parse(&format!("{}://{}", scheme, SocketAddr::new(from.ip(), *port))).ok()
+        })
+        .collect()
+}
+
+/// Create a UDP socket able to send and receive broadcasts on `port`,
+/// shared with the other nodes running on this machine.
+fn bind_socket(port: u16) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_broadcast(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    socket.set_nonblocking(true)?;
+
+    UdpSocket::try_from(std::net::UdpSocket::from(socket))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lan_addrs() {
+        let announcement = LanAnnouncement {
+            magic: LAN_MAGIC,
+            nonce: 42,
+            endpoints: vec![
+                ("tcp+tls".to_string(), 26661),
+                ("tor".to_string(), 26662),
+                ("tcp".to_string(), 26663),
+            ],
+        };
+
+        let bytes = serialize(&announcement);
+        let announcement: LanAnnouncement = deserialize(&bytes).unwrap();
+
+        let from = SocketAddr::from(([192, 168, 1, 7], 26659));
+        assert_eq!(
+            lan_addrs(&announcement, from),
+            vec![
+                Url::parse("tcp+tls://192.168.1.7:26661").unwrap(),
+                Url::parse("tcp://192.168.1.7:26663").unwrap(),
+            ]
+        );
+    }
+}
@@ -16,28 +16,198 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::{
+    io::Cursor,
+    sync::{Arc, OnceLock},
+};
+
 use darkfi_serial::{
-    async_trait, serialize_async, AsyncDecodable, AsyncEncodable, SerialDecodable, SerialEncodable,
+    async_trait, serialize_async, AsyncDecodable, AsyncEncodable, Decodable, Encodable,
+    SerialDecodable, SerialEncodable, VarInt,
 };
 use url::Url;
 
+use super::identity::PeerId;
+use crate::{Error, Result};
+
 pub(in crate::net) const MAGIC_BYTES: [u8; 4] = [0xd9, 0xef, 0xb6, 0x7d];
 
+/// Magic bytes of frames carrying a compressed payload. These are only
+/// sent to peers advertising `COMPRESSION_FEATURE` in their version message.
+pub(in crate::net) const COMPRESSED_MAGIC_BYTES: [u8; 4] = [0xd9, 0xef, 0xb6, 0x7e];
+
+/// Version message feature advertising support for compressed payloads
+pub const COMPRESSION_FEATURE: (&str, u32) = ("compress-zstd", 1);
+
+/// Magic bytes of frames carrying a sequence number, with a plain and a
+/// compressed payload respectively. These are only sent in cleartext to
+/// peers advertising `SEQUENCE_FEATURE` in their version message.
+/// Encrypted frames are ordered by the nonce of their cipher instead.
+pub(in crate::net) const SEQUENCED_MAGIC_BYTES: [u8; 4] = [0xd9, 0xef, 0xb6, 0x7f];
+pub(in crate::net) const SEQUENCED_COMPRESSED_MAGIC_BYTES: [u8; 4] = [0xd9, 0xef, 0xb6, 0x80];
+
+/// Version message feature advertising support for sequence numbers
+pub const SEQUENCE_FEATURE: (&str, u32) = ("sequence", 1);
+
+/// Size of the sequence number following the magic bytes
+pub(in crate::net) const SEQUENCE_LEN: usize = 8;
+
+/// Magic bytes of encrypted frames, carrying another frame sealed with
+/// the channel keys. These are only sent to peers advertising
+/// `ENCRYPTION_FEATURE` in their version message.
+pub(in crate::net) const ENCRYPTED_MAGIC_BYTES: [u8; 4] = [0xd9, 0xef, 0xb6, 0x81];
+
+/// Version message feature advertising support for channel encryption
+pub const ENCRYPTION_FEATURE: (&str, u32) = ("encrypt-chachapoly", 1);
+
+/// Version message feature advertising support for node nonces, sent in
+/// a `NodeNonceMessage` once the version exchange is done
+pub const NODE_NONCE_FEATURE: (&str, u32) = ("node-nonce", 1);
+
+/// Version message feature advertising support for identity proofs, sent
+/// in an `IdentityMessage` once the channel is encrypted
+pub const IDENTITY_FEATURE: (&str, u32) = ("identity", 1);
+
+/// Largest message payload accepted from peers, after decompression.
+/// Frames announcing more are refused before being read.
+pub const MAX_PAYLOAD_LEN: u64 = 32 * 1024 * 1024;
+
+/// Longest message command accepted from peers
+pub(in crate::net) const MAX_COMMAND_LEN: u64 = 255;
+
+/// zstd compression level used for message payloads
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Highest compression ratio accepted when decompressing a payload. This
+/// bounds the memory a peer can make us allocate with a small message.
+/// Payloads compressing better than this are sent uncompressed.
+const MAX_COMPRESSION_RATIO: usize = 64;
+
 /// Generic message template.
 pub trait Message: 'static + Send + Sync + AsyncDecodable + AsyncEncodable {
     const NAME: &'static str;
 }
 
 /// Generic serialized message template.
+///
+/// Holds the complete wire encoding of a message (magic bytes, command,
+/// payload length and payload) in a reference-counted buffer, so the
+/// message is encoded once and can be cheaply cloned and written to any
+/// number of channels without further copies.
+#[derive(Clone)]
 pub struct SerializedMessage {
     pub command: String,
-    pub payload: Vec<u8>,
+    /// Encoded frame, ready to be written to the wire
+    frame: Arc<Vec<u8>>,
+    /// Offset of the payload inside the frame
+    payload_offset: usize,
+    /// Encoded frame with a compressed payload, built on first use and
+    /// `None` if compression doesn't make the payload smaller
+    compressed: Arc<OnceLock<Option<Vec<u8>>>>,
 }
 
 impl SerializedMessage {
     pub async fn new<M: Message>(message: &M) -> Self {
-        Self { command: M::NAME.to_string(), payload: serialize_async(message).await }
+        let payload = serialize_async(message).await;
+        let payload_len = VarInt(payload.len() as u64);
+
+        let mut frame = Vec::with_capacity(
+            MAGIC_BYTES.len() +
+                VarInt(M::NAME.len() as u64).length() +
+                M::NAME.len() +
+                payload_len.length() +
+                payload.len(),
+        );
+        // Writing into a Vec can't fail
+        frame.extend_from_slice(&MAGIC_BYTES);
+        M::NAME.to_string().encode(&mut frame).unwrap();
+        payload_len.encode(&mut frame).unwrap();
+        let payload_offset = frame.len();
+        frame.extend_from_slice(&payload);
+
+        Self {
+            command: M::NAME.to_string(),
+            frame: Arc::new(frame),
+            payload_offset,
+            compressed: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Returns the complete encoded frame
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Returns the encoded message payload
+    pub fn payload(&self) -> &[u8] {
+        &self.frame[self.payload_offset..]
+    }
+
+    /// Returns the encoded frame with a compressed payload, or `None` if
+    /// compressing the payload isn't worth it. The compressed frame is
+    /// computed once and shared by all clones of this message.
+    pub fn compressed_frame(&self) -> Option<&[u8]> {
+        self.compressed.get_or_init(|| self.compress()).as_deref()
+    }
+
+    fn compress(&self) -> Option<Vec<u8>> {
+        let payload = self.payload();
+        let compressed = zstd::bulk::compress(payload, COMPRESSION_LEVEL).ok()?;
+        if payload.len() > compressed.len() * MAX_COMPRESSION_RATIO {
+            return None
+        }
+
+        // The compressed payload is prefixed with its uncompressed length
+        let raw_len = VarInt(payload.len() as u64);
+        let body_len = raw_len.length() + compressed.len();
+        if body_len >= payload.len() {
+            return None
+        }
+
+        let mut frame = Vec::with_capacity(self.payload_offset + body_len);
+        frame.extend_from_slice(&COMPRESSED_MAGIC_BYTES);
+        self.command.encode(&mut frame).unwrap();
+        VarInt(body_len as u64).encode(&mut frame).unwrap();
+        raw_len.encode(&mut frame).unwrap();
+        frame.extend_from_slice(&compressed);
+        Some(frame)
+    }
+}
+
+/// Decompress a payload received in a compressed frame
+pub(in crate::net) fn decompress_payload(body: &[u8]) -> Result<Vec<u8>> {
+    let mut cursor = Cursor::new(body);
+    let raw_len = VarInt::decode(&mut cursor)?.0;
+    let compressed = &body[cursor.position() as usize..];
+
+    if raw_len > MAX_PAYLOAD_LEN || raw_len > (compressed.len() * MAX_COMPRESSION_RATIO) as u64 {
+        return Err(Error::MalformedPacket)
     }
+
+    let Ok(payload) = zstd::bulk::decompress(compressed, raw_len as usize) else {
+        return Err(Error::MalformedPacket)
+    };
+    if payload.len() as u64 != raw_len {
+        return Err(Error::MalformedPacket)
+    }
+
+    Ok(payload)
+}
+
+/// Build the sequenced variant of an encoded frame, carrying the given
+/// sequence number right after its magic bytes
+pub(in crate::net) fn sequenced_frame(frame: &[u8], seq: u64) -> Vec<u8> {
+    let magic = if frame[..4] == COMPRESSED_MAGIC_BYTES {
+        SEQUENCED_COMPRESSED_MAGIC_BYTES
+    } else {
+        SEQUENCED_MAGIC_BYTES
+    };
+
+    let mut sequenced = Vec::with_capacity(frame.len() + SEQUENCE_LEN);
+    sequenced.extend_from_slice(&magic);
+    seq.encode(&mut sequenced).unwrap();
+    sequenced.extend_from_slice(&frame[4..]);
+    sequenced
 }
 
 #[macro_export]
@@ -108,6 +278,14 @@ pub struct VersionMessage {
 }
 impl_p2p_message!(VersionMessage, "version");
 
+impl VersionMessage {
+    /// Returns true if the sender advertised the given feature, in the
+    /// given version or a newer one.
+    pub fn has_feature(&self, (feature, version): (&str, u32)) -> bool {
+        self.features.iter().any(|(f, v)| f == feature && *v >= version)
+    }
+}
+
 /// Sends version information to inbound connection.
 /// Response to `VersionMessage`.
 #[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
@@ -116,3 +294,112 @@ pub struct VerackMessage {
     pub app_version: semver::Version,
 }
 impl_p2p_message!(VerackMessage, "verack");
+
+/// Peer ID of the sender, along with the proof it owns it. Only sent to
+/// peers advertising `IDENTITY_FEATURE`, over encrypted channels, once the
+/// version exchange is done.
+#[derive(Debug, Clone, SerialEncodable, SerialDecodable)]
+pub struct IdentityMessage {
+    /// Peer ID of the sending node
+    pub peer_id: PeerId,
+    /// Signature of the channel's key exchange transcript, made with the
+    /// identity key of `peer_id`
+    pub identity_proof: Vec<u8>,
+}
+impl_p2p_message!(IdentityMessage, "identity");
+
+/// Random nonce identifying the sending node for the lifetime of its P2P
+/// instance, used to detect duplicate channels. Only sent to peers
+/// advertising `NODE_NONCE_FEATURE`, once the version exchange is done.
+#[derive(Debug, Copy, Clone, SerialEncodable, SerialDecodable)]
+pub struct NodeNonceMessage {
+    pub nonce: u64,
+}
+impl_p2p_message!(NodeNonceMessage, "nodenonce");
+
+/// Ephemeral X25519 public key of the sender, used to agree on the keys
+/// encrypting the channel. Only sent to peers advertising
+/// `ENCRYPTION_FEATURE`, right after the version exchange.
+#[derive(Debug, Copy, Clone, SerialEncodable, SerialDecodable)]
+pub struct KeyExchangeMessage {
+    pub public_key: [u8; 32],
+    /// Random nonce of the sender, part of the key exchange transcript
+    pub nonce: [u8; 32],
+}
+impl_p2p_message!(KeyExchangeMessage, "keyexchange");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_features() {
+        let version = VersionMessage {
+            node_id: String::new(),
+            version: semver::Version::new(0, 4, 1),
+            timestamp: 0,
+            connect_recv_addr: Url::parse("tcp://127.0.0.1:26661").unwrap(),
+            resolve_recv_addr: None,
+            ext_send_addr: vec![],
+            features: vec![(COMPRESSION_FEATURE.0.to_string(), 2)],
+        };
+
+        assert!(version.has_feature(COMPRESSION_FEATURE));
+        assert!(version.has_feature((COMPRESSION_FEATURE.0, 2)));
+        assert!(!version.has_feature((COMPRESSION_FEATURE.0, 3)));
+        assert!(!version.has_feature(SEQUENCE_FEATURE));
+    }
+
+    #[test]
+    fn test_compressed_frame() {
+        smol::block_on(async {
+            let addrs = (0..100)
+                .map(|i| (Url::parse(&format!("tcp+tls://node{i}.example.com:26661")).unwrap(), i))
+                .collect();
+            let serialized = SerializedMessage::new(&AddrsMessage { addrs }).await;
+
+            let frame = serialized.compressed_frame().unwrap();
+            assert!(frame.len() < serialized.frame().len());
+            assert_eq!(frame[..4], COMPRESSED_MAGIC_BYTES);
+
+            // Skip the magic bytes, command and payload length
+            let mut cursor = Cursor::new(&frame[4..]);
+            assert_eq!(String::decode(&mut cursor).unwrap(), "addr");
+            let body_len = VarInt::decode(&mut cursor).unwrap().0 as usize;
+            let body = &frame[4 + cursor.position() as usize..];
+            assert_eq!(body.len(), body_len);
+            assert_eq!(decompress_payload(body).unwrap(), serialized.payload());
+
+            // Tiny payloads are not worth compressing
+            let ping = SerializedMessage::new(&PingMessage { nonce: 1 }).await;
+            assert!(ping.compressed_frame().is_none());
+
+            // A compressed payload claiming a huge size is refused
+            let mut bogus = vec![];
+            VarInt(u32::MAX as u64).encode(&mut bogus).unwrap();
+            bogus.extend_from_slice(&zstd::bulk::compress(&[0u8; 16], COMPRESSION_LEVEL).unwrap());
+            assert!(decompress_payload(&bogus).is_err());
+        });
+    }
+
+    #[test]
+    fn test_sequenced_frame() {
+        smol::block_on(async {
+            let ping = SerializedMessage::new(&PingMessage { nonce: 1 }).await;
+            let frame = sequenced_frame(ping.frame(), 42);
+            assert_eq!(frame[..4], SEQUENCED_MAGIC_BYTES);
+            assert_eq!(frame.len(), ping.frame().len() + SEQUENCE_LEN);
+
+            let mut cursor = Cursor::new(&frame[4..]);
+            assert_eq!(u64::decode(&mut cursor).unwrap(), 42);
+            assert_eq!(&frame[4 + SEQUENCE_LEN..], &ping.frame()[4..]);
+
+            let addrs = (0..100)
+                .map(|i| (Url::parse(&format!("tcp+tls://node{i}.example.com:26661")).unwrap(), i))
+                .collect();
+            let serialized = SerializedMessage::new(&AddrsMessage { addrs }).await;
+            let frame = sequenced_frame(serialized.compressed_frame().unwrap(), 43);
+            assert_eq!(frame[..4], SEQUENCED_COMPRESSED_MAGIC_BYTES);
+        });
+    }
+}
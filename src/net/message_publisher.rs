@@ -22,11 +22,11 @@ use async_trait::async_trait;
 use futures::stream::{FuturesUnordered, StreamExt};
 use log::{debug, error, warn};
 use rand::{rngs::OsRng, Rng};
-use smol::{io::AsyncReadExt, lock::Mutex};
+use smol::lock::Mutex;
 
 use super::message::Message;
-use crate::{net::transport::PtStream, system::timeout::timeout, Error, Result};
-use darkfi_serial::{AsyncDecodable, VarInt};
+use crate::{system::timeout::timeout, Error, Result};
+use darkfi_serial::deserialize_async;
 
 /// 64-bit identifier for message subscription.
 pub type MessageSubscriptionId = u64;
@@ -178,7 +178,7 @@ impl<M: Message> MessageSubscription<M> {
 /// Generic interface for the message dispatcher.
 #[async_trait]
 trait MessageDispatcherInterface: Send + Sync {
-    async fn trigger(&self, stream: &mut smol::io::ReadHalf<Box<dyn PtStream + 'static>>);
+    async fn trigger(&self, payload: &[u8]) -> Result<()>;
 
     async fn trigger_error(&self, err: Error);
 
@@ -188,41 +188,23 @@ trait MessageDispatcherInterface: Send + Sync {
 /// Local implementation of the Message Dispatcher Interface
 #[async_trait]
 impl<M: Message> MessageDispatcherInterface for MessageDispatcher<M> {
-    /// Internal function to deserialize data into a message type
-    /// and dispatch it across subscriber channels. Reads directly
-    /// from an inbound stream.
-    ///
-    /// We extract the message length from the stream and use `take()`
-    /// to allocate an appropiately sized buffer as a basic DDOS protection.
-    async fn trigger(&self, stream: &mut smol::io::ReadHalf<Box<dyn PtStream + 'static>>) {
-        match VarInt::decode_async(stream).await {
-            Ok(int) => {
-                // TODO: check the message length does not exceed some bound.
-                let len = int.0;
-                let mut take = stream.take(len);
-
-                // Deserialize stream into type, send down the pipes.
-                match M::decode_async(&mut take).await {
-                    Ok(payload) => {
-                        let message = Ok(Arc::new(payload));
-                        self._trigger_all(message).await
-                    }
-
-                    Err(err) => {
-                        error!(
-                            target: "net::message_publisher::trigger()",
-                            "Unable to decode data. Dropping...: {}",
-                            err,
-                        );
-                    }
-                }
+    /// Internal function to deserialize a payload into a message type
+    /// and dispatch it across subscriber channels. The payload must be
+    /// consumed entirely, otherwise it's dropped and an error returned.
+    async fn trigger(&self, payload: &[u8]) -> Result<()> {
+        // Deserialize payload into type, send down the pipes.
+        match deserialize_async::<M>(payload).await {
+            Ok(message) => {
+                self._trigger_all(Ok(Arc::new(message))).await;
+                Ok(())
             }
             Err(err) => {
                 error!(
                     target: "net::message_publisher::trigger()",
-                    "Unable to decode VarInt. Dropping...: {}",
+                    "Unable to decode data. Dropping...: {}",
                     err,
                 );
+                Err(err.into())
             }
         }
     }
@@ -282,13 +264,15 @@ impl MessageSubsystem {
         Ok(sub)
     }
 
+    /// Returns whether there's a dispatcher for the given command.
+    pub async fn has_dispatcher(&self, command: &str) -> bool {
+        self.dispatchers.lock().await.contains_key(command)
+    }
+
     /// Transmits a payload to a dispatcher.
-    /// Returns an error if the payload fails to transmit.
-    pub async fn notify(
-        &self,
-        command: &str,
-        reader: &mut smol::io::ReadHalf<Box<dyn PtStream + 'static>>,
-    ) -> Result<()> {
+    /// Returns an error if there's no dispatcher for the command, or if
+    /// the payload fails to decode.
+    pub async fn notify(&self, command: &str, payload: &[u8]) -> Result<()> {
         let Some(dispatcher) = self.dispatchers.lock().await.get(command).cloned() else {
             warn!(
                 target: "net::message_publisher::notify",
@@ -298,8 +282,7 @@ impl MessageSubsystem {
             return Err(Error::MissingDispatcher)
         };
 
-        dispatcher.trigger(reader).await;
-        Ok(())
+        dispatcher.trigger(payload).await
     }
 
     /// Concurrently transmits an error message across dispatchers.
@@ -0,0 +1,380 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+/// Number of seconds in a metering period
+const DAY_SECS: u64 = 86400;
+
+/// Bytes in a megabyte, used for the configured data cap
+pub const MEGABYTE: u64 = 1024 * 1024;
+
+/// Outbound slots used when running in low-data mode
+pub const LOW_DATA_OUTBOUND_SLOTS: usize = 2;
+
+/// Factor by which the heartbeat interval is stretched in low-data mode
+pub const LOW_DATA_HEARTBEAT_FACTOR: u64 = 4;
+
+/// Keeps track of the amount of data sent and received by a P2P instance
+/// during the current UTC day. Counters reset when the day rolls over.
+pub struct DataMeter {
+    /// Day index (days since UNIX epoch) the counters refer to
+    day: AtomicU64,
+    /// Bytes sent during `day`
+    sent: AtomicU64,
+    /// Bytes received during `day`
+    received: AtomicU64,
+}
+
+impl DataMeter {
+    pub fn new() -> Self {
+        Self { day: AtomicU64::new(today()), sent: AtomicU64::new(0), received: AtomicU64::new(0) }
+    }
+
+    /// Account for `bytes` sent to the network
+    pub fn add_sent(&self, bytes: u64) {
+        self.rollover();
+        self.sent.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Account for `bytes` received from the network
+    pub fn add_received(&self, bytes: u64) {
+        self.rollover();
+        self.received.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the bytes (sent, received) during the current day
+    pub fn usage(&self) -> (u64, u64) {
+        self.rollover();
+        (self.sent.load(Ordering::Relaxed), self.received.load(Ordering::Relaxed))
+    }
+
+    /// Returns true if the total usage for the current day reached the
+    /// given cap in megabytes. A cap of 0 means unlimited.
+    pub fn cap_reached(&self, cap_mb: u64) -> bool {
+        if cap_mb == 0 {
+            return false
+        }
+
+        let (sent, received) = self.usage();
+        sent + received >= cap_mb * MEGABYTE
+    }
+
+    /// Reset the counters if the day changed since we last looked
+    fn rollover(&self) {
+        let today = today();
+        let day = self.day.load(Ordering::Relaxed);
+        if day != today &&
+            self.day.compare_exchange(day, today, Ordering::Relaxed, Ordering::Relaxed).is_ok()
+        {
+            self.sent.store(0, Ordering::Relaxed);
+            self.received.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for DataMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Token bucket limiting the byte rate of a channel direction. Allows
+/// bursts of up to one second worth of data.
+pub struct RateLimiter {
+    /// Allowed bytes per second, 0 for unlimited
+    rate: u64,
+    /// Bucket state
+    state: Mutex<RateLimiterState>,
+}
+
+struct RateLimiterState {
+    /// Bytes available for immediate use, negative when in debt
+    tokens: f64,
+    /// Last time the bucket was refilled
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        let state = RateLimiterState { tokens: rate as f64, last: Instant::now() };
+        Self { rate, state: Mutex::new(state) }
+    }
+
+    /// Account for `bytes` and return how long the caller should wait
+    /// before carrying on, in order to stay within the rate.
+    pub fn consume(&self, bytes: u64) -> Duration {
+        if self.rate == 0 {
+            return Duration::ZERO
+        }
+
+        let rate = self.rate as f64;
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last).as_secs_f64();
+        state.last = now;
+        state.tokens = (state.tokens + elapsed * rate).min(rate) - bytes as f64;
+
+        if state.tokens >= 0.0 {
+            return Duration::ZERO
+        }
+
+        Duration::from_secs_f64(-state.tokens / rate)
+    }
+}
+
+/// Snapshot of a channel's compression counters. Only messages sent or
+/// received compressed are accounted for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// Frame bytes of sent messages before compression
+    pub sent_raw: u64,
+    /// Frame bytes of sent messages as written to the wire
+    pub sent_wire: u64,
+    /// Frame bytes of received messages after decompression
+    pub received_raw: u64,
+    /// Frame bytes of received messages as read from the wire
+    pub received_wire: u64,
+}
+
+impl CompressionStats {
+    /// Add the counters of `other` to ours
+    pub fn merge(&mut self, other: &CompressionStats) {
+        self.sent_raw += other.sent_raw;
+        self.sent_wire += other.sent_wire;
+        self.received_raw += other.received_raw;
+        self.received_wire += other.received_wire;
+    }
+}
+
+/// Compression counters of a channel
+#[derive(Default)]
+pub struct CompressionCounters {
+    sent_raw: AtomicU64,
+    sent_wire: AtomicU64,
+    received_raw: AtomicU64,
+    received_wire: AtomicU64,
+}
+
+impl CompressionCounters {
+    /// Account for a message of `raw` bytes sent as `wire` bytes
+    pub fn add_sent(&self, raw: u64, wire: u64) {
+        self.sent_raw.fetch_add(raw, Ordering::Relaxed);
+        self.sent_wire.fetch_add(wire, Ordering::Relaxed);
+    }
+
+    /// Account for a message of `raw` bytes received as `wire` bytes
+    pub fn add_received(&self, raw: u64, wire: u64) {
+        self.received_raw.fetch_add(raw, Ordering::Relaxed);
+        self.received_wire.fetch_add(wire, Ordering::Relaxed);
+    }
+
+    /// Returns the current values of the counters
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            sent_raw: self.sent_raw.load(Ordering::Relaxed),
+            sent_wire: self.sent_wire.load(Ordering::Relaxed),
+            received_raw: self.received_raw.load(Ordering::Relaxed),
+            received_wire: self.received_wire.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Outcome of a greylist refinery probe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    /// The handshake completed, in the given time
+    Success(Duration),
+    /// Connecting or the handshake failed
+    Failure,
+    /// Connecting or the handshake timed out
+    Timeout,
+}
+
+/// Greylist refinery probe counters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RefineryCounters {
+    pub probes: u64,
+    pub successes: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    /// Summed handshake time of the successful probes, in milliseconds
+    pub handshake_ms: u64,
+}
+
+impl RefineryCounters {
+    /// Account for a probe with the given outcome
+    pub fn record(&mut self, outcome: ProbeOutcome) {
+        self.probes += 1;
+        match outcome {
+            ProbeOutcome::Success(elapsed) => {
+                self.successes += 1;
+                self.handshake_ms += elapsed.as_millis() as u64;
+            }
+            ProbeOutcome::Failure => self.failures += 1,
+            ProbeOutcome::Timeout => self.timeouts += 1,
+        }
+    }
+
+    /// Add the counters of `other` to ours
+    pub fn merge(&mut self, other: &RefineryCounters) {
+        self.probes += other.probes;
+        self.successes += other.successes;
+        self.failures += other.failures;
+        self.timeouts += other.timeouts;
+        self.handshake_ms += other.handshake_ms;
+    }
+
+    /// Average handshake time of the successful probes in milliseconds
+    pub fn avg_handshake_ms(&self) -> Option<u64> {
+        self.handshake_ms.checked_div(self.successes)
+    }
+}
+
+/// Greylist refinery probe counters by transport
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RefineryStats {
+    pub transports: BTreeMap<String, RefineryCounters>,
+}
+
+impl RefineryStats {
+    /// Account for a probe of a host over `transport`
+    pub fn record(&mut self, transport: &str, outcome: ProbeOutcome) {
+        self.transports.entry(transport.to_string()).or_default().record(outcome);
+    }
+
+    /// Counters of all the transports together
+    pub fn total(&self) -> RefineryCounters {
+        let mut total = RefineryCounters::default();
+        for counters in self.transports.values() {
+            total.merge(counters);
+        }
+        total
+    }
+
+    /// Parse stats written by [`RefineryStats::to_tsv()`], skipping
+    /// malformed lines.
+    pub fn from_tsv(tsv: &str) -> Self {
+        let mut stats = Self::default();
+        for line in tsv.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [transport, rest @ ..] = fields.as_slice() else { continue };
+            let Ok(values) = rest.iter().map(|v| v.parse()).collect::<Result<Vec<u64>, _>>() else {
+                continue
+            };
+            let [probes, successes, failures, timeouts, handshake_ms] = values[..] else {
+                continue
+            };
+
+            stats.transports.insert(
+                transport.to_string(),
+                RefineryCounters { probes, successes, failures, timeouts, handshake_ms },
+            );
+        }
+        stats
+    }
+
+    /// One tab-separated line of counters per transport
+    pub fn to_tsv(&self) -> String {
+        let mut tsv = String::new();
+        for (transport, c) in &self.transports {
+            tsv.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\n",
+                transport, c.probes, c.successes, c.failures, c.timeouts, c.handshake_ms
+            ));
+        }
+        tsv
+    }
+}
+
+/// Returns the current day index since UNIX epoch
+fn today() -> u64 {
+    UNIX_EPOCH.elapsed().unwrap().as_secs() / DAY_SECS
+}
+
+/// Returns true if the current UTC hour is contained in `hours`
+pub fn in_hours(hours: &[u32]) -> bool {
+    let hour = (UNIX_EPOCH.elapsed().unwrap().as_secs() % DAY_SECS) / 3600;
+    hours.contains(&(hour as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_meter() {
+        let meter = DataMeter::new();
+        assert_eq!(meter.usage(), (0, 0));
+        assert!(!meter.cap_reached(0));
+        assert!(!meter.cap_reached(1));
+
+        meter.add_sent(MEGABYTE / 2);
+        meter.add_received(MEGABYTE / 2);
+        assert_eq!(meter.usage(), (MEGABYTE / 2, MEGABYTE / 2));
+        assert!(meter.cap_reached(1));
+        assert!(!meter.cap_reached(2));
+        assert!(!meter.cap_reached(0));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let unlimited = RateLimiter::new(0);
+        assert_eq!(unlimited.consume(u64::MAX), Duration::ZERO);
+
+        // One second worth of burst is allowed
+        let limiter = RateLimiter::new(1000);
+        assert_eq!(limiter.consume(500), Duration::ZERO);
+
+        // Going over it has to be paid back
+        let delay = limiter.consume(1000);
+        assert!(delay > Duration::from_millis(400) && delay <= Duration::from_millis(500));
+        assert!(limiter.consume(0) > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_refinery_stats() {
+        let mut stats = RefineryStats::default();
+        assert_eq!(stats.total(), RefineryCounters::default());
+        assert_eq!(stats.total().avg_handshake_ms(), None);
+
+        stats.record("tcp+tls", ProbeOutcome::Success(Duration::from_millis(100)));
+        stats.record("tcp+tls", ProbeOutcome::Success(Duration::from_millis(300)));
+        stats.record("tcp+tls", ProbeOutcome::Failure);
+        stats.record("tor", ProbeOutcome::Timeout);
+
+        let tls = stats.transports["tcp+tls"];
+        assert_eq!((tls.probes, tls.successes, tls.failures, tls.timeouts), (3, 2, 1, 0));
+        assert_eq!(tls.avg_handshake_ms(), Some(200));
+        assert_eq!(stats.transports["tor"].avg_handshake_ms(), None);
+
+        let total = stats.total();
+        assert_eq!((total.probes, total.successes, total.timeouts), (4, 2, 1));
+
+        // Survives a save and load, ignoring garbage
+        let tsv = format!("{}junk\t1\nbad\t1\t2\t3\t4\tx\n", stats.to_tsv());
+        assert_eq!(RefineryStats::from_tsv(&tsv), stats);
+    }
+}
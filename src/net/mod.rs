@@ -69,6 +69,15 @@ pub mod hosts;
 pub mod channel;
 pub use channel::ChannelPtr;
 
+/// Opportunistic channel encryption. Peers supporting it agree on
+/// ephemeral keys right after the version exchange, and seal all their
+/// following messages with them.
+pub mod encryption;
+
+/// Persistent node identity keys. Peers prove ownership of theirs over
+/// encrypted channels, and are recognized by it across address changes.
+pub mod identity;
+
 /// P2P provides all core functionality to interact with the P2P network.
 ///
 /// Used to create a network, to start and run it, to broadcast messages
@@ -80,6 +89,11 @@ pub use channel::ChannelPtr;
 pub mod p2p;
 pub use p2p::{P2p, P2pPtr};
 
+/// Several independent P2P networks run by a single process, each with
+/// its own settings, hostlist and datastore.
+pub mod networks;
+pub use networks::{P2pNetworks, P2pNetworksBuilder};
+
 /// Defines the networking protocol used at each stage in a connection.
 /// Consists of a series of messages that are sent across the network at
 /// the different connection stages.
@@ -119,6 +133,26 @@ pub mod acceptor;
 /// Used to establish an outbound connection.
 pub mod connector;
 
+/// Cache of peer hostname resolutions
+pub mod resolver;
+
+#[cfg(feature = "p2p-memory")]
+/// In-process network of P2P nodes over the memory transport, for tests
+pub mod testutil;
+
+/// Accounting of the data sent and received by a P2P instance, used to
+/// enforce the daily data cap when running in low-data mode.
+pub mod metering;
+
+/// Optional port mapping on NAT gateways using NAT-PMP or UPnP IGD, so
+/// inbound sessions can be reached from behind a home router.
+pub mod portmap;
+
+/// Optional discovery of other nodes on the local network, using UDP
+/// broadcast announcements that feed the greylist.
+#[cfg(feature = "p2p-tcp")]
+pub mod lan_discovery;
+
 /// Network configuration settings. This holds the configured P2P instance
 /// behaviour and is controlled by clients of this API.
 pub mod settings;
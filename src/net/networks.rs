@@ -0,0 +1,172 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use log::info;
+
+use super::{P2p, P2pPtr, Settings};
+use crate::{system::ExecutorPtr, Error, Result};
+
+/// Several independent P2P networks run by a single process, e.g. mainnet
+/// and testnet, or application specific overlays.
+///
+/// All P2P state is scoped to its [`P2p`] instance, so the networks share
+/// nothing but the executor. Each one uses its own settings, hostlist,
+/// datastore and inbound addresses.
+pub struct P2pNetworks {
+    /// Networks, in the order they were added
+    networks: Vec<(String, P2pPtr)>,
+}
+
+impl P2pNetworks {
+    /// Start building a new set of networks running on given executor
+    pub fn builder(executor: ExecutorPtr) -> P2pNetworksBuilder {
+        P2pNetworksBuilder { executor, networks: vec![] }
+    }
+
+    /// Fetch the network with given name
+    pub fn get(&self, name: &str) -> Option<&P2pPtr> {
+        self.networks.iter().find(|(n, _)| n == name).map(|(_, p2p)| p2p)
+    }
+
+    /// Iterate over the networks and their names
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &P2pPtr)> {
+        self.networks.iter().map(|(name, p2p)| (name, p2p))
+    }
+
+    /// Start all networks. On failure, the already started ones are stopped.
+    pub async fn start(&self) -> Result<()> {
+        for (i, (name, p2p)) in self.networks.iter().enumerate() {
+            info!(target: "net::networks::start", "[P2P] Starting network {}", name);
+            if let Err(e) = p2p.clone().start().await {
+                for (_, started) in &self.networks[..i] {
+                    started.stop().await;
+                }
+                return Err(e)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stop all networks
+    pub async fn stop(&self) {
+        for (name, p2p) in &self.networks {
+            info!(target: "net::networks::stop", "[P2P] Stopping network {}", name);
+            p2p.stop().await;
+        }
+    }
+}
+
+/// Builder of [`P2pNetworks`]
+pub struct P2pNetworksBuilder {
+    executor: ExecutorPtr,
+    networks: Vec<(String, Settings)>,
+}
+
+impl P2pNetworksBuilder {
+    /// Add a network with given name and settings
+    pub fn network(mut self, name: &str, settings: Settings) -> Self {
+        self.networks.push((name.to_string(), settings));
+        self
+    }
+
+    /// Create the P2P instances of all added networks. Fails if two networks
+    /// share a name, hostlist, datastore or inbound address, since they
+    /// would overwrite each other's state.
+    pub async fn build(self) -> Result<P2pNetworks> {
+        for (i, (name, settings)) in self.networks.iter().enumerate() {
+            for (other_name, other) in &self.networks[..i] {
+                if name == other_name {
+                    return Err(Error::Custom(format!("Duplicate P2P network name: {name}")))
+                }
+
+                let clash = |what: &str| {
+                    Error::Custom(format!(
+                        "P2P networks {other_name} and {name} use the same {what}"
+                    ))
+                };
+
+                if settings.hostlist.is_some() && settings.hostlist == other.hostlist {
+                    return Err(clash("hostlist"))
+                }
+
+                if settings.p2p_datastore.is_some() && settings.p2p_datastore == other.p2p_datastore
+                {
+                    return Err(clash("datastore"))
+                }
+
+                if settings.inbound_addrs.iter().any(|addr| other.inbound_addrs.contains(addr)) {
+                    return Err(clash("inbound address"))
+                }
+            }
+        }
+
+        let mut networks = Vec::with_capacity(self.networks.len());
+        for (name, settings) in self.networks {
+            let p2p = P2p::new(settings, self.executor.clone()).await?;
+            networks.push((name, p2p));
+        }
+
+        Ok(P2pNetworks { networks })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use smol::Executor;
+
+    use super::*;
+
+    #[test]
+    fn test_p2p_networks() {
+        let ex = Arc::new(Executor::new());
+        smol::block_on(async {
+            let mainnet =
+                Settings { hostlist: Some("/tmp/mainnet.tsv".into()), ..Default::default() };
+            let testnet =
+                Settings { hostlist: Some("/tmp/testnet.tsv".into()), ..Default::default() };
+
+            let networks = P2pNetworks::builder(ex.clone())
+                .network("mainnet", mainnet.clone())
+                .network("testnet", testnet)
+                .build()
+                .await
+                .unwrap();
+            assert!(networks.get("mainnet").is_some());
+            assert!(networks.get("devnet").is_none());
+            assert_eq!(networks.iter().count(), 2);
+
+            // Networks must not share their state
+            let res = P2pNetworks::builder(ex.clone())
+                .network("mainnet", mainnet.clone())
+                .network("overlay", mainnet.clone())
+                .build()
+                .await;
+            assert!(res.is_err());
+
+            let res = P2pNetworks::builder(ex.clone())
+                .network("mainnet", mainnet.clone())
+                .network("mainnet", Settings::default())
+                .build()
+                .await;
+            assert!(res.is_err());
+        });
+    }
+}
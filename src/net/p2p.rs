@@ -16,14 +16,19 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::UNIX_EPOCH,
 };
 
 use futures::{stream::FuturesUnordered, TryFutureExt};
 use futures_rustls::rustls::crypto::{ring, CryptoProvider};
 use log::{debug, error, info, warn};
+use rand::{rngs::OsRng, Rng};
 use smol::{
     fs::{self, unix::PermissionsExt},
     lock::RwLock as AsyncRwLock,
@@ -31,12 +36,17 @@ use smol::{
 };
 use url::Url;
 
+#[cfg(feature = "p2p-tcp")]
+use super::lan_discovery::{LanDiscovery, LanDiscoveryPtr};
 use super::{
-    channel::ChannelPtr,
-    dnet::DnetEvent,
+    channel::{ChannelPtr, StopReason},
+    dnet::{DnetEvent, DNET_RECENT_EVENTS},
     hosts::{Hosts, HostsPtr},
+    identity::{Identity, PeerId},
     message::{Message, SerializedMessage},
+    metering::DataMeter,
     protocol::{protocol_registry::ProtocolRegistry, register_default_protocols},
+    resolver::Resolver,
     session::{
         InboundSession, InboundSessionPtr, ManualSession, ManualSessionPtr, OutboundSession,
         OutboundSessionPtr, RefineSession, RefineSessionPtr, SeedSyncSession, SeedSyncSessionPtr,
@@ -76,6 +86,21 @@ pub struct P2p {
     pub dnet_enabled: AtomicBool,
     /// The publisher for which we can give dnet info over
     dnet_publisher: PublisherPtr<DnetEvent>,
+    /// The last dnet events along with their UNIX timestamp, oldest first
+    dnet_recent: Mutex<VecDeque<(u64, DnetEvent)>>,
+    /// Random nonce sent to peers after the version exchange, identifying this instance
+    nonce: u64,
+    /// Identity key proven to peers during the version handshake
+    identity: Identity,
+    /// Number of duplicate channels closed after the version handshake
+    pub duplicate_channels: AtomicUsize,
+    /// Daily data usage accounting
+    data_meter: DataMeter,
+    /// Cache of peer hostname resolutions
+    resolver: Resolver,
+    /// Discovery of other nodes on the local network
+    #[cfg(feature = "p2p-tcp")]
+    lan_discovery: LanDiscoveryPtr,
 }
 
 impl P2p {
@@ -88,12 +113,17 @@ impl P2p {
     /// Creates a weak pointer to self that is used by all sessions to access the
     /// p2p parent class.
     pub async fn new(settings: Settings, executor: ExecutorPtr) -> Result<P2pPtr> {
-        // Create the datastore
-        if let Some(ref datastore) = settings.p2p_datastore {
-            let datastore = expand_path(datastore)?;
-            fs::create_dir_all(&datastore).await?;
-            fs::set_permissions(&datastore, PermissionsExt::from_mode(0o700)).await?;
-        }
+        // Create the datastore, and keep our identity in it. Without a
+        // datastore, a new identity is used on every run.
+        let identity = match settings.p2p_datastore {
+            Some(ref datastore) => {
+                let datastore = expand_path(datastore)?;
+                fs::create_dir_all(&datastore).await?;
+                fs::set_permissions(&datastore, PermissionsExt::from_mode(0o700)).await?;
+                Identity::load_or_generate(&datastore)?
+            }
+            None => Identity::generate(),
+        };
 
         // Register a CryptoProvider for rustls
         let _ = CryptoProvider::install_default(ring::default_provider());
@@ -113,6 +143,14 @@ impl P2p {
             session_seedsync: SeedSyncSession::new(p2p.clone()),
             dnet_enabled: AtomicBool::new(false),
             dnet_publisher: Publisher::new(),
+            dnet_recent: Mutex::new(VecDeque::with_capacity(DNET_RECENT_EVENTS)),
+            nonce: OsRng.gen(),
+            identity,
+            duplicate_channels: AtomicUsize::new(0),
+            data_meter: DataMeter::new(),
+            resolver: Resolver::new(),
+            #[cfg(feature = "p2p-tcp")]
+            lan_discovery: LanDiscovery::new(p2p.clone()),
         });
 
         register_default_protocols(self_.clone()).await;
@@ -134,12 +172,24 @@ impl P2p {
         // Start the manual session
         self.session_manual().start().await;
 
-        // Start the seedsync session. Seed connections will not
-        // activate yet- they wait for a call to notify().
-        self.session_seedsync().start().await;
-
-        // Start the outbound session
-        self.session_outbound().start().await;
+        // In private network mode we only talk to allowlisted peers, so
+        // seeding and outbound peer discovery are disabled.
+        let private_network = self.settings.read().await.private_network;
+        if private_network {
+            info!(target: "net::p2p::start", "[P2P] Running as a private network");
+        } else {
+            // Start the seedsync session. Seed connections will not
+            // activate yet- they wait for a call to notify().
+            self.session_seedsync().start().await;
+
+            // Start the outbound session
+            self.session_outbound().start().await;
+
+            #[cfg(feature = "p2p-tcp")]
+            if self.settings.read().await.lan_discovery {
+                self.lan_discovery.clone().start().await;
+            }
+        }
 
         // Start the refine session
         self.session_refine().start().await;
@@ -152,6 +202,9 @@ impl P2p {
     pub async fn seed(self: Arc<Self>) {
         debug!(target: "net::p2p::seed()", "P2P::seed() [BEGIN]");
 
+        // Greylist the peers announced by the DNS seeds
+        self.session_seedsync().seed_dns().await;
+
         // Activate the seed session.
         self.session_seedsync().notify().await;
 
@@ -163,8 +216,12 @@ impl P2p {
         // Stop the sessions
         self.session_manual().stop().await;
         self.session_inbound().stop().await;
-        self.session_seedsync().stop().await;
-        self.session_outbound().stop().await;
+        if !self.settings.read().await.private_network {
+            #[cfg(feature = "p2p-tcp")]
+            self.lan_discovery.stop().await;
+            self.session_seedsync().stop().await;
+            self.session_outbound().stop().await;
+        }
         self.session_refine().stop().await;
     }
 
@@ -223,6 +280,37 @@ impl P2p {
         Arc::clone(&self.settings)
     }
 
+    /// Return the nonce identifying this P2P instance to peers
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Return the identity key we prove to peers
+    pub fn identity(&self) -> &Identity {
+        &self.identity
+    }
+
+    /// Return our peer ID
+    pub fn peer_id(&self) -> PeerId {
+        self.identity.peer_id()
+    }
+
+    /// Return a reference to the daily data usage meter
+    pub fn data_meter(&self) -> &DataMeter {
+        &self.data_meter
+    }
+
+    /// Reference the cache of peer hostname resolutions
+    pub fn resolver(&self) -> &Resolver {
+        &self.resolver
+    }
+
+    /// Returns true if the configured daily data cap has been reached.
+    /// While it is, no new connections are made or accepted.
+    pub async fn data_cap_reached(&self) -> bool {
+        self.data_meter.cap_reached(self.settings.read().await.daily_data_cap)
+    }
+
     /// Return an atomic pointer to the list of hosts
     pub fn hosts(&self) -> HostsPtr {
         self.hosts.clone()
@@ -280,13 +368,75 @@ impl P2p {
         self.dnet_publisher.clone().subscribe().await
     }
 
-    /// Send a dnet notification over the publisher
+    /// Send a dnet notification over the publisher, keeping it among
+    /// the recent events.
     pub(super) async fn dnet_notify(&self, event: DnetEvent) {
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        let mut recent = self.dnet_recent.lock().unwrap();
+        if recent.len() == DNET_RECENT_EVENTS {
+            recent.pop_front();
+        }
+        recent.push_back((now, event.clone()));
+        drop(recent);
+
         self.dnet_publisher.notify(event).await;
     }
 
+    /// Returns up to `limit` of the last dnet events along with their
+    /// UNIX timestamp, oldest first. Events are only recorded while
+    /// network debugging is enabled.
+    pub fn dnet_recent_events(&self, limit: usize) -> Vec<(u64, DnetEvent)> {
+        let recent = self.dnet_recent.lock().unwrap();
+        recent.iter().skip(recent.len().saturating_sub(limit)).cloned().collect()
+    }
+
     /// Grab the channel pointer of provided channel ID, if it exists.
     pub fn get_channel(&self, id: u32) -> Option<ChannelPtr> {
         self.hosts.get_channel(id)
     }
+
+    /// Disconnect from the given peer. Returns false if we aren't
+    /// connected to it. Outbound and manual slots reconnect as usual.
+    pub async fn disconnect_peer(&self, addr: &Url) -> bool {
+        let channels = self.peer_channels(addr);
+        for channel in &channels {
+            channel.stop_with_reason(StopReason::LocalShutdown).await;
+        }
+
+        !channels.is_empty()
+    }
+
+    /// Blacklist the given peer for the configured `ban_duration` and
+    /// disconnect from it. Connected inbound peers are banned on all
+    /// ports. Returns false if the peer can't be banned, like local hosts.
+    pub async fn blacklist_peer(&self, addr: &Url) -> bool {
+        let channels = self.peer_channels(addr);
+        let ban_addr = match channels.first() {
+            Some(channel) => channel.ban_addr(addr),
+            None => Some(addr.clone()),
+        };
+        let Some(ban_addr) = ban_addr else { return false };
+
+        if !self.hosts.ban_peer(&ban_addr, "blacklisted by operator").await {
+            return false
+        }
+
+        for channel in &channels {
+            channel.stop_with_reason(StopReason::LocalShutdown).await;
+        }
+
+        true
+    }
+
+    /// Open a manual connection to the given peer, which keeps getting
+    /// reconnected like the configured `peers`. Returns false if there
+    /// already is a manual connection to it.
+    pub async fn connect_peer(&self, addr: Url) -> bool {
+        self.session_manual.clone().connect(addr).await
+    }
+
+    /// Returns our channels with the given peer
+    fn peer_channels(&self, addr: &Url) -> Vec<ChannelPtr> {
+        self.hosts.channels().into_iter().filter(|c| c.address() == addr).collect()
+    }
 }
@@ -0,0 +1,436 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Port mapping on NAT gateways.
+//!
+//! NAT-PMP (RFC 6886) is tried first against the default gateway. If the
+//! gateway does not answer, we fall back to UPnP IGD: the gateway is
+//! discovered with SSDP, and the mapping is requested through SOAP calls
+//! to its WANIPConnection (or WANPPPConnection) service.
+
+use std::{
+    fmt,
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use log::debug;
+use smol::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+};
+use url::Url;
+
+use crate::{system::io_timeout, Error, Result};
+
+/// NAT-PMP server port on the gateway
+const NATPMP_PORT: u16 = 5351;
+/// Number of NAT-PMP request attempts, the timeout doubling every time
+const NATPMP_TRIES: u32 = 4;
+/// Initial NAT-PMP request timeout (in milliseconds)
+const NATPMP_INITIAL_TIMEOUT: u64 = 250;
+
+/// SSDP multicast address used for UPnP discovery
+const SSDP_ADDR: &str = "239.255.255.250:1900";
+/// Time we wait for a gateway to answer the SSDP search
+const SSDP_TIMEOUT: Duration = Duration::from_secs(2);
+/// Timeout of HTTP requests made to the gateway
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// UPnP services able to map ports, in order of preference
+const UPNP_SERVICES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+/// Description set on UPnP port mappings
+const UPNP_DESCRIPTION: &str = "darkfi";
+
+/// Protocol a port mapping was obtained with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MappingProtocol {
+    /// NAT-PMP request to the given gateway
+    NatPmp(Ipv4Addr),
+    /// UPnP IGD, with the service control URL and service type
+    Upnp(Url, String),
+}
+
+impl fmt::Display for MappingProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NatPmp(gateway) => write!(f, "NAT-PMP via {gateway}"),
+            Self::Upnp(control_url, _) => write!(f, "UPnP via {control_url}"),
+        }
+    }
+}
+
+/// A TCP port mapped on the NAT gateway
+#[derive(Clone, Debug)]
+pub struct PortMapping {
+    /// Protocol the mapping was obtained with
+    pub protocol: MappingProtocol,
+    /// Local port being forwarded
+    pub internal_port: u16,
+    /// External address of the gateway
+    pub external_ip: IpAddr,
+    /// Port forwarded on the gateway
+    pub external_port: u16,
+    /// Lease lifetime (in seconds) granted by the gateway
+    pub lifetime: u64,
+}
+
+impl PortMapping {
+    /// Renew the lease of this mapping using the same protocol
+    pub async fn renew(&self) -> Result<PortMapping> {
+        match &self.protocol {
+            MappingProtocol::NatPmp(gateway) => {
+                natpmp_map(*gateway, self.internal_port, self.external_port, self.lifetime).await
+            }
+            MappingProtocol::Upnp(control_url, service) => {
+                upnp_map(control_url, service, self.internal_port, self.lifetime).await
+            }
+        }
+    }
+
+    /// Remove this mapping from the gateway
+    pub async fn unmap(&self) -> Result<()> {
+        match &self.protocol {
+            MappingProtocol::NatPmp(gateway) => {
+                natpmp_map(*gateway, self.internal_port, 0, 0).await?;
+            }
+            MappingProtocol::Upnp(control_url, service) => {
+                let args = [
+                    ("NewRemoteHost", String::new()),
+                    ("NewExternalPort", self.external_port.to_string()),
+                    ("NewProtocol", "TCP".to_string()),
+                ];
+                soap_request(control_url, service, "DeletePortMapping", &args).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Map the given local TCP port on the NAT gateway for `lifetime` seconds.
+/// NAT-PMP is attempted first, then UPnP IGD.
+pub async fn map_port(port: u16, lifetime: u64) -> Result<PortMapping> {
+    let natpmp_err = match default_gateway() {
+        Ok(gateway) => match natpmp_map(gateway, port, port, lifetime).await {
+            Ok(mapping) => return Ok(mapping),
+            Err(e) => e,
+        },
+        Err(e) => e,
+    };
+    debug!(target: "net::portmap::map_port()", "NAT-PMP unavailable: {}", natpmp_err);
+
+    let (control_url, service) = upnp_discover().await?;
+    upnp_map(&control_url, &service, port, lifetime).await
+}
+
+/// Find the IPv4 default gateway from the kernel routing table
+fn default_gateway() -> Result<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route")?;
+    parse_default_gateway(&routes)
+        .ok_or_else(|| Error::PortMappingFailed("No default gateway found".to_string()))
+}
+
+/// Parse the default gateway out of `/proc/net/route` contents
+fn parse_default_gateway(routes: &str) -> Option<Ipv4Addr> {
+    for line in routes.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[1] != "00000000" {
+            continue
+        }
+
+        // The kernel prints the address as a native endian integer
+        let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+        return Some(Ipv4Addr::from(gateway.to_ne_bytes()))
+    }
+
+    None
+}
+
+/// Send a NAT-PMP request to the gateway and return the response,
+/// retrying with an exponential backoff.
+async fn natpmp_request(gateway: Ipv4Addr, request: &[u8]) -> Result<Vec<u8>> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(SocketAddr::new(gateway.into(), NATPMP_PORT)).await?;
+
+    let mut buf = [0u8; 16];
+    let mut timeout = Duration::from_millis(NATPMP_INITIAL_TIMEOUT);
+    for _ in 0..NATPMP_TRIES {
+        socket.send(request).await?;
+        match io_timeout(timeout, socket.recv(&mut buf)).await {
+            // Ignore responses to something else
+            Ok(n) if n >= 4 && buf[1] == request[1] + 128 => return Ok(buf[..n].to_vec()),
+            Ok(_) => continue,
+            Err(e) if e.kind() == ErrorKind::TimedOut => timeout *= 2,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(Error::PortMappingFailed(format!("No NAT-PMP response from {gateway}")))
+}
+
+/// Check the result code of a NAT-PMP response
+fn natpmp_result(response: &[u8], len: usize) -> Result<()> {
+    if response.len() < len {
+        return Err(Error::PortMappingFailed("Truncated NAT-PMP response".to_string()))
+    }
+
+    match u16::from_be_bytes([response[2], response[3]]) {
+        0 => Ok(()),
+        code => Err(Error::PortMappingFailed(format!("NAT-PMP result code {code}"))),
+    }
+}
+
+/// Build a NAT-PMP TCP mapping request. A lifetime of 0 removes the mapping.
+fn natpmp_map_request(internal_port: u16, external_port: u16, lifetime: u64) -> [u8; 12] {
+    let mut request = [0u8; 12];
+    request[1] = 2;
+    request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    request[6..8].copy_from_slice(&external_port.to_be_bytes());
+    request[8..12].copy_from_slice(&(lifetime.min(u32::MAX as u64) as u32).to_be_bytes());
+    request
+}
+
+/// Request a TCP mapping through NAT-PMP
+async fn natpmp_map(
+    gateway: Ipv4Addr,
+    internal_port: u16,
+    external_port: u16,
+    lifetime: u64,
+) -> Result<PortMapping> {
+    let response = natpmp_request(gateway, &[0, 0]).await?;
+    natpmp_result(&response, 12)?;
+    let external_ip = Ipv4Addr::new(response[8], response[9], response[10], response[11]);
+
+    let request = natpmp_map_request(internal_port, external_port, lifetime);
+    let response = natpmp_request(gateway, &request).await?;
+    natpmp_result(&response, 16)?;
+
+    Ok(PortMapping {
+        protocol: MappingProtocol::NatPmp(gateway),
+        internal_port,
+        external_ip: external_ip.into(),
+        external_port: u16::from_be_bytes([response[10], response[11]]),
+        lifetime: u32::from_be_bytes([response[12], response[13], response[14], response[15]])
+            as u64,
+    })
+}
+
+/// Discover an Internet Gateway Device using SSDP and return the control
+/// URL and type of its port mapping service
+async fn upnp_discover() -> Result<(Url, String)> {
+    let search = "M-SEARCH * HTTP/1.1\r\n\
+                  HOST: 239.255.255.250:1900\r\n\
+                  MAN: \"ssdp:discover\"\r\n\
+                  MX: 2\r\n\
+                  ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n";
+
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.send_to(search.as_bytes(), SSDP_ADDR).await?;
+
+    let mut buf = [0u8; 2048];
+    let n = match io_timeout(SSDP_TIMEOUT, socket.recv(&mut buf)).await {
+        Ok(n) => n,
+        Err(e) if e.kind() == ErrorKind::TimedOut => {
+            return Err(Error::PortMappingFailed("No UPnP gateway found".to_string()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let response = String::from_utf8_lossy(&buf[..n]);
+    let Some(location) = response.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case("location").then(|| value.trim().to_string())
+    }) else {
+        return Err(Error::PortMappingFailed("UPnP response without location".to_string()))
+    };
+
+    let location = Url::parse(&location)?;
+    let (description, _) = http_request(&location, "GET", &[], "").await?;
+
+    for service in UPNP_SERVICES {
+        if let Some(control_url) = find_control_url(&description, service) {
+            return Ok((location.join(&control_url)?, service.to_string()))
+        }
+    }
+
+    Err(Error::PortMappingFailed("UPnP gateway has no port mapping service".to_string()))
+}
+
+/// Request a TCP mapping through UPnP IGD
+async fn upnp_map(
+    control_url: &Url,
+    service: &str,
+    port: u16,
+    lifetime: u64,
+) -> Result<PortMapping> {
+    let (body, local_addr) =
+        soap_request(control_url, service, "GetExternalIPAddress", &[]).await?;
+    let Some(external_ip) = xml_tag(&body, "NewExternalIPAddress").and_then(|ip| ip.parse().ok())
+    else {
+        return Err(Error::PortMappingFailed("Invalid UPnP external address".to_string()))
+    };
+
+    let args = [
+        ("NewRemoteHost", String::new()),
+        ("NewExternalPort", port.to_string()),
+        ("NewProtocol", "TCP".to_string()),
+        ("NewInternalPort", port.to_string()),
+        ("NewInternalClient", local_addr.ip().to_string()),
+        ("NewEnabled", "1".to_string()),
+        ("NewPortMappingDescription", UPNP_DESCRIPTION.to_string()),
+        ("NewLeaseDuration", lifetime.to_string()),
+    ];
+    soap_request(control_url, service, "AddPortMapping", &args).await?;
+
+    Ok(PortMapping {
+        protocol: MappingProtocol::Upnp(control_url.clone(), service.to_string()),
+        internal_port: port,
+        external_ip,
+        external_port: port,
+        lifetime,
+    })
+}
+
+/// Call a SOAP action on a UPnP service. Returns the response body and
+/// the local address we reached the gateway from.
+async fn soap_request(
+    control_url: &Url,
+    service: &str,
+    action: &str,
+    args: &[(&str, String)],
+) -> Result<(String, SocketAddr)> {
+    let args: String = args.iter().map(|(k, v)| format!("<{k}>{v}</{k}>")).collect();
+    let body = format!(
+        "<?xml version=\"1.0\"?>\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body><u:{action} xmlns:u=\"{service}\">{args}</u:{action}></s:Body>\
+         </s:Envelope>"
+    );
+
+    let soap_action = format!("\"{service}#{action}\"");
+    let headers =
+        [("Content-Type", "text/xml; charset=\"utf-8\""), ("SOAPAction", soap_action.as_str())];
+    http_request(control_url, "POST", &headers, &body).await
+}
+
+/// Make a minimal HTTP/1.1 request and return the body of a successful
+/// response, along with the local address of the connection.
+async fn http_request(
+    url: &Url,
+    method: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> Result<(String, SocketAddr)> {
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Err(Error::PortMappingFailed(format!("Invalid gateway URL {url}")))
+    };
+
+    let mut request = format!(
+        "{method} {} HTTP/1.1\r\nHost: {host}:{port}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        url.path(),
+        body.len(),
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+
+    let exchange = async {
+        let mut stream = TcpStream::connect((host, port)).await?;
+        let local_addr = stream.local_addr()?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await?;
+        Ok::<_, std::io::Error>((response, local_addr))
+    };
+    let (response, local_addr) = io_timeout(HTTP_TIMEOUT, exchange).await?;
+
+    let response = String::from_utf8_lossy(&response);
+    let Some((head, body)) = response.split_once("\r\n\r\n") else {
+        return Err(Error::PortMappingFailed("Malformed HTTP response".to_string()))
+    };
+    let status = head.lines().next().unwrap_or_default();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(Error::PortMappingFailed(format!("{method} {url}: {status}")))
+    }
+
+    Ok((body.to_string(), local_addr))
+}
+
+/// Find the control URL of the given service in a UPnP device description
+fn find_control_url(description: &str, service: &str) -> Option<String> {
+    let start = description.find(&format!("<serviceType>{service}</serviceType>"))?;
+    xml_tag(&description[start..], "controlURL")
+}
+
+/// Return the contents of the first `tag` element of `xml`, ignoring
+/// namespace prefixes
+fn xml_tag(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("{tag}>"))? + tag.len() + 1;
+    let end = start + xml[start..].find('<')?;
+    Some(xml[start..end].trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_default_gateway() {
+        let routes = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+                      eth0\t0000A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+                      eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\n";
+        let expected = u32::from_str_radix("0101A8C0", 16).unwrap().to_ne_bytes();
+        assert_eq!(parse_default_gateway(routes), Some(Ipv4Addr::from(expected)));
+        assert_eq!(parse_default_gateway("Iface\tDestination\tGateway\n"), None);
+    }
+
+    #[test]
+    fn test_natpmp_map_request() {
+        let request = natpmp_map_request(26661, 26661, 3600);
+        assert_eq!(request, [0, 2, 0, 0, 0x68, 0x25, 0x68, 0x25, 0, 0, 0x0e, 0x10]);
+        assert!(natpmp_result(&[0, 130, 0, 3], 4).is_err());
+        assert!(natpmp_result(&[0, 130, 0, 0], 16).is_err());
+    }
+
+    #[test]
+    fn test_find_control_url() {
+        let description = "<root><device><serviceList>\
+            <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+            <controlURL>/l3f</controlURL></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+            <controlURL>/ctl/IPConn</controlURL></service>\
+            </serviceList></device></root>";
+        assert_eq!(find_control_url(description, UPNP_SERVICES[1]), Some("/ctl/IPConn".into()));
+        assert_eq!(find_control_url(description, UPNP_SERVICES[2]), None);
+
+        let response = "<s:Body><u:GetExternalIPAddressResponse>\
+            <NewExternalIPAddress>203.0.113.7</NewExternalIPAddress>\
+            </u:GetExternalIPAddressResponse></s:Body>";
+        assert_eq!(xml_tag(response, "NewExternalIPAddress"), Some("203.0.113.7".into()));
+    }
+}
@@ -85,9 +85,16 @@ pub mod protocol_base;
 pub mod protocol_registry;
 
 /// Register the default network protocols for a p2p instance.
+/// When running as a private network, address gossip and seeding are
+/// disabled, so only the ping protocol gets registered.
 pub async fn register_default_protocols(p2p: P2pPtr) {
     let registry = p2p.protocol_registry();
     registry.register(SESSION_DEFAULT | SESSION_SEED, ProtocolPing::init).await;
+
+    if p2p.settings().read().await.private_network {
+        return
+    }
+
     registry.register(SESSION_DEFAULT, ProtocolAddress::init).await;
     registry.register(SESSION_SEED, ProtocolSeed::init).await;
 }
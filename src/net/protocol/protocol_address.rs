@@ -25,7 +25,7 @@ use smol::{lock::RwLock as AsyncRwLock, Executor};
 use super::{
     super::{
         channel::ChannelPtr,
-        hosts::{HostColor, HostsPtr},
+        hosts::{HostColor, HostOrigin, HostsPtr},
         message::{AddrsMessage, GetAddrsMessage},
         message_publisher::MessageSubscription,
         p2p::P2pPtr,
@@ -74,7 +74,8 @@ const PROTO_NAME: &str = "ProtocolAddress";
 /// combinations.  Should be updated if and when new transports are
 /// added. Creates a upper bound on the number of transports a given peer
 /// can request.
-const TRANSPORT_COMBOS: [&str; 7] = ["tor", "tls", "tcp", "nym", "tor+tls", "nym+tls", "tcp+tls"];
+const TRANSPORT_COMBOS: [&str; 9] =
+    ["tor", "tls", "tcp", "nym", "tor+tls", "nym+tls", "tcp+tls", "socks5", "socks5+tls"];
 
 impl ProtocolAddress {
     /// Creates a new address protocol. Makes an address, an external address
@@ -120,7 +121,7 @@ impl ProtocolAddress {
                 "Appending to greylist...",
             );
 
-            self.hosts.insert(HostColor::Grey, &addrs_msg.addrs).await;
+            self.hosts.insert(HostColor::Grey, &addrs_msg.addrs, HostOrigin::Discovery).await;
         }
     }
 
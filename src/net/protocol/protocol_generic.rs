@@ -28,7 +28,9 @@ use smol::{
 
 use super::{
     super::{
-        channel::ChannelPtr, message::Message, message_publisher::MessageSubscription,
+        channel::{ChannelPtr, StopReason},
+        message::Message,
+        message_publisher::MessageSubscription,
         session::SessionBitFlag,
     },
     protocol_base::{ProtocolBase, ProtocolBasePtr},
@@ -285,7 +287,7 @@ impl<M: Message + Clone, R: Message + Clone + Debug> ProtocolGeneric<M, R> {
                     );
                 }
                 ProtocolGenericAction::Stop => {
-                    self.channel.stop().await;
+                    self.channel.stop_with_reason(StopReason::ProtocolViolation).await;
                     return Err(Error::ChannelStopped)
                 }
             }
@@ -28,9 +28,11 @@ use smol::{lock::RwLock as AsyncRwLock, Executor};
 
 use super::{
     super::{
-        channel::ChannelPtr,
+        channel::{ChannelPtr, ProtocolError, StopReason},
+        hosts::HostsPtr,
         message::{PingMessage, PongMessage},
         message_publisher::MessageSubscription,
+        metering::LOW_DATA_HEARTBEAT_FACTOR,
         p2p::P2pPtr,
         settings::Settings,
     },
@@ -48,6 +50,7 @@ pub struct ProtocolPing {
     ping_sub: MessageSubscription<PingMessage>,
     pong_sub: MessageSubscription<PongMessage>,
     settings: Arc<AsyncRwLock<Settings>>,
+    hosts: HostsPtr,
     jobsman: ProtocolJobsManagerPtr,
 }
 
@@ -69,6 +72,7 @@ impl ProtocolPing {
             ping_sub,
             pong_sub,
             settings: p2p.settings(),
+            hosts: p2p.hosts(),
             jobsman: ProtocolJobsManager::new(PROTO_NAME, channel),
         })
     }
@@ -76,7 +80,9 @@ impl ProtocolPing {
     /// Runs the ping-pong protocol. Creates a subscription to pong, then
     /// starts a loop. Loop sleeps for the duration of the channel heartbeat,
     /// then sends a ping message with a random nonce. Loop starts a timer,
-    /// waits for the pong reply and ensures the nonce is the same.
+    /// waits for the pong reply and ensures the nonce is the same. The
+    /// round-trip time is recorded in the hosts, and peers not replying
+    /// in time get disconnected.
     async fn run_ping_pong(self: Arc<Self>) -> Result<()> {
         debug!(
             target: "net::protocol_ping::run_ping_pong()",
@@ -85,8 +91,11 @@ impl ProtocolPing {
 
         loop {
             let settings = self.settings.read().await;
-            let outbound_connect_timeout = settings.outbound_connect_timeout;
-            let channel_heartbeat_interval = settings.channel_heartbeat_interval;
+            let channel_heartbeat_timeout = settings.channel_heartbeat_timeout;
+            let mut channel_heartbeat_interval = settings.channel_heartbeat_interval;
+            if settings.low_data_mode {
+                channel_heartbeat_interval *= LOW_DATA_HEARTBEAT_FACTOR;
+            }
             drop(settings);
 
             // Create a random nonce.
@@ -101,7 +110,7 @@ impl ProtocolPing {
 
             // Wait for pong, check nonce matches.
             let pong_msg = match timeout(
-                Duration::from_secs(outbound_connect_timeout),
+                Duration::from_secs(channel_heartbeat_timeout),
                 self.pong_sub.receive(),
             )
             .await
@@ -118,7 +127,8 @@ impl ProtocolPing {
                         target: "net::protocol_ping::run_ping_pong()",
                         "[P2P] Ping-Pong protocol timed out for {}", self.channel.address(),
                     );
-                    self.channel.stop().await;
+                    self.channel.record_protocol_error(ProtocolError::Timeout);
+                    self.channel.stop_with_reason(StopReason::Timeout).await;
                     return Err(Error::ChannelStopped)
                 }
             };
@@ -129,16 +139,16 @@ impl ProtocolPing {
                     "[P2P] Wrong nonce in pingpong, disconnecting {}",
                     self.channel.address(),
                 );
-                self.channel.stop().await;
+                self.channel.stop_with_reason(StopReason::ProtocolViolation).await;
                 return Err(Error::ChannelStopped)
             }
 
+            let rtt = timer.elapsed();
             debug!(
                 target: "net::protocol_ping::run_ping_pong()",
-                "Received Pong from {}: {:?}",
-                self.channel.address(),
-                timer.elapsed(),
+                "Received Pong from {}: {:?}", self.channel.address(), rtt,
             );
+            self.hosts.record_ping(&self.channel.stats_addr(), rtt);
 
             // Sleep until next heartbeat
             sleep(channel_heartbeat_interval).await;
@@ -25,7 +25,7 @@ use smol::{lock::RwLock as AsyncRwLock, Executor};
 use super::{
     super::{
         channel::ChannelPtr,
-        hosts::{HostColor, HostsPtr},
+        hosts::{HostColor, HostOrigin, HostsPtr},
         message::{AddrsMessage, GetAddrsMessage},
         message_publisher::MessageSubscription,
         p2p::P2pPtr,
@@ -131,7 +131,7 @@ impl ProtocolBase for ProtocolSeed {
                 target: "net::protocol_seed::start()",
                 "Appending to greylist...",
             );
-            self.hosts.insert(HostColor::Grey, &addrs_msg.addrs).await;
+            self.hosts.insert(HostColor::Grey, &addrs_msg.addrs, HostOrigin::Seed).await;
         }
 
         debug!(target: "net::protocol_seed::start()", "END => address={}", self.channel.address());
@@ -29,9 +29,13 @@ use log::{debug, error};
 use smol::{lock::RwLock as AsyncRwLock, Executor, Timer};
 
 use super::super::{
-    channel::ChannelPtr,
-    message::{VerackMessage, VersionMessage},
+    channel::{ChannelPtr, ProtocolError, StopReason},
+    message::{
+        IdentityMessage, NodeNonceMessage, VerackMessage, VersionMessage, COMPRESSION_FEATURE,
+        ENCRYPTION_FEATURE, IDENTITY_FEATURE, NODE_NONCE_FEATURE, SEQUENCE_FEATURE,
+    },
     message_publisher::MessageSubscription,
+    session::SESSION_INBOUND,
     settings::Settings,
 };
 use crate::{Error, Result};
@@ -42,6 +46,8 @@ pub struct ProtocolVersion {
     channel: ChannelPtr,
     version_sub: MessageSubscription<VersionMessage>,
     verack_sub: MessageSubscription<VerackMessage>,
+    nonce_sub: MessageSubscription<NodeNonceMessage>,
+    identity_sub: MessageSubscription<IdentityMessage>,
     settings: Arc<AsyncRwLock<Settings>>,
 }
 
@@ -59,7 +65,17 @@ impl ProtocolVersion {
         let verack_sub =
             channel.subscribe_msg::<VerackMessage>().await.expect("Missing verack dispatcher!");
 
-        Arc::new(Self { channel, version_sub, verack_sub, settings })
+        // Creates a node nonce subscription
+        let nonce_sub = channel
+            .subscribe_msg::<NodeNonceMessage>()
+            .await
+            .expect("Missing node nonce dispatcher!");
+
+        // Creates an identity subscription
+        let identity_sub =
+            channel.subscribe_msg::<IdentityMessage>().await.expect("Missing identity dispatcher!");
+
+        Arc::new(Self { channel, version_sub, verack_sub, nonce_sub, identity_sub, settings })
     }
 
     /// Start version information exchange. Start the timer. Send version
@@ -91,7 +107,7 @@ impl ProtocolVersion {
                     self.channel.address(), e,
                 );
 
-                self.channel.stop().await;
+                self.channel.stop_with_reason(StopReason::ProtocolViolation).await;
                 Err(e)
             }
 
@@ -102,7 +118,8 @@ impl ProtocolVersion {
                     self.channel.address(),
                 );
 
-                self.channel.stop().await;
+                self.channel.record_protocol_error(ProtocolError::Timeout);
+                self.channel.stop_with_reason(StopReason::Timeout).await;
                 Err(Error::ChannelTimeout)
             }
         }
@@ -135,6 +152,10 @@ impl ProtocolVersion {
             return Err(e.clone())
         }
 
+        self.exchange_nonces().await?;
+        self.exchange_identities().await?;
+        self.check_peer_id().await?;
+
         debug!(
             target: "net::protocol_version::exchange_versions()",
             "END => address={}", self.channel.address(),
@@ -142,6 +163,93 @@ impl ProtocolVersion {
         Ok(())
     }
 
+    /// Send our node nonce and receive the peer's, if it advertised
+    /// support for them. Older peers don't, and are left without one.
+    async fn exchange_nonces(&self) -> Result<()> {
+        if !self.channel.has_feature(NODE_NONCE_FEATURE).await {
+            return Ok(())
+        }
+
+        self.channel.send(&NodeNonceMessage { nonce: self.channel.p2p().nonce() }).await?;
+        let nonce_msg = self.nonce_sub.receive().await?;
+        self.channel.set_peer_nonce(nonce_msg.nonce);
+        Ok(())
+    }
+
+    /// Prove our identity to the peer and verify its proof, if it
+    /// advertised support for them and the channel is encrypted. Proofs
+    /// sign the key exchange transcript, so they can't be replayed on
+    /// another channel. Other peers are left without a peer ID.
+    async fn exchange_identities(&self) -> Result<()> {
+        if !self.channel.has_feature(IDENTITY_FEATURE).await {
+            return Ok(())
+        }
+        let Some((ours, theirs)) = self.channel.transcripts() else { return Ok(()) };
+
+        let p2p = self.channel.p2p();
+        let identity =
+            IdentityMessage { peer_id: p2p.peer_id(), identity_proof: p2p.identity().prove(&ours) };
+        self.channel.send(&identity).await?;
+
+        let identity_msg = self.identity_sub.receive().await?;
+        if !identity_msg.peer_id.verify(&theirs, &identity_msg.identity_proof) {
+            error!(
+                target: "net::protocol_version::exchange_identities()",
+                "[P2P] Invalid identity proof from {}. Disconnecting...",
+                self.channel.address(),
+            );
+
+            self.channel.stop_with_reason(StopReason::ProtocolViolation).await;
+            return Err(Error::ChannelStopped)
+        }
+        self.channel.set_peer_id(identity_msg.peer_id);
+
+        // Inbound peers connect from ephemeral addresses, so identities
+        // are only tracked for the addresses we dial.
+        if self.channel.session_type_id() & SESSION_INBOUND == 0 &&
+            !p2p.hosts().register_identity(self.channel.address(), identity_msg.peer_id)
+        {
+            error!(
+                target: "net::protocol_version::exchange_identities()",
+                "[P2P] Peer {} is banned under a previous address. Disconnecting...",
+                self.channel.address(),
+            );
+
+            self.channel.stop_with_reason(StopReason::ProtocolViolation).await;
+            return Err(Error::ChannelStopped)
+        }
+
+        Ok(())
+    }
+
+    /// In private network mode, verify the peer ID the peer proved if
+    /// configured. Peers that didn't prove one are refused as well.
+    async fn check_peer_id(&self) -> Result<()> {
+        let settings = self.settings.read().await;
+        if !settings.private_network || settings.allowlist_peer_ids.is_empty() {
+            return Ok(())
+        }
+
+        let allowed = match self.channel.peer_id() {
+            Some(peer_id) => settings.allowlist_peer_ids.contains(&peer_id.to_string()),
+            None => false,
+        };
+        drop(settings);
+
+        if !allowed {
+            error!(
+                target: "net::protocol_version::check_peer_id()",
+                "[P2P] Peer ID of {} is not allowlisted. Disconnecting...",
+                self.channel.address(),
+            );
+
+            self.channel.stop_with_reason(StopReason::ProtocolViolation).await;
+            return Err(Error::PeerNotAllowed)
+        }
+
+        Ok(())
+    }
+
     /// Send version info and wait for version acknowledgement.
     /// Ensures that the app version is the same.
     async fn send_version(self: Arc<Self>) -> Result<()> {
@@ -154,6 +262,23 @@ impl ProtocolVersion {
         let node_id = settings.node_id.clone();
         let app_version = settings.app_version.clone();
         let external_addrs = settings.external_addrs.clone();
+        let mut features = vec![];
+        for (feature, feature_version) in [NODE_NONCE_FEATURE, IDENTITY_FEATURE] {
+            features.push((feature.to_string(), feature_version));
+        }
+        if settings.channel_compression {
+            let (feature, feature_version) = COMPRESSION_FEATURE;
+            features.push((feature.to_string(), feature_version));
+        }
+        if settings.channel_sequencing {
+            let (feature, feature_version) = SEQUENCE_FEATURE;
+            features.push((feature.to_string(), feature_version));
+        }
+        if settings.channel_encryption || settings.channel_require_encryption {
+            let (feature, feature_version) = ENCRYPTION_FEATURE;
+            features.push((feature.to_string(), feature_version));
+        }
+        features.extend(settings.app_features.iter().cloned());
         drop(settings);
 
         let version = VersionMessage {
@@ -164,9 +289,9 @@ impl ProtocolVersion {
             resolve_recv_addr: self.channel.resolve_addr().clone(),
             ext_send_addr: external_addrs,
             /* NOTE: `features` is a list of enabled features in the
-            format Vec<(service, version)>. In the future, Protocols will
-            add their own data to this field when they are attached.*/
-            features: vec![],
+            format Vec<(service, version)>. Application protocols add
+            their own through `Settings::app_features`.*/
+            features,
         };
         self.channel.send(&version).await?;
 
@@ -190,7 +315,7 @@ impl ProtocolVersion {
                 self.channel.address(),
             );
 
-            self.channel.stop().await;
+            self.channel.stop_with_reason(StopReason::ProtocolViolation).await;
             return Err(Error::ChannelStopped)
         }
 
@@ -203,7 +328,8 @@ impl ProtocolVersion {
     }
 
     /// Receive version info, check the message is okay and send verack
-    /// with app version attached.
+    /// with app version attached. If both ends support encryption, our
+    /// ephemeral key is sent right before the verack.
     async fn recv_version(self: Arc<Self>) -> Result<()> {
         debug!(
             target: "net::protocol_version::recv_version()",
@@ -212,7 +338,24 @@ impl ProtocolVersion {
 
         // Receive version message
         let version = self.version_sub.receive().await?;
+
+        // Refuse cleartext peers if we require encryption
+        let settings = self.settings.read().await;
+        if settings.channel_require_encryption && !version.has_feature(ENCRYPTION_FEATURE) {
+            drop(settings);
+            error!(
+                target: "net::protocol_version::recv_version()",
+                "[P2P] Peer {} doesn't support encryption. Disconnecting...",
+                self.channel.address(),
+            );
+
+            self.channel.stop_with_reason(StopReason::ProtocolViolation).await;
+            return Err(Error::ChannelStopped)
+        }
+        drop(settings);
+
         self.channel.set_version(version).await;
+        self.channel.start_encryption().await?;
 
         // Send verack
         let verack = VerackMessage { app_version: self.settings.read().await.app_version.clone() };
@@ -0,0 +1,163 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use log::debug;
+
+/// A cached resolution of a peer hostname
+struct CacheEntry {
+    /// Addresses the hostname resolved to
+    addrs: Vec<SocketAddr>,
+    /// When the resolution expires, `None` if it's pinned
+    expiry: Option<Instant>,
+}
+
+/// Cache of peer hostname resolutions, so connecting to hostname-based
+/// peers doesn't go through the system resolver on every attempt. The
+/// system resolver doesn't report record TTLs, so resolutions are kept
+/// for the configured `dns_cache_ttl` instead, and dropped early when
+/// connecting to them fails, in case the records changed.
+pub struct Resolver {
+    /// Cached resolutions, keyed by hostname and port
+    cache: Mutex<HashMap<(String, u16), CacheEntry>>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self { cache: Mutex::new(HashMap::new()) }
+    }
+
+    /// Resolve `host:port`, reusing a cached resolution if there's one.
+    /// Fresh resolutions are cached for `ttl` seconds, or forever if
+    /// `pin` is set. A `ttl` of 0 disables the cache.
+    pub async fn resolve(
+        &self,
+        host: &str,
+        port: u16,
+        ttl: u64,
+        pin: bool,
+    ) -> io::Result<Vec<SocketAddr>> {
+        if let Some(addrs) = self.cached(host, port, Instant::now()) {
+            return Ok(addrs)
+        }
+
+        // This runs the system resolver on the blocking threadpool, so a
+        // stalled resolver doesn't hold up the executor.
+        let addrs = smol::net::resolve((host, port)).await?;
+        if addrs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Hostname resolved to nothing"))
+        }
+        debug!(target: "net::resolver::resolve", "Resolved {}:{} to {:?}", host, port, addrs);
+
+        let expiry = match (pin, ttl) {
+            (true, _) => None,
+            (false, 0) => return Ok(addrs),
+            (false, ttl) => Some(Instant::now() + Duration::from_secs(ttl)),
+        };
+        self.insert(host, port, addrs.clone(), expiry);
+
+        Ok(addrs)
+    }
+
+    /// Drop the cached resolution of `host:port` unless it's pinned, so
+    /// the next connection attempt resolves it again
+    pub fn invalidate(&self, host: &str, port: u16) {
+        let mut cache = self.cache.lock().unwrap();
+        let key = (host.to_string(), port);
+        if cache.get(&key).is_some_and(|entry| entry.expiry.is_some()) {
+            debug!(target: "net::resolver::invalidate", "Dropping cached {}:{}", host, port);
+            cache.remove(&key);
+        }
+    }
+
+    /// Grab the cached resolution of `host:port` if it's still valid at `now`
+    fn cached(&self, host: &str, port: u16, now: Instant) -> Option<Vec<SocketAddr>> {
+        let mut cache = self.cache.lock().unwrap();
+        let key = (host.to_string(), port);
+        let entry = cache.get(&key)?;
+        if entry.expiry.is_some_and(|expiry| expiry <= now) {
+            cache.remove(&key);
+            return None
+        }
+
+        Some(entry.addrs.clone())
+    }
+
+    /// Cache a resolution of `host:port`
+    fn insert(&self, host: &str, port: u16, addrs: Vec<SocketAddr>, expiry: Option<Instant>) {
+        self.cache.lock().unwrap().insert((host.to_string(), port), CacheEntry { addrs, expiry });
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolver_cache() {
+        let resolver = Resolver::new();
+        let now = Instant::now();
+        let addrs = vec!["127.0.0.1:26661".parse().unwrap()];
+
+        // Expiring entries are only served until their expiry
+        resolver.insert("peer.dark.fi", 26661, addrs.clone(), Some(now + Duration::from_secs(10)));
+        assert_eq!(resolver.cached("peer.dark.fi", 26661, now), Some(addrs.clone()));
+        assert_eq!(resolver.cached("peer.dark.fi", 26662, now), None);
+        assert_eq!(resolver.cached("peer.dark.fi", 26661, now + Duration::from_secs(10)), None);
+
+        // and get dropped on connect failures
+        resolver.insert("peer.dark.fi", 26661, addrs.clone(), Some(now + Duration::from_secs(10)));
+        resolver.invalidate("peer.dark.fi", 26661);
+        assert_eq!(resolver.cached("peer.dark.fi", 26661, now), None);
+
+        // Pinned entries stay
+        resolver.insert("peer.dark.fi", 26661, addrs.clone(), None);
+        resolver.invalidate("peer.dark.fi", 26661);
+        assert_eq!(
+            resolver.cached("peer.dark.fi", 26661, now + Duration::from_secs(86400)),
+            Some(addrs)
+        );
+    }
+
+    #[test]
+    fn test_resolver_resolve() {
+        smol::block_on(async {
+            let resolver = Resolver::new();
+            let addrs = resolver.resolve("127.0.0.1", 26661, 300, false).await.unwrap();
+            assert_eq!(addrs, vec!["127.0.0.1:26661".parse().unwrap()]);
+            assert_eq!(resolver.cached("127.0.0.1", 26661, Instant::now()), Some(addrs));
+
+            // Nothing is cached with a TTL of 0
+            resolver.resolve("127.0.0.2", 26661, 0, false).await.unwrap();
+            assert_eq!(resolver.cached("127.0.0.2", 26661, Instant::now()), None);
+        });
+    }
+}
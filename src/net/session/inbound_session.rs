@@ -23,7 +23,10 @@
 //! an acceptor pointer, and a stoppable task pointer. Using a weak pointer
 //! to P2P allows us to avoid circular dependencies.
 
-use std::sync::{Arc, Weak};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
 
 use async_trait::async_trait;
 use log::{debug, error, info, warn};
@@ -36,21 +39,30 @@ use super::{
         channel::ChannelPtr,
         dnet::{self, dnetev, DnetEvent},
         p2p::{P2p, P2pPtr},
+        portmap::{self, PortMapping},
     },
     Session, SessionBitFlag, SESSION_INBOUND,
 };
 use crate::{
-    system::{StoppableTask, StoppableTaskPtr, Subscription},
+    system::{sleep, StoppableTask, StoppableTaskPtr, Subscription},
     Error, Result,
 };
 
 pub type InboundSessionPtr = Arc<InboundSession>;
 
+/// Time (in seconds) to wait before retrying a failed port mapping
+const PORT_MAPPING_RETRY_INTERVAL: u64 = 300;
+
 /// Defines inbound connections session
 pub struct InboundSession {
     pub(in crate::net) p2p: Weak<P2p>,
     acceptors: Mutex<Vec<AcceptorPtr>>,
     accept_tasks: Mutex<Vec<StoppableTaskPtr>>,
+    /// Tasks keeping the inbound ports mapped on the NAT gateway
+    port_mapping_tasks: Mutex<Vec<StoppableTaskPtr>>,
+    /// Active port mappings and the external address advertised for
+    /// them, keyed by inbound address
+    port_mappings: Mutex<HashMap<Url, (PortMapping, Url)>>,
 }
 
 impl InboundSession {
@@ -60,6 +72,8 @@ impl InboundSession {
             p2p,
             acceptors: Mutex::new(Vec::new()),
             accept_tasks: Mutex::new(Vec::new()),
+            port_mapping_tasks: Mutex::new(Vec::new()),
+            port_mappings: Mutex::new(HashMap::new()),
         })
     }
 
@@ -107,6 +121,11 @@ impl InboundSession {
                 .start_accept_session(index, accept_addr.clone(), acceptor, ex.clone())
                 .await?;
         }
+        drop(accept_tasks);
+
+        if self.p2p().settings().read().await.port_mapping {
+            self.start_port_mapping(&inbound_addrs, ex).await;
+        }
 
         Ok(())
     }
@@ -127,6 +146,108 @@ impl InboundSession {
         for accept_task in accept_tasks {
             accept_task.stop().await;
         }
+
+        let port_mapping_tasks = &*self.port_mapping_tasks.lock().await;
+        for task in port_mapping_tasks {
+            task.stop().await;
+        }
+
+        for (accept_addr, (mapping, external_addr)) in self.port_mappings.lock().await.drain() {
+            if let Err(e) = mapping.unmap().await {
+                warn!(
+                    target: "net::inbound_session",
+                    "[P2P] Failed removing port mapping of {}: {}", accept_addr, e,
+                );
+            }
+            self.p2p().settings().write().await.external_addrs.retain(|a| a != &external_addr);
+        }
+    }
+
+    /// Start a task keeping each TCP inbound port mapped on the NAT gateway
+    async fn start_port_mapping(self: &Arc<Self>, inbound_addrs: &[Url], ex: Arc<Executor<'_>>) {
+        let mut port_mapping_tasks = self.port_mapping_tasks.lock().await;
+
+        for accept_addr in inbound_addrs {
+            if !["tcp", "tcp+tls"].contains(&accept_addr.scheme()) || accept_addr.port().is_none() {
+                debug!(
+                    target: "net::inbound_session",
+                    "[P2P] Not mapping port of {}: unsupported address", accept_addr,
+                );
+                continue
+            }
+
+            let task = StoppableTask::new();
+            task.clone().start(
+                self.clone().port_mapping_loop(accept_addr.clone()),
+                // Ignore stop handler
+                |_| async {},
+                Error::NetworkServiceStopped,
+                ex.clone(),
+            );
+
+            port_mapping_tasks.push(task);
+        }
+    }
+
+    /// Map the port of `accept_addr` on the NAT gateway, advertise the
+    /// resulting external address and keep renewing the lease.
+    async fn port_mapping_loop(self: Arc<Self>, accept_addr: Url) -> Result<()> {
+        let port = accept_addr.port().unwrap();
+        let lifetime = self.p2p().settings().read().await.port_mapping_lifetime;
+
+        loop {
+            let current = self.port_mappings.lock().await.get(&accept_addr).cloned();
+            let result = match &current {
+                Some((mapping, _)) => mapping.renew().await,
+                None => portmap::map_port(port, lifetime).await,
+            };
+
+            let mapping = match result {
+                Ok(mapping) => mapping,
+                Err(e) => {
+                    warn!(
+                        target: "net::inbound_session::port_mapping_loop()",
+                        "[P2P] Port mapping of {} failed: {}", accept_addr, e,
+                    );
+
+                    // Stop advertising an address that may not be reachable
+                    if let Some((_, external_addr)) =
+                        self.port_mappings.lock().await.remove(&accept_addr)
+                    {
+                        let settings = self.p2p().settings();
+                        settings.write().await.external_addrs.retain(|a| a != &external_addr);
+                    }
+
+                    sleep(PORT_MAPPING_RETRY_INTERVAL).await;
+                    continue
+                }
+            };
+
+            let mut external_addr = accept_addr.clone();
+            external_addr.set_ip_host(mapping.external_ip).unwrap();
+            external_addr.set_port(Some(mapping.external_port)).unwrap();
+
+            let previous = current.map(|(_, addr)| addr);
+            if previous.as_ref() != Some(&external_addr) {
+                info!(
+                    target: "net::inbound_session::port_mapping_loop()",
+                    "[P2P] Mapped {} to {} using {}", accept_addr, external_addr, mapping.protocol,
+                );
+
+                let settings = self.p2p().settings();
+                let mut settings = settings.write().await;
+                if let Some(previous) = previous {
+                    settings.external_addrs.retain(|a| a != &previous);
+                }
+                if !settings.external_addrs.contains(&external_addr) {
+                    settings.external_addrs.push(external_addr.clone());
+                }
+            }
+
+            let renew_after = (mapping.lifetime / 2).max(1);
+            self.port_mappings.lock().await.insert(accept_addr.clone(), (mapping, external_addr));
+            sleep(renew_after).await;
+        }
     }
 
     /// Start accepting connections for inbound session.
@@ -47,7 +47,10 @@ use super::{
     Session, SessionBitFlag, SESSION_MANUAL,
 };
 use crate::{
-    net::{hosts::HostState, settings::Settings},
+    net::{
+        hosts::{HostOrigin, HostState},
+        settings::Settings,
+    },
     system::{sleep, StoppableTask, StoppableTaskPtr},
     Error, Result,
 };
@@ -85,6 +88,22 @@ impl ManualSession {
         while (futures.next().await).is_some() {}
     }
 
+    /// Add a manual connection to the given peer at runtime. Like the
+    /// configured `peers`, it keeps getting reconnected until the session
+    /// stops. Returns false if there already is a slot for this peer.
+    pub async fn connect(self: Arc<Self>, addr: Url) -> bool {
+        let mut slots = self.slots.lock().await;
+        if slots.iter().any(|slot| slot.addr == addr) {
+            return false
+        }
+
+        let slot = Slot::new(Arc::downgrade(&self), addr, self.p2p().settings());
+        slot.clone().start().await;
+        slots.push(slot);
+
+        true
+    }
+
     /// Stops the manual session.
     pub async fn stop(&self) {
         let slots = &*self.slots.lock().await;
@@ -198,6 +217,8 @@ impl Slot {
                     // Register the new channel
                     match self.session().register_channel(channel.clone(), ex.clone()).await {
                         Ok(()) => {
+                            self.p2p().hosts().container.record_origin(&url, HostOrigin::Manual);
+
                             // Wait for channel to close
                             stop_sub.receive().await;
 
@@ -17,7 +17,7 @@
  */
 
 use std::{
-    sync::{Arc, Weak},
+    sync::{atomic::Ordering, Arc, Weak},
     time::UNIX_EPOCH,
 };
 
@@ -96,6 +96,59 @@ pub async fn remove_sub_on_stop(
     debug!(target: "net::session::remove_sub_on_stop()", "[END]");
 }
 
+/// Checks whether we already hold a channel to the peer on the other end
+/// of a freshly handshaked channel, by comparing the nonces sent after
+/// the version exchange. Peers not sending one are never considered
+/// duplicates. Of the two channels, we keep the one dialed by the
+/// node with the higher nonce. Both ends apply the same rule so they agree
+/// on which channel survives. When the same node dialed both, the new
+/// channel is kept, as the old one may be a stale half-open connection
+/// the peer gave up on. The losing channel is stopped, and `true` is
+/// returned if that was the new channel.
+async fn drop_duplicate_channel(p2p: &P2pPtr, channel: &ChannelPtr) -> bool {
+    let Some(peer_nonce) = channel.peer_nonce() else { return false };
+    let our_nonce = p2p.nonce();
+
+    // Returns the nonce of the node that initiated the given channel
+    let dialer_nonce = |c: &ChannelPtr| {
+        if c.session_type_id() & SESSION_INBOUND != 0 {
+            peer_nonce
+        } else {
+            our_nonce
+        }
+    };
+
+    for other in p2p.hosts().peers() {
+        if other.info.id == channel.info.id {
+            continue
+        }
+
+        if other.peer_nonce() != Some(peer_nonce) {
+            continue
+        }
+
+        p2p.duplicate_channels.fetch_add(1, Ordering::SeqCst);
+
+        if dialer_nonce(channel) >= dialer_nonce(&other) {
+            debug!(
+                target: "net::session::drop_duplicate_channel()",
+                "Closing duplicate channel {} in favor of {}", other.address(), channel.address(),
+            );
+            other.stop().await;
+            return false
+        }
+
+        debug!(
+            target: "net::session::drop_duplicate_channel()",
+            "Closing duplicate channel {} in favor of {}", channel.address(), other.address(),
+        );
+        channel.stop().await;
+        return true
+    }
+
+    false
+}
+
 /// Session trait. Defines methods that are used across sessions.
 /// Implements registering the channel and initializing the channel by
 /// performing a network handshake.
@@ -181,6 +234,14 @@ pub trait Session: Sync {
         // Perform handshake
         match protocol_version.run(executor.clone()).await {
             Ok(()) => {
+                // Seed and refine channels are short-lived, so we only look
+                // for duplicates among the remaining sessions.
+                if self.type_id() & (SESSION_SEED | SESSION_REFINE) == 0 &&
+                    drop_duplicate_channel(&self.p2p(), &channel).await
+                {
+                    return Err(Error::DuplicateChannel)
+                }
+
                 // Upgrade to goldlist if this is a outbound session.
                 if self.type_id() & SESSION_OUTBOUND != 0 {
                     debug!(
@@ -193,6 +254,7 @@ pub trait Session: Sync {
                         .hosts()
                         .move_host(channel.address(), last_seen, HostColor::Gold)
                         .unwrap();
+                    self.p2p().hosts().clear_failures(channel.address());
                 }
 
                 // Attempt to add channel to registry
@@ -28,10 +28,10 @@
 
 use std::{
     sync::{
-        atomic::{AtomicU32, Ordering},
-        Arc, Weak,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex as SyncMutex, Weak,
     },
-    time::{Duration, Instant},
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 
 use async_trait::async_trait;
@@ -47,6 +47,7 @@ use super::{
         dnet::{self, dnetev, DnetEvent},
         hosts::{HostColor, HostState},
         message::GetAddrsMessage,
+        metering::LOW_DATA_OUTBOUND_SLOTS,
         p2p::{P2p, P2pPtr},
     },
     Session, SessionBitFlag, SESSION_OUTBOUND,
@@ -58,6 +59,12 @@ use crate::{
 
 pub type OutboundSessionPtr = Arc<OutboundSession>;
 
+/// Seconds an outbound slot waits before rechecking the daily data cap
+const DATA_CAP_RETRY_INTERVAL: u64 = 60;
+
+/// Seconds between checks for stalled outbound slots
+const SLOT_STALL_CHECK_INTERVAL: u64 = 10;
+
 /// Defines outbound connections session.
 pub struct OutboundSession {
     /// Weak pointer to parent p2p object
@@ -66,6 +73,10 @@ pub struct OutboundSession {
     slots: Mutex<Vec<Arc<Slot>>>,
     /// Peer discovery task
     peer_discovery: Arc<PeerDiscovery>,
+    /// Task restarting stalled slots
+    stall_watchdog: StoppableTaskPtr,
+    /// Number of times a stalled slot got restarted
+    slot_stalls: AtomicU64,
 }
 
 impl OutboundSession {
@@ -75,12 +86,25 @@ impl OutboundSession {
             p2p,
             slots: Mutex::new(Vec::new()),
             peer_discovery: PeerDiscovery::new(session.clone()),
+            stall_watchdog: StoppableTask::new(),
+            slot_stalls: AtomicU64::new(0),
         })
     }
 
     /// Start the outbound session. Runs the channel connect loop.
     pub(crate) async fn start(self: Arc<Self>) {
-        let n_slots = self.p2p().settings().read().await.outbound_connections;
+        let settings = self.p2p().settings().read_arc().await;
+        let n_slots = if settings.low_data_mode {
+            settings.outbound_connections.min(LOW_DATA_OUTBOUND_SLOTS)
+        } else {
+            settings.outbound_connections
+        };
+        let transports = slot_transports(
+            n_slots,
+            &settings.outbound_transport_quotas,
+            &settings.allowed_transports,
+        );
+        drop(settings);
         info!(target: "net::outbound_session", "[P2P] Starting {} outbound connection slots.", n_slots);
 
         // Activate mutex lock on connection slots.
@@ -90,8 +114,8 @@ impl OutboundSession {
 
         let self_ = Arc::downgrade(&self);
 
-        for i in 0..n_slots as u32 {
-            let slot = Slot::new(self_.clone(), i);
+        for (i, transport) in transports.into_iter().enumerate() {
+            let slot = Slot::new(self_.clone(), i as u32, transport);
             futures.push(slot.clone().start());
             slots.push(slot);
         }
@@ -99,11 +123,26 @@ impl OutboundSession {
         while (futures.next().await).is_some() {}
 
         self.peer_discovery.clone().start().await;
+
+        let ex = self.p2p().executor();
+        self.stall_watchdog.clone().start(
+            self.clone().watch_stalls(),
+            // Ignore stop handler
+            |_| async {},
+            Error::NetworkServiceStopped,
+            ex,
+        );
     }
 
     /// Stops the outbound session.
     pub(crate) async fn stop(&self) {
         debug!(target: "net::outbound_session", "Stopping outbound session..");
+
+        // Remember our outbound peers before disconnecting from them
+        self.p2p().hosts().record_anchors().await;
+
+        self.stall_watchdog.stop().await;
+
         let slots = &*self.slots.lock().await;
         let mut futures = FuturesUnordered::new();
 
@@ -126,6 +165,54 @@ impl OutboundSession {
         info
     }
 
+    /// Usage of the outbound slots reserved to transports, as (transport,
+    /// reserved slots, connected slots), in the configured order
+    pub async fn transport_quotas(&self) -> Vec<(String, usize, usize)> {
+        let mut quotas: Vec<(String, usize, usize)> = vec![];
+        let slots = &*self.slots.lock().await;
+        for slot in slots {
+            let Some(ref transport) = slot.transport else { continue };
+            let connected = slot.channel_id.load(Ordering::Relaxed) != 0;
+
+            match quotas.iter_mut().find(|(t, _, _)| t == transport) {
+                Some(quota) => {
+                    quota.1 += 1;
+                    quota.2 += connected as usize;
+                }
+                None => quotas.push((transport.clone(), 1, connected as usize)),
+            }
+        }
+        quotas
+    }
+
+    /// Number of times an outbound slot was found stalled and restarted
+    pub fn slot_stalls(&self) -> u64 {
+        self.slot_stalls.load(Ordering::Relaxed)
+    }
+
+    /// Periodically restart the slots that have been trying to connect for
+    /// longer than `outbound_slot_stall_timeout`. The connector is bounded by
+    /// `outbound_connect_timeout`, but a hanging transport or proxy can still
+    /// keep a slot from ever connecting again.
+    async fn watch_stalls(self: Arc<Self>) -> Result<()> {
+        loop {
+            sleep(SLOT_STALL_CHECK_INTERVAL).await;
+
+            let stall_timeout = self.p2p().settings().read().await.outbound_slot_stall_timeout;
+            if stall_timeout == 0 {
+                continue
+            }
+
+            let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+            let slots = self.slots.lock().await.clone();
+            for slot in slots {
+                if slot.connecting_for(now).is_some_and(|secs| secs > stall_timeout) {
+                    slot.recycle().await;
+                }
+            }
+        }
+    }
+
     fn wakeup_peer_discovery(&self) {
         self.peer_discovery.notify()
     }
@@ -138,6 +225,30 @@ impl OutboundSession {
     }
 }
 
+/// Transport each outbound slot is reserved to, following the
+/// `outbound_transport_quotas` setting. Slots past the quotas are not
+/// reserved, and quotas exceeding the number of slots get truncated.
+fn slot_transports(
+    n_slots: usize,
+    quotas: &[(String, usize)],
+    allowed_transports: &[String],
+) -> Vec<Option<String>> {
+    let mut transports = vec![];
+    for (transport, slots) in quotas {
+        if !allowed_transports.contains(transport) {
+            warn!(
+                target: "net::outbound_session",
+                "[P2P] Ignoring quota of transport {} missing from allowed transports", transport,
+            );
+            continue
+        }
+        transports.extend(std::iter::repeat(Some(transport.clone())).take(*slots));
+    }
+
+    transports.resize(n_slots, None);
+    transports
+}
+
 #[async_trait]
 impl Session for OutboundSession {
     fn p2p(&self) -> P2pPtr {
@@ -155,12 +266,17 @@ struct Slot {
     wakeup_self: CondVar,
     session: Weak<OutboundSession>,
     connector: Connector,
+    /// Transport the slot is reserved to, if any
+    transport: Option<String>,
     // For debugging
     channel_id: AtomicU32,
+    /// Address being connected to, its last seen time, and the time the
+    /// connection attempt started
+    connecting: SyncMutex<Option<(Url, u64, u64)>>,
 }
 
 impl Slot {
-    fn new(session: Weak<OutboundSession>, slot: u32) -> Arc<Self> {
+    fn new(session: Weak<OutboundSession>, slot: u32, transport: Option<String>) -> Arc<Self> {
         let settings = session.upgrade().unwrap().p2p().settings();
 
         Arc::new(Self {
@@ -169,7 +285,9 @@ impl Slot {
             wakeup_self: CondVar::new(),
             session: session.clone(),
             connector: Connector::new(settings, session),
+            transport,
             channel_id: AtomicU32::new(0),
+            connecting: SyncMutex::new(None),
         })
     }
 
@@ -194,11 +312,55 @@ impl Slot {
         self.process.stop().await;
     }
 
-    /// Address selection algorithm that works as follows: up to
+    /// Returns for how many seconds the slot has been trying to connect,
+    /// if it is.
+    fn connecting_for(&self, now: u64) -> Option<u64> {
+        self.connecting.lock().unwrap().as_ref().map(|(_, _, since)| now.saturating_sub(*since))
+    }
+
+    /// Cancel the stalled connection attempt of the slot and restart it.
+    /// The address it hung on is downgraded, the same way as when the
+    /// connection fails.
+    async fn recycle(self: Arc<Self>) {
+        self.process.stop().await;
+
+        // The attempt may have finished while we were stopping the slot
+        let stalled = self.connecting.lock().unwrap().take();
+        if let Some((addr, last_seen, _)) = stalled {
+            warn!(
+                target: "net::outbound_session::recycle()",
+                "[P2P] Outbound slot #{} stalled connecting to [{}], restarting it",
+                self.slot, addr,
+            );
+
+            dnetev!(self, OutboundSlotStalled, {
+                slot: self.slot,
+                addr: addr.clone(),
+            });
+
+            let hosts = self.p2p().hosts();
+            if let Err(e) = hosts.move_host(&addr, last_seen, HostColor::Grey) {
+                debug!(
+                    target: "net::outbound_session::recycle()",
+                    "[P2P] Failed downgrading stalled addr=[{}]: {}", addr, e,
+                );
+            }
+            hosts.try_register(addr, HostState::Suspend).unwrap();
+
+            self.session().slot_stalls.fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.channel_id.store(0, Ordering::Relaxed);
+        self.start().await;
+    }
+
+    /// Address selection algorithm that works as follows: first, connect
+    /// to the recent enough anchors saved on shutdown. Then, up to
     /// gold_count, select from the goldlist. Up to white_count,
     /// select from the whitelist. For all other slots, select from
     /// the greylist. If none of these preferences are satisfied, do
-    /// peer discovery.
+    /// peer discovery. Slots reserved to a transport only select
+    /// addresses reachable through it.
     ///
     /// Selecting from the greylist for some % of the slots is necessary
     /// and healthy since we require the network retains some unreliable
@@ -215,13 +377,35 @@ impl Slot {
         let white_count = (settings.white_connect_percent * settings.outbound_connections) / 100;
         let gold_count = settings.gold_connect_count;
 
-        let transports = settings.allowed_transports.clone();
+        let transports = match self.transport {
+            Some(ref transport) => vec![transport.clone()],
+            None => settings.allowed_transports.clone(),
+        };
         let transport_mixing = settings.transport_mixing;
         let preference_strict = settings.slot_preference_strict;
+        let anchor_count = settings.anchor_connect_count;
+        let anchor_max_age = settings.anchor_max_age;
 
         // Drop Settings read lock
         drop(settings);
 
+        // Reconnect to the peers we had before restarting first, unless
+        // it's been too long since then.
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        while anchor_count > 0 {
+            let Some(anchor) = container.pop_anchor() else { break };
+            if !transports.contains(&anchor.0.scheme().to_string()) ||
+                container.contains(HostColor::Black as usize, &anchor.0) ||
+                now.saturating_sub(anchor.1) > anchor_max_age
+            {
+                continue
+            }
+
+            if let Some(addr) = hosts.check_addrs(vec![anchor]).await {
+                return Some(addr)
+            }
+        }
+
         let grey_only = hosts.container.is_empty(HostColor::White) &&
             hosts.container.is_empty(HostColor::Gold) &&
             !hosts.container.is_empty(HostColor::Grey);
@@ -233,7 +417,11 @@ impl Slot {
         } else if slot < gold_count {
             container.fetch(HostColor::Gold, &transports, transport_mixing)
         } else if slot < white_count {
-            container.fetch(HostColor::White, &transports, transport_mixing)
+            // Prefer the whitelist entries we failed connecting to the least,
+            // then the ones that responded fastest to the refinery
+            let mut addrs = container.fetch(HostColor::White, &transports, transport_mixing);
+            hosts.sort_by_reliability(&mut addrs);
+            addrs
         } else {
             container.fetch(HostColor::Grey, &transports, transport_mixing)
         };
@@ -255,11 +443,22 @@ impl Slot {
                 self.slot,
             );
 
+            // Stop making new connections once the daily data cap is reached.
+            if self.p2p().data_cap_reached().await {
+                debug!(
+                    target: "net::outbound_session::run()",
+                    "Daily data cap reached, outbound slot #{} sleeping", self.slot,
+                );
+                sleep(DATA_CAP_RETRY_INTERVAL).await;
+                continue
+            }
+
             // Do peer discovery if we don't have any peers on the Grey, White or Gold list
             // (first time connecting to the network).
             if hosts.container.is_empty(HostColor::Grey) &&
                 hosts.container.is_empty(HostColor::White) &&
-                hosts.container.is_empty(HostColor::Gold)
+                hosts.container.is_empty(HostColor::Gold) &&
+                !hosts.container.has_anchors()
             {
                 dnetev!(self, OutboundSlotSleeping, {
                     slot: self.slot,
@@ -308,7 +507,12 @@ impl Slot {
                 addr: host.clone(),
             });
 
-            let (addr, channel) = match self.try_connect(host.clone(), last_seen).await {
+            let now = UNIX_EPOCH.elapsed().unwrap().as_secs();
+            *self.connecting.lock().unwrap() = Some((host.clone(), last_seen, now));
+            let connect_result = self.try_connect(host.clone(), last_seen).await;
+            self.connecting.lock().unwrap().take();
+
+            let (addr, channel) = match connect_result {
                 Ok(connect_info) => connect_info,
                 Err(err) => {
                     debug!(
@@ -367,6 +571,7 @@ impl Slot {
                 );
 
                 // At this point we failed to connect. We'll downgrade this peer now.
+                self.p2p().hosts().record_failure(&addr);
                 self.p2p().hosts().move_host(&addr, last_seen, HostColor::Grey)?;
 
                 // Mark its state as Suspend, which sends this node to the Refinery for processing.
@@ -410,6 +615,7 @@ impl Slot {
                 }
 
                 // At this point we failed to connect. We'll downgrade this peer now.
+                self.p2p().hosts().record_failure(&addr);
                 self.p2p().hosts().move_host(&addr, last_seen, HostColor::Grey)?;
 
                 // Mark its state as Suspend, which sends it to the Refinery for processing.
@@ -502,7 +708,7 @@ impl PeerDiscoveryBase for PeerDiscovery {
     /// On the third attempt, and if we still haven't made any connections,
     /// this function will then call `p2p.seed()` which triggers a
     /// `SeedSyncSession` that will connect to configured seeds and request
-    /// peers from them.
+    /// peers from them, after greylisting the peers of the DNS seeds.
     ///
     /// This function will also sleep `outbound_peer_discovery_attempt_time`
     /// seconds after broadcasting in order to let the P2P stack receive and
@@ -527,6 +733,7 @@ impl PeerDiscoveryBase for PeerDiscovery {
             let outbound_connections = settings.outbound_connections;
             let allowed_transports = settings.allowed_transports.clone();
             let seeds = settings.seeds.clone();
+            let dns_seeds = !settings.dns_seeds.is_empty();
             drop(settings);
 
             if sleep_was_instant {
@@ -606,7 +813,7 @@ impl PeerDiscoveryBase for PeerDiscovery {
                 // Drop. For now it's sufficient for publishers to be
                 // de-allocated when the Session completes.
                 store_sub.unsubscribe().await;
-            } else if !seeds.is_empty() {
+            } else if !seeds.is_empty() || dns_seeds {
                 info!(
                     target: "net::outbound_session::peer_discovery()",
                     "[P2P] [PEER DISCOVERY] Asking seeds for new peers to connect to...");
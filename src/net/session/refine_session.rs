@@ -24,14 +24,21 @@
 //! a provided address, and then does a version exchange across the channel
 //! (`perform_handshake_protocols`). `handshake_node()` can either succeed,
 //! fail, or timeout.
+//!
+//! The outcomes of the probes are counted by transport in `RefineryStats`,
+//! which are kept in the P2P datastore across restarts.
 
 use futures::{
     future::{select, Either},
     pin_mut,
+    stream::{FuturesUnordered, StreamExt},
 };
 use smol::Timer;
 use std::{
-    sync::{Arc, Weak},
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
     time::{Duration, Instant, UNIX_EPOCH},
 };
 
@@ -45,13 +52,21 @@ use crate::{
     net::{
         connector::Connector,
         hosts::{HostColor, HostState},
+        metering::{in_hours, ProbeOutcome, RefineryStats},
         protocol::ProtocolVersion,
         session::{Session, SessionBitFlag, SESSION_REFINE},
     },
     system::{sleep, StoppableTask, StoppableTaskPtr},
+    util::{
+        file::{load_file, save_file},
+        path::expand_path,
+    },
     Error,
 };
 
+/// File in the P2P datastore keeping the refinery stats
+const REFINERY_STATS_FILE: &str = "refinery_stats";
+
 pub type RefineSessionPtr = Arc<RefineSession>;
 
 pub struct RefineSession {
@@ -60,11 +75,30 @@ pub struct RefineSession {
 
     /// Task that periodically checks entries in the greylist.
     pub(in crate::net) refinery: Arc<GreylistRefinery>,
+
+    /// Outcomes of the refinery probes
+    stats: Mutex<RefineryStats>,
 }
 
 impl RefineSession {
     pub fn new(p2p: Weak<P2p>) -> RefineSessionPtr {
-        Arc::new_cyclic(|session| Self { p2p, refinery: GreylistRefinery::new(session.clone()) })
+        Arc::new_cyclic(|session| Self {
+            p2p,
+            refinery: GreylistRefinery::new(session.clone()),
+            stats: Mutex::new(RefineryStats::default()),
+        })
+    }
+
+    /// Outcomes of the refinery probes, including the ones of previous
+    /// runs when the node has a P2P datastore.
+    pub fn stats(&self) -> RefineryStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Where the refinery stats are kept, if anywhere
+    async fn stats_path(&self) -> Option<PathBuf> {
+        let datastore = self.p2p().settings().read().await.p2p_datastore.clone()?;
+        expand_path(&datastore).ok().map(|path| path.join(REFINERY_STATS_FILE))
     }
 
     /// Start the refinery and self handshake processes.
@@ -90,14 +124,31 @@ impl RefineSession {
             }
         }
 
+        if let Some(path) = self.stats_path().await {
+            match load_file(&path) {
+                Ok(tsv) => *self.stats.lock().unwrap() = RefineryStats::from_tsv(&tsv),
+                Err(e) => {
+                    debug!(target: "net::refine_session::start", "No refinery stats loaded {}", e);
+                }
+            }
+        }
+
+        // Private networks don't gossip addresses, so there is nothing to refine
+        if self.p2p().settings().read().await.private_network {
+            debug!(target: "net::refine_session", "Private network, not starting refinery");
+            return
+        }
+
         debug!(target: "net::refine_session", "Starting greylist refinery process");
         self.refinery.clone().start().await;
     }
 
     /// Stop the refinery and self handshake processes.
     pub(crate) async fn stop(&self) {
-        debug!(target: "net::refine_session", "Stopping refinery process");
-        self.refinery.clone().stop().await;
+        if !self.p2p().settings().read().await.private_network {
+            debug!(target: "net::refine_session", "Stopping refinery process");
+            self.refinery.clone().stop().await;
+        }
 
         if let Some(ref hostlist) = self.p2p().settings().read().await.hostlist {
             match self.p2p().hosts().container.save_all(hostlist) {
@@ -109,14 +160,36 @@ impl RefineSession {
                 }
             }
         }
+
+        if let Some(path) = self.stats_path().await {
+            if let Some(parent) = path.parent() {
+                if let Err(e) = fs::create_dir_all(parent) {
+                    warn!(target: "net::refine_session::stop()", "Error creating datastore {}", e);
+                }
+            }
+
+            if let Err(e) = save_file(&path, &self.stats().to_tsv()) {
+                warn!(target: "net::refine_session::stop()", "Error saving refinery stats {}", e);
+            }
+        }
     }
 
     /// Globally accessible function to perform a version exchange with a
     /// given address.  Returns `true` if an address is accessible, false
-    /// otherwise.  
+    /// otherwise. On success, the handshake round-trip time is recorded
+    /// in the hosts.
     pub async fn handshake_node(self: Arc<Self>, addr: Url, p2p: P2pPtr) -> bool {
+        matches!(self.probe_node(addr, p2p).await, ProbeOutcome::Success(_))
+    }
+
+    /// Like [`RefineSession::handshake_node()`], telling failures and
+    /// timeouts apart.
+    async fn probe_node(self: Arc<Self>, addr: Url, p2p: P2pPtr) -> ProbeOutcome {
         let self_ = Arc::downgrade(&self);
         let connector = Connector::new(self.p2p().settings(), self_);
+        let handshake_timeout =
+            self.p2p().settings().read().await.greylist_refinery_handshake_timeout;
+        let start = Instant::now();
 
         debug!(target: "net::refinery::handshake_node()", "Attempting to connect to {}", addr);
         match connector.connect(&addr).await {
@@ -136,7 +209,7 @@ impl RefineSession {
                 // Ensure the channel gets stopped by adding a timeout to the handshake. Otherwise if
                 // the handshake does not finish channel.stop() will never get called, resulting in
                 // zombie processes.
-                let timeout = Timer::after(Duration::from_secs(5));
+                let timeout = Timer::after(Duration::from_secs(handshake_timeout));
 
                 pin_mut!(timeout);
                 pin_mut!(handshake);
@@ -144,15 +217,17 @@ impl RefineSession {
                 let result = match select(handshake, timeout).await {
                     Either::Left((Ok(_), _)) => {
                         debug!(target: "net::refinery::handshake_node()", "Handshake success!");
-                        true
+                        let elapsed = start.elapsed();
+                        p2p.hosts().record_latency(&addr, elapsed);
+                        ProbeOutcome::Success(elapsed)
                     }
                     Either::Left((Err(e), _)) => {
                         debug!(target: "net::refinery::handshake_node()", "Handshake error={}", e);
-                        false
+                        ProbeOutcome::Failure
                     }
                     Either::Right((_, _)) => {
                         debug!(target: "net::refinery::handshake_node()", "Handshake timed out");
-                        false
+                        ProbeOutcome::Timeout
                     }
                 };
 
@@ -164,7 +239,10 @@ impl RefineSession {
 
             Err(e) => {
                 debug!(target: "net::refinery::handshake_node()", "Failed to connect to {}, ({})", addr, e);
-                false
+                match e {
+                    Error::ConnectTimeout | Error::Io(ErrorKind::TimedOut) => ProbeOutcome::Timeout,
+                    _ => ProbeOutcome::Failure,
+                }
             }
         }
     }
@@ -183,9 +261,10 @@ impl Session for RefineSession {
 
 /// Periodically probes entries in the greylist.
 ///
-/// Randomly selects a greylist entry and tries to establish a local
-/// connection to it using the method handshake_node(), which creates a
-/// channel and does a version exchange using `perform_handshake_protocols()`.
+/// Randomly selects up to `greylist_refinery_concurrency` greylist entries
+/// and simultaneously tries to establish a local connection to each of them
+/// using the method handshake_node(), which creates a channel and does a
+/// version exchange using `perform_handshake_protocols()`.
 ///
 /// If successful, the entry is removed from the greylist and added to the
 /// whitelist with an updated last_seen timestamp. If non-successful, the
@@ -228,12 +307,29 @@ impl GreylistRefinery {
             // Acquire read lock on P2P settings and load necessary settings
             let settings = self.p2p().settings().read_arc().await;
             let greylist_refinery_interval = settings.greylist_refinery_interval;
+            let greylist_refinery_concurrency = settings.greylist_refinery_concurrency.max(1);
             let time_with_no_connections = settings.time_with_no_connections;
             let allowed_transports = settings.allowed_transports.clone();
+            let low_data_mode = settings.low_data_mode;
+            let low_data_refinery_hours = settings.low_data_refinery_hours.clone();
             drop(settings);
 
             sleep(greylist_refinery_interval).await;
 
+            hosts.refresh_bans().await;
+            hosts.check_diversity().await;
+
+            // In low-data mode, only refine during the configured hours
+            if low_data_mode && !in_hours(&low_data_refinery_hours) {
+                debug!(target: "net::refinery", "Outside of low-data refinery hours, skipping");
+                continue
+            }
+
+            if self.p2p().data_cap_reached().await {
+                debug!(target: "net::refinery", "Daily data cap reached, skipping");
+                continue
+            }
+
             if hosts.container.is_empty(HostColor::Grey) {
                 debug!(target: "net::refinery",
                 "Greylist is empty! Cannot start refinery process");
@@ -265,47 +361,62 @@ impl GreylistRefinery {
             }
 
             // Only attempt to refine peers that match our transports.
-            match hosts.container.fetch_random_with_schemes(HostColor::Grey, &allowed_transports) {
-                Some((entry, _)) => {
-                    let url = &entry.0;
-
-                    if let Err(e) = hosts.try_register(url.clone(), HostState::Refine) {
-                        debug!(target: "net::refinery", "Unable to refine addr={}, err={}",
-                               url.clone(), e);
-                        continue
-                    }
+            let entries = hosts.container.fetch_n_random_with_schemes(
+                HostColor::Grey,
+                &allowed_transports,
+                greylist_refinery_concurrency as u32,
+            );
 
-                    if !self.session().handshake_node(url.clone(), self.p2p().clone()).await {
-                        hosts.container.remove_if_exists(HostColor::Grey, url);
+            if entries.is_empty() {
+                debug!(target: "net::refinery", "No matching greylist entries found. Cannot proceed with refinery");
 
-                        debug!(
-                            target: "net::refinery",
-                            "Peer {} handshake failed. Removed from greylist", url,
-                        );
+                continue
+            }
 
-                        // Free up this addr for future operations.
-                        hosts.unregister(url);
+            let mut probes = FuturesUnordered::new();
+            for (url, _) in entries {
+                if let Err(e) = hosts.try_register(url.clone(), HostState::Refine) {
+                    debug!(target: "net::refinery", "Unable to refine addr={}, err={}",
+                           url.clone(), e);
+                    continue
+                }
+
+                let session = self.session();
+                let p2p = self.p2p();
+                probes.push(async move {
+                    let outcome = session.probe_node(url.clone(), p2p).await;
+                    (url, outcome)
+                });
+            }
+
+            // Process the probes as they complete
+            while let Some((url, outcome)) = probes.next().await {
+                self.session().stats.lock().unwrap().record(url.scheme(), outcome);
+
+                if !matches!(outcome, ProbeOutcome::Success(_)) {
+                    hosts.container.remove_if_exists(HostColor::Grey, &url);
+                    hosts.container.remove_metadata(&url);
 
-                        continue
-                    }
                     debug!(
                         target: "net::refinery",
-                        "Peer {} handshake successful. Adding to whitelist", url,
+                        "Peer {} handshake failed. Removed from greylist", url,
                     );
-                    let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
-
-                    hosts.whitelist_host(url, last_seen).unwrap();
 
-                    debug!(target: "net::refinery", "GreylistRefinery complete!");
+                    // Free up this addr for future operations.
+                    hosts.unregister(&url);
 
                     continue
                 }
-                None => {
-                    debug!(target: "net::refinery", "No matching greylist entries found. Cannot proceed with refinery");
+                debug!(
+                    target: "net::refinery",
+                    "Peer {} handshake successful. Adding to whitelist", url,
+                );
+                let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
 
-                    continue
-                }
+                hosts.whitelist_host(&url, last_seen).unwrap();
             }
+
+            debug!(target: "net::refinery", "GreylistRefinery complete!");
         }
     }
 
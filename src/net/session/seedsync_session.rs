@@ -42,10 +42,17 @@
 //! task that runs the version exchange with the `perform_handshake_protocols()`
 //! function. This runs the version exchange protocol, stores the channel in the
 //! p2p list of channels, and subscribes to a stop signal.
-
-use std::sync::{
-    atomic::{AtomicBool, Ordering::SeqCst},
-    Arc, Weak,
+//!
+//! Before activating the slots, `p2p.seed()` also resolves the configured DNS
+//! seeds. The addresses they point to are greylisted without connecting to
+//! them, so the refinery verifies them like any other discovered address.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc, Weak,
+    },
+    time::UNIX_EPOCH,
 };
 
 use async_trait::async_trait;
@@ -57,7 +64,7 @@ use url::Url;
 use super::{
     super::{
         connector::Connector,
-        hosts::HostColor,
+        hosts::{HostColor, HostOrigin},
         p2p::{P2p, P2pPtr},
         settings::Settings,
     },
@@ -114,6 +121,52 @@ impl SeedSyncSession {
         }
     }
 
+    /// Resolve the configured DNS seeds and greylist the addresses they
+    /// point to, using the transport and port of their seed. Resolutions
+    /// are not cached, so rotated records get picked up on the next call.
+    pub(crate) async fn seed_dns(&self) {
+        let dns_seeds = self.p2p().settings().read().await.dns_seeds.clone();
+        if dns_seeds.is_empty() {
+            return
+        }
+
+        let last_seen = UNIX_EPOCH.elapsed().unwrap().as_secs();
+        let mut addrs = vec![];
+        for seed in dns_seeds {
+            let (Some(host), Some(port)) = (seed.host_str(), seed.port()) else {
+                warn!(
+                    target: "net::seedsync_session::seed_dns()",
+                    "[P2P] Ignoring DNS seed {} without a host and port", seed,
+                );
+                continue
+            };
+
+            let resolved = match self.p2p().resolver().resolve(host, port, 0, false).await {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    warn!(
+                        target: "net::seedsync_session::seed_dns()",
+                        "[P2P] Failed resolving DNS seed {}: {}", seed, e,
+                    );
+                    continue
+                }
+            };
+
+            for sockaddr in resolved {
+                let mut addr = seed.clone();
+                if addr.set_ip_host(sockaddr.ip()).is_ok() {
+                    addrs.push((addr, last_seen));
+                }
+            }
+        }
+
+        info!(
+            target: "net::seedsync_session::seed_dns()",
+            "[P2P] DNS seeds announced {} peers", addrs.len(),
+        );
+        self.p2p().hosts().insert(HostColor::Grey, &addrs, HostOrigin::Seed).await;
+    }
+
     /// Stop the seedsync session.
     pub(crate) async fn stop(&self) {
         debug!(target: "net::seedsync_session", "Stopping seed sync session...");
@@ -55,8 +55,20 @@ pub struct Settings {
     /// Seed nodes to connect to for peer discovery and/or adversising our
     /// own external addresses
     pub seeds: Vec<Url>,
+    /// DNS seeds resolved to peer addresses during peer discovery, which
+    /// get greylisted for the refinery to verify. The hostname of each
+    /// seed resolves to the peers, which are assumed to listen on the
+    /// seed's transport and port, e.g. `tcp+tls://seed.dark.fi:26661`.
+    pub dns_seeds: Vec<Url>,
     /// Application version, used for convenient protocol matching
     pub app_version: semver::Version,
+    /// Features of the application protocols, advertised in the version
+    /// handshake as (feature, version) next to the built-in ones. Peers
+    /// check them with [`Channel::has_feature()`] to enable protocol
+    /// extensions only with nodes supporting them.
+    ///
+    /// [`Channel::has_feature()`]: crate::net::channel::Channel::has_feature
+    pub app_features: Vec<(String, u32)>,
     /// Whitelisted network transports for outbound connections
     pub allowed_transports: Vec<String>,
     /// Allow transport mixing (e.g. Tor would be allowed to connect to `tcp://`)
@@ -64,15 +76,26 @@ pub struct Settings {
     /// Outbound connection slots number, this many connections will be
     /// attempted. (This does not include manual connections)
     pub outbound_connections: usize,
+    /// Outbound slots reserved to given transports, as (transport, slots).
+    /// Reserved slots only connect through their transport, the remaining
+    /// ones through any of the allowed transports. Transports missing from
+    /// `allowed_transports` are ignored.
+    pub outbound_transport_quotas: Vec<(String, usize)>,
     /// Inbound connection slots number, this many active listening connections
     /// will be allowed. (This does not include manual connections)
     pub inbound_connections: usize,
     /// Outbound connection timeout (in seconds)
     pub outbound_connect_timeout: u64,
+    /// Time (in seconds) after which an outbound slot still connecting
+    /// is considered stalled, and gets restarted. 0 disables the check.
+    pub outbound_slot_stall_timeout: u64,
     /// Exchange versions (handshake) timeout (in seconds)
     pub channel_handshake_timeout: u64,
     /// Ping-pong exchange execution interval (in seconds)
     pub channel_heartbeat_interval: u64,
+    /// Time (in seconds) to wait for a pong before the peer is considered
+    /// dead and the channel gets stopped
+    pub channel_heartbeat_timeout: u64,
     /// Allow localnet hosts
     pub localnet: bool,
     /// Cooling off time for peer discovery when unsuccessful
@@ -85,10 +108,20 @@ pub struct Settings {
     pub hostlist: Option<String>,
     /// Pause interval within greylist refinery process
     pub greylist_refinery_interval: u64,
+    /// Number of greylist entries probed simultaneously by the refinery
+    /// on each interval
+    pub greylist_refinery_concurrency: usize,
+    /// Handshake timeout (in seconds) of greylist refinery probes
+    pub greylist_refinery_handshake_timeout: u64,
     /// Percent of connections to come from the whitelist
     pub white_connect_percent: usize,
     /// Number of goldlist connections
     pub gold_connect_count: usize,
+    /// Number of outbound peers saved on shutdown and reconnected to
+    /// first on startup, 0 to disable
+    pub anchor_connect_count: usize,
+    /// Maximum age (in seconds) of the anchors reconnected to on startup
+    pub anchor_max_age: u64,
     /// If this is true, strictly follow the gold_connect_count and
     /// white_connect_percent settings. Otherwise, connect to greylist
     /// entries if we have no white or gold connections.
@@ -104,6 +137,96 @@ pub struct Settings {
     /// Do not ban nodes that send messages without dispatchers if set
     /// to `Relaxed`. For most uses, should be set to `Strict`.
     pub ban_policy: BanPolicy,
+    /// Run as a private network. Only peers matching the allowlist are
+    /// accepted or dialed, and address gossip, seeding, outbound peer
+    /// discovery and the greylist refinery are all disabled.
+    pub private_network: bool,
+    /// Addresses allowed to connect to us, or be connected to, when
+    /// running as a private network. If the port is omitted, all ports
+    /// from the given host are allowed. Ports are only checked when
+    /// dialing, as inbound peers connect from ephemeral ports. Configured
+    /// `peers` are always allowed.
+    pub allowlist: Vec<Url>,
+    /// Peer IDs allowed to complete a version handshake when running as
+    /// a private network. Peers prove theirs over encrypted channels, so
+    /// setting any refuses cleartext peers. If empty, peer IDs are not
+    /// checked.
+    pub allowlist_peer_ids: Vec<String>,
+    /// Low-data mode for metered connections. Reduces outbound slots,
+    /// stretches the heartbeat interval and only runs the greylist
+    /// refinery during `low_data_refinery_hours`.
+    pub low_data_mode: bool,
+    /// Daily data cap in megabytes, 0 for unlimited. Once reached, no new
+    /// connections are made or accepted until the next UTC day.
+    pub daily_data_cap: u64,
+    /// UTC hours of the day (0-23) during which the refinery is allowed
+    /// to run in low-data mode
+    pub low_data_refinery_hours: Vec<u32>,
+    /// Maximum bytes per second received from a single peer, 0 for
+    /// unlimited. Reads from peers exceeding it are slowed down.
+    pub channel_recv_rate_limit: u64,
+    /// Maximum bytes per second sent to a single peer, 0 for unlimited
+    pub channel_send_rate_limit: u64,
+    /// Misbehavior score at which a peer gets banned
+    pub ban_score_threshold: u64,
+    /// Time (in seconds) it takes for a peer's misbehavior score to halve
+    pub ban_score_half_life: u64,
+    /// Duration (in seconds) of bans caused by misbehavior
+    pub ban_duration: u64,
+    /// Map the inbound ports on the local NAT gateway using NAT-PMP or
+    /// UPnP IGD, and advertise the discovered external addresses
+    pub port_mapping: bool,
+    /// Lease lifetime (in seconds) requested for port mappings. Leases
+    /// are renewed halfway through.
+    pub port_mapping_lifetime: u64,
+    /// SOCKS5 proxy used by the `socks5` and `socks5+tls` transports,
+    /// e.g. a local Tor daemon. Defaults to `socks5://127.0.0.1:9050`.
+    pub socks5_proxy: Option<Url>,
+    /// Compress message payloads sent to peers supporting it. Support is
+    /// advertised to peers during the version exchange.
+    pub channel_compression: bool,
+    /// Minimum payload size (in bytes) for a message to be compressed
+    pub channel_compression_threshold: usize,
+    /// Warn when outbound peers span fewer subnets (/16 for IPv4, /32
+    /// for IPv6) than this
+    pub outbound_min_subnets: usize,
+    /// Warn when more than this percentage of outbound peers share a
+    /// single subnet
+    pub outbound_max_subnet_share: usize,
+    /// Outbound peers in a single subnet (/16 for IPv4, /32 for IPv6)
+    /// after which addresses in other subnets are preferred. Addresses in
+    /// crowded subnets are only connected to when there's nothing else.
+    /// 0 disables the preference.
+    pub outbound_max_per_subnet: usize,
+    /// Announce our TCP inbound addresses on the local network, and add
+    /// the nodes announcing themselves to the greylist
+    pub lan_discovery: bool,
+    /// UDP port LAN announcements are broadcast on. Nodes of the same
+    /// network must use the same port.
+    pub lan_discovery_port: u16,
+    /// Interval (in seconds) between LAN announcements
+    pub lan_discovery_interval: u64,
+    /// Number the cleartext messages sent to peers supporting it, and
+    /// disconnect peers skipping or reordering theirs. This catches
+    /// faulty peers, not attackers, who can rewrite the numbers: only
+    /// encrypted channels are protected against replays. Support is
+    /// advertised to peers during the version exchange.
+    pub channel_sequencing: bool,
+    /// Encrypt channels with peers supporting it, using keys agreed
+    /// during the version exchange. This protects plain transports like
+    /// tcp:// against eavesdropping and tampering. Peers supporting it
+    /// also prove their peer ID by signing the key exchange.
+    pub channel_encryption: bool,
+    /// Refuse peers not supporting channel encryption, and cleartext
+    /// messages after the version exchange. Implies `channel_encryption`.
+    pub channel_require_encryption: bool,
+    /// Time (in seconds) hostname resolutions of peer addresses are
+    /// cached for. Cached entries are also dropped when connecting to
+    /// them fails. 0 disables the cache.
+    pub dns_cache_ttl: u64,
+    /// Keep the first resolution of each peer hostname for the lifetime
+    /// of the node, ignoring `dns_cache_ttl` and connect failures
+    pub dns_pin_addresses: bool,
 }
 
 impl Default for Settings {
@@ -117,30 +240,86 @@ impl Default for Settings {
             external_addrs: vec![],
             peers: vec![],
             seeds: vec![],
+            dns_seeds: vec![],
             app_version,
+            app_features: vec![],
             allowed_transports: vec!["tcp+tls".to_string()],
             transport_mixing: true,
             outbound_connections: 8,
+            outbound_transport_quotas: vec![],
             inbound_connections: 8,
             outbound_connect_timeout: 15,
+            outbound_slot_stall_timeout: 120,
             channel_handshake_timeout: 10,
             channel_heartbeat_interval: 30,
+            channel_heartbeat_timeout: 15,
             localnet: false,
             outbound_peer_discovery_cooloff_time: 30,
             outbound_peer_discovery_attempt_time: 5,
             p2p_datastore: None,
             hostlist: None,
             greylist_refinery_interval: 15,
+            greylist_refinery_concurrency: 1,
+            greylist_refinery_handshake_timeout: 5,
             white_connect_percent: 70,
             gold_connect_count: 2,
+            anchor_connect_count: 2,
+            anchor_max_age: 259200,
             slot_preference_strict: false,
             time_with_no_connections: 30,
             blacklist: vec![],
             ban_policy: BanPolicy::Strict,
+            private_network: false,
+            allowlist: vec![],
+            allowlist_peer_ids: vec![],
+            low_data_mode: false,
+            daily_data_cap: 0,
+            low_data_refinery_hours: vec![],
+            channel_recv_rate_limit: 0,
+            channel_send_rate_limit: 0,
+            ban_score_threshold: 100,
+            ban_score_half_life: 3600,
+            ban_duration: 86400,
+            port_mapping: false,
+            port_mapping_lifetime: 3600,
+            socks5_proxy: None,
+            channel_compression: true,
+            channel_compression_threshold: 1024,
+            outbound_min_subnets: 2,
+            outbound_max_subnet_share: 50,
+            outbound_max_per_subnet: 2,
+            lan_discovery: false,
+            lan_discovery_port: 26659,
+            lan_discovery_interval: 30,
+            channel_sequencing: true,
+            channel_encryption: true,
+            channel_require_encryption: false,
+            dns_cache_ttl: 300,
+            dns_pin_addresses: false,
         }
     }
 }
 
+impl Settings {
+    /// Apply the settings of `new` that a running P2P instance picks up
+    /// without restarting: connection limits and timeouts, the blacklist,
+    /// data and rate limits, and the ban policy thresholds. Rate limits
+    /// only apply to channels created afterwards.
+    pub fn reload(&mut self, new: &Settings) {
+        self.inbound_connections = new.inbound_connections;
+        self.outbound_connect_timeout = new.outbound_connect_timeout;
+        self.channel_handshake_timeout = new.channel_handshake_timeout;
+        self.greylist_refinery_interval = new.greylist_refinery_interval;
+        self.blacklist = new.blacklist.clone();
+        self.daily_data_cap = new.daily_data_cap;
+        self.channel_recv_rate_limit = new.channel_recv_rate_limit;
+        self.channel_send_rate_limit = new.channel_send_rate_limit;
+        self.ban_score_threshold = new.ban_score_threshold;
+        self.ban_score_half_life = new.ban_score_half_life;
+        self.ban_duration = new.ban_duration;
+    }
+}
+
 // The following is used so we can have P2P settings configurable
 // from TOML files.
 
@@ -157,6 +336,11 @@ pub struct SettingsOpt {
     #[structopt(long = "outbound-slots")]
     pub outbound_connections: Option<usize>,
 
+    /// Outbound slots reserved to given transports, as (transport, slots)
+    #[serde(default)]
+    #[structopt(skip)]
+    pub outbound_transport_quotas: Vec<(String, usize)>,
+
     /// Inbound connection slots number
     #[structopt(long = "inbound-slots")]
     pub inbound_connections: Option<usize>,
@@ -179,10 +363,19 @@ pub struct SettingsOpt {
     #[structopt(long)]
     pub seeds: Vec<Url>,
 
+    /// DNS seeds resolved to peer addresses during peer discovery
+    #[serde(default)]
+    #[structopt(long = "dns-seed")]
+    pub dns_seeds: Vec<Url>,
+
     /// Connection establishment timeout in seconds
     #[structopt(skip)]
     pub outbound_connect_timeout: Option<u64>,
 
+    /// Seconds after which an outbound slot still connecting is restarted
+    #[structopt(skip)]
+    pub outbound_slot_stall_timeout: Option<u64>,
+
     /// Exchange versions (handshake) timeout in seconds
     #[structopt(skip)]
     pub channel_handshake_timeout: Option<u64>,
@@ -191,6 +384,10 @@ pub struct SettingsOpt {
     #[structopt(skip)]
     pub channel_heartbeat_interval: Option<u64>,
 
+    /// Seconds to wait for a pong before stopping the channel
+    #[structopt(skip)]
+    pub channel_heartbeat_timeout: Option<u64>,
+
     /// Only used for debugging. Compromises privacy when set.
     #[serde(default)]
     #[structopt(skip)]
@@ -234,6 +431,14 @@ pub struct SettingsOpt {
     #[structopt(skip)]
     pub greylist_refinery_interval: Option<u64>,
 
+    /// Number of greylist entries probed simultaneously by the refinery
+    #[structopt(skip)]
+    pub greylist_refinery_concurrency: Option<usize>,
+
+    /// Handshake timeout (in seconds) of greylist refinery probes
+    #[structopt(skip)]
+    pub greylist_refinery_handshake_timeout: Option<u64>,
+
     /// Number of whitelist connections
     #[structopt(skip)]
     pub white_connect_percent: Option<usize>,
@@ -242,6 +447,14 @@ pub struct SettingsOpt {
     #[structopt(skip)]
     pub gold_connect_count: Option<usize>,
 
+    /// Number of outbound peers saved on shutdown and reconnected to first
+    #[structopt(skip)]
+    pub anchor_connect_count: Option<usize>,
+
+    /// Maximum age (in seconds) of the anchors reconnected to on startup
+    #[structopt(skip)]
+    pub anchor_max_age: Option<u64>,
+
     /// Allow localnet hosts
     #[serde(default)]
     #[structopt(long)]
@@ -265,6 +478,128 @@ pub struct SettingsOpt {
     #[serde(default)]
     #[structopt(skip)]
     pub ban_policy: BanPolicy,
+
+    /// Run as a private network, only talking to allowlisted peers
+    #[serde(default)]
+    #[structopt(long)]
+    pub private_network: bool,
+
+    /// Addresses allowed to connect to us when running as a private
+    /// network. If the port is omitted, all ports are allowed. Ports are
+    /// only checked when dialing.
+    #[serde(default)]
+    #[structopt(long)]
+    pub allowlist: Vec<Url>,
+
+    /// Peer IDs allowed to complete a version handshake when running
+    /// as a private network
+    #[serde(default)]
+    #[structopt(skip)]
+    pub allowlist_peer_ids: Vec<String>,
+
+    /// Low-data mode for metered connections
+    #[serde(default)]
+    #[structopt(long)]
+    pub low_data_mode: bool,
+
+    /// Daily data cap in megabytes, 0 for unlimited
+    #[structopt(skip)]
+    pub daily_data_cap: Option<u64>,
+
+    /// UTC hours of the day (0-23) during which the refinery is
+    /// allowed to run in low-data mode
+    #[serde(default)]
+    #[structopt(skip)]
+    pub low_data_refinery_hours: Vec<u32>,
+
+    /// Maximum bytes per second received from a single peer, 0 for unlimited
+    #[structopt(long)]
+    pub channel_recv_rate_limit: Option<u64>,
+
+    /// Maximum bytes per second sent to a single peer, 0 for unlimited
+    #[structopt(long)]
+    pub channel_send_rate_limit: Option<u64>,
+
+    /// Misbehavior score at which a peer gets banned
+    #[structopt(long)]
+    pub ban_score_threshold: Option<u64>,
+
+    /// Time (in seconds) it takes for a peer's misbehavior score to halve
+    #[structopt(long)]
+    pub ban_score_half_life: Option<u64>,
+
+    /// Duration (in seconds) of bans caused by misbehavior
+    #[structopt(long)]
+    pub ban_duration: Option<u64>,
+
+    /// Map the inbound ports on the local NAT gateway
+    #[serde(default)]
+    #[structopt(long)]
+    pub port_mapping: bool,
+
+    /// Lease lifetime (in seconds) requested for port mappings
+    #[structopt(skip)]
+    pub port_mapping_lifetime: Option<u64>,
+
+    /// SOCKS5 proxy used by the socks5 transports
+    #[structopt(long)]
+    pub socks5_proxy: Option<Url>,
+
+    /// Compress message payloads sent to peers supporting it
+    #[structopt(long)]
+    pub channel_compression: Option<bool>,
+
+    /// Minimum payload size (in bytes) for a message to be compressed
+    #[structopt(skip)]
+    pub channel_compression_threshold: Option<usize>,
+
+    /// Warn when outbound peers span fewer subnets than this
+    #[structopt(skip)]
+    pub outbound_min_subnets: Option<usize>,
+
+    /// Warn when more than this percentage of outbound peers share a subnet
+    #[structopt(skip)]
+    pub outbound_max_subnet_share: Option<usize>,
+
+    /// Outbound peers in a single subnet after which addresses in other
+    /// subnets are preferred, 0 to disable
+    #[structopt(skip)]
+    pub outbound_max_per_subnet: Option<usize>,
+
+    /// Discover other nodes on the local network
+    #[serde(default)]
+    #[structopt(long)]
+    pub lan_discovery: bool,
+
+    /// UDP port LAN announcements are broadcast on
+    #[structopt(skip)]
+    pub lan_discovery_port: Option<u16>,
+
+    /// Interval (in seconds) between LAN announcements
+    #[structopt(skip)]
+    pub lan_discovery_interval: Option<u64>,
+
+    /// Number cleartext messages sent to peers supporting it
+    #[structopt(long)]
+    pub channel_sequencing: Option<bool>,
+
+    /// Encrypt channels with peers supporting it
+    #[structopt(long)]
+    pub channel_encryption: Option<bool>,
+
+    /// Refuse peers not supporting channel encryption
+    #[serde(default)]
+    #[structopt(long)]
+    pub channel_require_encryption: bool,
+
+    /// Seconds peer hostname resolutions are cached for, 0 to disable
+    #[structopt(skip)]
+    pub dns_cache_ttl: Option<u64>,
+
+    /// Keep the first resolution of each peer hostname
+    #[serde(default)]
+    #[structopt(long)]
+    pub dns_pin_addresses: bool,
 }
 
 impl From<SettingsOpt> for Settings {
@@ -277,20 +612,29 @@ impl From<SettingsOpt> for Settings {
             external_addrs: opt.external_addrs,
             peers: opt.peers,
             seeds: opt.seeds,
+            dns_seeds: opt.dns_seeds,
             app_version: def.app_version,
+            app_features: def.app_features,
             allowed_transports: opt.allowed_transports.unwrap_or(def.allowed_transports),
             transport_mixing: opt.transport_mixing.unwrap_or(def.transport_mixing),
             outbound_connections: opt.outbound_connections.unwrap_or(def.outbound_connections),
+            outbound_transport_quotas: opt.outbound_transport_quotas,
             inbound_connections: opt.inbound_connections.unwrap_or(def.inbound_connections),
             outbound_connect_timeout: opt
                 .outbound_connect_timeout
                 .unwrap_or(def.outbound_connect_timeout),
+            outbound_slot_stall_timeout: opt
+                .outbound_slot_stall_timeout
+                .unwrap_or(def.outbound_slot_stall_timeout),
             channel_handshake_timeout: opt
                 .channel_handshake_timeout
                 .unwrap_or(def.channel_handshake_timeout),
             channel_heartbeat_interval: opt
                 .channel_heartbeat_interval
                 .unwrap_or(def.channel_heartbeat_interval),
+            channel_heartbeat_timeout: opt
+                .channel_heartbeat_timeout
+                .unwrap_or(def.channel_heartbeat_timeout),
             localnet: opt.localnet,
             outbound_peer_discovery_cooloff_time: opt
                 .outbound_peer_discovery_cooloff_time
@@ -303,14 +647,61 @@ impl From<SettingsOpt> for Settings {
             greylist_refinery_interval: opt
                 .greylist_refinery_interval
                 .unwrap_or(def.greylist_refinery_interval),
+            greylist_refinery_concurrency: opt
+                .greylist_refinery_concurrency
+                .unwrap_or(def.greylist_refinery_concurrency),
+            greylist_refinery_handshake_timeout: opt
+                .greylist_refinery_handshake_timeout
+                .unwrap_or(def.greylist_refinery_handshake_timeout),
             white_connect_percent: opt.white_connect_percent.unwrap_or(def.white_connect_percent),
             gold_connect_count: opt.gold_connect_count.unwrap_or(def.gold_connect_count),
+            anchor_connect_count: opt.anchor_connect_count.unwrap_or(def.anchor_connect_count),
+            anchor_max_age: opt.anchor_max_age.unwrap_or(def.anchor_max_age),
             slot_preference_strict: opt.slot_preference_strict,
             time_with_no_connections: opt
                 .time_with_no_connections
                 .unwrap_or(def.time_with_no_connections),
             blacklist: opt.blacklist,
             ban_policy: opt.ban_policy,
+            private_network: opt.private_network,
+            allowlist: opt.allowlist,
+            allowlist_peer_ids: opt.allowlist_peer_ids,
+            low_data_mode: opt.low_data_mode,
+            daily_data_cap: opt.daily_data_cap.unwrap_or(def.daily_data_cap),
+            low_data_refinery_hours: opt.low_data_refinery_hours,
+            channel_recv_rate_limit: opt
+                .channel_recv_rate_limit
+                .unwrap_or(def.channel_recv_rate_limit),
+            channel_send_rate_limit: opt
+                .channel_send_rate_limit
+                .unwrap_or(def.channel_send_rate_limit),
+            ban_score_threshold: opt.ban_score_threshold.unwrap_or(def.ban_score_threshold),
+            ban_score_half_life: opt.ban_score_half_life.unwrap_or(def.ban_score_half_life),
+            ban_duration: opt.ban_duration.unwrap_or(def.ban_duration),
+            port_mapping: opt.port_mapping,
+            port_mapping_lifetime: opt.port_mapping_lifetime.unwrap_or(def.port_mapping_lifetime),
+            socks5_proxy: opt.socks5_proxy,
+            channel_compression: opt.channel_compression.unwrap_or(def.channel_compression),
+            channel_compression_threshold: opt
+                .channel_compression_threshold
+                .unwrap_or(def.channel_compression_threshold),
+            outbound_min_subnets: opt.outbound_min_subnets.unwrap_or(def.outbound_min_subnets),
+            outbound_max_subnet_share: opt
+                .outbound_max_subnet_share
+                .unwrap_or(def.outbound_max_subnet_share),
+            outbound_max_per_subnet: opt
+                .outbound_max_per_subnet
+                .unwrap_or(def.outbound_max_per_subnet),
+            lan_discovery: opt.lan_discovery,
+            lan_discovery_port: opt.lan_discovery_port.unwrap_or(def.lan_discovery_port),
+            lan_discovery_interval: opt
+                .lan_discovery_interval
+                .unwrap_or(def.lan_discovery_interval),
+            channel_sequencing: opt.channel_sequencing.unwrap_or(def.channel_sequencing),
+            channel_encryption: opt.channel_encryption.unwrap_or(def.channel_encryption),
+            channel_require_encryption: opt.channel_require_encryption,
+            dns_cache_ttl: opt.dns_cache_ttl.unwrap_or(def.dns_cache_ttl),
+            dns_pin_addresses: opt.dns_pin_addresses,
         }
     }
 }
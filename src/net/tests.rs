@@ -30,6 +30,9 @@ use crate::{
     system::sleep,
 };
 
+#[cfg(feature = "p2p-memory")]
+use crate::net::testutil::TestNet;
+
 // Number of nodes to spawn and number of peers each node connects to
 const N_NODES: usize = 5;
 const N_CONNS: usize = 4;
@@ -473,3 +476,166 @@ async fn p2p_test_real(ex: Arc<Executor<'static>>) {
         p2p.clone().stop().await;
     }
 }
+
+#[test]
+fn refinery_test() {
+    test_body!(refinery_test_real);
+}
+
+/// Settings of a node refining the hostlist stored at given path, without
+/// connecting to anyone on its own
+fn refinery_settings(hostlist: &str, time_with_no_connections: u64) -> Settings {
+    Settings {
+        localnet: true,
+        outbound_connections: 0,
+        inbound_connections: usize::MAX,
+        seeds: vec![],
+        peers: vec![],
+        allowed_transports: vec!["tcp".to_string()],
+        greylist_refinery_interval: 2,
+        greylist_refinery_concurrency: 4,
+        time_with_no_connections,
+        hostlist: Some(hostlist.to_string()),
+        node_id: "refinery".to_string(),
+        ..Default::default()
+    }
+}
+
+async fn refinery_test_real(ex: Arc<Executor<'static>>) {
+    // ============================================================
+    // 1. Spawn some live nodes, and pick some addresses nobody
+    //    listens on.
+    // ============================================================
+    let ports = get_unique_ports();
+    let mut live_addrs = vec![];
+    let mut live_instances = vec![];
+    for port in ports {
+        let addr = Url::parse(&format!("tcp://127.0.0.1:{}", port)).unwrap();
+        let settings = Settings {
+            localnet: true,
+            inbound_addrs: vec![addr.clone()],
+            outbound_connections: 0,
+            inbound_connections: usize::MAX,
+            seeds: vec![],
+            peers: vec![],
+            allowed_transports: vec!["tcp".to_string()],
+            node_id: port.to_string(),
+            ..Default::default()
+        };
+
+        let p2p = P2p::new(settings, ex.clone()).await.unwrap();
+        p2p.clone().start().await.unwrap();
+        live_addrs.push(addr);
+        live_instances.push(p2p);
+    }
+
+    let dead_addrs: Vec<Url> = (0..2)
+        .map(|_| Url::parse(&format!("tcp://127.0.0.1:{}", get_random_available_port())).unwrap())
+        .collect();
+
+    // The last live node is banned, and must stay so
+    let banned_addr = live_addrs.pop().unwrap();
+
+    // ============================================================
+    // 2. Pre-seed the refining node hostlist with the live and dead
+    //    addresses on the greylist, and the banned one.
+    // ============================================================
+    let hostlist = std::env::temp_dir().join(format!("darkfi_refinery_{}.tsv", std::process::id()));
+    let hostlist = hostlist.to_str().unwrap().to_string();
+    let mut tsv = String::new();
+    for addr in live_addrs.iter().chain(dead_addrs.iter()) {
+        tsv.push_str(&format!("grey\t{}\t0\n", addr));
+    }
+    let ban_until = std::time::UNIX_EPOCH.elapsed().unwrap().as_secs() + 3600;
+    tsv.push_str(&format!("banned\t{}\t{}\ttest\n", banned_addr, ban_until));
+    std::fs::write(&hostlist, tsv).unwrap();
+
+    let node = P2p::new(refinery_settings(&hostlist, 3600), ex.clone()).await.unwrap();
+    node.clone().start().await.unwrap();
+
+    info!("========================================================");
+    info!("Waiting for the refinery to go through the greylist...");
+    info!("========================================================");
+    for _ in 0..20 {
+        if node.hosts().container.is_empty(HostColor::Grey) {
+            break
+        }
+        sleep(1).await;
+    }
+
+    // ============================================================
+    // 3. Live nodes got promoted to the whitelist, while nodes
+    //    failing the handshake got evicted.
+    // ============================================================
+    let hosts = node.hosts();
+    let container = &hosts.container;
+    assert!(container.is_empty(HostColor::Grey));
+    for addr in &live_addrs {
+        assert!(container.contains(HostColor::White as usize, addr));
+    }
+    for addr in &dead_addrs {
+        assert!(!container.contains(HostColor::White as usize, addr));
+    }
+    assert!(container.contains(HostColor::Black as usize, &banned_addr));
+    assert!(!container.contains(HostColor::White as usize, &banned_addr));
+
+    // ============================================================
+    // 4. Restart the node. The hostlists are persisted, and with
+    //    no connections the refinery pauses, leaving the greylist
+    //    untouched.
+    // ============================================================
+    node.stop().await;
+
+    let node = P2p::new(refinery_settings(&hostlist, 0), ex.clone()).await.unwrap();
+    node.clone().start().await.unwrap();
+
+    let hosts = node.hosts();
+    let container = &hosts.container;
+    for addr in &live_addrs {
+        assert!(container.contains(HostColor::White as usize, addr));
+    }
+    assert!(container.contains(HostColor::Black as usize, &banned_addr));
+
+    container.hostlists[HostColor::Grey as usize].write().unwrap().push((dead_addrs[0].clone(), 0));
+    sleep(5).await;
+    assert!(container.contains(HostColor::Grey as usize, &dead_addrs[0]));
+
+    info!("========================================================");
+    info!("Refinery test successful! Shutting down...");
+    info!("========================================================");
+    node.stop().await;
+    for p2p in live_instances {
+        p2p.stop().await;
+    }
+    let _ = std::fs::remove_file(&hostlist);
+}
+
+#[cfg(feature = "p2p-memory")]
+#[test]
+fn testnet_test() {
+    test_body!(testnet_test_real);
+}
+
+#[cfg(feature = "p2p-memory")]
+async fn testnet_test_real(ex: Arc<Executor<'static>>) {
+    let template = Settings {
+        outbound_connections: 2,
+        outbound_connect_timeout: 2,
+        outbound_peer_discovery_cooloff_time: 2,
+        inbound_connections: usize::MAX,
+        greylist_refinery_interval: 2,
+        ..Default::default()
+    };
+
+    let net = TestNet::new(N_NODES * 2, 1, &template, ex).await.unwrap();
+    net.start().await.unwrap();
+
+    // Nodes only know the seed, and discover each other through it
+    let timeout = std::time::Duration::from_secs(30);
+    assert!(net.wait_for(timeout, |p2p| !p2p.hosts().peers().is_empty()).await);
+
+    info!("========================================================");
+    info!("Simulated network formed! Shutting down...");
+    info!("========================================================");
+    net.stop().await;
+}
@@ -0,0 +1,139 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! In-process P2P network simulator. Spawns any number of [`P2p`] nodes
+//! wired together over the memory transport, so behaviour spanning
+//! several nodes, like address propagation or the greylist refinery,
+//! can be tested within `cargo test` without opening ports or spawning
+//! daemons.
+//!
+//! The first nodes of a [`TestNet`] act as seeds for the rest, which
+//! discover each other through them, the same way a real network forms.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use url::Url;
+
+use super::{P2p, P2pPtr, Settings};
+use crate::{
+    system::{msleep, ExecutorPtr},
+    Error, Result,
+};
+
+/// First port handed out to simulated nodes
+const BASE_PORT: usize = 20000;
+
+/// Milliseconds between checks of a [`TestNet::wait_for()`] condition
+const POLL_INTERVAL: u64 = 100;
+
+/// Next free port, so networks spawned by concurrent tests don't collide
+static NEXT_PORT: AtomicUsize = AtomicUsize::new(BASE_PORT);
+
+/// A network of in-process P2P nodes
+pub struct TestNet {
+    /// Spawned nodes, the first `n_seeds` of them acting as seeds
+    pub nodes: Vec<P2pPtr>,
+    /// Addresses the nodes listen on, in the same order
+    pub addrs: Vec<Url>,
+    /// Number of seed nodes
+    pub n_seeds: usize,
+}
+
+impl TestNet {
+    /// Create `n_nodes` nodes from the settings template, the first
+    /// `n_seeds` of which act as seeds for the rest. The nodes aren't
+    /// started, so protocols can be registered on them beforehand.
+    pub async fn new(
+        n_nodes: usize,
+        n_seeds: usize,
+        template: &Settings,
+        ex: ExecutorPtr,
+    ) -> Result<Self> {
+        if n_seeds > n_nodes {
+            return Err(Error::Custom("More seed nodes than nodes requested".to_string()))
+        }
+
+        let base = NEXT_PORT.fetch_add(n_nodes, Ordering::Relaxed);
+        if base + n_nodes > u16::MAX as usize {
+            return Err(Error::Custom("Ran out of simulated node ports".to_string()))
+        }
+
+        let addrs: Vec<Url> = (base..base + n_nodes)
+            .map(|port| Url::parse(&format!("memory://127.0.0.1:{}", port)).unwrap())
+            .collect();
+
+        let mut nodes = Vec::with_capacity(n_nodes);
+        for (index, addr) in addrs.iter().enumerate() {
+            let seeds = if index < n_seeds { &[] } else { &addrs[..n_seeds] };
+            nodes.push(P2p::new(node_settings(template, addr, seeds), ex.clone()).await?);
+        }
+
+        Ok(Self { nodes, addrs, n_seeds })
+    }
+
+    /// Start all the nodes, seeds first
+    pub async fn start(&self) -> Result<()> {
+        for node in &self.nodes {
+            node.clone().start().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop all the nodes
+    pub async fn stop(&self) {
+        for node in &self.nodes {
+            node.stop().await;
+        }
+    }
+
+    /// Poll the given condition until it holds for every node, or the
+    /// timeout elapses. Returns whether it held.
+    pub async fn wait_for(&self, timeout: Duration, cond: impl Fn(&P2pPtr) -> bool) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.nodes.iter().all(&cond) {
+                return true
+            }
+
+            if Instant::now() >= deadline {
+                return false
+            }
+
+            msleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Settings of a simulated node listening on `addr`, built from the
+/// template. Nodes keep no state on disk, so runs are independent.
+pub fn node_settings(template: &Settings, addr: &Url, seeds: &[Url]) -> Settings {
+    let mut settings = template.clone();
+    settings.node_id = format!("testnet-{}", addr.port().unwrap());
+    settings.inbound_addrs = vec![addr.clone()];
+    settings.external_addrs = vec![addr.clone()];
+    settings.seeds = seeds.to_vec();
+    settings.allowed_transports = vec!["memory".to_string()];
+    settings.localnet = true;
+    settings.p2p_datastore = None;
+    settings.hostlist = None;
+    settings
+}
@@ -0,0 +1,136 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! In-process transport, connecting P2P instances running within the
+//! same process without opening any ports or socket files. Endpoints
+//! are `memory://host:port` URLs living in a process-wide registry,
+//! and each connection is backed by a socket pair.
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use async_trait::async_trait;
+use log::debug;
+use smol::{
+    channel::{Receiver, Sender},
+    net::unix::UnixStream,
+};
+use url::Url;
+
+use super::{PtListener, PtStream};
+
+/// Listening endpoints, mapped by their `host:port`
+type Registry = Mutex<HashMap<String, Sender<(UnixStream, Url)>>>;
+
+/// Process-wide registry of the listening endpoints
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Counter giving each dialed connection a unique peer address
+static DIAL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(Default::default)
+}
+
+fn registry_key(endpoint: &Url) -> String {
+    format!("{}:{}", endpoint.host_str().unwrap(), endpoint.port().unwrap())
+}
+
+/// Memory Dialer implementation
+#[derive(Debug, Clone)]
+pub struct MemoryDialer;
+
+impl MemoryDialer {
+    /// Instantiate a new [`MemoryDialer`] object
+    pub(crate) async fn new() -> io::Result<Self> {
+        Ok(Self {})
+    }
+
+    /// Internal dial function
+    pub(crate) async fn do_dial(&self, endpoint: &Url) -> io::Result<UnixStream> {
+        debug!(target: "net::memory::do_dial", "Dialing {} in-process...", endpoint);
+        let sender = registry().lock().unwrap().get(&registry_key(endpoint)).cloned();
+        let Some(sender) = sender else {
+            return Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+        };
+
+        let (stream, peer_stream) = UnixStream::pair()?;
+        let id = DIAL_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let peer_addr = Url::parse(&format!("memory://dialer-{}:1", id)).unwrap();
+        if sender.send((peer_stream, peer_addr)).await.is_err() {
+            return Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+        }
+
+        Ok(stream)
+    }
+}
+
+/// Memory Listener implementation
+#[derive(Debug, Clone)]
+pub struct MemoryListener;
+
+impl MemoryListener {
+    /// Instantiate a new [`MemoryListener`] object
+    pub(crate) async fn new() -> io::Result<Self> {
+        Ok(Self {})
+    }
+
+    /// Internal listen function, registering the endpoint until the
+    /// returned listener gets dropped.
+    pub(crate) async fn do_listen(&self, endpoint: &Url) -> io::Result<MemoryPtListener> {
+        let key = registry_key(endpoint);
+        let mut registry = registry().lock().unwrap();
+        if registry.get(&key).is_some_and(|sender| !sender.is_closed()) {
+            return Err(io::Error::from(io::ErrorKind::AddrInUse))
+        }
+
+        let (sender, receiver) = smol::channel::unbounded();
+        registry.insert(key.clone(), sender);
+        Ok(MemoryPtListener { key, receiver })
+    }
+}
+
+/// Listening in-process endpoint
+pub struct MemoryPtListener {
+    /// Registry key of the endpoint
+    key: String,
+    /// Incoming connections along with their peer address
+    receiver: Receiver<(UnixStream, Url)>,
+}
+
+impl Drop for MemoryPtListener {
+    fn drop(&mut self) {
+        registry().lock().unwrap().remove(&self.key);
+    }
+}
+
+#[async_trait]
+impl PtListener for MemoryPtListener {
+    async fn next(&self) -> io::Result<(Box<dyn PtStream>, Url)> {
+        match self.receiver.recv().await {
+            Ok((stream, url)) => Ok((Box::new(stream), url)),
+            Err(_) => Err(io::Error::from(io::ErrorKind::ConnectionAborted)),
+        }
+    }
+}
@@ -18,6 +18,7 @@
 
 use std::{
     io::{self, ErrorKind},
+    net::SocketAddr,
     time::Duration,
 };
 
@@ -41,10 +42,20 @@ pub(crate) mod tor;
 /// Nym transport
 pub(crate) mod nym;
 
+#[cfg(feature = "p2p-socks5")]
+/// SOCKS5 proxy transport
+pub(crate) mod socks5;
+#[cfg(feature = "p2p-socks5")]
+pub use socks5::DEFAULT_SOCKS5_PROXY;
+
 #[cfg(feature = "p2p-unix")]
 /// Unix socket transport
 pub(crate) mod unix;
 
+#[cfg(feature = "p2p-memory")]
+/// In-process memory transport
+pub(crate) mod memory;
+
 /// Dialer variants
 #[derive(Debug, Clone)]
 pub enum DialerVariant {
@@ -72,9 +83,21 @@ pub enum DialerVariant {
     /// Nym with TLS
     NymTls(nym::NymDialer),
 
+    #[cfg(feature = "p2p-socks5")]
+    /// SOCKS5 proxy
+    Socks5(socks5::Socks5Dialer),
+
+    #[cfg(feature = "p2p-socks5")]
+    /// SOCKS5 proxy with TLS
+    Socks5Tls(socks5::Socks5Dialer),
+
     #[cfg(feature = "p2p-unix")]
     /// Unix socket
     Unix(unix::UnixDialer),
+
+    #[cfg(feature = "p2p-memory")]
+    /// In-process memory
+    Memory(memory::MemoryDialer),
 }
 
 /// Listener variants
@@ -95,6 +118,10 @@ pub enum ListenerVariant {
     #[cfg(feature = "p2p-unix")]
     /// Unix socket
     Unix(unix::UnixListener),
+
+    #[cfg(feature = "p2p-memory")]
+    /// In-process memory
+    Memory(memory::MemoryListener),
 }
 
 /// A dialer that is able to transparently operate over arbitrary transports.
@@ -103,6 +130,9 @@ pub struct Dialer {
     endpoint: Url,
     /// The dialer variant (transport protocol)
     variant: DialerVariant,
+    /// Already resolved addresses of the endpoint, used instead of
+    /// resolving its hostname when dialing
+    resolved: Option<Vec<SocketAddr>>,
 }
 
 macro_rules! enforce_hostport {
@@ -135,7 +165,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tcp::TcpDialer::new(None).await?;
                 let variant = DialerVariant::Tcp(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, resolved: None })
             }
 
             #[cfg(feature = "p2p-tcp")]
@@ -144,7 +174,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tcp::TcpDialer::new(None).await?;
                 let variant = DialerVariant::TcpTls(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, resolved: None })
             }
 
             #[cfg(feature = "p2p-tor")]
@@ -153,7 +183,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tor::TorDialer::new(datastore).await?;
                 let variant = DialerVariant::Tor(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, resolved: None })
             }
 
             #[cfg(feature = "p2p-tor")]
@@ -162,7 +192,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = tor::TorDialer::new(datastore).await?;
                 let variant = DialerVariant::TorTls(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, resolved: None })
             }
 
             #[cfg(feature = "p2p-nym")]
@@ -171,7 +201,7 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = nym::NymDialer::new().await?;
                 let variant = DialerVariant::Nym(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, resolved: None })
             }
 
             #[cfg(feature = "p2p-nym")]
@@ -180,7 +210,25 @@ impl Dialer {
                 enforce_hostport!(endpoint);
                 let variant = nym::NymDialer::new().await?;
                 let variant = DialerVariant::NymTls(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, resolved: None })
+            }
+
+            #[cfg(feature = "p2p-socks5")]
+            "socks5" => {
+                // Build a SOCKS5 dialer using the default proxy
+                enforce_hostport!(endpoint);
+                let variant = socks5::Socks5Dialer::new(None).await?;
+                let variant = DialerVariant::Socks5(variant);
+                Ok(Self { endpoint, variant, resolved: None })
+            }
+
+            #[cfg(feature = "p2p-socks5")]
+            "socks5+tls" => {
+                // Build a SOCKS5 dialer wrapped with TLS using the default proxy
+                enforce_hostport!(endpoint);
+                let variant = socks5::Socks5Dialer::new(None).await?;
+                let variant = DialerVariant::Socks5Tls(variant);
+                Ok(Self { endpoint, variant, resolved: None })
             }
 
             #[cfg(feature = "p2p-unix")]
@@ -189,7 +237,16 @@ impl Dialer {
                 // Build a Unix socket dialer
                 let variant = unix::UnixDialer::new().await?;
                 let variant = DialerVariant::Unix(variant);
-                Ok(Self { endpoint, variant })
+                Ok(Self { endpoint, variant, resolved: None })
+            }
+
+            #[cfg(feature = "p2p-memory")]
+            "memory" => {
+                // Build an in-process memory dialer
+                enforce_hostport!(endpoint);
+                let variant = memory::MemoryDialer::new().await?;
+                let variant = DialerVariant::Memory(variant);
+                Ok(Self { endpoint, variant, resolved: None })
             }
 
             x => {
@@ -209,14 +266,14 @@ impl Dialer {
             #[cfg(feature = "p2p-tcp")]
             DialerVariant::Tcp(dialer) => {
                 // NOTE: sockaddr here is an array, can contain both ipv4 and ipv6
-                let sockaddr = self.endpoint.socket_addrs(|| None)?;
+                let sockaddr = self.socket_addrs()?;
                 let stream = dialer.do_dial(sockaddr[0], timeout).await?;
                 Ok(Box::new(stream))
             }
 
             #[cfg(feature = "p2p-tcp")]
             DialerVariant::TcpTls(dialer) => {
-                let sockaddr = self.endpoint.socket_addrs(|| None)?;
+                let sockaddr = self.socket_addrs()?;
                 let stream = dialer.do_dial(sockaddr[0], timeout).await?;
                 let tlsupgrade = tls::TlsUpgrade::new().await;
                 let stream = tlsupgrade.upgrade_dialer_tls(stream).await?;
@@ -251,6 +308,24 @@ impl Dialer {
                 todo!();
             }
 
+            #[cfg(feature = "p2p-socks5")]
+            DialerVariant::Socks5(dialer) => {
+                let host = self.endpoint.host().unwrap();
+                let port = self.endpoint.port().unwrap();
+                let stream = dialer.do_dial(host, port, timeout).await?;
+                Ok(Box::new(stream))
+            }
+
+            #[cfg(feature = "p2p-socks5")]
+            DialerVariant::Socks5Tls(dialer) => {
+                let host = self.endpoint.host().unwrap();
+                let port = self.endpoint.port().unwrap();
+                let stream = dialer.do_dial(host, port, timeout).await?;
+                let tlsupgrade = tls::TlsUpgrade::new().await;
+                let stream = tlsupgrade.upgrade_dialer_tls(stream).await?;
+                Ok(Box::new(stream))
+            }
+
             #[cfg(feature = "p2p-unix")]
             DialerVariant::Unix(dialer) => {
                 let path = match self.endpoint.to_file_path() {
@@ -261,20 +336,58 @@ impl Dialer {
                 Ok(Box::new(stream))
             }
 
+            #[cfg(feature = "p2p-memory")]
+            DialerVariant::Memory(dialer) => {
+                let stream = dialer.do_dial(&self.endpoint).await?;
+                Ok(Box::new(stream))
+            }
+
             #[cfg(not(any(
                 feature = "p2p-tcp",
                 feature = "p2p-tor",
                 feature = "p2p-nym",
-                feature = "p2p-unix"
+                feature = "p2p-unix",
+                feature = "p2p-memory"
             )))]
             _ => panic!("No compiled p2p transports!"),
         }
     }
 
+    /// Use the given SOCKS5 proxy instead of the default one. Does
+    /// nothing for dialers of other transports.
+    #[allow(unused_variables)]
+    pub async fn set_socks5_proxy(&mut self, proxy: Url) -> io::Result<()> {
+        match &mut self.variant {
+            #[cfg(feature = "p2p-socks5")]
+            DialerVariant::Socks5(dialer) | DialerVariant::Socks5Tls(dialer) => {
+                *dialer = socks5::Socks5Dialer::new(Some(proxy)).await?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Dial the given already resolved addresses instead of resolving
+    /// the endpoint hostname. Only used by the TCP-based variants.
+    pub fn set_resolved_addrs(&mut self, addrs: Vec<SocketAddr>) {
+        self.resolved = Some(addrs);
+    }
+
     /// Return a reference to the `Dialer` endpoint
     pub fn endpoint(&self) -> &Url {
         &self.endpoint
     }
+
+    /// Addresses to dial for the endpoint, resolving its hostname unless
+    /// they were already provided
+    #[cfg(feature = "p2p-tcp")]
+    fn socket_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        match self.resolved {
+            Some(ref addrs) => Ok(addrs.clone()),
+            None => self.endpoint.socket_addrs(|| None),
+        }
+    }
 }
 
 /// A listener that is able to transparently listen over arbitrary transports.
@@ -325,6 +438,15 @@ impl Listener {
                 Ok(Self { endpoint, variant })
             }
 
+            #[cfg(feature = "p2p-memory")]
+            "memory" => {
+                // Build an in-process memory listener
+                enforce_hostport!(endpoint);
+                let variant = memory::MemoryListener::new().await?;
+                let variant = ListenerVariant::Memory(variant);
+                Ok(Self { endpoint, variant })
+            }
+
             x => {
                 error!("[P2P] Requested unsupported transport: {}", x);
                 Err(io::Error::from_raw_os_error(libc::ENETUNREACH))
@@ -332,6 +454,17 @@ impl Listener {
         }
     }
 
+    /// Only make Unix sockets accessible by our own user, so filesystem
+    /// permissions act as access control. Other transports are unaffected.
+    #[cfg_attr(not(feature = "p2p-unix"), allow(unused_mut))]
+    pub fn owner_only(mut self) -> Self {
+        #[cfg(feature = "p2p-unix")]
+        if let ListenerVariant::Unix(ref mut listener) = self.variant {
+            listener.owner_only = true;
+        }
+        self
+    }
+
     /// Listen on an instantiated [`Listener`].
     /// This will open a socket and return the listener.
     pub async fn listen(&self) -> io::Result<Box<dyn PtListener>> {
@@ -369,7 +502,13 @@ impl Listener {
                 Ok(Box::new(l))
             }
 
-            #[cfg(not(any(feature = "p2p-tcp", feature = "p2p-unix")))]
+            #[cfg(feature = "p2p-memory")]
+            ListenerVariant::Memory(listener) => {
+                let l = listener.do_listen(&self.endpoint).await?;
+                Ok(Box::new(l))
+            }
+
+            #[cfg(not(any(feature = "p2p-tcp", feature = "p2p-unix", feature = "p2p-memory")))]
             _ => panic!("No compiled p2p transports!"),
         }
     }
@@ -398,7 +537,7 @@ impl PtStream for arti_client::DataStream {}
 #[cfg(feature = "p2p-tor")]
 impl PtStream for futures_rustls::TlsStream<arti_client::DataStream> {}
 
-#[cfg(feature = "p2p-unix")]
+#[cfg(any(feature = "p2p-unix", feature = "p2p-memory"))]
 impl PtStream for smol::net::unix::UnixStream {}
 
 /// Wrapper trait for async listeners
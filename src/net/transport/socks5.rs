@@ -0,0 +1,238 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{io, net::IpAddr, time::Duration};
+
+use log::debug;
+use smol::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use url::{Host, Url};
+
+use super::tcp::TcpDialer;
+use crate::system::io_timeout;
+
+/// Proxy used when none is configured, the default Tor SOCKS port
+pub const DEFAULT_SOCKS5_PROXY: &str = "socks5://127.0.0.1:9050";
+
+const SOCKS_VERSION: u8 = 5;
+const METHOD_NO_AUTH: u8 = 0;
+const METHOD_USERPASS: u8 = 2;
+const METHOD_UNACCEPTABLE: u8 = 0xff;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// SOCKS5 Dialer implementation. Connections are made through a SOCKS5
+/// proxy such as the one provided by a local Tor daemon. Hostnames are
+/// resolved by the proxy, so `.onion` addresses can be dialed as well.
+#[derive(Debug, Clone)]
+pub struct Socks5Dialer {
+    /// Proxy to connect through. Credentials given in the URL are used
+    /// for username/password authentication, which Tor uses for stream
+    /// isolation.
+    proxy: Url,
+}
+
+impl Socks5Dialer {
+    /// Instantiate a new [`Socks5Dialer`] using the given proxy, or the
+    /// default one if `None`.
+    pub(crate) async fn new(proxy: Option<Url>) -> io::Result<Self> {
+        let proxy = proxy.unwrap_or_else(|| Url::parse(DEFAULT_SOCKS5_PROXY).unwrap());
+        if proxy.host_str().is_none() || proxy.port().is_none() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid SOCKS5 proxy"))
+        }
+
+        Ok(Self { proxy })
+    }
+
+    /// Internal dial function
+    pub(crate) async fn do_dial(
+        &self,
+        host: Host<&str>,
+        port: u16,
+        timeout: Option<Duration>,
+    ) -> io::Result<TcpStream> {
+        debug!(
+            target: "net::socks5::do_dial",
+            "Dialing {}:{} through SOCKS5 proxy {}...", host, port, self.proxy,
+        );
+
+        let sockaddr = self.proxy.socket_addrs(|| None)?;
+        let tcp = TcpDialer::new(None).await?;
+        let mut stream = tcp.do_dial(sockaddr[0], timeout).await?;
+
+        let handshake = self.handshake(&mut stream, host, port);
+        match timeout {
+            Some(t) => io_timeout(t, handshake).await?,
+            None => handshake.await?,
+        }
+
+        Ok(stream)
+    }
+
+    /// Authenticate with the proxy and request a connection to the target
+    async fn handshake(
+        &self,
+        stream: &mut TcpStream,
+        host: Host<&str>,
+        port: u16,
+    ) -> io::Result<()> {
+        let username = self.proxy.username();
+        let password = self.proxy.password().unwrap_or_default();
+        let use_auth = !username.is_empty();
+
+        // Method negotiation
+        let greeting = if use_auth {
+            vec![SOCKS_VERSION, 2, METHOD_NO_AUTH, METHOD_USERPASS]
+        } else {
+            vec![SOCKS_VERSION, 1, METHOD_NO_AUTH]
+        };
+        stream.write_all(&greeting).await?;
+
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply).await?;
+        if reply[0] != SOCKS_VERSION {
+            return Err(socks_error("Invalid proxy reply version"))
+        }
+
+        match reply[1] {
+            METHOD_NO_AUTH => {}
+            METHOD_USERPASS if use_auth => {
+                // RFC 1929 username/password subnegotiation
+                if username.len() > 255 || password.len() > 255 {
+                    return Err(socks_error("Proxy credentials too long"))
+                }
+                let mut auth = vec![1, username.len() as u8];
+                auth.extend_from_slice(username.as_bytes());
+                auth.push(password.len() as u8);
+                auth.extend_from_slice(password.as_bytes());
+                stream.write_all(&auth).await?;
+
+                stream.read_exact(&mut reply).await?;
+                if reply[1] != 0 {
+                    return Err(socks_error("Proxy authentication failed"))
+                }
+            }
+            METHOD_UNACCEPTABLE => return Err(socks_error("No acceptable proxy auth method")),
+            _ => return Err(socks_error("Unsupported proxy auth method")),
+        }
+
+        // Connect request
+        stream.write_all(&connect_request(host, port)?).await?;
+
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header).await?;
+        if header[0] != SOCKS_VERSION {
+            return Err(socks_error("Invalid proxy reply version"))
+        }
+        if header[1] != 0 {
+            return Err(reply_error(header[1]))
+        }
+
+        // Skip over the bound address and port
+        let addr_len = match header[3] {
+            ATYP_IPV4 => 4,
+            ATYP_IPV6 => 16,
+            ATYP_DOMAIN => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len).await?;
+                len[0] as usize
+            }
+            _ => return Err(socks_error("Invalid proxy reply address type")),
+        };
+        let mut bound = vec![0u8; addr_len + 2];
+        stream.read_exact(&mut bound).await?;
+
+        Ok(())
+    }
+}
+
+/// Build a SOCKS5 CONNECT request for the given target
+fn connect_request(host: Host<&str>, port: u16) -> io::Result<Vec<u8>> {
+    let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0];
+
+    // Non-special URL schemes keep IP literals as opaque hostnames
+    let host = match host {
+        Host::Domain(domain) => match domain.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => Host::Ipv4(ip),
+            Ok(IpAddr::V6(ip)) => Host::Ipv6(ip),
+            Err(_) => Host::Domain(domain),
+        },
+        host => host,
+    };
+
+    match host {
+        Host::Ipv4(ip) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Host::Ipv6(ip) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Host::Domain(domain) => {
+            if domain.len() > 255 {
+                return Err(socks_error("Hostname too long"))
+            }
+            request.push(ATYP_DOMAIN);
+            request.push(domain.len() as u8);
+            request.extend_from_slice(domain.as_bytes());
+        }
+    }
+
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+}
+
+/// Map a SOCKS5 reply code to an I/O error
+fn reply_error(code: u8) -> io::Error {
+    match code {
+        2 => io::Error::new(io::ErrorKind::PermissionDenied, "Connection not allowed by proxy"),
+        5 => io::ErrorKind::ConnectionRefused.into(),
+        6 => io::ErrorKind::TimedOut.into(),
+        3 => socks_error("Proxy reports network unreachable"),
+        4 => socks_error("Proxy reports host unreachable"),
+        _ => socks_error(&format!("Proxy connect failed with reply code {code}")),
+    }
+}
+
+fn socks_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("SOCKS5: {msg}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_request() {
+        let request = connect_request(Host::Domain("dark.fi"), 26661).unwrap();
+        assert_eq!(request[..5], [5, 1, 0, ATYP_DOMAIN, 7]);
+        assert_eq!(&request[5..12], b"dark.fi");
+        assert_eq!(request[12..], [0x68, 0x25]);
+
+        let request = connect_request(Host::Ipv4([127, 0, 0, 1].into()), 80).unwrap();
+        assert_eq!(request, [5, 1, 0, ATYP_IPV4, 127, 0, 0, 1, 0, 80]);
+        assert_eq!(connect_request(Host::Domain("127.0.0.1"), 80).unwrap(), request);
+
+        assert!(connect_request(Host::Domain(&"a".repeat(256)), 80).is_err());
+    }
+}
@@ -35,10 +35,7 @@ use futures::{
     Stream,
 };
 use log::{debug, error, info, warn};
-use smol::{
-    lock::{Mutex, OnceCell},
-    Timer,
-};
+use smol::{lock::Mutex, Timer};
 use tor_cell::relaycell::msg::Connected;
 use tor_error::ErrorReport;
 use tor_hsservice::{HsNickname, RendRequest, RunningOnionService};
@@ -49,8 +46,36 @@ use url::Url;
 use super::{PtListener, PtStream};
 use crate::util::path::expand_path;
 
-/// A static for `TorClient` reusability
-static TOR_CLIENT: OnceCell<TorClient<PreferredRuntime>> = OnceCell::new();
+/// Bootstrapped `TorClient`s for reusability, one per datastore so P2P
+/// instances using different datastores don't share Tor state.
+static TOR_CLIENTS: Mutex<Vec<(Option<String>, TorClient<PreferredRuntime>)>> =
+    Mutex::new(Vec::new());
+
+/// Fetch the `TorClient` using the given datastore, bootstrapping it
+/// on first use.
+async fn tor_client(
+    datastore: &Option<String>,
+) -> Result<TorClient<PreferredRuntime>, arti_client::Error> {
+    let mut clients = TOR_CLIENTS.lock().await;
+    if let Some((_, client)) = clients.iter().find(|(d, _)| d == datastore) {
+        return Ok(client.clone())
+    }
+
+    debug!(target: "net::tor::tor_client", "Bootstrapping...");
+    let client = if let Some(datadir) = datastore {
+        let datadir = expand_path(datadir).unwrap();
+
+        let config =
+            TorClientConfigBuilder::from_directories(datadir.clone(), datadir).build().unwrap();
+
+        TorClient::create_bootstrapped(config).await?
+    } else {
+        TorClient::builder().create_bootstrapped().await?
+    };
+
+    clients.push((datastore.clone(), client.clone()));
+    Ok(client)
+}
 
 /// Tor Dialer implementation
 #[derive(Debug, Clone)]
@@ -73,25 +98,9 @@ impl TorDialer {
     ) -> io::Result<DataStream> {
         debug!(target: "net::tor::do_dial", "Dialing {}:{} with Tor...", host, port);
 
-        // Initialize or fetch the static TOR_CLIENT that should be reused in
-        // the Tor dialer
-        let client = match TOR_CLIENT
-            .get_or_try_init(|| async {
-                debug!(target: "net::tor::do_dial", "Bootstrapping...");
-                if let Some(datadir) = &self.datastore {
-                    let datadir = expand_path(datadir).unwrap();
-
-                    let config = TorClientConfigBuilder::from_directories(datadir.clone(), datadir)
-                        .build()
-                        .unwrap();
-
-                    TorClient::create_bootstrapped(config).await
-                } else {
-                    TorClient::builder().create_bootstrapped().await
-                }
-            })
-            .await
-        {
+        // Initialize or fetch the TorClient of our datastore that should be
+        // reused in the Tor dialer and listener
+        let client = match tor_client(&self.datastore).await {
             Ok(client) => client,
             Err(e) => {
                 warn!("{}", e.report());
@@ -165,25 +174,9 @@ impl TorListener {
 
     /// Internal listen function
     pub(crate) async fn do_listen(&self, port: u16) -> io::Result<TorListenerIntern> {
-        // Initialize or fetch the static TOR_CLIENT that should be reused in
-        // the Tor dialer
-        let client = match TOR_CLIENT
-            .get_or_try_init(|| async {
-                debug!(target: "net::tor::do_listen", "Bootstrapping...");
-                if let Some(datadir) = &self.datastore {
-                    let datadir = expand_path(datadir).unwrap();
-
-                    let config = TorClientConfigBuilder::from_directories(datadir.clone(), datadir)
-                        .build()
-                        .unwrap();
-
-                    TorClient::create_bootstrapped(config).await
-                } else {
-                    TorClient::builder().create_bootstrapped().await
-                }
-            })
-            .await
-        {
+        // Initialize or fetch the TorClient of our datastore that should be
+        // reused in the Tor dialer and listener
+        let client = match tor_client(&self.datastore).await {
             Ok(client) => client,
             Err(e) => {
                 warn!("{}", e.report());
@@ -54,20 +54,31 @@ impl UnixDialer {
 
 /// Unix Listener implementation
 #[derive(Debug, Clone)]
-pub struct UnixListener;
+pub struct UnixListener {
+    /// Only make the socket accessible by our own user
+    pub(crate) owner_only: bool,
+}
 
 impl UnixListener {
     /// Instantiate a new [`UnixListener`] object
     pub(crate) async fn new() -> io::Result<Self> {
-        Ok(Self {})
+        Ok(Self { owner_only: false })
     }
 
     /// Internal listen function
     pub(crate) async fn do_listen(&self, path: &PathBuf) -> io::Result<SmolUnixListener> {
         // This rm is a bit aggressive, but c'est la vie.
         let _ = fs::remove_file(path).await;
-        let listener = SmolUnixListener::bind(path)?;
-        Ok(listener)
+        if !self.owner_only {
+            return SmolUnixListener::bind(path)
+        }
+
+        // Create the socket file with owner-only permissions, so it is
+        // never accessible by others, not even right after the bind.
+        let umask = unsafe { libc::umask(0o177) };
+        let listener = SmolUnixListener::bind(path);
+        unsafe { libc::umask(umask) };
+        listener
     }
 }
 
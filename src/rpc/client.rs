@@ -26,6 +26,7 @@ use url::Url;
 use super::{
     common::{read_from_stream, write_to_stream, INIT_BUF_SIZE, READ_TIMEOUT},
     jsonrpc::*,
+    server::KEEPALIVE_METHOD,
 };
 use crate::{
     net::transport::{Dialer, PtStream},
@@ -44,13 +45,24 @@ pub struct RpcClient {
     req_skip_send: channel::Sender<()>,
     /// The stoppable task pointer, used on [`RpcClient::stop()`]
     task: StoppableTaskPtr,
+    /// Authentication token attached to outgoing requests
+    auth: Option<String>,
 }
 
 impl RpcClient {
     /// Instantiate a new JSON-RPC client that connects to the given endpoint.
     /// The function takes an `Executor` object, which is needed to start the
     /// `StoppableTask` which represents the client-server connection.
-    pub async fn new(endpoint: Url, ex: Arc<Executor<'_>>) -> Result<Self> {
+    /// An authentication token can be given as the endpoint username,
+    /// e.g. `tcp://token@127.0.0.1:8240`, and is then sent along with
+    /// every request.
+    pub async fn new(mut endpoint: Url, ex: Arc<Executor<'_>>) -> Result<Self> {
+        let auth = match endpoint.username() {
+            "" => None,
+            token => Some(token.to_string()),
+        };
+        let _ = endpoint.set_username("");
+
         // Instantiate communication channels
         let (req_send, req_recv) = channel::unbounded();
         let (rep_send, rep_recv) = channel::unbounded();
@@ -77,7 +89,7 @@ impl RpcClient {
             ex.clone(),
         );
 
-        Ok(Self { req_send, rep_recv, task, req_skip_send })
+        Ok(Self { req_send, rep_recv, task, req_skip_send, auth })
     }
 
     /// Stop the JSON-RPC client. This will trigger `stop()` on the inner
@@ -87,6 +99,15 @@ impl RpcClient {
         self.task.stop().await;
     }
 
+    /// Attach our authentication token to the request, if we have one
+    /// and the request doesn't carry its own.
+    fn authenticate(&self, req: JsonRequest) -> JsonRequest {
+        match (&self.auth, &req.auth) {
+            (Some(token), None) => req.with_auth(token),
+            _ => req,
+        }
+    }
+
     /// Internal function that loops on a given stream and multiplexes the data
     async fn reqrep_loop(
         stream: Box<dyn PtStream>,
@@ -135,8 +156,9 @@ impl RpcClient {
 
     /// Send a given JSON-RPC request over the instantiated client and
     /// return a possible result. If the response is an error, returns
-    /// a `JsonRpcError`.
+    /// a `JsonRpcError` carrying its code, message and data.
     pub async fn request(&self, req: JsonRequest) -> Result<JsonValue> {
+        let req = self.authenticate(req);
         let req_id = req.id;
         debug!(target: "rpc::client", "--> {}", req.stringify()?);
 
@@ -156,7 +178,7 @@ impl RpcClient {
                 // Check if the IDs match
                 if req_id != rep.id {
                     let e = JsonError::new(ErrorCode::IdMismatch, None, rep.id);
-                    return Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                    return Err(Error::JsonRpcError(e.error))
                 }
 
                 Ok(rep.result)
@@ -164,25 +186,25 @@ impl RpcClient {
 
             JsonResult::Error(e) => {
                 debug!(target: "rpc::client", "<-- {}", e.stringify()?);
-                Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                Err(Error::JsonRpcError(e.error))
             }
 
             JsonResult::Notification(n) => {
                 debug!(target: "rpc::client", "<-- {}", n.stringify()?);
                 let e = JsonError::new(ErrorCode::InvalidReply, None, req_id);
-                Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                Err(Error::JsonRpcError(e.error))
             }
 
             JsonResult::Request(r) => {
                 debug!(target: "rpc::client", "<-- {}", r.stringify()?);
                 let e = JsonError::new(ErrorCode::InvalidReply, None, req_id);
-                Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                Err(Error::JsonRpcError(e.error))
             }
 
             JsonResult::Subscriber(_) => {
                 // When?
                 let e = JsonError::new(ErrorCode::InvalidReply, None, req_id);
-                Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                Err(Error::JsonRpcError(e.error))
             }
         }
     }
@@ -210,6 +232,7 @@ impl RpcClient {
         publisher: PublisherPtr<JsonResult>,
     ) -> Result<()> {
         // Perform initial request
+        let req = self.authenticate(req);
         debug!(target: "rpc::client", "--> {}", req.stringify()?);
         let req_id = req.id;
 
@@ -228,31 +251,34 @@ impl RpcClient {
                 JsonResult::Notification(ref n) => {
                     debug!(target: "rpc::client", "<-- {}", n.stringify()?);
                     self.req_skip_send.send(()).await?;
-                    publisher.notify(notification.clone()).await;
+                    // Server keepalives are not meant for the subscriber
+                    if n.method != KEEPALIVE_METHOD {
+                        publisher.notify(notification.clone()).await;
+                    }
                     continue
                 }
 
                 JsonResult::Error(e) => {
                     debug!(target: "rpc::client", "<-- {}", e.stringify()?);
-                    return Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                    return Err(Error::JsonRpcError(e.error))
                 }
 
                 JsonResult::Response(r) | JsonResult::SubscriberWithReply(_, r) => {
                     debug!(target: "rpc::client", "<-- {}", r.stringify()?);
                     let e = JsonError::new(ErrorCode::InvalidReply, None, req_id);
-                    return Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                    return Err(Error::JsonRpcError(e.error))
                 }
 
                 JsonResult::Request(r) => {
                     debug!(target: "rpc::client", "<-- {}", r.stringify()?);
                     let e = JsonError::new(ErrorCode::InvalidReply, None, req_id);
-                    return Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                    return Err(Error::JsonRpcError(e.error))
                 }
 
                 JsonResult::Subscriber(_) => {
                     // When?
                     let e = JsonError::new(ErrorCode::InvalidReply, None, req_id);
-                    return Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                    return Err(Error::JsonRpcError(e.error))
                 }
             }
         }
@@ -347,7 +373,7 @@ impl RpcChadClient {
 
     /// Send a given JSON-RPC request over the instantiated client and
     /// return a possible result. If the response is an error, returns
-    /// a `JsonRpcError`.
+    /// a `JsonRpcError` carrying its code, message and data.
     pub async fn request(&self, req: JsonRequest) -> Result<JsonValue> {
         // Perform request
         let req_id = req.id;
@@ -386,25 +412,25 @@ impl RpcChadClient {
                         continue
                     }
 
-                    return Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                    return Err(Error::JsonRpcError(e.error))
                 }
 
                 JsonResult::Notification(n) => {
                     debug!(target: "rpc::chad_client", "<-- {}", n.stringify()?);
                     let e = JsonError::new(ErrorCode::InvalidReply, None, req_id);
-                    return Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                    return Err(Error::JsonRpcError(e.error))
                 }
 
                 JsonResult::Request(r) => {
                     debug!(target: "rpc::chad_client", "<-- {}", r.stringify()?);
                     let e = JsonError::new(ErrorCode::InvalidReply, None, req_id);
-                    return Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                    return Err(Error::JsonRpcError(e.error))
                 }
 
                 JsonResult::Subscriber(_) => {
                     // When?
                     let e = JsonError::new(ErrorCode::InvalidReply, None, req_id);
-                    return Err(Error::JsonRpcError((e.error.code, e.error.message)))
+                    return Err(Error::JsonRpcError(e.error))
                 }
             }
         }
@@ -85,6 +85,13 @@ impl From<net::dnet::OutboundSlotDisconnected> for JsonValue {
     }
 }
 
+#[cfg(feature = "net")]
+impl From<net::dnet::OutboundSlotStalled> for JsonValue {
+    fn from(info: net::dnet::OutboundSlotStalled) -> JsonValue {
+        json_map([("slot", JsonNum(info.slot.into())), ("addr", JsonStr(info.addr.to_string()))])
+    }
+}
+
 #[cfg(feature = "net")]
 impl From<net::dnet::OutboundPeerDiscovery> for JsonValue {
     fn from(info: net::dnet::OutboundPeerDiscovery) -> JsonValue {
@@ -95,6 +102,35 @@ impl From<net::dnet::OutboundPeerDiscovery> for JsonValue {
     }
 }
 
+#[cfg(feature = "net")]
+impl From<net::metering::RefineryCounters> for JsonValue {
+    fn from(counters: net::metering::RefineryCounters) -> JsonValue {
+        let avg_handshake_ms = match counters.avg_handshake_ms() {
+            Some(ms) => JsonNum(ms as f64),
+            None => JsonValue::Null,
+        };
+
+        json_map([
+            ("probes", JsonNum(counters.probes as f64)),
+            ("successes", JsonNum(counters.successes as f64)),
+            ("failures", JsonNum(counters.failures as f64)),
+            ("timeouts", JsonNum(counters.timeouts as f64)),
+            ("avg_handshake_ms", avg_handshake_ms),
+        ])
+    }
+}
+
+#[cfg(feature = "net")]
+impl From<net::metering::RefineryStats> for JsonValue {
+    fn from(stats: net::metering::RefineryStats) -> JsonValue {
+        let total = stats.total().into();
+        let transports =
+            stats.transports.into_iter().map(|(transport, c)| (transport, c.into())).collect();
+
+        json_map([("total", total), ("transports", JsonObj(transports))])
+    }
+}
+
 #[cfg(feature = "net")]
 impl From<net::dnet::DnetEvent> for JsonValue {
     fn from(event: net::dnet::DnetEvent) -> JsonValue {
@@ -123,6 +159,9 @@ impl From<net::dnet::DnetEvent> for JsonValue {
             net::dnet::DnetEvent::OutboundSlotDisconnected(info) => {
                 json_map([("event", json_str("outbound_slot_disconnected")), ("info", info.into())])
             }
+            net::dnet::DnetEvent::OutboundSlotStalled(info) => {
+                json_map([("event", json_str("outbound_slot_stalled")), ("info", info.into())])
+            }
             net::dnet::DnetEvent::OutboundPeerDiscovery(info) => {
                 json_map([("event", json_str("outbound_peer_discovery")), ("info", info.into())])
             }
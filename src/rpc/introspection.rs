@@ -0,0 +1,555 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Typed schema of the `p2p.get_info` payload, shared between the node
+//! side handler and monitoring clients.
+//!
+//! Fields may be added to the payload without notice, and clients must
+//! ignore the ones they don't know. [`NODE_INFO_VERSION`] is bumped when
+//! existing fields are removed or change meaning.
+use std::collections::HashMap;
+
+use super::util::*;
+use crate::{
+    error::RpcError,
+    net::{hosts::ProtocolErrors, metering::CompressionStats},
+};
+
+/// Current version of the `p2p.get_info` payload schema
+pub const NODE_INFO_VERSION: u64 = 1;
+
+type ParseResult<T> = std::result::Result<T, RpcError>;
+
+/// Payload of `p2p.get_info`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeInfo {
+    /// Schema version the payload was built with
+    pub version: u64,
+    /// Peer ID of the node
+    pub peer_id: String,
+    /// Currently open channels
+    pub channels: Vec<ChannelEntry>,
+    /// Channel ID connected in each outbound slot, 0 for an empty slot
+    pub outbound_slots: Vec<u32>,
+    /// Usage of the outbound slots reserved to transports
+    pub transport_quotas: Vec<TransportQuota>,
+    /// Number of duplicate channels refused
+    pub duplicate_channels: u64,
+    /// Data usage for the current day
+    pub data_usage: DataUsage,
+    /// Currently banned peers
+    pub bans: Vec<BanEntry>,
+    /// Reasons channels were stopped for, over all peers
+    pub stop_reasons: StopReasons,
+    /// Compression counters, over all channels
+    pub compression: CompressionStats,
+    /// Address diversity of our hosts and peers
+    pub diversity: DiversityInfo,
+}
+
+/// An open channel
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelEntry {
+    /// Peer address
+    pub url: String,
+    /// Session the channel belongs to
+    pub session: String,
+    /// Channel ID
+    pub id: u32,
+    /// Peer ID proven by the peer over the encrypted channel, empty if none
+    pub peer_id: String,
+    /// Compression counters of the channel
+    pub compression: CompressionStats,
+    /// Protocol errors seen on channels with the peer
+    pub errors: ProtocolErrors,
+    /// Average round-trip time of the last pings answered by the peer,
+    /// in milliseconds
+    pub ping_ms: Option<u64>,
+}
+
+/// Outbound slots reserved to a transport
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransportQuota {
+    /// Transport scheme the slots are reserved to
+    pub transport: String,
+    /// Number of reserved slots
+    pub slots: u64,
+    /// Number of reserved slots currently connected
+    pub connected: u64,
+}
+
+/// Data usage for the current day
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DataUsage {
+    /// Bytes sent
+    pub sent: u64,
+    /// Bytes received
+    pub received: u64,
+    /// Configured daily cap in megabytes, 0 for unlimited
+    pub daily_data_cap: u64,
+}
+
+/// A banned peer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BanEntry {
+    /// Peer address
+    pub url: String,
+    /// UNIX timestamp at which the ban expires
+    pub until: u64,
+    /// Misbehavior that got the peer banned
+    pub reason: String,
+}
+
+/// Number of channels stopped per reason
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StopReasons {
+    pub remote_close: u64,
+    pub timeout: u64,
+    pub protocol_violation: u64,
+    pub local_shutdown: u64,
+}
+
+/// Address diversity of our hosts and peers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiversityInfo {
+    /// Diversity of the whitelisted hosts
+    pub whitelist: DiversitySummary,
+    /// Diversity of the connected peers
+    pub peers: DiversitySummary,
+    /// Diversity of the outbound peers
+    pub outbound: DiversitySummary,
+    /// Whether the outbound peers are below the diversity thresholds
+    pub skewed: bool,
+}
+
+/// Summary of a [`crate::net::hosts::HostDiversity`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiversitySummary {
+    /// Number of hosts per address family
+    pub families: HashMap<String, u64>,
+    /// Number of hosts per transport
+    pub transports: HashMap<String, u64>,
+    /// Number of distinct subnets
+    pub subnets: u64,
+    /// Percentage of the hosts sharing the most common subnet
+    pub largest_subnet_share: u64,
+}
+
+impl From<&crate::net::hosts::HostDiversity> for DiversitySummary {
+    fn from(diversity: &crate::net::hosts::HostDiversity) -> Self {
+        let counts = |map: &HashMap<String, usize>| {
+            map.iter().map(|(k, v)| (k.clone(), *v as u64)).collect()
+        };
+
+        Self {
+            families: counts(&diversity.families),
+            transports: counts(&diversity.transports),
+            subnets: diversity.subnets.len() as u64,
+            largest_subnet_share: diversity.largest_subnet_share() as u64,
+        }
+    }
+}
+
+impl From<NodeInfo> for JsonValue {
+    fn from(info: NodeInfo) -> JsonValue {
+        json_map([
+            ("version", JsonNum(info.version as f64)),
+            ("peer_id", JsonStr(info.peer_id)),
+            ("channels", JsonArray(info.channels.into_iter().map(|c| c.into()).collect())),
+            (
+                "outbound_slots",
+                JsonArray(info.outbound_slots.into_iter().map(|s| JsonNum(s.into())).collect()),
+            ),
+            (
+                "transport_quotas",
+                JsonArray(info.transport_quotas.into_iter().map(|q| q.into()).collect()),
+            ),
+            ("duplicate_channels", JsonNum(info.duplicate_channels as f64)),
+            ("data_usage", info.data_usage.into()),
+            ("bans", JsonArray(info.bans.into_iter().map(|b| b.into()).collect())),
+            ("stop_reasons", info.stop_reasons.into()),
+            ("compression", compression_json(&info.compression)),
+            ("diversity", info.diversity.into()),
+        ])
+    }
+}
+
+impl TryFrom<&JsonValue> for NodeInfo {
+    type Error = RpcError;
+
+    fn try_from(value: &JsonValue) -> ParseResult<Self> {
+        let map = object(value, "info")?;
+
+        let version = u64_field(map, "version")?;
+        if version > NODE_INFO_VERSION {
+            return Err(RpcError::InvalidJson(format!(
+                "Unsupported node info version {version}, expected at most {NODE_INFO_VERSION}"
+            )))
+        }
+
+        let mut outbound_slots = vec![];
+        for slot in array_field(map, "outbound_slots")? {
+            let Some(slot) = slot.get::<f64>() else { return Err(invalid("outbound_slots")) };
+            outbound_slots.push(*slot as u32);
+        }
+
+        Ok(Self {
+            version,
+            peer_id: str_field(map, "peer_id")?,
+            channels: array_field(map, "channels")?
+                .iter()
+                .map(ChannelEntry::try_from)
+                .collect::<ParseResult<_>>()?,
+            outbound_slots,
+            transport_quotas: array_field(map, "transport_quotas")?
+                .iter()
+                .map(TransportQuota::try_from)
+                .collect::<ParseResult<_>>()?,
+            duplicate_channels: u64_field(map, "duplicate_channels")?,
+            data_usage: field(map, "data_usage")?.try_into()?,
+            bans: array_field(map, "bans")?
+                .iter()
+                .map(BanEntry::try_from)
+                .collect::<ParseResult<_>>()?,
+            stop_reasons: field(map, "stop_reasons")?.try_into()?,
+            compression: parse_compression(field(map, "compression")?)?,
+            diversity: field(map, "diversity")?.try_into()?,
+        })
+    }
+}
+
+impl From<ChannelEntry> for JsonValue {
+    fn from(channel: ChannelEntry) -> JsonValue {
+        json_map([
+            ("url", JsonStr(channel.url)),
+            ("session", JsonStr(channel.session)),
+            ("id", JsonNum(channel.id.into())),
+            ("peer_id", JsonStr(channel.peer_id)),
+            ("compression", compression_json(&channel.compression)),
+            ("errors", errors_json(&channel.errors)),
+            ("ping_ms", channel.ping_ms.map_or(JsonValue::Null, |ms| JsonNum(ms as f64))),
+        ])
+    }
+}
+
+impl TryFrom<&JsonValue> for ChannelEntry {
+    type Error = RpcError;
+
+    fn try_from(value: &JsonValue) -> ParseResult<Self> {
+        let map = object(value, "channel")?;
+        Ok(Self {
+            url: str_field(map, "url")?,
+            session: str_field(map, "session")?,
+            id: u64_field(map, "id")? as u32,
+            peer_id: str_field(map, "peer_id")?,
+            compression: parse_compression(field(map, "compression")?)?,
+            errors: parse_errors(field(map, "errors")?)?,
+            ping_ms: match field(map, "ping_ms")? {
+                JsonValue::Null => None,
+                _ => Some(u64_field(map, "ping_ms")?),
+            },
+        })
+    }
+}
+
+impl From<TransportQuota> for JsonValue {
+    fn from(quota: TransportQuota) -> JsonValue {
+        json_map([
+            ("transport", JsonStr(quota.transport)),
+            ("slots", JsonNum(quota.slots as f64)),
+            ("connected", JsonNum(quota.connected as f64)),
+        ])
+    }
+}
+
+impl TryFrom<&JsonValue> for TransportQuota {
+    type Error = RpcError;
+
+    fn try_from(value: &JsonValue) -> ParseResult<Self> {
+        let map = object(value, "transport_quota")?;
+        Ok(Self {
+            transport: str_field(map, "transport")?,
+            slots: u64_field(map, "slots")?,
+            connected: u64_field(map, "connected")?,
+        })
+    }
+}
+
+impl From<DataUsage> for JsonValue {
+    fn from(usage: DataUsage) -> JsonValue {
+        json_map([
+            ("sent", JsonNum(usage.sent as f64)),
+            ("received", JsonNum(usage.received as f64)),
+            ("daily_data_cap", JsonNum(usage.daily_data_cap as f64)),
+        ])
+    }
+}
+
+impl TryFrom<&JsonValue> for DataUsage {
+    type Error = RpcError;
+
+    fn try_from(value: &JsonValue) -> ParseResult<Self> {
+        let map = object(value, "data_usage")?;
+        Ok(Self {
+            sent: u64_field(map, "sent")?,
+            received: u64_field(map, "received")?,
+            daily_data_cap: u64_field(map, "daily_data_cap")?,
+        })
+    }
+}
+
+impl From<BanEntry> for JsonValue {
+    fn from(ban: BanEntry) -> JsonValue {
+        json_map([
+            ("url", JsonStr(ban.url)),
+            ("until", JsonNum(ban.until as f64)),
+            ("reason", JsonStr(ban.reason)),
+        ])
+    }
+}
+
+impl TryFrom<&JsonValue> for BanEntry {
+    type Error = RpcError;
+
+    fn try_from(value: &JsonValue) -> ParseResult<Self> {
+        let map = object(value, "ban")?;
+        Ok(Self {
+            url: str_field(map, "url")?,
+            until: u64_field(map, "until")?,
+            reason: str_field(map, "reason")?,
+        })
+    }
+}
+
+impl From<StopReasons> for JsonValue {
+    fn from(stops: StopReasons) -> JsonValue {
+        json_map([
+            ("remote_close", JsonNum(stops.remote_close as f64)),
+            ("timeout", JsonNum(stops.timeout as f64)),
+            ("protocol_violation", JsonNum(stops.protocol_violation as f64)),
+            ("local_shutdown", JsonNum(stops.local_shutdown as f64)),
+        ])
+    }
+}
+
+impl TryFrom<&JsonValue> for StopReasons {
+    type Error = RpcError;
+
+    fn try_from(value: &JsonValue) -> ParseResult<Self> {
+        let map = object(value, "stop_reasons")?;
+        Ok(Self {
+            remote_close: u64_field(map, "remote_close")?,
+            timeout: u64_field(map, "timeout")?,
+            protocol_violation: u64_field(map, "protocol_violation")?,
+            local_shutdown: u64_field(map, "local_shutdown")?,
+        })
+    }
+}
+
+impl From<DiversityInfo> for JsonValue {
+    fn from(diversity: DiversityInfo) -> JsonValue {
+        json_map([
+            ("whitelist", diversity.whitelist.into()),
+            ("peers", diversity.peers.into()),
+            ("outbound", diversity.outbound.into()),
+            ("skewed", JsonValue::Boolean(diversity.skewed)),
+        ])
+    }
+}
+
+impl TryFrom<&JsonValue> for DiversityInfo {
+    type Error = RpcError;
+
+    fn try_from(value: &JsonValue) -> ParseResult<Self> {
+        let map = object(value, "diversity")?;
+        let Some(skewed) = field(map, "skewed")?.get::<bool>() else {
+            return Err(invalid("skewed"))
+        };
+
+        Ok(Self {
+            whitelist: field(map, "whitelist")?.try_into()?,
+            peers: field(map, "peers")?.try_into()?,
+            outbound: field(map, "outbound")?.try_into()?,
+            skewed: *skewed,
+        })
+    }
+}
+
+impl From<DiversitySummary> for JsonValue {
+    fn from(diversity: DiversitySummary) -> JsonValue {
+        let counts = |map: HashMap<String, u64>| {
+            JsonObj(map.into_iter().map(|(k, v)| (k, JsonNum(v as f64))).collect())
+        };
+
+        json_map([
+            ("families", counts(diversity.families)),
+            ("transports", counts(diversity.transports)),
+            ("subnets", JsonNum(diversity.subnets as f64)),
+            ("largest_subnet_share", JsonNum(diversity.largest_subnet_share as f64)),
+        ])
+    }
+}
+
+impl TryFrom<&JsonValue> for DiversitySummary {
+    type Error = RpcError;
+
+    fn try_from(value: &JsonValue) -> ParseResult<Self> {
+        let map = object(value, "diversity summary")?;
+        let counts = |key: &str| -> ParseResult<HashMap<String, u64>> {
+            let counts = object(field(map, key)?, key)?;
+            counts.keys().map(|k| Ok((k.clone(), u64_field(counts, k)?))).collect()
+        };
+
+        Ok(Self {
+            families: counts("families")?,
+            transports: counts("transports")?,
+            subnets: u64_field(map, "subnets")?,
+            largest_subnet_share: u64_field(map, "largest_subnet_share")?,
+        })
+    }
+}
+
+/// Auxiliary function to convert compression counters to JSON
+fn compression_json(stats: &CompressionStats) -> JsonValue {
+    json_map([
+        ("sent_raw", JsonNum(stats.sent_raw as f64)),
+        ("sent_wire", JsonNum(stats.sent_wire as f64)),
+        ("received_raw", JsonNum(stats.received_raw as f64)),
+        ("received_wire", JsonNum(stats.received_wire as f64)),
+    ])
+}
+
+/// Auxiliary function to parse compression counters from JSON
+fn parse_compression(value: &JsonValue) -> ParseResult<CompressionStats> {
+    let map = object(value, "compression")?;
+    Ok(CompressionStats {
+        sent_raw: u64_field(map, "sent_raw")?,
+        sent_wire: u64_field(map, "sent_wire")?,
+        received_raw: u64_field(map, "received_raw")?,
+        received_wire: u64_field(map, "received_wire")?,
+    })
+}
+
+/// Auxiliary function to convert protocol error counters to JSON
+fn errors_json(errors: &ProtocolErrors) -> JsonValue {
+    json_map([
+        ("decode_failures", JsonNum(errors.decode_failures as f64)),
+        ("unknown_messages", JsonNum(errors.unknown_messages as f64)),
+        ("timeouts", JsonNum(errors.timeouts as f64)),
+    ])
+}
+
+/// Auxiliary function to parse protocol error counters from JSON
+fn parse_errors(value: &JsonValue) -> ParseResult<ProtocolErrors> {
+    let map = object(value, "errors")?;
+    Ok(ProtocolErrors {
+        decode_failures: u64_field(map, "decode_failures")?,
+        unknown_messages: u64_field(map, "unknown_messages")?,
+        timeouts: u64_field(map, "timeouts")?,
+    })
+}
+
+fn invalid(key: &str) -> RpcError {
+    RpcError::InvalidJson(format!("Node info does not contain valid \"{key}\" field"))
+}
+
+fn object<'a>(value: &'a JsonValue, what: &str) -> ParseResult<&'a HashMap<String, JsonValue>> {
+    value.get::<HashMap<String, JsonValue>>().ok_or_else(|| invalid(what))
+}
+
+fn field<'a>(map: &'a HashMap<String, JsonValue>, key: &str) -> ParseResult<&'a JsonValue> {
+    map.get(key).ok_or_else(|| invalid(key))
+}
+
+fn array_field<'a>(
+    map: &'a HashMap<String, JsonValue>,
+    key: &str,
+) -> ParseResult<&'a Vec<JsonValue>> {
+    field(map, key)?.get::<Vec<JsonValue>>().ok_or_else(|| invalid(key))
+}
+
+fn u64_field(map: &HashMap<String, JsonValue>, key: &str) -> ParseResult<u64> {
+    field(map, key)?.get::<f64>().map(|n| *n as u64).ok_or_else(|| invalid(key))
+}
+
+fn str_field(map: &HashMap<String, JsonValue>, key: &str) -> ParseResult<String> {
+    field(map, key)?.get::<String>().cloned().ok_or_else(|| invalid(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_info_roundtrip() {
+        let info = NodeInfo {
+            version: NODE_INFO_VERSION,
+            peer_id: "9iYXX7QcwsjkLHMXr1hUvhJGLdQ5nrCHo3vuPDhS8Ke5".to_string(),
+            channels: vec![ChannelEntry {
+                url: "tcp+tls://dark.fi:26661".to_string(),
+                session: "outbound".to_string(),
+                id: 42,
+                peer_id: "3yjVK4mDDdzBGJ2Ww9w1bvVGaDbGXDMXrD6h7EUJUGaL".to_string(),
+                compression: CompressionStats { sent_raw: 10, sent_wire: 5, ..Default::default() },
+                errors: ProtocolErrors { decode_failures: 2, timeouts: 1, ..Default::default() },
+                ping_ms: Some(120),
+            }],
+            outbound_slots: vec![42, 0],
+            transport_quotas: vec![TransportQuota {
+                transport: "tor".to_string(),
+                slots: 1,
+                connected: 1,
+            }],
+            duplicate_channels: 1,
+            data_usage: DataUsage { sent: 100, received: 200, daily_data_cap: 0 },
+            bans: vec![BanEntry {
+                url: "tcp://1.2.3.4:26661".to_string(),
+                until: 1700000000,
+                reason: "Spam".to_string(),
+            }],
+            stop_reasons: StopReasons { timeout: 3, ..Default::default() },
+            compression: CompressionStats { sent_raw: 10, sent_wire: 5, ..Default::default() },
+            diversity: DiversityInfo {
+                outbound: DiversitySummary {
+                    families: HashMap::from([("ipv4".to_string(), 1)]),
+                    transports: HashMap::from([("tcp+tls".to_string(), 1)]),
+                    subnets: 1,
+                    largest_subnet_share: 100,
+                },
+                skewed: true,
+                ..Default::default()
+            },
+        };
+
+        let json: JsonValue = info.clone().into();
+        let parsed = NodeInfo::try_from(&json.stringify().unwrap().parse::<JsonValue>().unwrap());
+        assert_eq!(parsed.unwrap(), info);
+
+        // Unknown fields are ignored, missing ones are not
+        let JsonObj(mut map) = json else { unreachable!() };
+        map.insert("future_field".to_string(), JsonNum(1.0));
+        assert!(NodeInfo::try_from(&JsonObj(map.clone())).is_ok());
+        map.remove("bans");
+        assert!(NodeInfo::try_from(&JsonObj(map.clone())).is_err());
+
+        // Newer schema versions are refused
+        let mut newer = info;
+        newer.version = NODE_INFO_VERSION + 1;
+        assert!(NodeInfo::try_from(&JsonValue::from(newer)).is_err());
+    }
+}
@@ -30,7 +30,7 @@ use crate::{
 
 /// JSON-RPC error codes.
 /// The error codes `[-32768, -32000]` are reserved for predefined errors.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ErrorCode {
     /// Invalid JSON was received by the server.
     /// An error occurred on the server while parsing the JSON text.
@@ -47,6 +47,10 @@ pub enum ErrorCode {
     IdMismatch,
     /// Invalid/Unexpected reply
     InvalidReply,
+    /// The request is missing valid credentials for the method.
+    Unauthorized,
+    /// The method handler did not complete in time.
+    RequestTimeout,
     /// Reserved for implementation-defined server-errors.
     ServerError(i32),
 }
@@ -61,6 +65,8 @@ impl ErrorCode {
             Self::InternalError => -32603,
             Self::IdMismatch => -32360,
             Self::InvalidReply => -32361,
+            Self::Unauthorized => -32362,
+            Self::RequestTimeout => -32363,
             Self::ServerError(c) => c,
         }
     }
@@ -74,6 +80,8 @@ impl ErrorCode {
             Self::InternalError => "internal error".to_string(),
             Self::IdMismatch => "id mismatch".to_string(),
             Self::InvalidReply => "invalid reply".to_string(),
+            Self::Unauthorized => "unauthorized".to_string(),
+            Self::RequestTimeout => "request timed out".to_string(),
             Self::ServerError(_) => "server error".to_string(),
         }
     }
@@ -83,6 +91,23 @@ impl ErrorCode {
     }
 }
 
+impl From<i32> for ErrorCode {
+    fn from(code: i32) -> Self {
+        match code {
+            -32700 => Self::ParseError,
+            -32600 => Self::InvalidRequest,
+            -32601 => Self::MethodNotFound,
+            -32602 => Self::InvalidParams,
+            -32603 => Self::InternalError,
+            -32360 => Self::IdMismatch,
+            -32361 => Self::InvalidReply,
+            -32362 => Self::Unauthorized,
+            -32363 => Self::RequestTimeout,
+            c => Self::ServerError(c),
+        }
+    }
+}
+
 // ANCHOR: jsonresult
 /// Wrapping enum around the available JSON-RPC object types
 #[derive(Clone, Debug)]
@@ -156,6 +181,8 @@ pub struct JsonRequest {
     pub method: String,
     /// Request parameters
     pub params: JsonValue,
+    /// Optional authentication token
+    pub auth: Option<String>,
 }
 // ANCHOR_END: jsonrequest
 
@@ -164,7 +191,19 @@ impl JsonRequest {
     /// The request ID is chosen randomly.
     pub fn new(method: &str, params: JsonValue) -> Self {
         assert!(params.is_object() || params.is_array());
-        Self { jsonrpc: "2.0", id: OsRng::gen(&mut OsRng), method: method.to_string(), params }
+        Self {
+            jsonrpc: "2.0",
+            id: OsRng::gen(&mut OsRng),
+            method: method.to_string(),
+            params,
+            auth: None,
+        }
+    }
+
+    /// Attach the given authentication token to the request.
+    pub fn with_auth(mut self, token: &str) -> Self {
+        self.auth = Some(token.to_string());
+        self
     }
 
     /// Convert the object into a JSON string
@@ -176,12 +215,18 @@ impl JsonRequest {
 
 impl From<&JsonRequest> for JsonValue {
     fn from(req: &JsonRequest) -> JsonValue {
-        JsonValue::Object(HashMap::from([
+        let mut map = HashMap::from([
             ("jsonrpc".to_string(), JsonValue::String(req.jsonrpc.to_string())),
             ("id".to_string(), JsonValue::Number(req.id.into())),
             ("method".to_string(), JsonValue::String(req.method.clone())),
             ("params".to_string(), req.params.clone()),
-        ]))
+        ]);
+
+        if let Some(ref auth) = req.auth {
+            map.insert("auth".to_string(), JsonValue::String(auth.clone()));
+        }
+
+        JsonValue::Object(map)
     }
 }
 
@@ -228,11 +273,22 @@ impl TryFrom<&JsonValue> for JsonRequest {
             ))
         }
 
+        let auth = match map.get("auth") {
+            Some(JsonValue::String(auth)) => Some(auth.clone()),
+            Some(_) => {
+                return Err(RpcError::InvalidJson(
+                    "Request does not contain valid \"auth\" field".to_string(),
+                ))
+            }
+            None => None,
+        };
+
         Ok(Self {
             jsonrpc: "2.0",
             id: *map["id"].get::<f64>().unwrap() as u16,
             method: map["method"].get::<String>().unwrap().clone(),
             params: map["params"].clone(),
+            auth,
         })
     }
 }
@@ -398,17 +454,37 @@ pub struct JsonError {
     pub jsonrpc: &'static str,
     /// Request ID
     pub id: u16,
-    /// JSON-RPC error (code and message)
+    /// JSON-RPC error (code, message and optional data)
     pub error: JsonErrorVal,
 }
 
-/// A JSON-RPC error value (code and message)
-#[derive(Clone, Debug)]
+/// A JSON-RPC error value (code, message and optional data)
+#[derive(Clone, Debug, PartialEq)]
 pub struct JsonErrorVal {
     /// Error code
     pub code: i32,
     /// Error message
     pub message: String,
+    /// Structured details about the error, if the server provided any
+    pub data: Option<JsonValue>,
+}
+
+impl JsonErrorVal {
+    /// Kind of the error, as one of the [`ErrorCode`] variants.
+    /// Application-specific codes map to [`ErrorCode::ServerError`].
+    pub fn kind(&self) -> ErrorCode {
+        self.code.into()
+    }
+}
+
+impl std::fmt::Display for JsonErrorVal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)?;
+        if let Some(ref data) = self.data {
+            write!(f, ": {}", data.stringify().unwrap_or_default())?;
+        }
+        Ok(())
+    }
 }
 
 impl JsonError {
@@ -416,10 +492,17 @@ impl JsonError {
     /// message, and a response ID.
     /// Creating a `JsonError` implies that the method call was unsuccessful.
     pub fn new(c: ErrorCode, message: Option<String>, id: u16) -> Self {
-        let error = JsonErrorVal { code: c.code(), message: message.unwrap_or(c.message()) };
+        let error =
+            JsonErrorVal { code: c.code(), message: message.unwrap_or(c.message()), data: None };
         Self { jsonrpc: "2.0", id, error }
     }
 
+    /// Attach structured details about the error, for clients to act on.
+    pub fn with_data(mut self, data: JsonValue) -> Self {
+        self.error.data = Some(data);
+        self
+    }
+
     /// Convert the object into a JSON string
     pub fn stringify(&self) -> Result<String> {
         let v: JsonValue = self.into();
@@ -429,15 +512,18 @@ impl JsonError {
 
 impl From<&JsonError> for JsonValue {
     fn from(err: &JsonError) -> JsonValue {
-        let errmap = JsonValue::Object(HashMap::from([
+        let mut errmap = HashMap::from([
             ("code".to_string(), JsonValue::Number(err.error.code.into())),
             ("message".to_string(), JsonValue::String(err.error.message.clone())),
-        ]));
+        ]);
+        if let Some(ref data) = err.error.data {
+            errmap.insert("data".to_string(), data.clone());
+        }
 
         JsonValue::Object(HashMap::from([
             ("jsonrpc".to_string(), JsonValue::String(err.jsonrpc.to_string())),
             ("id".to_string(), JsonValue::Number(err.id.into())),
-            ("error".to_string(), errmap),
+            ("error".to_string(), JsonValue::Object(errmap)),
         ]))
     }
 }
@@ -485,12 +571,15 @@ impl TryFrom<&JsonValue> for JsonError {
             ))
         }
 
+        let errmap: &HashMap<String, JsonValue> = map["error"].get().unwrap();
+
         Ok(Self {
             jsonrpc: "2.0",
             id: *map["id"].get::<f64>().unwrap() as u16,
             error: JsonErrorVal {
-                code: *map["error"]["code"].get::<f64>().unwrap() as i32,
-                message: map["error"]["message"].get::<String>().unwrap().to_string(),
+                code: *errmap["code"].get::<f64>().unwrap() as i32,
+                message: errmap["message"].get::<String>().unwrap().to_string(),
+                data: errmap.get("data").cloned(),
             },
         })
     }
@@ -0,0 +1,175 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use log::debug;
+use tinyjson::JsonValue;
+use url::Url;
+
+use super::jsonrpc::JsonRequest;
+
+/// Placeholder written to the logs instead of redacted values
+pub const REDACTED: &str = "<redacted>";
+
+/// Object keys containing any of these (case-insensitive) get their
+/// values redacted from the logs
+pub const SENSITIVE_KEYS: &[&str] =
+    &["secret", "key", "seed", "password", "passphrase", "mnemonic", "token", "auth"];
+
+/// Source of the request correlation IDs
+static NEXT_CORRELATION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Identity of a request, shared by all the log lines it produces
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    /// ID unique to this request for the lifetime of the process, so its
+    /// log lines can be correlated even when clients reuse their IDs
+    pub correlation_id: u64,
+    /// Address of the peer the request came from
+    pub addr: Url,
+    /// Requested method
+    pub method: String,
+    /// Request ID chosen by the client
+    pub id: u16,
+}
+
+impl RequestContext {
+    pub fn new(addr: Url, req: &JsonRequest) -> Self {
+        let correlation_id = NEXT_CORRELATION_ID.fetch_add(1, Ordering::Relaxed);
+        Self { correlation_id, addr, method: req.method.clone(), id: req.id }
+    }
+}
+
+/// Trait logging the lifecycle of the requests served by the JSON-RPC
+/// server. Handlers pick their logger through
+/// [`RequestHandler::request_logger()`](super::server::RequestHandler::request_logger),
+/// so daemons share [`DefaultRequestLogger`] unless they plug in their own.
+pub trait RequestLogger: Sync + Send {
+    /// Methods whose params get redacted as a whole, for the ones taking
+    /// sensitive positional params
+    fn sensitive_methods(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Copy of the request params that is safe to write to the logs
+    fn redact(&self, method: &str, params: &JsonValue) -> JsonValue {
+        if self.sensitive_methods().contains(&method) {
+            return JsonValue::String(REDACTED.to_string())
+        }
+
+        redact_params(params)
+    }
+
+    /// Called once a request was read from its connection
+    fn started(&self, ctx: &RequestContext, params: &JsonValue) {
+        let params = self.redact(&ctx.method, params).stringify().unwrap_or_default();
+        debug!(
+            target: "rpc::server::request",
+            "[{}] {} --> {} #{} {}", ctx.correlation_id, ctx.addr, ctx.method, ctx.id, params,
+        );
+    }
+
+    /// Called once the handler replied, with how long it took
+    fn finished(&self, ctx: &RequestContext, elapsed: Duration, outcome: &str) {
+        debug!(
+            target: "rpc::server::request",
+            "[{}] {} <-- {} #{} finished in {:?}: {}",
+            ctx.correlation_id, ctx.addr, ctx.method, ctx.id, elapsed, outcome,
+        );
+    }
+}
+
+/// [`RequestLogger`] writing to the `rpc::server::request` log target,
+/// redacting the values of [`SENSITIVE_KEYS`]
+pub struct DefaultRequestLogger;
+
+impl RequestLogger for DefaultRequestLogger {}
+
+/// Recursively replace the values of object keys matching
+/// [`SENSITIVE_KEYS`] with [`REDACTED`].
+pub fn redact_params(params: &JsonValue) -> JsonValue {
+    match params {
+        JsonValue::Object(map) => JsonValue::Object(
+            map.iter()
+                .map(|(k, v)| {
+                    let key = k.to_lowercase();
+                    if SENSITIVE_KEYS.iter().any(|s| key.contains(s)) {
+                        return (k.clone(), JsonValue::String(REDACTED.to_string()))
+                    }
+                    (k.clone(), redact_params(v))
+                })
+                .collect(),
+        ),
+        JsonValue::Array(values) => JsonValue::Array(values.iter().map(redact_params).collect()),
+        v => v.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_redact_params() {
+        let params = JsonValue::Array(vec![
+            JsonValue::String("tx".to_string()),
+            JsonValue::Object(HashMap::from([
+                ("Secret_Key".to_string(), JsonValue::String("hunter2".to_string())),
+                ("amount".to_string(), JsonValue::Number(42.0)),
+                (
+                    "inner".to_string(),
+                    JsonValue::Object(HashMap::from([(
+                        "seed".to_string(),
+                        JsonValue::Array(vec![JsonValue::Number(1.0)]),
+                    )])),
+                ),
+            ])),
+        ]);
+
+        let redacted = redact_params(&params);
+        assert_eq!(redacted[0], params[0]);
+        assert_eq!(redacted[1]["Secret_Key"], JsonValue::String(REDACTED.to_string()));
+        assert_eq!(redacted[1]["amount"], params[1]["amount"]);
+        assert_eq!(redacted[1]["inner"]["seed"], JsonValue::String(REDACTED.to_string()));
+        assert!(!redacted.stringify().unwrap().contains("hunter2"));
+
+        struct WalletLogger;
+        impl RequestLogger for WalletLogger {
+            fn sensitive_methods(&self) -> &[&str] {
+                &["wallet.import"]
+            }
+        }
+
+        let logger = WalletLogger;
+        let redacted = logger.redact("wallet.import", &params);
+        assert_eq!(redacted, JsonValue::String(REDACTED.to_string()));
+        assert_eq!(logger.redact("tx.broadcast", &params)[0], params[0]);
+
+        let req = JsonRequest::new("ping", JsonValue::Array(vec![]));
+        let addr = Url::parse("tcp://127.0.0.1:1234").unwrap();
+        let a = RequestContext::new(addr.clone(), &req);
+        let b = RequestContext::new(addr, &req);
+        assert_ne!(a.correlation_id, b.correlation_id);
+    }
+}
@@ -0,0 +1,152 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Programmable JSON-RPC server for integration tests.
+//!
+//! [`MockRpcServer`] replies to each method with a canned result or error
+//! configured by the test, and records every request it receives so they
+//! can be asserted on afterwards. Unknown methods get a `MethodNotFound`
+//! error, except for `ping` which is always answered with `pong`.
+//!
+//! ```ignore
+//! let server = MockRpcServer::new();
+//! server.respond("blockchain.last_known_block", JsonValue::Number(42.0)).await;
+//! let task = server.clone().start(endpoint.clone(), executor.clone());
+//! // ... run the code under test against `endpoint` ...
+//! assert_eq!(server.requests_for("blockchain.last_known_block").await.len(), 1);
+//! task.stop().await;
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use log::error;
+use smol::lock::{Mutex, MutexGuard};
+use tinyjson::JsonValue;
+use url::Url;
+
+use super::{
+    jsonrpc::{ErrorCode, JsonError, JsonRequest, JsonResponse, JsonResult},
+    server::{listen_and_serve, RequestHandler},
+};
+use crate::{
+    system::{ExecutorPtr, StoppableTask, StoppableTaskPtr},
+    Error,
+};
+
+/// Canned reply for a mocked method
+#[derive(Clone, Debug)]
+enum MockReply {
+    Result(JsonValue),
+    Error(ErrorCode, String),
+}
+
+/// JSON-RPC server replying with canned responses and recording requests
+pub struct MockRpcServer {
+    /// Canned replies, keyed by method name
+    replies: Mutex<HashMap<String, MockReply>>,
+    /// Every request received so far, in order of arrival
+    requests: Mutex<Vec<JsonRequest>>,
+    /// Active RPC connections
+    rpc_connections: Mutex<HashSet<StoppableTaskPtr>>,
+}
+
+impl MockRpcServer {
+    /// Create a new mock server with no configured replies
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            replies: Mutex::new(HashMap::new()),
+            requests: Mutex::new(vec![]),
+            rpc_connections: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Reply to `method` with the given result. Replaces any previously
+    /// configured reply for this method.
+    pub async fn respond(&self, method: &str, result: JsonValue) {
+        self.replies.lock().await.insert(method.to_string(), MockReply::Result(result));
+    }
+
+    /// Reply to `method` with the given error. Replaces any previously
+    /// configured reply for this method.
+    pub async fn respond_error(&self, method: &str, code: ErrorCode, message: &str) {
+        self.replies
+            .lock()
+            .await
+            .insert(method.to_string(), MockReply::Error(code, message.to_string()));
+    }
+
+    /// Return all the requests received so far
+    pub async fn requests(&self) -> Vec<JsonRequest> {
+        self.requests.lock().await.clone()
+    }
+
+    /// Return the requests received so far for the given method
+    pub async fn requests_for(&self, method: &str) -> Vec<JsonRequest> {
+        self.requests.lock().await.iter().filter(|r| r.method == method).cloned().collect()
+    }
+
+    /// Forget all the recorded requests
+    pub async fn clear_requests(&self) {
+        self.requests.lock().await.clear();
+    }
+
+    /// Start serving on the given endpoint in the background. Stopping
+    /// the returned task closes the listener and all its connections.
+    pub fn start(self: Arc<Self>, endpoint: Url, ex: ExecutorPtr) -> StoppableTaskPtr {
+        let self_ = self.clone();
+        let task = StoppableTask::new();
+        task.clone().start(
+            listen_and_serve(endpoint, self, None, ex.clone()),
+            |res| async move {
+                match res {
+                    Ok(()) | Err(Error::RpcServerStopped) => self_.stop_connections().await,
+                    Err(e) => error!(target: "rpc::mock", "Failed starting mock server: {}", e),
+                }
+            },
+            Error::RpcServerStopped,
+            ex,
+        );
+
+        task
+    }
+}
+
+#[async_trait]
+impl RequestHandler for MockRpcServer {
+    async fn handle_request(&self, req: JsonRequest) -> JsonResult {
+        self.requests.lock().await.push(req.clone());
+
+        let reply = self.replies.lock().await.get(&req.method).cloned();
+        match reply {
+            Some(MockReply::Result(result)) => JsonResponse::new(result, req.id).into(),
+            Some(MockReply::Error(code, message)) => {
+                JsonError::new(code, Some(message), req.id).into()
+            }
+            None if req.method == "ping" => self.pong(req.id, req.params).await,
+            None => JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
+        }
+    }
+
+    async fn connections_mut(&self) -> MutexGuard<'life0, HashSet<StoppableTaskPtr>> {
+        self.rpc_connections.lock().await
+    }
+}
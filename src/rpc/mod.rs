@@ -28,6 +28,9 @@ pub mod client;
 /// Server-side JSON-RPC implementation
 pub mod server;
 
+/// Structured request logging with redaction of sensitive params
+pub mod logging;
+
 /// Clock sync utility module
 pub mod clock_sync;
 
@@ -37,5 +40,14 @@ pub mod from_impl;
 /// Provides optional `p2p.get_info()` method
 pub mod p2p_method;
 
+/// Typed schema of the `p2p.get_info()` payload
+pub mod introspection;
+
+/// Provides optional `get_version()` method
+pub mod version_method;
+
+/// Programmable JSON-RPC server for integration tests
+pub mod mock;
+
 /// Json helper methods and types
 pub mod util;
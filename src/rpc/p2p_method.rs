@@ -16,10 +16,17 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::{collections::HashMap, sync::atomic::Ordering};
+
 use async_trait::async_trait;
+use url::Url;
 
 use super::{
-    jsonrpc::{JsonResponse, JsonResult},
+    introspection::{
+        BanEntry, ChannelEntry, DataUsage, DiversityInfo, NodeInfo, StopReasons, TransportQuota,
+        NODE_INFO_VERSION,
+    },
+    jsonrpc::{ErrorCode, JsonError, JsonResponse, JsonResult},
     util::*,
 };
 use crate::net;
@@ -27,32 +34,175 @@ use crate::net;
 #[async_trait]
 pub trait HandlerP2p: Sync + Send {
     async fn p2p_get_info(&self, id: u16, _params: JsonValue) -> JsonResult {
-        let mut channels = Vec::new();
-        for channel in self.p2p().hosts().channels() {
-            let session = match channel.session_type_id() {
-                net::session::SESSION_INBOUND => "inbound",
-                net::session::SESSION_OUTBOUND => "outbound",
-                net::session::SESSION_MANUAL => "manual",
-                net::session::SESSION_REFINE => "refine",
-                net::session::SESSION_SEED => "seed",
-                _ => panic!("invalid result from channel.session_type_id()"),
-            };
-            channels.push(json_map([
-                ("url", JsonStr(channel.address().clone().into())),
-                ("session", json_str(session)),
-                ("id", JsonNum(channel.info.id.into())),
-            ]));
-        }
+        JsonResponse::new(node_info(&self.p2p()).await.into(), id).into()
+    }
 
-        let mut slots = Vec::new();
-        for channel_id in self.p2p().session_outbound().slot_info().await {
-            slots.push(JsonNum(channel_id.into()));
-        }
+    /// `p2p.disconnect_peer`: Disconnect from the peer with given URL.
+    /// Returns false if we aren't connected to it.
+    async fn p2p_disconnect_peer(&self, id: u16, params: JsonValue) -> JsonResult {
+        let Some(addr) = peer_param(&params) else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        JsonResponse::new(JsonValue::Boolean(self.p2p().disconnect_peer(&addr).await), id).into()
+    }
+
+    /// `p2p.blacklist_peer`: Blacklist the peer with given URL for the
+    /// configured ban duration, disconnecting from it. Returns false if
+    /// the peer can't be blacklisted.
+    async fn p2p_blacklist_peer(&self, id: u16, params: JsonValue) -> JsonResult {
+        let Some(addr) = peer_param(&params) else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        JsonResponse::new(JsonValue::Boolean(self.p2p().blacklist_peer(&addr).await), id).into()
+    }
+
+    /// `p2p.connect_peer`: Open a manual connection to the peer with
+    /// given URL. Returns false if there already is one.
+    async fn p2p_connect_peer(&self, id: u16, params: JsonValue) -> JsonResult {
+        let Some(addr) = peer_param(&params) else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
 
-        let result =
-            json_map([("channels", JsonArray(channels)), ("outbound_slots", JsonArray(slots))]);
-        JsonResponse::new(result, id).into()
+        JsonResponse::new(JsonValue::Boolean(self.p2p().connect_peer(addr).await), id).into()
+    }
+
+    /// `p2p.get_refinery_stats`: Returns the outcomes of the greylist
+    /// refinery probes, in total and by transport. Counts are kept across
+    /// restarts when the node has a P2P datastore.
+    async fn p2p_get_refinery_stats(&self, id: u16, _params: JsonValue) -> JsonResult {
+        JsonResponse::new(self.p2p().session_refine().stats().into(), id).into()
+    }
+
+    /// `dnet.get_recent_events`: Returns the last dnet events recorded
+    /// while network debugging was enabled, oldest first, as
+    /// `[timestamp, event]` pairs. Takes an optional maximum number of
+    /// events to return.
+    async fn dnet_get_recent_events(&self, id: u16, params: JsonValue) -> JsonResult {
+        let Some(params) = params.get::<Vec<JsonValue>>() else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        let limit = match params.as_slice() {
+            [] => net::dnet::DNET_RECENT_EVENTS,
+            [JsonValue::Number(limit)] if *limit >= 0.0 => *limit as usize,
+            _ => return JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+        };
+
+        let events = self
+            .p2p()
+            .dnet_recent_events(limit)
+            .into_iter()
+            .map(|(time, event)| JsonValue::Array(vec![JsonNum(time as f64), event.into()]))
+            .collect();
+
+        JsonResponse::new(JsonValue::Array(events), id).into()
     }
 
     fn p2p(&self) -> net::P2pPtr;
 }
+
+/// Parse the peer URL, the only param of the peer management methods
+fn peer_param(params: &JsonValue) -> Option<Url> {
+    let params = params.get::<Vec<JsonValue>>()?;
+    let [JsonValue::String(url)] = params.as_slice() else { return None };
+    Url::parse(url).ok()
+}
+
+/// Optional `p2p.get_info` method for daemons running several P2P networks.
+/// Takes the network name as its only param, and returns the info of each
+/// network keyed by name when called without params.
+#[async_trait]
+pub trait HandlerP2pNetworks: Sync + Send {
+    async fn p2p_get_info(&self, id: u16, params: JsonValue) -> JsonResult {
+        let Some(params) = params.get::<Vec<JsonValue>>() else {
+            return JsonError::new(ErrorCode::InvalidParams, None, id).into()
+        };
+
+        match params.as_slice() {
+            [] => {
+                let mut infos = HashMap::new();
+                for (name, p2p) in self.p2p_networks().iter() {
+                    infos.insert(name.clone(), node_info(p2p).await.into());
+                }
+                JsonResponse::new(JsonObj(infos), id).into()
+            }
+            [JsonValue::String(name)] => match self.p2p_networks().get(name) {
+                Some(p2p) => JsonResponse::new(node_info(p2p).await.into(), id).into(),
+                None => JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+            },
+            _ => JsonError::new(ErrorCode::InvalidParams, None, id).into(),
+        }
+    }
+
+    fn p2p_networks(&self) -> &net::P2pNetworks;
+}
+
+/// Gather the `p2p.get_info` payload of given P2P instance.
+pub async fn node_info(p2p: &net::P2pPtr) -> NodeInfo {
+    let mut info = NodeInfo {
+        version: NODE_INFO_VERSION,
+        peer_id: p2p.peer_id().to_string(),
+        ..Default::default()
+    };
+
+    for channel in p2p.hosts().channels() {
+        let session = match channel.session_type_id() {
+            net::session::SESSION_INBOUND => "inbound",
+            net::session::SESSION_OUTBOUND => "outbound",
+            net::session::SESSION_MANUAL => "manual",
+            net::session::SESSION_REFINE => "refine",
+            net::session::SESSION_SEED => "seed",
+            _ => panic!("invalid result from channel.session_type_id()"),
+        };
+        let compression = channel.compression_stats();
+        info.compression.merge(&compression);
+        let stats_addr = channel.stats_addr();
+        info.channels.push(ChannelEntry {
+            url: channel.address().to_string(),
+            session: session.to_string(),
+            id: channel.info.id,
+            peer_id: channel.peer_id().map(|p| p.to_string()).unwrap_or_default(),
+            compression,
+            errors: p2p.hosts().protocol_errors(&stats_addr),
+            ping_ms: p2p.hosts().ping_latency(&stats_addr).map(|rtt| rtt.as_millis() as u64),
+        });
+    }
+
+    info.outbound_slots = p2p.session_outbound().slot_info().await;
+    for (transport, slots, connected) in p2p.session_outbound().transport_quotas().await {
+        info.transport_quotas.push(TransportQuota {
+            transport,
+            slots: slots as u64,
+            connected: connected as u64,
+        });
+    }
+    info.duplicate_channels = p2p.duplicate_channels.load(Ordering::SeqCst) as u64;
+
+    let (sent, received) = p2p.data_meter().usage();
+    let daily_data_cap = p2p.settings().read().await.daily_data_cap;
+    info.data_usage = DataUsage { sent, received, daily_data_cap };
+
+    for (url, ban) in p2p.hosts().bans() {
+        info.bans.push(BanEntry { url: url.to_string(), until: ban.until, reason: ban.reason });
+    }
+
+    let stops = p2p.hosts().total_stop_counts();
+    info.stop_reasons = StopReasons {
+        remote_close: stops.remote_close,
+        timeout: stops.timeout,
+        protocol_violation: stops.protocol_violation,
+        local_shutdown: stops.local_shutdown,
+    };
+
+    let hosts = p2p.hosts();
+    info.diversity = DiversityInfo {
+        whitelist: (&hosts.whitelist_diversity()).into(),
+        peers: (&hosts.peers_diversity()).into(),
+        outbound: (&hosts.outbound_diversity()).into(),
+        skewed: hosts.diversity_skewed(),
+    };
+
+    info
+}
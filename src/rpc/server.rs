@@ -16,13 +16,19 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{collections::HashSet, io::ErrorKind, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::ErrorKind,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
 use log::{debug, error, info};
 use smol::{
+    channel::{Receiver, Sender},
     io::{BufReader, ReadHalf, WriteHalf},
-    lock::{Mutex, MutexGuard},
+    lock::{Mutex, MutexGuard, Semaphore},
 };
 use tinyjson::JsonValue;
 use url::Url;
@@ -30,13 +36,158 @@ use url::Url;
 use super::{
     common::{read_from_stream, write_to_stream, INIT_BUF_SIZE},
     jsonrpc::*,
+    logging::{DefaultRequestLogger, RequestContext, RequestLogger},
 };
 use crate::{
     net::transport::{Listener, PtListener, PtStream},
-    system::{StoppableTask, StoppableTaskPtr},
+    system::{StoppableTask, StoppableTaskPtr, Subscription},
     Error, Result,
 };
 
+/// Method of the keepalive notifications pushed on subscription connections
+pub const KEEPALIVE_METHOD: &str = "keepalive";
+
+/// Interval at which connections are checked for idleness
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limits applied by the JSON-RPC server to its clients
+#[derive(Clone, Debug)]
+pub struct RpcLimits {
+    /// Maximum number of concurrent connections, `None` for unlimited
+    pub max_connections: Option<usize>,
+    /// Maximum number of requests a single connection may have in flight.
+    /// Once reached, no more requests are read from that connection until
+    /// one of them completes. `None` for unlimited.
+    pub max_in_flight_per_conn: Option<usize>,
+    /// Maximum number of requests in flight across all connections.
+    /// Free slots are handed out to waiting connections in turn. `None`
+    /// for unlimited.
+    pub max_in_flight: Option<usize>,
+    /// Close connections once nothing was received from them for this
+    /// long, while they have no requests in flight nor subscriptions.
+    /// `None` keeps idle connections open.
+    pub idle_timeout: Option<Duration>,
+    /// Push a keepalive notification on subscription connections once no
+    /// notification was sent for this long, so clients that vanished
+    /// without closing their socket get noticed. `None` disables them.
+    pub keepalive_interval: Option<Duration>,
+    /// Reply with a timeout error to requests whose handler didn't
+    /// complete within this long. The handler future gets dropped, so
+    /// whatever it holds is released. `None` lets handlers run for as
+    /// long as they need.
+    pub request_timeout: Option<Duration>,
+    /// Timeouts of specific methods, overriding `request_timeout`
+    pub method_timeouts: HashMap<String, Duration>,
+}
+
+impl Default for RpcLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            max_in_flight_per_conn: None,
+            max_in_flight: None,
+            idle_timeout: None,
+            keepalive_interval: None,
+            request_timeout: None,
+            method_timeouts: HashMap::new(),
+        }
+    }
+}
+
+impl RpcLimits {
+    /// Timeout applied to requests of the given method, if any
+    pub fn timeout(&self, method: &str) -> Option<Duration> {
+        self.method_timeouts.get(method).copied().or(self.request_timeout)
+    }
+}
+
+/// Hands out request slots to connections in the order they asked for
+/// them. A connection only reads its next request once the previous one
+/// got a slot, so busy connections are served round-robin and a single
+/// client can't starve the others.
+struct FairScheduler {
+    /// Maximum number of slots handed out at once
+    capacity: usize,
+    /// Scheduler state, only held for short non-async sections
+    state: std::sync::Mutex<SchedulerState>,
+}
+
+struct SchedulerState {
+    /// Number of slots currently handed out
+    in_flight: usize,
+    /// Connections waiting for a slot, in arrival order
+    waiters: VecDeque<Sender<()>>,
+}
+
+impl FairScheduler {
+    fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            capacity: capacity.max(1),
+            state: std::sync::Mutex::new(SchedulerState { in_flight: 0, waiters: VecDeque::new() }),
+        })
+    }
+
+    /// Wait for our turn to get a request slot
+    async fn acquire(self: &Arc<Self>) -> SchedulerSlot {
+        let rx = {
+            let mut state = self.state.lock().unwrap();
+            if state.in_flight < self.capacity && state.waiters.is_empty() {
+                state.in_flight += 1;
+                return SchedulerSlot(self.clone())
+            }
+
+            let (tx, rx) = smol::channel::bounded(1);
+            state.waiters.push_back(tx);
+            rx
+        };
+
+        // The slot gets handed over to us by the one releasing it
+        let mut waiter = SchedulerWaiter { scheduler: self.clone(), rx, granted: false };
+        let _ = waiter.rx.recv().await;
+        waiter.granted = true;
+        SchedulerSlot(self.clone())
+    }
+
+    /// Hand a released slot over to the next waiting connection
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(waiter) = state.waiters.pop_front() {
+            if waiter.try_send(()).is_ok() {
+                return
+            }
+        }
+        state.in_flight -= 1;
+    }
+}
+
+/// Request slot handed out by the [`FairScheduler`], released on drop
+struct SchedulerSlot(Arc<FairScheduler>);
+
+impl Drop for SchedulerSlot {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Pending [`FairScheduler::acquire()`]. If it gets cancelled after a slot
+/// was handed over, the slot is released again.
+struct SchedulerWaiter {
+    scheduler: Arc<FairScheduler>,
+    rx: Receiver<()>,
+    granted: bool,
+}
+
+impl Drop for SchedulerWaiter {
+    fn drop(&mut self) {
+        if !self.granted {
+            self.rx.close();
+            if self.rx.try_recv().is_ok() {
+                self.scheduler.release();
+            }
+        }
+    }
+}
+
 /// Asynchronous trait implementing a handler for incoming JSON-RPC requests.
 #[async_trait]
 pub trait RequestHandler: Sync + Send {
@@ -48,6 +199,11 @@ pub trait RequestHandler: Sync + Send {
 
     async fn connections_mut(&self) -> MutexGuard<'life0, HashSet<StoppableTaskPtr>>;
 
+    /// Logger of the requests served to this handler
+    fn request_logger(&self) -> &dyn RequestLogger {
+        &DefaultRequestLogger
+    }
+
     async fn connections(&self) -> Vec<StoppableTaskPtr> {
         self.connections_mut().await.iter().cloned().collect()
     }
@@ -73,16 +229,84 @@ pub trait RequestHandler: Sync + Send {
     }
 }
 
+/// Wait for the next notification of a subscription. If a keepalive
+/// interval is given and nothing gets published for that long, a
+/// keepalive notification is returned instead.
+async fn next_notification(
+    subscription: &Subscription<JsonNotification>,
+    keepalive: Option<Duration>,
+) -> JsonNotification {
+    let Some(interval) = keepalive else { return subscription.receive().await };
+
+    smol::future::or(subscription.receive(), async {
+        smol::Timer::after(interval).await;
+        JsonNotification::new(KEEPALIVE_METHOD, JsonValue::Array(vec![]))
+    })
+    .await
+}
+
+/// Resolve once the connection has been idle for `timeout`, meaning it
+/// had no requests in flight nor subscriptions for that long. This is
+/// raced against reading the next request, so it only runs while nothing
+/// is being received.
+async fn idle_watch(
+    tasks: &Mutex<HashSet<Arc<StoppableTask>>>,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let Some(timeout) = timeout else { return std::future::pending().await };
+
+    let mut idle_since = Instant::now();
+    loop {
+        smol::Timer::after(IDLE_CHECK_INTERVAL).await;
+
+        if !tasks.lock().await.is_empty() {
+            idle_since = Instant::now();
+            continue
+        }
+
+        if idle_since.elapsed() >= timeout {
+            return Err(Error::RpcConnectionIdle)
+        }
+    }
+}
+
 /// Auxiliary function to handle a request in the background.
+#[allow(clippy::too_many_arguments)]
 async fn handle_request(
     writer: Arc<Mutex<WriteHalf<Box<dyn PtStream>>>>,
     addr: Url,
     rh: Arc<impl RequestHandler + 'static>,
     ex: Arc<smol::Executor<'_>>,
     tasks: Arc<Mutex<HashSet<Arc<StoppableTask>>>>,
+    keepalive: Option<Duration>,
+    timeout: Option<Duration>,
+    ctx: RequestContext,
     req: JsonRequest,
 ) -> Result<()> {
-    let rep = rh.handle_request(req).await;
+    // Time the handler, so the logger can report how long it took
+    let id = req.id;
+    let started = Instant::now();
+
+    // Once timed out, the handler future is dropped along with
+    // everything it holds.
+    let rep = match timeout {
+        Some(timeout) => {
+            smol::future::or(rh.handle_request(req), async {
+                smol::Timer::after(timeout).await;
+                JsonError::new(ErrorCode::RequestTimeout, None, id).into()
+            })
+            .await
+        }
+        None => rh.handle_request(req).await,
+    };
+
+    let outcome = match rep {
+        JsonResult::Error(ref e) => format!("error {}", e.error.code),
+        JsonResult::Subscriber(_) | JsonResult::SubscriberWithReply(_, _) => "subscribed".into(),
+        _ => "ok".into(),
+    };
+    rh.request_logger().finished(&ctx, started.elapsed(), &outcome);
+
     match rep {
         JsonResult::Subscriber(subscriber) => {
             let task = StoppableTask::new();
@@ -100,7 +324,7 @@ async fn handle_request(
                     let subscription = subscriber.publisher.subscribe().await;
                     loop {
                         // Listen for notifications
-                        let notification = subscription.receive().await;
+                        let notification = next_notification(&subscription, keepalive).await;
 
                         // Push notification
                         debug!(target: "rpc::server", "{} <-- {}", addr_, notification.stringify().unwrap());
@@ -150,7 +374,7 @@ async fn handle_request(
                     let subscription = subscriber.publisher.subscribe().await;
                     loop {
                         // Listen for notifications
-                        let notification = subscription.receive().await;
+                        let notification = next_notification(&subscription, keepalive).await;
 
                         // Push notification
                         debug!(target: "rpc::server", "{} <-- {}", addr_, notification.stringify().unwrap());
@@ -202,6 +426,29 @@ async fn handle_request(
     Ok(())
 }
 
+/// Background tasks of a connection. Stops them when dropped, which
+/// happens once the connection gets closed, so in-flight handler futures
+/// are dropped instead of running for a client that's gone.
+struct ConnectionTasks<'a> {
+    tasks: Arc<Mutex<HashSet<Arc<StoppableTask>>>>,
+    ex: Arc<smol::Executor<'a>>,
+}
+
+impl Drop for ConnectionTasks<'_> {
+    fn drop(&mut self) {
+        // We can't wait for the lock here, so the tasks get stopped in
+        // the background.
+        let tasks = self.tasks.clone();
+        self.ex
+            .spawn(async move {
+                for task in tasks.lock().await.iter() {
+                    task.stop_nowait();
+                }
+            })
+            .detach();
+    }
+}
+
 /// Accept function that should run inside a loop for accepting incoming
 /// JSON-RPC requests and passing them to the [`RequestHandler`].
 #[allow(clippy::type_complexity)]
@@ -212,10 +459,26 @@ pub async fn accept(
     rh: Arc<impl RequestHandler + 'static>,
     conn_limit: Option<usize>,
     ex: Arc<smol::Executor<'_>>,
+) -> Result<()> {
+    let limits = RpcLimits { max_connections: conn_limit, ..Default::default() };
+    accept_with_limits(reader, writer, addr, rh, limits, None, ex).await
+}
+
+/// Same as [`accept()`], applying the given [`RpcLimits`]. Requests in
+/// flight across all connections are limited by the given scheduler.
+#[allow(clippy::type_complexity)]
+async fn accept_with_limits(
+    reader: Arc<Mutex<BufReader<ReadHalf<Box<dyn PtStream>>>>>,
+    writer: Arc<Mutex<WriteHalf<Box<dyn PtStream>>>>,
+    addr: Url,
+    rh: Arc<impl RequestHandler + 'static>,
+    limits: RpcLimits,
+    scheduler: Option<Arc<FairScheduler>>,
+    ex: Arc<smol::Executor<'_>>,
 ) -> Result<()> {
     // If there's a connection limit set, we will refuse connections
     // after this point.
-    if let Some(conn_limit) = conn_limit {
+    if let Some(conn_limit) = limits.max_connections {
         if rh.clone().active_connections().await >= conn_limit {
             debug!(
                 target: "rpc::server::accept()",
@@ -225,14 +488,27 @@ pub async fn accept(
         }
     }
 
-    // We'll hold our background tasks here
+    // We'll hold our background tasks here, stopping them once the
+    // connection gets closed
     let tasks = Arc::new(Mutex::new(HashSet::new()));
+    let _tasks_guard = ConnectionTasks { tasks: tasks.clone(), ex: ex.clone() };
+
+    // Request slots of this connection, if limited
+    let conn_slots = limits.max_in_flight_per_conn.map(|n| Arc::new(Semaphore::new(n.max(1))));
 
     loop {
         let mut buf = Vec::with_capacity(INIT_BUF_SIZE);
 
+        // Read the next request, unless the connection goes idle first
         let mut reader_lock = reader.lock().await;
-        let _ = read_from_stream(&mut reader_lock, &mut buf).await?;
+        smol::future::or(
+            async {
+                read_from_stream(&mut reader_lock, &mut buf).await?;
+                Ok(())
+            },
+            idle_watch(&tasks, limits.idle_timeout),
+        )
+        .await?;
         drop(reader_lock);
 
         let line = match String::from_utf8(buf) {
@@ -270,7 +546,21 @@ pub async fn accept(
             }
         };
 
-        debug!(target: "rpc::server", "{} --> {}", addr, val.stringify()?);
+        // Log the request, with its sensitive params redacted
+        let ctx = RequestContext::new(addr.clone(), &req);
+        rh.request_logger().started(&ctx, &req.params);
+
+        // Wait for a free slot on this connection, and then for our turn
+        // among all the connections. We don't read further requests from
+        // this client until then.
+        let conn_slot = match conn_slots {
+            Some(ref conn_slots) => Some(conn_slots.acquire_arc().await),
+            None => None,
+        };
+        let slot = match scheduler {
+            Some(ref scheduler) => Some(scheduler.acquire().await),
+            None => None,
+        };
 
         // Create a new task to handle request in the background
         let task = StoppableTask::new();
@@ -278,17 +568,24 @@ pub async fn accept(
         // Clone what needs to go in the background
         let task_ = task.clone();
         let tasks_ = tasks.clone();
+        let request = handle_request(
+            writer.clone(),
+            addr.clone(),
+            rh.clone(),
+            ex.clone(),
+            tasks.clone(),
+            limits.keepalive_interval,
+            limits.timeout(&req.method),
+            ctx,
+            req,
+        );
 
-        // Detach the task
+        // Detach the task. The slots are released once it finishes.
         task.clone().start(
-            handle_request(
-                writer.clone(),
-                addr.clone(),
-                rh.clone(),
-                ex.clone(),
-                tasks.clone(),
-                req,
-            ),
+            async move {
+                let _slots = (conn_slot, slot);
+                request.await
+            },
             move |_| async move {
                 debug!(
                     target: "rpc::server",
@@ -305,14 +602,17 @@ pub async fn accept(
     }
 }
 
-/// Wrapper function around [`accept()`] to take the incoming connection and
+/// Wrapper function around [`accept_with_limits()`] to take the incoming connection and
 /// pass it forward.
 async fn run_accept_loop(
     listener: Box<dyn PtListener>,
     rh: Arc<impl RequestHandler + 'static>,
-    conn_limit: Option<usize>,
+    limits: RpcLimits,
     ex: Arc<smol::Executor<'_>>,
 ) -> Result<()> {
+    // Request slots shared by all connections, if limited
+    let scheduler = limits.max_in_flight.map(FairScheduler::new);
+
     loop {
         match listener.next().await {
             Ok((stream, url)) => {
@@ -327,7 +627,15 @@ async fn run_accept_loop(
                 let task_ = task.clone();
                 let ex_ = ex.clone();
                 task.clone().start(
-                    accept(reader, writer, url.clone(), rh.clone(), conn_limit, ex_),
+                    accept_with_limits(
+                        reader,
+                        writer,
+                        url.clone(),
+                        rh.clone(),
+                        limits.clone(),
+                        scheduler.clone(),
+                        ex_,
+                    ),
                     |_| async move {
                         info!(target: "rpc::server", "[RPC] Closed conn from {}", url);
                         rh_.clone().unmark_connection(task_.clone()).await;
@@ -376,14 +684,28 @@ async fn run_accept_loop(
 
 /// Start a JSON-RPC server bound to the given accept URL and use the
 /// given [`RequestHandler`] to handle incoming requests.
+/// Requests are scheduled using the default [`RpcLimits`].
 pub async fn listen_and_serve(
     accept_url: Url,
     rh: Arc<impl RequestHandler + 'static>,
     conn_limit: Option<usize>,
     ex: Arc<smol::Executor<'_>>,
 ) -> Result<()> {
-    let listener = Listener::new(accept_url, None).await?.listen().await?;
-    run_accept_loop(listener, rh, conn_limit, ex.clone()).await
+    let limits = RpcLimits { max_connections: conn_limit, ..Default::default() };
+    listen_and_serve_with_limits(accept_url, rh, limits, ex).await
+}
+
+/// Start a JSON-RPC server bound to the given accept URL, using the given
+/// [`RequestHandler`] to handle incoming requests and the given
+/// [`RpcLimits`] to schedule them.
+pub async fn listen_and_serve_with_limits(
+    accept_url: Url,
+    rh: Arc<impl RequestHandler + 'static>,
+    limits: RpcLimits,
+    ex: Arc<smol::Executor<'_>>,
+) -> Result<()> {
+    let listener = Listener::new(accept_url, None).await?.owner_only().listen().await?;
+    run_accept_loop(listener, rh, limits, ex.clone()).await
 }
 
 #[cfg(test)]
@@ -392,15 +714,34 @@ mod tests {
     use crate::{rpc::client::RpcClient, system::msleep};
     use smol::{net::TcpListener, Executor};
 
+    use std::sync::atomic::{AtomicBool, Ordering};
+
     struct RpcServer {
         rpc_connections: Mutex<HashSet<StoppableTaskPtr>>,
     }
 
+    /// Set once a `sleep` handler future gets dropped before completing
+    static SLEEP_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+    struct SleepGuard;
+
+    impl Drop for SleepGuard {
+        fn drop(&mut self) {
+            SLEEP_CANCELLED.store(true, Ordering::SeqCst);
+        }
+    }
+
     #[async_trait]
     impl RequestHandler for RpcServer {
         async fn handle_request(&self, req: JsonRequest) -> JsonResult {
             match req.method.as_str() {
                 "ping" => return self.pong(req.id, req.params).await,
+                "sleep" => {
+                    let guard = SleepGuard;
+                    msleep(5000).await;
+                    std::mem::forget(guard);
+                    return self.pong(req.id, req.params).await
+                }
                 _ => panic!(),
             }
         }
@@ -482,4 +823,205 @@ mod tests {
             Ok(())
         }))
     }
+
+    #[test]
+    fn idle_timeout() -> Result<()> {
+        let executor = Arc::new(Executor::new());
+
+        smol::block_on(executor.run(async {
+            // Find an available port
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let sockaddr = listener.local_addr()?;
+            let endpoint = Url::parse(&format!("tcp://127.0.0.1:{}", sockaddr.port()))?;
+            drop(listener);
+
+            let rpc_server = Arc::new(RpcServer { rpc_connections: Mutex::new(HashSet::new()) });
+            let limits =
+                RpcLimits { idle_timeout: Some(Duration::from_secs(2)), ..Default::default() };
+
+            let server_task = StoppableTask::new();
+            server_task.clone().start(
+                listen_and_serve_with_limits(
+                    endpoint.clone(),
+                    rpc_server.clone(),
+                    limits,
+                    executor.clone(),
+                ),
+                |res| async move {
+                    match res {
+                        Ok(()) | Err(Error::RpcServerStopped) => {}
+                        Err(e) => panic!("{}", e),
+                    }
+                },
+                Error::RpcServerStopped,
+                executor.clone(),
+            );
+
+            // Let the server spawn
+            msleep(500).await;
+
+            let rpc_client = RpcClient::new(endpoint, executor.clone()).await?;
+            msleep(500).await;
+            assert!(rpc_server.active_connections().await == 1);
+
+            // Traffic keeps the connection open
+            for _ in 0..3 {
+                msleep(1000).await;
+                let req = JsonRequest::new("ping", JsonValue::Array(vec![]));
+                rpc_client.request(req).await?;
+            }
+            assert!(rpc_server.active_connections().await == 1);
+
+            // Once quiet for long enough, the server closes it
+            msleep(3500).await;
+            assert!(rpc_server.active_connections().await == 0);
+
+            rpc_client.stop().await;
+            server_task.stop().await;
+
+            Ok(())
+        }))
+    }
+
+    #[test]
+    fn request_timeout() -> Result<()> {
+        let executor = Arc::new(Executor::new());
+
+        smol::block_on(executor.run(async {
+            // Find an available port
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let sockaddr = listener.local_addr()?;
+            let endpoint = Url::parse(&format!("tcp://127.0.0.1:{}", sockaddr.port()))?;
+            drop(listener);
+
+            let rpc_server = Arc::new(RpcServer { rpc_connections: Mutex::new(HashSet::new()) });
+            let limits = RpcLimits {
+                request_timeout: Some(Duration::from_secs(10)),
+                method_timeouts: HashMap::from([("sleep".to_string(), Duration::from_secs(1))]),
+                ..Default::default()
+            };
+            assert_eq!(limits.timeout("ping"), Some(Duration::from_secs(10)));
+
+            let server_task = StoppableTask::new();
+            server_task.clone().start(
+                listen_and_serve_with_limits(
+                    endpoint.clone(),
+                    rpc_server.clone(),
+                    limits,
+                    executor.clone(),
+                ),
+                |res| async move {
+                    match res {
+                        Ok(()) | Err(Error::RpcServerStopped) => {}
+                        Err(e) => panic!("{}", e),
+                    }
+                },
+                Error::RpcServerStopped,
+                executor.clone(),
+            );
+
+            // Let the server spawn
+            msleep(500).await;
+
+            let rpc_client = RpcClient::new(endpoint, executor.clone()).await?;
+
+            // The slow handler gets cut short and dropped
+            let req = JsonRequest::new("sleep", JsonValue::Array(vec![]));
+            match rpc_client.request(req).await {
+                Err(Error::JsonRpcError(e)) => assert_eq!(e.kind(), ErrorCode::RequestTimeout),
+                r => panic!("Unexpected reply: {:?}", r),
+            }
+            assert!(SLEEP_CANCELLED.load(Ordering::SeqCst));
+
+            // The connection is still usable
+            let req = JsonRequest::new("ping", JsonValue::Array(vec![]));
+            assert_eq!(rpc_client.request(req).await?, JsonValue::String("pong".to_string()));
+
+            rpc_client.stop().await;
+            server_task.stop().await;
+
+            Ok(())
+        }))
+    }
+
+    #[test]
+    #[cfg(feature = "p2p-unix")]
+    fn unix_socket() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let executor = Arc::new(Executor::new());
+
+        smol::block_on(executor.run(async {
+            let path = std::env::temp_dir().join(format!("darkfi-rpc-{}.sock", std::process::id()));
+            let endpoint = Url::parse(&format!("unix://{}", path.display()))?;
+
+            let rpc_server = Arc::new(RpcServer { rpc_connections: Mutex::new(HashSet::new()) });
+
+            let server_task = StoppableTask::new();
+            server_task.clone().start(
+                listen_and_serve(endpoint.clone(), rpc_server.clone(), None, executor.clone()),
+                |res| async move {
+                    match res {
+                        Ok(()) | Err(Error::RpcServerStopped) => {}
+                        Err(e) => panic!("{}", e),
+                    }
+                },
+                Error::RpcServerStopped,
+                executor.clone(),
+            );
+
+            // Let the server spawn
+            msleep(500).await;
+
+            // Only our own user can access the socket
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            let rpc_client = RpcClient::new(endpoint, executor.clone()).await?;
+            let rep =
+                rpc_client.request(JsonRequest::new("ping", JsonValue::Array(vec![]))).await?;
+            assert_eq!(rep, JsonValue::String("pong".to_string()));
+
+            rpc_client.stop().await;
+            server_task.stop().await;
+            let _ = std::fs::remove_file(&path);
+
+            Ok(())
+        }))
+    }
+
+    #[test]
+    fn fair_scheduler() {
+        let executor = Arc::new(Executor::new());
+
+        smol::block_on(executor.run(async {
+            let scheduler = FairScheduler::new(1);
+            let order = Arc::new(Mutex::new(vec![]));
+
+            // Hold the only slot, so the following requests have to wait
+            let slot = scheduler.acquire().await;
+
+            let mut tasks = vec![];
+            for i in 0..3 {
+                let scheduler_ = scheduler.clone();
+                let order_ = order.clone();
+                tasks.push(executor.spawn(async move {
+                    let _slot = scheduler_.acquire().await;
+                    order_.lock().await.push(i);
+                }));
+                // Make sure they queue up in order
+                msleep(50).await;
+            }
+
+            // Slots are handed out in the order they were requested
+            drop(slot);
+            for task in tasks {
+                task.await;
+            }
+            assert_eq!(*order.lock().await, vec![0, 1, 2]);
+
+            // Everything was released
+            assert_eq!(scheduler.state.lock().unwrap().in_flight, 0);
+        }))
+    }
 }
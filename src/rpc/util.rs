@@ -16,7 +16,11 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
+
+use darkfi_serial::{deserialize, serialize, Decodable, Encodable};
+
+use crate::{Error, Result};
 
 pub use tinyjson::JsonValue::{
     self, Array as JsonArray, Number as JsonNum, Object as JsonObj, String as JsonStr,
@@ -30,3 +34,67 @@ pub fn json_map<const N: usize>(vals: [(&str, JsonValue); N]) -> JsonValue {
 pub fn json_str(val: &str) -> JsonValue {
     JsonStr(val.to_string())
 }
+
+// Canonical string encoding of crypto types in JSON: a type prefix, an
+// underscore and the base58 encoded serialized value, e.g. `pk_8KwL...`.
+// The prefix tells clients what kind of value a string holds, so they
+// don't have to guess it from its length or alphabet.
+
+/// Prefix of public keys and addresses
+pub const PUBLIC_KEY_PREFIX: &str = "pk";
+/// Prefix of token IDs
+pub const TOKEN_ID_PREFIX: &str = "tk";
+/// Prefix of transaction hashes
+pub const TX_HASH_PREFIX: &str = "tx";
+/// Prefix of coins
+pub const COIN_PREFIX: &str = "cn";
+/// Prefix of contract IDs
+pub const CONTRACT_ID_PREFIX: &str = "ct";
+
+/// Encode `value` into its canonical string with given type prefix
+pub fn encode_typed<T: Encodable>(prefix: &str, value: &T) -> String {
+    format!("{prefix}_{}", bs58::encode(serialize(value)).into_string())
+}
+
+/// Encode `value` into a JSON string of its canonical form
+pub fn json_typed<T: Encodable>(prefix: &str, value: &T) -> JsonValue {
+    JsonStr(encode_typed(prefix, value))
+}
+
+/// Decode a value of given type prefix from its canonical string.
+/// Strings without a prefix are parsed with the type's `FromStr`, so
+/// clients predating the canonical format keep working.
+pub fn decode_typed<T: Decodable + FromStr>(prefix: &str, value: &str) -> Result<T> {
+    let Some((value_prefix, encoded)) = value.split_once('_') else {
+        return T::from_str(value).map_err(|_| Error::ParseFailed("Invalid bare value encoding"))
+    };
+
+    if value_prefix != prefix {
+        return Err(Error::ParseFailed("Unexpected value type prefix"))
+    }
+
+    let Ok(bytes) = bs58::decode(encoded).into_vec() else {
+        return Err(Error::ParseFailed("Invalid value base58 encoding"))
+    };
+
+    Ok(deserialize(&bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_encoding() {
+        let encoded = encode_typed(TX_HASH_PREFIX, &42_u64);
+        assert!(encoded.starts_with("tx_"));
+        assert_eq!(decode_typed::<u64>(TX_HASH_PREFIX, &encoded).unwrap(), 42);
+
+        // Bare strings are still accepted
+        assert_eq!(decode_typed::<u64>(TX_HASH_PREFIX, "42").unwrap(), 42);
+
+        // A value of another type is rejected
+        assert!(decode_typed::<u64>(PUBLIC_KEY_PREFIX, &encoded).is_err());
+        assert!(decode_typed::<u64>(TX_HASH_PREFIX, "tx_0OIl").is_err());
+    }
+}
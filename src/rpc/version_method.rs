@@ -0,0 +1,151 @@
+/* This file is part of DarkFi (https://dark.fi)
+ *
+ * Copyright (C) 2020-2024 Dyne.org foundation
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Optional `get_version` method, returning the build metadata of a
+//! daemon so client tooling can adapt to it and bug reports carry
+//! precise versions.
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use super::{
+    jsonrpc::{JsonResponse, JsonResult},
+    util::*,
+};
+use crate::error::RpcError;
+
+/// Build metadata of a daemon, as returned by `get_version`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DaemonVersion {
+    /// Daemon name
+    pub name: String,
+    /// Daemon version
+    pub version: String,
+    /// Revision of the daemon's RPC API, bumped on breaking changes
+    pub api_revision: u64,
+    /// Git commit the daemon was built from, if known
+    pub git_hash: Option<String>,
+    /// Optional features the daemon was built with
+    pub features: Vec<String>,
+}
+
+/// Build a [`DaemonVersion`] from the calling crate's package metadata.
+/// The git commit is taken from the `DARKFI_GIT_HASH` environment
+/// variable at build time, if set.
+#[macro_export]
+macro_rules! daemon_version {
+    ($api_revision:expr) => {
+        $crate::rpc::version_method::DaemonVersion {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            api_revision: $api_revision,
+            git_hash: option_env!("DARKFI_GIT_HASH").map(|h| h.to_string()),
+            features: vec![],
+        }
+    };
+}
+
+impl From<DaemonVersion> for JsonValue {
+    fn from(version: DaemonVersion) -> JsonValue {
+        let git_hash = match version.git_hash {
+            Some(hash) => JsonStr(hash),
+            None => JsonValue::Null,
+        };
+
+        json_map([
+            ("name", JsonStr(version.name)),
+            ("version", JsonStr(version.version)),
+            ("api_revision", JsonNum(version.api_revision as f64)),
+            ("git_hash", git_hash),
+            ("features", JsonArray(version.features.into_iter().map(JsonStr).collect())),
+        ])
+    }
+}
+
+impl TryFrom<&JsonValue> for DaemonVersion {
+    type Error = RpcError;
+
+    fn try_from(value: &JsonValue) -> std::result::Result<Self, RpcError> {
+        let invalid =
+            |key: &str| RpcError::InvalidJson(format!("Version does not contain valid \"{key}\""));
+
+        let Some(map) = value.get::<HashMap<String, JsonValue>>() else {
+            return Err(invalid("version"))
+        };
+        let str_field = |key: &str| -> std::result::Result<String, RpcError> {
+            map.get(key).and_then(|v| v.get::<String>()).cloned().ok_or_else(|| invalid(key))
+        };
+
+        let Some(api_revision) = map.get("api_revision").and_then(|v| v.get::<f64>()) else {
+            return Err(invalid("api_revision"))
+        };
+
+        let git_hash = match map.get("git_hash") {
+            Some(JsonValue::Null) | None => None,
+            Some(_) => Some(str_field("git_hash")?),
+        };
+
+        let Some(features) = map.get("features").and_then(|v| v.get::<Vec<JsonValue>>()) else {
+            return Err(invalid("features"))
+        };
+        let mut parsed = Vec::with_capacity(features.len());
+        for feature in features {
+            let Some(feature) = feature.get::<String>() else { return Err(invalid("features")) };
+            parsed.push(feature.clone());
+        }
+
+        Ok(Self {
+            name: str_field("name")?,
+            version: str_field("version")?,
+            api_revision: *api_revision as u64,
+            git_hash,
+            features: parsed,
+        })
+    }
+}
+
+#[async_trait]
+pub trait HandlerVersion: Sync + Send {
+    async fn get_version(&self, id: u16, _params: JsonValue) -> JsonResult {
+        JsonResponse::new(self.daemon_version().into(), id).into()
+    }
+
+    fn daemon_version(&self) -> DaemonVersion;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daemon_version_roundtrip() {
+        let mut version = daemon_version!(3);
+        version.features = vec!["tor".to_string()];
+        assert_eq!(version.name, "darkfi");
+        assert_eq!(version.api_revision, 3);
+
+        let json: JsonValue = version.clone().into();
+        assert_eq!(DaemonVersion::try_from(&json).unwrap(), version);
+
+        version.git_hash = Some("abcdef0".to_string());
+        let json: JsonValue = version.clone().into();
+        assert_eq!(DaemonVersion::try_from(&json).unwrap(), version);
+
+        assert!(DaemonVersion::try_from(&JsonValue::Array(vec![])).is_err());
+    }
+}
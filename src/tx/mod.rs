@@ -120,13 +120,18 @@ impl Transaction {
         Ok(())
     }
 
-    /// Verify Schnorr signatures for the entire transaction.
-    pub fn verify_sigs(&self, pub_table: Vec<Vec<PublicKey>>) -> Result<()> {
-        // Hash the transaction without the signatures
+    /// Hash of the transaction without the signatures, which is the
+    /// message its signatures are made over.
+    pub fn signing_hash(&self) -> Result<blake3::Hash> {
         let mut hasher = blake3::Hasher::new();
         self.calls.encode(&mut hasher)?;
         self.proofs.encode(&mut hasher)?;
-        let data_hash = hasher.finalize();
+        Ok(hasher.finalize())
+    }
+
+    /// Verify Schnorr signatures for the entire transaction.
+    pub fn verify_sigs(&self, pub_table: Vec<Vec<PublicKey>>) -> Result<()> {
+        let data_hash = self.signing_hash()?;
 
         debug!(
             target: "tx::verify_sigs",
@@ -160,11 +165,7 @@ impl Transaction {
 
     /// Create Schnorr signatures for the entire transaction.
     pub fn create_sigs(&self, secret_keys: &[SecretKey]) -> Result<Vec<Signature>> {
-        // Hash the transaction without the signatures
-        let mut hasher = blake3::Hasher::new();
-        self.calls.encode(&mut hasher)?;
-        self.proofs.encode(&mut hasher)?;
-        let data_hash = hasher.finalize();
+        let data_hash = self.signing_hash()?;
 
         debug!(
             target: "tx::create_sigs",
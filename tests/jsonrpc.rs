@@ -32,6 +32,7 @@ use darkfi::{
     rpc::{
         client::RpcClient,
         jsonrpc::*,
+        mock::MockRpcServer,
         server::{listen_and_serve, RequestHandler},
     },
     system::{msleep, StoppableTask, StoppableTaskPtr},
@@ -52,6 +53,13 @@ impl RpcSrv {
         self.stop_sub.0.send(()).await.unwrap();
         JsonResponse::new(JsonValue::String("bye".to_string()), id).into()
     }
+
+    async fn locked(&self, id: u16, _params: JsonValue) -> JsonResult {
+        let data = JsonValue::Array(vec![JsonValue::String("wallet".to_string())]);
+        JsonError::new(ErrorCode::ServerError(-32001), Some("locked".to_string()), id)
+            .with_data(data)
+            .into()
+    }
 }
 
 #[async_trait]
@@ -62,6 +70,7 @@ impl RequestHandler for RpcSrv {
         return match req.method.as_str() {
             "ping" => self.pong(req.id, req.params).await,
             "kill" => self.kill(req.id, req.params).await,
+            "locked" => self.locked(req.id, req.params).await,
             _ => JsonError::new(ErrorCode::MethodNotFound, None, req.id).into(),
         }
     }
@@ -110,6 +119,23 @@ fn jsonrpc_reqrep() -> Result<()> {
         let rep = String::try_from(rep).unwrap();
         assert_eq!(&rep, "pong");
 
+        // Errors keep their code, message and data
+        let req = JsonRequest::new("unknown", vec![].into());
+        match client.request(req).await {
+            Err(Error::JsonRpcError(e)) => assert_eq!(e.kind(), ErrorCode::MethodNotFound),
+            r => panic!("Unexpected reply: {:?}", r),
+        }
+
+        let req = JsonRequest::new("locked", vec![].into());
+        match client.request(req).await {
+            Err(Error::JsonRpcError(e)) => {
+                assert_eq!(e.kind(), ErrorCode::ServerError(-32001));
+                assert_eq!(e.message, "locked");
+                assert_eq!(e.data, Some(JsonValue::Array(vec!["wallet".to_string().into()])));
+            }
+            r => panic!("Unexpected reply: {:?}", r),
+        }
+
         let req = JsonRequest::new("kill", vec![].into());
         let rep = client.request(req).await?;
 
@@ -119,3 +145,42 @@ fn jsonrpc_reqrep() -> Result<()> {
         Ok(())
     }))
 }
+
+#[test]
+fn jsonrpc_mock_server() -> Result<()> {
+    let executor = Arc::new(Executor::new());
+
+    smol::block_on(executor.run(async {
+        // Find an available port
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let sockaddr = listener.local_addr()?;
+        let endpoint = Url::parse(&format!("tcp://127.0.0.1:{}", sockaddr.port()))?;
+        drop(listener);
+
+        let server = MockRpcServer::new();
+        server.respond("blockchain.last_known_block", JsonValue::Number(42.0)).await;
+        server.respond_error("tx.broadcast", ErrorCode::InvalidParams, "bad tx").await;
+        let rpc_task = server.clone().start(endpoint.clone(), executor.clone());
+
+        msleep(500).await;
+
+        let client = RpcClient::new(endpoint, executor.clone()).await?;
+        let req = JsonRequest::new("blockchain.last_known_block", vec![].into());
+        let rep = client.request(req).await?;
+        assert_eq!(*rep.get::<f64>().unwrap(), 42.0);
+
+        let req = JsonRequest::new("tx.broadcast", vec![].into());
+        assert!(client.request(req).await.is_err());
+
+        let req = JsonRequest::new("unknown", vec![].into());
+        assert!(client.request(req).await.is_err());
+
+        assert_eq!(server.requests().await.len(), 3);
+        assert_eq!(server.requests_for("tx.broadcast").await.len(), 1);
+
+        client.stop().await;
+        rpc_task.stop().await;
+
+        Ok(())
+    }))
+}
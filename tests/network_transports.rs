@@ -112,3 +112,67 @@ fn unix_transport() {
         assert_eq!(buf, payload);
     }));
 }
+
+#[test]
+fn socks5_transport() {
+    use smol::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    let executor = LocalExecutor::new();
+    let url = Url::parse("tcp://127.0.0.1:5434").unwrap();
+    let proxy = Url::parse("socks5://127.0.0.1:5435").unwrap();
+
+    smol::block_on(executor.run(async {
+        let listener = Listener::new(url.clone(), None).await.unwrap().listen().await.unwrap();
+        executor
+            .spawn(async move {
+                let (stream, _) = listener.next().await.unwrap();
+                let (mut reader, mut writer) = smol::io::split(stream);
+                io::copy(&mut reader, &mut writer).await.unwrap();
+            })
+            .detach();
+
+        // Minimal SOCKS5 proxy accepting a single unauthenticated CONNECT
+        let proxy_listener = TcpListener::bind("127.0.0.1:5435").await.unwrap();
+        executor
+            .spawn(async move {
+                let (mut client, _) = proxy_listener.accept().await.unwrap();
+                let mut greeting = [0u8; 3];
+                client.read_exact(&mut greeting).await.unwrap();
+                assert_eq!(greeting, [5, 1, 0]);
+                client.write_all(&[5, 0]).await.unwrap();
+
+                let mut request = [0u8; 10];
+                client.read_exact(&mut request).await.unwrap();
+                assert_eq!(request[..4], [5, 1, 0, 1]);
+                let port = u16::from_be_bytes([request[8], request[9]]);
+                let target =
+                    format!("{}.{}.{}.{}:{}", request[4], request[5], request[6], request[7], port);
+                let upstream = TcpStream::connect(target).await.unwrap();
+                client.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).await.unwrap();
+
+                let (mut client_r, mut client_w) = smol::io::split(client);
+                let (mut upstream_r, mut upstream_w) = smol::io::split(upstream);
+                let _ = smol::future::zip(
+                    io::copy(&mut client_r, &mut upstream_w),
+                    io::copy(&mut upstream_r, &mut client_w),
+                )
+                .await;
+            })
+            .detach();
+
+        let payload = "ohai socks5";
+
+        let endpoint = Url::parse("socks5://127.0.0.1:5434").unwrap();
+        let mut dialer = Dialer::new(endpoint, None).await.unwrap();
+        dialer.set_socks5_proxy(proxy).await.unwrap();
+        let mut client = dialer.dial(None).await.unwrap();
+        payload.encode_async(&mut client).await.unwrap();
+
+        let buf: String = AsyncDecodable::decode_async(&mut client).await.unwrap();
+
+        assert_eq!(buf, payload);
+    }));
+}